@@ -0,0 +1,173 @@
+//! Compares the old single `Mutex<Connection>` against `DbPool`. Run with
+//! `cargo bench --bench concurrent_reads`.
+//!
+//! Two measurements:
+//! - how long a point lookup waits while another task runs a long scan.
+//!   Behind one mutex the lookup waits for the whole scan; with the pool it
+//!   gets its own connection. This holds on any machine.
+//! - total time for `TASKS` tasks running the same scan at once. Behind one
+//!   mutex they queue up; with the pool they run side by side, so the
+//!   speedup grows with the number of CPU cores (none on a single core).
+
+use rusqlite::Connection;
+use rwe_lib::database_pool::DbPool;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const ROWS: i64 = 200_000;
+const TASKS: usize = 8;
+const ROUNDS: usize = 3;
+
+const SCAN: &str = "SELECT COUNT(*), SUM(LENGTH(body)) FROM samples WHERE body LIKE '%42%'";
+const LONG_SCAN_REPEATS: usize = 8;
+
+fn scan(conn: &Connection) -> Result<i64, String> {
+    conn.query_row(SCAN, [], |row| row.get(0))
+        .map_err(|e| format!("Query error: {}", e))
+}
+
+fn long_scan(conn: &Connection) -> Result<i64, String> {
+    let mut total = 0;
+    for _ in 0..LONG_SCAN_REPEATS {
+        total += scan(conn)?;
+    }
+    Ok(total)
+}
+
+fn lookup(conn: &Connection) -> Result<String, String> {
+    conn.query_row("SELECT body FROM samples WHERE id = ?1", [ROWS / 2], |row| row.get(0))
+        .map_err(|e| format!("Query error: {}", e))
+}
+
+/// Gives a task spawned just before time to start its query.
+const HEAD_START: Duration = Duration::from_millis(5);
+
+fn seed(path: &PathBuf) {
+    let conn = Connection::open(path).expect("open database");
+    conn.execute_batch(
+        r#"
+        CREATE TABLE samples (id INTEGER PRIMARY KEY, body TEXT NOT NULL);
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 200000)
+        INSERT INTO samples (id, body)
+            SELECT i, printf('message %d with some text to scan through %d', i, i * 7919) FROM n;
+        "#,
+    ).expect("seed samples");
+    let rows: i64 = conn.query_row("SELECT COUNT(*) FROM samples", [], |row| row.get(0)).unwrap();
+    assert_eq!(rows, ROWS);
+}
+
+async fn single_connection_lookup(conn: Arc<Mutex<Connection>>) -> Duration {
+    let scanning = {
+        let conn = conn.clone();
+        tokio::task::spawn_blocking(move || long_scan(&conn.lock().unwrap()).unwrap())
+    };
+    tokio::time::sleep(HEAD_START).await;
+
+    let started = Instant::now();
+    tokio::task::spawn_blocking(move || lookup(&conn.lock().unwrap()).unwrap()).await.unwrap();
+    let waited = started.elapsed();
+
+    scanning.await.unwrap();
+    waited
+}
+
+async fn pooled_lookup(pool: Arc<DbPool>) -> Duration {
+    let scanning = {
+        let pool = pool.clone();
+        tokio::spawn(async move { pool.run(long_scan).await.unwrap() })
+    };
+    tokio::time::sleep(HEAD_START).await;
+
+    let started = Instant::now();
+    pool.run(lookup).await.unwrap();
+    let waited = started.elapsed();
+
+    scanning.await.unwrap();
+    waited
+}
+
+async fn single_connection(conn: Arc<Mutex<Connection>>) -> Duration {
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let conn = conn.clone();
+            tokio::spawn(async move {
+                let conn = conn.lock().unwrap();
+                scan(&conn).unwrap()
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    started.elapsed()
+}
+
+async fn pooled(pool: Arc<DbPool>) -> Duration {
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.run(scan).await.unwrap() })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    started.elapsed()
+}
+
+fn best(times: &[Duration]) -> Duration {
+    times.iter().copied().min().unwrap_or_default()
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    let dir = std::env::temp_dir().join(format!("rwe-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("create bench directory");
+    let path = dir.join("bench.db");
+    seed(&path);
+
+    let single = Arc::new(Mutex::new(Connection::open(&path).expect("open database")));
+    let pool = Arc::new(DbPool::new(Some(DbPool::open(&path, None).expect("open pool"))));
+
+    // Warm the page cache and the pool's connections before timing
+    single_connection(single.clone()).await;
+    pooled(pool.clone()).await;
+
+    let mut single_lookups = Vec::new();
+    let mut pooled_lookups = Vec::new();
+    let mut single_times = Vec::new();
+    let mut pooled_times = Vec::new();
+    for _ in 0..ROUNDS {
+        single_lookups.push(single_connection_lookup(single.clone()).await);
+        pooled_lookups.push(pooled_lookup(pool.clone()).await);
+        single_times.push(single_connection(single.clone()).await);
+        pooled_times.push(pooled(pool.clone()).await);
+    }
+
+    let one_scan = {
+        let started = Instant::now();
+        scan(&single.lock().unwrap()).unwrap();
+        started.elapsed()
+    };
+    let single_best = best(&single_times);
+    let pooled_best = best(&pooled_times);
+
+    println!("Point lookup during a {}-scan query (best of {})", LONG_SCAN_REPEATS, ROUNDS);
+    println!("  single Mutex<Connection>: {:>8.1?}", best(&single_lookups));
+    println!("  DbPool:                   {:>8.1?}", best(&pooled_lookups));
+    println!("{} concurrent scans of {} rows (best of {})", TASKS, ROWS, ROUNDS);
+    println!("  one scan alone:           {:>8.1?}", one_scan);
+    println!("  single Mutex<Connection>: {:>8.1?}", single_best);
+    println!(
+        "  DbPool:                   {:>8.1?}  ({:.1}x faster)",
+        pooled_best,
+        single_best.as_secs_f64() / pooled_best.as_secs_f64()
+    );
+
+    drop(single);
+    drop(pool);
+    std::fs::remove_dir_all(&dir).ok();
+}
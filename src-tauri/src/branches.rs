@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+use rusqlite::{Connection, OptionalExtension};
+use crate::database::{self, Message, MESSAGE_COLUMNS};
+use crate::database_pool::DbPool;
+use crate::embeddings::EmbeddingIndexer;
+use crate::error::RweError;
+
+/// One line through a conversation's message tree. A conversation has no
+/// branches until it is first forked; then its original line becomes "Main".
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Branch {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub name: String,
+    /// The last message the branch shares with the one it was forked from;
+    /// `None` for the original line.
+    pub fork_message_id: Option<i64>,
+    pub head_message_id: i64,
+    pub active: bool,
+    pub created_at: String,
+    /// Messages on the branch, shared history included.
+    pub message_count: i64,
+}
+
+const PATH_SQL: &str = r#"
+    WITH RECURSIVE path(id) AS (
+        SELECT ?1
+        UNION ALL
+        SELECT m.parent_id FROM messages m JOIN path ON m.id = path.id
+        WHERE m.parent_id IS NOT NULL
+    )
+"#;
+
+/// Condition for queries over `messages m` that keeps only messages on their
+/// conversation's active branch. A conversation that was never forked has a
+/// single line, so all of its messages pass. The active paths are walked the
+/// same way as `PATH_SQL`, once per query rather than once per row.
+pub const ON_ACTIVE_BRANCH_SQL: &str = r#"(
+    NOT EXISTS (SELECT 1 FROM conversation_branches b WHERE b.conversation_id = m.conversation_id)
+    OR m.id IN (
+        WITH RECURSIVE path(id) AS (
+            SELECT head_message_id FROM conversation_branches WHERE active = 1
+            UNION ALL
+            SELECT p.parent_id FROM messages p JOIN path ON p.id = path.id
+            WHERE p.parent_id IS NOT NULL
+        )
+        SELECT id FROM path
+    )
+)"#;
+
+fn load_branch(conn: &Connection, branch_id: i64) -> Result<Branch, RweError> {
+    let mut branch = conn.query_row(
+        r#"
+        SELECT id, conversation_id, name, fork_message_id, head_message_id, active, created_at
+        FROM conversation_branches WHERE id = ?1
+        "#,
+        [branch_id],
+        |row| {
+            Ok(Branch {
+                id: row.get(0)?,
+                conversation_id: row.get(1)?,
+                name: row.get(2)?,
+                fork_message_id: row.get(3)?,
+                head_message_id: row.get(4)?,
+                active: row.get(5)?,
+                created_at: row.get(6)?,
+                message_count: 0,
+            })
+        },
+    ).map_err(|e| RweError::database("Query branch error", e))?;
+
+    branch.message_count = conn.query_row(
+        &format!("{} SELECT COUNT(*) FROM path", PATH_SQL),
+        [branch.head_message_id],
+        |row| row.get(0),
+    ).map_err(|e| RweError::database("Query error", e))?;
+    Ok(branch)
+}
+
+/// The last message on the conversation's active branch, if it has been forked.
+pub fn active_head(conn: &Connection, conversation_id: i64) -> Result<Option<i64>, RweError> {
+    conn.query_row(
+        "SELECT head_message_id FROM conversation_branches WHERE conversation_id = ?1 AND active = 1",
+        [conversation_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| RweError::database("Query error", e))
+}
+
+/// The messages leading up to and including `head_message_id`, oldest first.
+pub fn path_to(conn: &Connection, head_message_id: i64) -> Result<Vec<Message>, RweError> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} SELECT {} FROM messages WHERE id IN (SELECT id FROM path) ORDER BY seq ASC",
+            PATH_SQL, MESSAGE_COLUMNS
+        ))
+        .map_err(|e| RweError::database("Prepare error", e))?;
+
+    let messages = stmt
+        .query_map([head_message_id], database::row_to_message)
+        .map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+
+    Ok(messages)
+}
+
+/// Records the conversation's current, unforked line as "Main" the first
+/// time it is forked.
+fn ensure_main_branch(conn: &Connection, conversation_id: i64) -> Result<(), RweError> {
+    conn.execute(
+        r#"
+        INSERT INTO conversation_branches (conversation_id, name, head_message_id, active)
+        SELECT ?1, 'Main', id, 1 FROM messages
+        WHERE conversation_id = ?1
+          AND NOT EXISTS (SELECT 1 FROM conversation_branches WHERE conversation_id = ?1)
+        ORDER BY seq DESC LIMIT 1
+        "#,
+        [conversation_id],
+    ).map_err(|e| RweError::database("Insert branch error", e))?;
+    Ok(())
+}
+
+#[command]
+pub async fn get_branches(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<Vec<Branch>, RweError> {
+    db.run(move |conn| list_branches(conn, conversation_id)).await
+}
+
+fn list_branches(conn: &Connection, conversation_id: i64) -> Result<Vec<Branch>, RweError> {
+    let mut stmt = conn
+        .prepare("SELECT id FROM conversation_branches WHERE conversation_id = ?1 ORDER BY id")
+        .map_err(|e| RweError::database("Prepare error", e))?;
+
+    let ids = stmt
+        .query_map([conversation_id], |row| row.get::<_, i64>(0))
+        .map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+
+    ids.into_iter().map(|id| load_branch(conn, id)).collect()
+}
+
+/// Starts a new branch that shares the conversation's history up to and
+/// including `message_id`, and makes it the active one, so the next message
+/// sent continues from there. The line it was forked from is kept, but
+/// search only sees the active branch.
+#[command]
+pub async fn fork_conversation(
+    conversation_id: i64,
+    message_id: i64,
+    name: Option<String>,
+    db: State<'_, DbPool>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<Branch, RweError> {
+    let branch = db.run(move |conn| fork(conn, conversation_id, message_id, name.as_deref())).await?;
+
+    indexer.schedule();
+    Ok(branch)
+}
+
+fn fork(conn: &Connection, conversation_id: i64, message_id: i64, name: Option<&str>) -> Result<Branch, RweError> {
+    database::with_transaction(conn, |tx| {
+        let owner: Option<i64> = tx
+            .query_row("SELECT conversation_id FROM messages WHERE id = ?1", [message_id], |row| row.get(0))
+            .optional()
+            .map_err(|e| RweError::database("Query error", e))?;
+        if owner != Some(conversation_id) {
+            return Err(RweError::NotFound(format!(
+                "Message {} not found in conversation {}", message_id, conversation_id
+            )));
+        }
+
+        ensure_main_branch(tx, conversation_id)?;
+        let name = match name.map(str::trim).filter(|name| !name.is_empty()) {
+            Some(name) => name.to_string(),
+            None => {
+                let forks: i64 = tx.query_row(
+                    "SELECT COUNT(*) FROM conversation_branches WHERE conversation_id = ?1",
+                    [conversation_id],
+                    |row| row.get(0),
+                ).map_err(|e| RweError::database("Query error", e))?;
+                format!("Branch {}", forks)
+            }
+        };
+
+        tx.execute(
+            "UPDATE conversation_branches SET active = 0 WHERE conversation_id = ?1 AND active = 1",
+            [conversation_id],
+        ).map_err(|e| RweError::database("Update branch error", e))?;
+        tx.execute(
+            r#"
+            INSERT INTO conversation_branches (conversation_id, name, fork_message_id, head_message_id, active)
+            VALUES (?1, ?2, ?3, ?3, 1)
+            "#,
+            rusqlite::params![conversation_id, name, message_id],
+        ).map_err(|e| RweError::database("Insert branch error", e))?;
+
+        load_branch(tx, tx.last_insert_rowid())
+    })
+}
+
+/// Makes `branch_id` the conversation's active branch and returns its messages.
+/// Messages that become active are embedded in the background.
+#[command]
+pub async fn switch_branch(
+    conversation_id: i64,
+    branch_id: i64,
+    db: State<'_, DbPool>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<Vec<Message>, RweError> {
+    let messages = db.run(move |conn| switch(conn, conversation_id, branch_id)).await?;
+
+    indexer.schedule();
+    Ok(messages)
+}
+
+fn switch(conn: &Connection, conversation_id: i64, branch_id: i64) -> Result<Vec<Message>, RweError> {
+    database::with_transaction(conn, |tx| {
+        let head: i64 = tx.query_row(
+            "SELECT head_message_id FROM conversation_branches WHERE id = ?1 AND conversation_id = ?2",
+            [branch_id, conversation_id],
+            |row| row.get(0),
+        ).map_err(|e| RweError::database("Branch not found", e))?;
+
+        tx.execute(
+            "UPDATE conversation_branches SET active = 0 WHERE conversation_id = ?1 AND active = 1",
+            [conversation_id],
+        ).map_err(|e| RweError::database("Update branch error", e))?;
+        tx.execute(
+            "UPDATE conversation_branches SET active = 1 WHERE id = ?1",
+            [branch_id],
+        ).map_err(|e| RweError::database("Update branch error", e))?;
+
+        path_to(tx, head)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+    use crate::search::run_search;
+    use crate::search_query::parse_query;
+
+    /// A conversation of four messages, and their ids.
+    fn conversation() -> (Connection, Vec<i64>) {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO conversations (id, name) VALUES (1, 'Retries')", []).unwrap();
+        let ids = ["how do I retry", "use a loop", "with backoff", "exponential backoff"]
+            .iter()
+            .enumerate()
+            .map(|(i, content)| database::insert_message(&conn, 1, if i % 2 == 0 { "user" } else { "ai" }, content, false).unwrap())
+            .collect();
+        (conn, ids)
+    }
+
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    fn found(conn: &Connection, query: &str) -> Vec<i64> {
+        let query = parse_query(&format!("type:message {}", query)).unwrap();
+        run_search(conn, &query).unwrap().into_iter().map(|result| result.id).collect()
+    }
+
+    #[test]
+    fn messages_follow_the_active_branch_after_a_fork() {
+        let (conn, ids) = conversation();
+        let branch = fork(&conn, 1, ids[1], None).unwrap();
+        assert_eq!((branch.name.as_str(), branch.head_message_id, branch.message_count), ("Branch 1", ids[1], 2));
+        assert_eq!(contents(&database::load_messages(&conn, 1).unwrap()), ["how do I retry", "use a loop"]);
+
+        // New messages continue from the fork point, numbered after every
+        // message in the conversation
+        let reply = database::insert_message_with_metadata(&conn, 1, "user", "with jitter", false, &Default::default()).unwrap();
+        let reply = database::load_message(&conn, reply).unwrap();
+        assert_eq!((reply.parent_id, reply.seq), (Some(ids[1]), 5));
+        assert_eq!(contents(&database::load_messages(&conn, 1).unwrap()), ["how do I retry", "use a loop", "with jitter"]);
+
+        let branches = list_branches(&conn, 1).unwrap();
+        let heads: Vec<_> = branches.iter().map(|b| (b.name.as_str(), b.head_message_id, b.active, b.message_count)).collect();
+        assert_eq!(heads, [("Main", ids[3], false, 4), ("Branch 1", reply.id, true, 3)]);
+
+        let main = switch(&conn, 1, branches[0].id).unwrap();
+        assert_eq!(contents(&main), ["how do I retry", "use a loop", "with backoff", "exponential backoff"]);
+        assert_eq!(contents(&database::load_messages(&conn, 1).unwrap()), contents(&main));
+        let next = database::insert_message(&conn, 1, "user", "and a cap", false).unwrap();
+        let next = database::load_message(&conn, next).unwrap();
+        assert_eq!((next.parent_id, next.seq), (Some(ids[3]), 6));
+        assert!(matches!(switch(&conn, 2, branches[1].id), Err(RweError::NotFound(_))));
+    }
+
+    #[test]
+    fn search_sees_only_the_active_branch() {
+        let (conn, ids) = conversation();
+        assert_eq!(found(&conn, "backoff").len(), 2);
+
+        let branch = fork(&conn, 1, ids[1], Some("  jitter ")).unwrap();
+        assert_eq!(branch.name, "jitter");
+        let jitter = database::insert_message(&conn, 1, "user", "with jitter", false).unwrap();
+        assert!(found(&conn, "backoff").is_empty());
+        assert_eq!(found(&conn, "jitter"), [jitter]);
+        // Shared history is on both lines
+        assert_eq!(found(&conn, "loop"), [ids[1]]);
+
+        let main = list_branches(&conn, 1).unwrap().into_iter().find(|b| b.name == "Main").unwrap();
+        switch(&conn, 1, main.id).unwrap();
+        assert!(found(&conn, "jitter").is_empty());
+        let mut backoff = found(&conn, "backoff");
+        backoff.sort();
+        assert_eq!(backoff, [ids[2], ids[3]]);
+    }
+}
@@ -1,7 +1,12 @@
 use serde::{Deserialize, Serialize};
 use tauri::{command, State};
 use rusqlite::{Connection};
-use std::sync::Mutex;
+use crate::branches;
+use crate::database_pool::DbPool;
+use crate::embeddings::EmbeddingIndexer;
+use crate::encryption::DatabaseEncryption;
+use crate::error::RweError;
+use crate::secrets::{self, SecretStore};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Conversation {
@@ -10,6 +15,8 @@ pub struct Conversation {
     pub created_at: String,
     pub summary: Option<String>,
     pub notes: Option<String>,
+    pub folder_id: Option<i64>,
+    pub deleted_at: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,19 +25,40 @@ pub struct Message {
     pub conversation_id: i64,
     pub role: String,
     pub content: String,
+    /// Position in the conversation, counting from 1.
     pub seq: i64,
+    pub partial: bool,
+    pub created_at: Option<String>,
+    /// For replies: the model that wrote it, the token counts the provider
+    /// reported (if any) and how long it took to arrive.
+    pub model: Option<String>,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub latency_ms: Option<i64>,
+    /// The message this one follows.
+    pub parent_id: Option<i64>,
+    /// When the content was last changed by `edit_message`.
+    pub edited_at: Option<String>,
 }
 
+/// What's known about how a reply was generated.
+#[derive(Debug, Clone, Default)]
+pub struct ReplyMetadata {
+    pub model: Option<String>,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    pub latency_ms: Option<u64>,
+}
+
+/// An earlier content of an edited message.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct MindMapData {
-    pub id: i64,
-    pub conversation_id: i64,
-    pub title: String,
-    pub nodes: String,
-    pub connections: String,
-    pub theme: String,
-    pub created_at: String,
-    pub updated_at: String,
+pub struct MessageVersion {
+    pub message_id: i64,
+    /// 1 for the original content, counting up with each edit.
+    pub version: i64,
+    pub content: String,
+    /// When an edit replaced this content.
+    pub replaced_at: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,139 +67,304 @@ pub struct AnalyticsData {
     pub count: i64,
 }
 
-type DbConnection = Mutex<Connection>;
+/// How long a connection waits for another one's write lock before giving up.
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
-#[command]
-pub async fn init_database(db: State<'_, DbConnection>) -> Result<bool, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    match conn.query_row("SELECT 1", [], |_row| Ok(())) {
-        Ok(_) => Ok(true),
-        Err(e) => Err(format!("Database check failed: {}", e)),
+/// Per-connection settings every connection to main.db needs. SQLite leaves
+/// foreign key enforcement off unless each connection asks for it.
+pub fn configure_connection(conn: &Connection) -> rusqlite::Result<()> {
+    conn.pragma_update(None, "foreign_keys", true)?;
+    conn.busy_timeout(BUSY_TIMEOUT)
+}
+
+/// Runs `f` atomically. The outermost call takes the write lock up front
+/// (`BEGIN IMMEDIATE`), so a transaction that reads before it writes waits
+/// for other pooled connections instead of failing with SQLITE_BUSY halfway.
+/// Nested calls become savepoints, so helpers that need atomicity can be
+/// called from commands that wrap several of them in one.
+pub fn with_transaction<T>(
+    conn: &Connection,
+    f: impl FnOnce(&Connection) -> Result<T, RweError>,
+) -> Result<T, RweError> {
+    let (begin, commit, rollback) = if conn.is_autocommit() {
+        ("BEGIN IMMEDIATE", "COMMIT", "ROLLBACK")
+    } else {
+        ("SAVEPOINT rwe_transaction", "RELEASE rwe_transaction", "ROLLBACK TO rwe_transaction; RELEASE rwe_transaction")
+    };
+    conn.execute_batch(begin)
+        .map_err(|e| RweError::database("Transaction error", e))?;
+    match f(conn) {
+        Ok(value) => {
+            conn.execute_batch(commit)
+                .map_err(|e| RweError::database("Commit error", e))?;
+            Ok(value)
+        }
+        Err(e) => {
+            conn.execute_batch(rollback).ok();
+            Err(e)
+        }
     }
 }
 
+pub const CONVERSATION_COLUMNS: &str = "id, name, created_at, summary, notes, folder_id, deleted_at";
+
+pub fn row_to_conversation(row: &rusqlite::Row) -> rusqlite::Result<Conversation> {
+    Ok(Conversation {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_at: row.get(2)?,
+        summary: row.get(3)?,
+        notes: row.get(4)?,
+        folder_id: row.get(5)?,
+        deleted_at: row.get(6)?,
+    })
+}
+
+#[command]
+pub async fn init_database(db: State<'_, DbPool>) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        match conn.query_row("SELECT 1", [], |_row| Ok(())) {
+            Ok(_) => Ok(true),
+            Err(e) => Err(RweError::database("Database check failed", e)),
+        }
+    }).await
+}
+
 #[command]
 pub async fn create_conversation(
     name: String,
     summary: String,
-    db: State<'_, DbConnection>,
-) -> Result<i64, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    match conn.execute(
-        "INSERT INTO conversations (name, summary) VALUES (?1, ?2)",
-        [&name, &summary],
-    ) {
-        Ok(_) => Ok(conn.last_insert_rowid()),
-        Err(e) => Err(format!("Insert conversation error: {}", e)),
-    }
+    db: State<'_, DbPool>,
+) -> Result<i64, RweError> {
+    db.run(move |conn| {
+        match conn.execute(
+            "INSERT INTO conversations (name, summary) VALUES (?1, ?2)",
+            [&name, &summary],
+        ) {
+            Ok(_) => Ok(conn.last_insert_rowid()),
+            Err(e) => Err(RweError::database("Insert conversation error", e)),
+        }
+    }).await
 }
 
 #[command]
-pub async fn get_conversations(db: State<'_, DbConnection>) -> Result<Vec<Conversation>, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let mut stmt = conn.prepare("SELECT id, name, created_at, summary, notes FROM conversations ORDER BY created_at DESC")
-        .map_err(|e| format!("Prepare error: {}", e))?;
-    
-    let conversation_iter = stmt.query_map([], |row| {
-        Ok(Conversation {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            created_at: row.get(2)?,
-            summary: row.get(3)?,
-            notes: row.get(4)?,
-        })
-    }).map_err(|e| format!("Query error: {}", e))?;
-    
-    let mut conversations = Vec::new();
-    for conversation in conversation_iter {
-        conversations.push(conversation.map_err(|e| format!("Row error: {}", e))?);
-    }
-    
-    Ok(conversations)
+pub async fn get_conversations(db: State<'_, DbPool>) -> Result<Vec<Conversation>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM conversations WHERE deleted_at IS NULL ORDER BY created_at DESC",
+            CONVERSATION_COLUMNS
+        )).map_err(|e| RweError::database("Prepare error", e))?;
+        
+        let conversation_iter = stmt.query_map([], row_to_conversation)
+            .map_err(|e| RweError::database("Query error", e))?;
+        
+        let mut conversations = Vec::new();
+        for conversation in conversation_iter {
+            conversations.push(conversation.map_err(|e| RweError::database("Row error", e))?);
+        }
+        
+        Ok(conversations)
+    }).await
 }
 
 #[command]
 pub async fn get_conversations_paginated(
     limit: i64,
     offset: i64,
-    db: State<'_, DbConnection>,
-) -> Result<Vec<Conversation>, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let mut stmt = conn.prepare("SELECT id, name, created_at, summary, notes FROM conversations ORDER BY created_at DESC LIMIT ?1 OFFSET ?2")
-        .map_err(|e| format!("Prepare error: {}", e))?;
-    
-    let conversation_iter = stmt.query_map([limit, offset], |row| {
-        Ok(Conversation {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            created_at: row.get(2)?,
-            summary: row.get(3)?,
-            notes: row.get(4)?,
-        })
-    }).map_err(|e| format!("Query error: {}", e))?;
-    
-    let mut conversations = Vec::new();
-    for conversation in conversation_iter {
-        conversations.push(conversation.map_err(|e| format!("Row error: {}", e))?);
-    }
-    
-    Ok(conversations)
+    tag_id: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<Vec<Conversation>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn.prepare(&format!(r#"
+            SELECT {} FROM conversations
+            WHERE deleted_at IS NULL
+              AND (?3 IS NULL OR id IN (SELECT conversation_id FROM conversation_tags WHERE tag_id = ?3))
+            ORDER BY created_at DESC LIMIT ?1 OFFSET ?2
+        "#, CONVERSATION_COLUMNS)).map_err(|e| RweError::database("Prepare error", e))?;
+        
+        let conversation_iter = stmt.query_map(rusqlite::params![limit, offset, tag_id], row_to_conversation)
+            .map_err(|e| RweError::database("Query error", e))?;
+        
+        let mut conversations = Vec::new();
+        for conversation in conversation_iter {
+            conversations.push(conversation.map_err(|e| RweError::database("Row error", e))?);
+        }
+        
+        Ok(conversations)
+    }).await
 }
 
 #[command]
-pub async fn get_conversations_count(db: State<'_, DbConnection>) -> Result<i64, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let count: i64 = conn.query_row("SELECT COUNT(*) FROM conversations", [], |row| {
-        row.get(0)
-    }).map_err(|e| format!("Query error: {}", e))?;
-    
-    Ok(count)
+pub async fn get_conversations_count(
+    tag_id: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<i64, RweError> {
+    db.run(move |conn| {
+        let count: i64 = conn.query_row(r#"
+            SELECT COUNT(*) FROM conversations
+            WHERE deleted_at IS NULL
+              AND (?1 IS NULL OR id IN (SELECT conversation_id FROM conversation_tags WHERE tag_id = ?1))
+        "#, [tag_id], |row| {
+            row.get(0)
+        }).map_err(|e| RweError::database("Query error", e))?;
+        
+        Ok(count)
+    }).await
 }
 
 #[command]
 pub async fn get_conversation(
     conversation_id: i64,
-    db: State<'_, DbConnection>,
-) -> Result<Option<Conversation>, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let mut stmt = conn.prepare("SELECT id, name, created_at, summary, notes FROM conversations WHERE id = ?1")
-        .map_err(|e| format!("Prepare error: {}", e))?;
-    
-    match stmt.query_row([conversation_id], |row| {
-        Ok(Conversation {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            created_at: row.get(2)?,
-            summary: row.get(3)?,
-            notes: row.get(4)?,
-        })
-    }) {
-        Ok(conversation) => Ok(Some(conversation)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(format!("Query error: {}", e)),
-    }
+    db: State<'_, DbPool>,
+) -> Result<Option<Conversation>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM conversations WHERE id = ?1 AND deleted_at IS NULL",
+            CONVERSATION_COLUMNS
+        )).map_err(|e| RweError::database("Prepare error", e))?;
+        
+        match stmt.query_row([conversation_id], row_to_conversation) {
+            Ok(conversation) => Ok(Some(conversation)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(RweError::database("Query error", e)),
+        }
+    }).await
 }
 
+/// Moves a conversation to the trash. It stays restorable until it is
+/// purged with `purge_conversation` or `purge_trash`.
 #[command]
 pub async fn delete_conversation(
     conversation_id: i64,
-    db: State<'_, DbConnection>,
-) -> Result<bool, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    conn.execute("DELETE FROM messages WHERE conversation_id = ?1", [conversation_id])
-        .map_err(|e| format!("Delete messages error: {}", e))?;
-    
-    conn.execute("DELETE FROM mindmaps WHERE conversation_id = ?1", [conversation_id])
-        .map_err(|e| format!("Delete mindmaps error: {}", e))?;
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        trash_conversation(conn, conversation_id)
+    }).await
+}
+
+pub fn trash_conversation(conn: &Connection, conversation_id: i64) -> Result<bool, RweError> {
+    let affected = conn.execute(
+        "UPDATE conversations SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?1 AND deleted_at IS NULL",
+        [conversation_id],
+    ).map_err(|e| RweError::database("Trash conversation error", e))?;
     
+    Ok(affected > 0)
+}
+
+#[command]
+pub async fn list_trash(db: State<'_, DbPool>) -> Result<Vec<Conversation>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM conversations WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC",
+            CONVERSATION_COLUMNS
+        )).map_err(|e| RweError::database("Prepare error", e))?;
+        
+        let conversation_iter = stmt.query_map([], row_to_conversation)
+            .map_err(|e| RweError::database("Query error", e))?;
+        
+        let mut conversations = Vec::new();
+        for conversation in conversation_iter {
+            conversations.push(conversation.map_err(|e| RweError::database("Row error", e))?);
+        }
+        
+        Ok(conversations)
+    }).await
+}
+
+/// Takes a conversation back out of the trash. If its folder was deleted in
+/// the meantime it comes back at the top level. Its text is embedded again
+/// for semantic search.
+#[command]
+pub async fn restore_conversation(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<bool, RweError> {
+    let restored = db.run(move |conn| {
+        let affected = conn.execute(
+            r#"
+            UPDATE conversations SET
+                deleted_at = NULL,
+                folder_id = CASE WHEN folder_id IN (SELECT id FROM folders) THEN folder_id END,
+                folder_position = (
+                    SELECT COALESCE(MAX(other.folder_position) + 1, 0) FROM conversations other
+                    WHERE other.folder_id IS conversations.folder_id AND other.deleted_at IS NULL
+                )
+            WHERE id = ?1 AND deleted_at IS NOT NULL
+            "#,
+            [conversation_id],
+        ).map_err(|e| RweError::database("Restore conversation error", e))?;
+        
+        Ok::<_, RweError>(affected > 0)
+    }).await?;
+    if restored {
+        indexer.schedule();
+    }
+    Ok(restored)
+}
+
+/// Permanently deletes one trashed conversation.
+#[command]
+pub async fn purge_conversation(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        let trashed: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM conversations WHERE id = ?1 AND deleted_at IS NOT NULL)",
+            [conversation_id],
+            |row| row.get(0),
+        ).map_err(|e| RweError::database("Query error", e))?;
+        if !trashed {
+            return Ok(false);
+        }
+        
+        remove_conversation(conn, conversation_id)
+    }).await
+}
+
+/// Permanently deletes trashed conversations, only those trashed before
+/// `older_than` (an SQLite datetime in UTC, e.g. "2026-01-31 00:00:00") when
+/// given. Returns how many were deleted.
+#[command]
+pub async fn purge_trash(
+    older_than: Option<String>,
+    db: State<'_, DbPool>,
+) -> Result<usize, RweError> {
+    db.run(move |conn| {
+        if let Some(older_than) = &older_than {
+            let valid: bool = conn.query_row("SELECT datetime(?1) IS NOT NULL", [older_than], |row| row.get(0))
+                .map_err(|e| RweError::database("Query error", e))?;
+            if !valid {
+                return Err(RweError::Validation(format!("Invalid date: {}", older_than)));
+            }
+        }
+        
+        let ids = {
+            let mut stmt = conn.prepare(
+                "SELECT id FROM conversations WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < datetime(?1))",
+            ).map_err(|e| RweError::database("Prepare error", e))?;
+            let id_iter = stmt.query_map([&older_than], |row| row.get::<_, i64>(0))
+                .map_err(|e| RweError::database("Query error", e))?;
+            id_iter.collect::<Result<Vec<_>, _>>()
+                .map_err(|e| RweError::database("Row error", e))?
+        };
+        
+        with_transaction(conn, |tx| {
+            for id in &ids {
+                remove_conversation(tx, *id)?;
+            }
+            Ok(ids.len())
+        })
+    }).await
+}
+
+/// Deletes a conversation. Its messages, mind map, tag links and embeddings
+/// go with it through `ON DELETE CASCADE`, in the same statement.
+pub fn remove_conversation(conn: &Connection, conversation_id: i64) -> Result<bool, RweError> {
     let affected = conn.execute("DELETE FROM conversations WHERE id = ?1", [conversation_id])
-        .map_err(|e| format!("Delete conversation error: {}", e))?;
+        .map_err(|e| RweError::database("Delete conversation error", e))?;
     
     Ok(affected > 0)
 }
@@ -181,208 +374,299 @@ pub async fn save_message(
     conversation_id: i64,
     role: String,
     content: String,
-    db: State<'_, DbConnection>,
-) -> Result<i64, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let seq = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as i64;
-    
-    match conn.execute(
-        "INSERT INTO messages (conversation_id, role, content, seq) VALUES (?1, ?2, ?3, ?4)",
-        [&conversation_id.to_string(), &role, &content, &seq.to_string()],
-    ) {
-        Ok(_) => Ok(conn.last_insert_rowid()),
-        Err(e) => Err(format!("Insert message error: {}", e)),
-    }
+    db: State<'_, DbPool>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<i64, RweError> {
+    let message_id = db.run(move |conn| {
+        insert_message(conn, conversation_id, &role, &content, false)
+    }).await?;
+    indexer.schedule();
+    Ok(message_id)
+}
+
+/// Inserts a message; `partial` marks replies whose generation was cut short.
+pub fn insert_message(
+    conn: &Connection,
+    conversation_id: i64,
+    role: &str,
+    content: &str,
+    partial: bool,
+) -> Result<i64, RweError> {
+    insert_message_with_metadata(conn, conversation_id, role, content, partial, &ReplyMetadata::default())
+}
+
+/// Appends a message to the conversation's active branch, or after its last
+/// message if it has never been forked.
+pub fn insert_message_with_metadata(
+    conn: &Connection,
+    conversation_id: i64,
+    role: &str,
+    content: &str,
+    partial: bool,
+    metadata: &ReplyMetadata,
+) -> Result<i64, RweError> {
+    with_transaction(conn, |tx| {
+        tx.execute(
+            r#"
+            INSERT INTO messages (
+                conversation_id, role, content, seq, partial, parent_id,
+                model, prompt_tokens, completion_tokens, latency_ms
+            )
+            SELECT
+                ?1, ?2, ?3, COALESCE(MAX(seq), 0) + 1, ?4,
+                COALESCE(
+                    (SELECT head_message_id FROM conversation_branches WHERE conversation_id = ?1 AND active = 1),
+                    (SELECT id FROM messages WHERE conversation_id = ?1 ORDER BY seq DESC LIMIT 1)
+                ),
+                ?5, ?6, ?7, ?8
+            FROM messages WHERE conversation_id = ?1
+            "#,
+            rusqlite::params![
+                conversation_id,
+                role,
+                content,
+                partial,
+                metadata.model,
+                metadata.prompt_tokens,
+                metadata.completion_tokens,
+                metadata.latency_ms.map(|ms| ms as i64),
+            ],
+        ).map_err(|e| RweError::database("Insert message error", e))?;
+        let message_id = tx.last_insert_rowid();
+
+        tx.execute(
+            "UPDATE conversation_branches SET head_message_id = ?1 WHERE conversation_id = ?2 AND active = 1",
+            rusqlite::params![message_id, conversation_id],
+        ).map_err(|e| RweError::database("Update branch error", e))?;
+
+        Ok(message_id)
+    })
 }
 
+pub const MESSAGE_COLUMNS: &str = r#"
+    id, conversation_id, role, content, seq, partial, created_at,
+    model, prompt_tokens, completion_tokens, latency_ms, parent_id, edited_at
+"#;
+
+pub fn row_to_message(row: &rusqlite::Row) -> rusqlite::Result<Message> {
+    Ok(Message {
+        id: row.get(0)?,
+        conversation_id: row.get(1)?,
+        role: row.get(2)?,
+        content: row.get(3)?,
+        seq: row.get(4)?,
+        partial: row.get(5)?,
+        created_at: row.get(6)?,
+        model: row.get(7)?,
+        prompt_tokens: row.get(8)?,
+        completion_tokens: row.get(9)?,
+        latency_ms: row.get(10)?,
+        parent_id: row.get(11)?,
+        edited_at: row.get(12)?,
+    })
+}
+
+/// Messages on the conversation's active branch, oldest first; until it is
+/// forked, that's all of them.
 #[command]
 pub async fn get_messages(
     conversation_id: i64,
-    db: State<'_, DbConnection>,
-) -> Result<Vec<Message>, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let mut stmt = conn.prepare("SELECT id, conversation_id, role, content, seq FROM messages WHERE conversation_id = ?1 ORDER BY seq ASC")
-        .map_err(|e| format!("Prepare error: {}", e))?;
+    db: State<'_, DbPool>,
+) -> Result<Vec<Message>, RweError> {
+    db.run(move |conn| load_messages(conn, conversation_id)).await
+}
+
+pub fn load_messages(conn: &Connection, conversation_id: i64) -> Result<Vec<Message>, RweError> {
+    if let Some(head) = branches::active_head(conn, conversation_id)? {
+        return branches::path_to(conn, head);
+    }
+
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM messages WHERE conversation_id = ?1 ORDER BY seq ASC", MESSAGE_COLUMNS))
+        .map_err(|e| RweError::database("Prepare error", e))?;
     
-    let message_iter = stmt.query_map([conversation_id], |row| {
-        Ok(Message {
-            id: row.get(0)?,
-            conversation_id: row.get(1)?,
-            role: row.get(2)?,
-            content: row.get(3)?,
-            seq: row.get(4)?,
-        })
-    }).map_err(|e| format!("Query error: {}", e))?;
+    let message_iter = stmt.query_map([conversation_id], row_to_message)
+        .map_err(|e| RweError::database("Query error", e))?;
     
     let mut messages = Vec::new();
     for message in message_iter {
-        messages.push(message.map_err(|e| format!("Row error: {}", e))?);
+        messages.push(message.map_err(|e| RweError::database("Row error", e))?);
     }
     
     Ok(messages)
 }
 
+pub fn load_message(conn: &Connection, message_id: i64) -> Result<Message, RweError> {
+    conn.query_row(
+        &format!("SELECT {} FROM messages WHERE id = ?1", MESSAGE_COLUMNS),
+        [message_id],
+        row_to_message,
+    ).map_err(|e| RweError::database("Query message error", e))
+}
+
+/// Replaces a message's content, keeping what it said before as a new entry
+/// in `get_message_versions`. Search and embeddings follow the new content.
 #[command]
-pub async fn update_conversation_notes(
-    conversation_id: i64,
-    notes: String,
-    db: State<'_, DbConnection>,
-) -> Result<bool, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let affected = conn.execute(
-        "UPDATE conversations SET notes = ?1 WHERE id = ?2",
-        [&notes, &conversation_id.to_string()],
-    ).map_err(|e| format!("Update notes error: {}", e))?;
-    
-    Ok(affected > 0)
+pub async fn edit_message(
+    message_id: i64,
+    content: String,
+    db: State<'_, DbPool>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<Message, RweError> {
+    if content.trim().is_empty() {
+        return Err(RweError::Validation("Message content cannot be empty".to_string()));
+    }
+
+    let message = db.run(move |conn| {
+        with_transaction(conn, |tx| {
+            let current = load_message(tx, message_id)?;
+            if current.content == content {
+                return Ok(current);
+            }
+
+            tx.execute(
+                r#"
+                INSERT INTO message_versions (message_id, version, content)
+                SELECT ?1, COALESCE(MAX(version), 0) + 1, ?2
+                FROM message_versions WHERE message_id = ?1
+                "#,
+                rusqlite::params![message_id, current.content],
+            ).map_err(|e| RweError::database("Save message version error", e))?;
+            tx.execute(
+                "UPDATE messages SET content = ?1, edited_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                rusqlite::params![content, message_id],
+            ).map_err(|e| RweError::database("Update message error", e))?;
+
+            load_message(tx, message_id)
+        })
+    }).await?;
+    indexer.schedule();
+    Ok(message)
 }
 
+/// Earlier contents of a message, oldest first. Empty if it was never edited.
 #[command]
-pub async fn update_conversation_summary(
-    conversation_id: i64,
-    summary: String,
-    db: State<'_, DbConnection>,
-) -> Result<bool, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let affected = conn.execute(
-        "UPDATE conversations SET summary = ?1 WHERE id = ?2",
-        [&summary, &conversation_id.to_string()],
-    ).map_err(|e| format!("Update summary error: {}", e))?;
-    
-    Ok(affected > 0)
+pub async fn get_message_versions(
+    message_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<Vec<MessageVersion>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn.prepare(
+            "SELECT message_id, version, content, replaced_at FROM message_versions WHERE message_id = ?1 ORDER BY version ASC"
+        ).map_err(|e| RweError::database("Prepare error", e))?;
+
+        let versions = stmt.query_map([message_id], |row| {
+            Ok(MessageVersion {
+                message_id: row.get(0)?,
+                version: row.get(1)?,
+                content: row.get(2)?,
+                replaced_at: row.get(3)?,
+            })
+        }).map_err(|e| RweError::database("Query error", e))?;
+
+        versions.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RweError::database("Row error", e))
+    }).await
 }
 
 #[command]
-pub async fn get_mindmap_data(
+pub async fn update_conversation_notes(
     conversation_id: i64,
-    db: State<'_, DbConnection>,
-) -> Result<Option<MindMapData>, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let mut stmt = conn.prepare("SELECT id, conversation_id, title, nodes, connections, theme, created_at, updated_at FROM mindmaps WHERE conversation_id = ?1")
-        .map_err(|e| format!("Prepare error: {}", e))?;
-    
-    match stmt.query_row([conversation_id], |row| {
-        Ok(MindMapData {
-            id: row.get(0)?,
-            conversation_id: row.get(1)?,
-            title: row.get(2)?,
-            nodes: row.get(3)?,
-            connections: row.get(4)?,
-            theme: row.get(5)?,
-            created_at: row.get(6)?,
-            updated_at: row.get(7)?,
-        })
-    }) {
-        Ok(mindmap) => Ok(Some(mindmap)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(format!("Query error: {}", e)),
-    }
+    notes: String,
+    db: State<'_, DbPool>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<bool, RweError> {
+    let updated = db.run(move |conn| {
+        let affected = conn.execute(
+            "UPDATE conversations SET notes = ?1 WHERE id = ?2",
+            [&notes, &conversation_id.to_string()],
+        ).map_err(|e| RweError::database("Update notes error", e))?;
+        
+        Ok::<_, RweError>(affected > 0)
+    }).await?;
+    indexer.schedule();
+    Ok(updated)
 }
 
 #[command]
-pub async fn save_mindmap_data(
+pub async fn update_conversation_summary(
     conversation_id: i64,
-    title: String,
-    nodes: String,
-    connections: String,
-    theme: String,
-    db: State<'_, DbConnection>,
-) -> Result<i64, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let existing: Result<i64, rusqlite::Error> = conn.query_row(
-        "SELECT id FROM mindmaps WHERE conversation_id = ?1",
-        [conversation_id],
-        |row| row.get(0),
-    );
-    
-    match existing {
-        Ok(mindmap_id) => {
-            conn.execute(
-                "UPDATE mindmaps SET title = ?1, nodes = ?2, connections = ?3, theme = ?4, updated_at = CURRENT_TIMESTAMP WHERE conversation_id = ?5",
-                [&title, &nodes, &connections, &theme, &conversation_id.to_string()],
-            ).map_err(|e| format!("Update mindmap error: {}", e))?;
-            Ok(mindmap_id)
-        }
-        Err(rusqlite::Error::QueryReturnedNoRows) => {
-            conn.execute(
-                "INSERT INTO mindmaps (conversation_id, title, nodes, connections, theme) VALUES (?1, ?2, ?3, ?4, ?5)",
-                [&conversation_id.to_string(), &title, &nodes, &connections, &theme],
-            ).map_err(|e| format!("Insert mindmap error: {}", e))?;
-            Ok(conn.last_insert_rowid())
-        }
-        Err(e) => Err(format!("Query error: {}", e)),
-    }
+    summary: String,
+    db: State<'_, DbPool>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<bool, RweError> {
+    let updated = db.run(move |conn| {
+        let affected = conn.execute(
+            "UPDATE conversations SET summary = ?1 WHERE id = ?2",
+            [&summary, &conversation_id.to_string()],
+        ).map_err(|e| RweError::database("Update summary error", e))?;
+        
+        Ok::<_, RweError>(affected > 0)
+    }).await?;
+    indexer.schedule();
+    Ok(updated)
 }
 
 #[command]
 pub async fn get_conversation_analytics(
     period: String,
-    db: State<'_, DbConnection>,
-) -> Result<Vec<AnalyticsData>, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let query = match period.as_str() {
-        "day" => "
-            SELECT 
-                DATE(created_at) as date,
-                COUNT(*) as count
-            FROM conversations 
-            WHERE created_at >= DATE('now', '-30 days')
-            GROUP BY DATE(created_at)
-            ORDER BY date DESC
-        ",
-        "week" => "
-            SELECT 
-                DATE(created_at, 'weekday 0', '-6 days') as date,
-                COUNT(*) as count
-            FROM conversations 
-            WHERE created_at >= DATE('now', '-12 weeks')
-            GROUP BY DATE(created_at, 'weekday 0', '-6 days')
-            ORDER BY date DESC
-        ",
-        "month" => "
-            SELECT 
-                DATE(created_at, 'start of month') as date,
-                COUNT(*) as count
-            FROM conversations 
-            WHERE created_at >= DATE('now', '-12 months')
-            GROUP BY DATE(created_at, 'start of month')
-            ORDER BY date DESC
-        ",
-        _ => return Err("Invalid period. Use 'day', 'week', or 'month'".to_string()),
-    };
-    
-    let mut stmt = conn.prepare(query)
-        .map_err(|e| format!("Prepare error: {}", e))?;
-    
-    let analytics_iter = stmt.query_map([], |row| {
-        Ok(AnalyticsData {
-            date: row.get(0)?,
-            count: row.get(1)?,
-        })
-    }).map_err(|e| format!("Query error: {}", e))?;
-    
-    let mut analytics = Vec::new();
-    for item in analytics_iter {
-        analytics.push(item.map_err(|e| format!("Row error: {}", e))?);
-    }
-    
-    Ok(analytics)
+    db: State<'_, DbPool>,
+) -> Result<Vec<AnalyticsData>, RweError> {
+    db.run(move |conn| {
+        let query = match period.as_str() {
+            "day" => "
+                SELECT 
+                    DATE(created_at) as date,
+                    COUNT(*) as count
+                FROM conversations 
+                WHERE deleted_at IS NULL AND created_at >= DATE('now', '-30 days')
+                GROUP BY DATE(created_at)
+                ORDER BY date DESC
+            ",
+            "week" => "
+                SELECT 
+                    DATE(created_at, 'weekday 0', '-6 days') as date,
+                    COUNT(*) as count
+                FROM conversations 
+                WHERE deleted_at IS NULL AND created_at >= DATE('now', '-12 weeks')
+                GROUP BY DATE(created_at, 'weekday 0', '-6 days')
+                ORDER BY date DESC
+            ",
+            "month" => "
+                SELECT 
+                    DATE(created_at, 'start of month') as date,
+                    COUNT(*) as count
+                FROM conversations 
+                WHERE deleted_at IS NULL AND created_at >= DATE('now', '-12 months')
+                GROUP BY DATE(created_at, 'start of month')
+                ORDER BY date DESC
+            ",
+            _ => return Err(RweError::Validation("Invalid period. Use 'day', 'week', or 'month'".to_string())),
+        };
+        
+        let mut stmt = conn.prepare(query)
+            .map_err(|e| RweError::database("Prepare error", e))?;
+        
+        let analytics_iter = stmt.query_map([], |row| {
+            Ok(AnalyticsData {
+                date: row.get(0)?,
+                count: row.get(1)?,
+            })
+        }).map_err(|e| RweError::database("Query error", e))?;
+        
+        let mut analytics = Vec::new();
+        for item in analytics_iter {
+            analytics.push(item.map_err(|e| RweError::database("Row error", e))?);
+        }
+        
+        Ok(analytics)
+    }).await
 }
 
 #[command]
-pub async fn backup_database(db: State<'_, DbConnection>) -> Result<String, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
+pub async fn backup_database(
+    db: State<'_, DbPool>,
+    encryption: State<'_, DatabaseEncryption>,
+) -> Result<String, RweError> {
     let app_data_dir = std::env::var("APPDATA")
         .or_else(|_| std::env::var("HOME"))
         .unwrap_or_else(|_| ".".to_string());
@@ -394,86 +678,80 @@ pub async fn backup_database(db: State<'_, DbConnection>) -> Result<String, Stri
         .join(format!("backup_{}.db", timestamp));
     
     if let Some(parent) = backup_path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create backup directory: {}", e))?;
+        std::fs::create_dir_all(parent).map_err(|e| RweError::io("Failed to create backup directory", e))?;
     }
     
-    let mut backup_conn = Connection::open(&backup_path)
-        .map_err(|e| format!("Failed to create backup connection: {}", e))?;
-    
-    let backup = rusqlite::backup::Backup::new(&*conn, &mut backup_conn)
-        .map_err(|e| format!("Failed to create backup: {}", e))?;
-    backup.run_to_completion(5, std::time::Duration::from_millis(250), None)
-        .map_err(|e| format!("Backup failed: {}", e))?;
+    // An encrypted database can only be backed up into a file keyed the same way
+    let mut backup_conn = encryption.open(&backup_path)
+        .map_err(|e| RweError::Storage(format!("Failed to create backup connection: {}", e)))?;
     
-    Ok(backup_path.to_string_lossy().to_string())
+    db.run(move |conn| {
+        copy_database(conn, &mut backup_conn)?;
+        Ok(backup_path.to_string_lossy().to_string())
+    }).await
 }
 
-#[command]
-pub async fn get_database_info(db: State<'_, DbConnection>) -> Result<serde_json::Value, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    let version: u32 = conn.query_row(
-        "SELECT MAX(version) FROM schema_migrations",
-        [],
-        |row| row.get::<_, Option<u32>>(0).map(|v| v.unwrap_or(0))
-    ).unwrap_or(0);
-    
-    let conversations_count: i64 = conn.query_row(
-        "SELECT COUNT(*) FROM conversations",
-        [],
-        |row| row.get(0)
-    ).unwrap_or(0);
-    
-    let messages_count: i64 = conn.query_row(
-        "SELECT COUNT(*) FROM messages",
-        [],
-        |row| row.get(0)
-    ).unwrap_or(0);
-    
-    let db_size: i64 = conn.query_row(
-        "SELECT page_count * page_size as size FROM pragma_page_count(), pragma_page_size()",
-        [],
-        |row| row.get(0)
-    ).unwrap_or(0);
-    
-    Ok(serde_json::json!({
-        "schema_version": version,
-        "conversations_count": conversations_count,
-        "messages_count": messages_count,
-        "database_size_bytes": db_size,
-        "app_version": env!("CARGO_PKG_VERSION")
-    }))
+/// Copies the whole database into `target` with SQLite's online backup,
+/// which sees writes still sitting in the WAL, unlike copying main.db.
+pub fn copy_database(conn: &Connection, target: &mut Connection) -> Result<(), RweError> {
+    let backup = rusqlite::backup::Backup::new(conn, target)
+        .map_err(|e| RweError::database("Failed to create backup", e))?;
+    backup.run_to_completion(5, std::time::Duration::from_millis(250), None)
+        .map_err(|e| RweError::database("Backup failed", e))
 }
 
 #[command]
-pub async fn get_api_key(db: State<'_, DbConnection>) -> Result<Option<String>, String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    match conn.query_row(
-        "SELECT value FROM user_preferences WHERE key = 'gemini_api_key'",
-        [],
-        |row| row.get::<_, Option<String>>(0)
-    ) {
-        Ok(value) => Ok(value),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(format!("Failed to get API key: {}", e)),
-    }
+pub async fn get_database_info(db: State<'_, DbPool>) -> Result<serde_json::Value, RweError> {
+    db.run(move |conn| {
+        let version: u32 = conn.query_row(
+            "SELECT MAX(version) FROM schema_migrations",
+            [],
+            |row| row.get::<_, Option<u32>>(0).map(|v| v.unwrap_or(0))
+        ).unwrap_or(0);
+        
+        let conversations_count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM conversations WHERE deleted_at IS NULL",
+            [],
+            |row| row.get(0)
+        ).unwrap_or(0);
+        
+        let trashed_conversations_count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM conversations WHERE deleted_at IS NOT NULL",
+            [],
+            |row| row.get(0)
+        ).unwrap_or(0);
+        
+        let messages_count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM messages m JOIN conversations c ON c.id = m.conversation_id WHERE c.deleted_at IS NULL",
+            [],
+            |row| row.get(0)
+        ).unwrap_or(0);
+        
+        let db_size: i64 = conn.query_row(
+            "SELECT page_count * page_size as size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0)
+        ).unwrap_or(0);
+        
+        Ok(serde_json::json!({
+            "schema_version": version,
+            "conversations_count": conversations_count,
+            "trashed_conversations_count": trashed_conversations_count,
+            "messages_count": messages_count,
+            "database_size_bytes": db_size,
+            "app_version": env!("CARGO_PKG_VERSION")
+        }))
+    }).await
 }
 
 #[command]
-pub async fn set_api_key(db: State<'_, DbConnection>, api_key: String) -> Result<(), String> {
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
-    
-    conn.execute(
-        r#"
-        INSERT INTO user_preferences (key, value, updated_at) 
-        VALUES ('gemini_api_key', ?1, CURRENT_TIMESTAMP)
-        ON CONFLICT(key) DO UPDATE SET 
-            value = excluded.value,
-            updated_at = excluded.updated_at
-        "#,
-        [&api_key]
-    ).map_err(|e| format!("Failed to set API key: {}", e))?;
-    
-    Ok(())
+pub async fn set_api_key(
+    db: State<'_, DbPool>,
+    secrets: State<'_, SecretStore>,
+    api_key: String,
+) -> Result<(), RweError> {
+    let secrets = secrets.inner().clone();
+    db.run(move |conn| {
+        secrets::put_active(conn, &secrets, "gemini", api_key.trim())
+    }).await
 }
@@ -0,0 +1,334 @@
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::Duration;
+use zeroize::Zeroizing;
+use crate::database;
+use crate::error::RweError;
+
+/// Upper bound on connections to main.db. In WAL mode readers don't block
+/// each other or the writer; writers still take turns through `busy_timeout`.
+const MAX_CONNECTIONS: u32 = 8;
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long `drain` waits for commands to give their connections back.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type SqlitePool = Pool<SqliteConnectionManager>;
+pub type PooledConn = PooledConnection<SqliteConnectionManager>;
+
+/// Pooled connections to main.db. Commands do their database work through
+/// `run`, on a blocking thread with a connection of their own, so a slow
+/// backup or analytics query no longer holds up every other command.
+///
+/// The pool is empty while an encrypted database is locked and while main.db
+/// itself is being rekeyed or replaced; commands fail with `RweError::Locked`
+/// then.
+pub struct DbPool {
+    pool: RwLock<Option<SqlitePool>>,
+    leases: Arc<Leases>,
+}
+
+#[derive(Default)]
+struct LeaseCount {
+    active: usize,
+    draining: bool,
+}
+
+/// Counts the connections commands have checked out, so `drain` can wait
+/// for them to come back.
+#[derive(Default)]
+struct Leases {
+    count: Mutex<LeaseCount>,
+    returned: Condvar,
+}
+
+/// Held for as long as a command uses a connection.
+struct Lease(Arc<Leases>);
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut count = self.0.count.lock().unwrap_or_else(|e| e.into_inner());
+        count.active -= 1;
+        self.0.returned.notify_all();
+    }
+}
+
+/// A connection checked out with `DbPool::get`.
+pub struct LeasedConn {
+    conn: PooledConn,
+    // Declared after `conn`, so the connection is back in the pool before
+    // the lease is released
+    _lease: Lease,
+}
+
+impl Deref for LeasedConn {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl DbPool {
+    pub fn new(pool: Option<SqlitePool>) -> Self {
+        Self { pool: RwLock::new(pool), leases: Arc::default() }
+    }
+
+    /// Opens a pool on `path`, keying every connection with `passphrase` when
+    /// the file is encrypted, and switches the file to WAL mode. Callers
+    /// check the passphrase first (see `encryption::open_with_passphrase`);
+    /// a wrong one only surfaces here as an unreadable file.
+    pub fn open(path: &Path, passphrase: Option<&str>) -> Result<SqlitePool, RweError> {
+        let passphrase = passphrase.map(|passphrase| Zeroizing::new(passphrase.to_string()));
+        let manager = SqliteConnectionManager::file(path).with_init(move |conn| {
+            // PRAGMA key must come before anything else touches the file
+            if let Some(passphrase) = &passphrase {
+                conn.pragma_update(None, "key", passphrase.as_str())?;
+            }
+            database::configure_connection(conn)
+        });
+
+        let pool = Pool::builder()
+            .max_size(MAX_CONNECTIONS)
+            .min_idle(Some(1))
+            .connection_timeout(CONNECTION_TIMEOUT)
+            .build(manager)
+            .map_err(|e| RweError::Storage(format!("Failed to open database: {}", e)))?;
+
+        let conn = pool.get().map_err(|e| RweError::Storage(format!("Failed to open database: {}", e)))?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .map_err(|e| RweError::database("Failed to enable WAL", e))?;
+        drop(conn);
+
+        Ok(pool)
+    }
+
+    fn current(&self) -> Result<SqlitePool, RweError> {
+        self.pool.read()
+            .map_err(|e| RweError::Internal(format!("Lock error: {}", e)))?
+            .clone()
+            .ok_or_else(|| RweError::Locked("Database is locked".to_string()))
+    }
+
+    fn lease(&self) -> Result<(Lease, SqlitePool), RweError> {
+        let mut count = self.leases.count.lock().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))?;
+        if count.draining {
+            return Err(RweError::Locked("Database is being rekeyed or replaced".to_string()));
+        }
+        let pool = self.current()?;
+        count.active += 1;
+        Ok((Lease(self.leases.clone()), pool))
+    }
+
+    /// Checks a connection out on the calling thread. Prefer `run` from async
+    /// commands; this is for setup code and callers already off the runtime.
+    pub fn get(&self) -> Result<LeasedConn, RweError> {
+        let (lease, pool) = self.lease()?;
+        let conn = pool.get().map_err(|e| RweError::Storage(format!("Connection error: {}", e)))?;
+        Ok(LeasedConn { conn, _lease: lease })
+    }
+
+    /// Runs `f` with a pooled connection on tokio's blocking thread pool.
+    pub async fn run<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        T: Send + 'static,
+        E: From<RweError> + Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, E> + Send + 'static,
+    {
+        let (lease, pool) = self.lease()?;
+        tokio::task::spawn_blocking(move || {
+            let result = pool.get()
+                .map_err(|e| E::from(RweError::Storage(format!("Connection error: {}", e))))
+                .and_then(|conn| f(&conn));
+            // The pool handle goes before the lease, so once `drain` sees no
+            // leases it holds the only handle left
+            drop(pool);
+            drop(lease);
+            result
+        })
+        .await
+        .map_err(|e| RweError::Internal(format!("Database task error: {}", e)))?
+    }
+
+    /// Swaps in a new pool (or none) and returns the old one. Connections it
+    /// handed out stay open until the commands using them finish; use `drain`
+    /// when the file itself is about to change.
+    pub fn replace(&self, pool: Option<SqlitePool>) -> Result<Option<SqlitePool>, RweError> {
+        let mut current = self.pool.write().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))?;
+        Ok(std::mem::replace(&mut *current, pool))
+    }
+
+    /// Stops handing out connections, waits for the ones commands hold to
+    /// come back and checkpoints the WAL into main.db, so the file can be
+    /// rekeyed or replaced. Commands answer `Locked` until the returned guard
+    /// is dropped, which puts the drained pool back unless it was closed.
+    pub fn drain(&self) -> Result<Drained<'_>, RweError> {
+        {
+            let mut count = self.leases.count.lock().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))?;
+            if count.draining {
+                return Err(RweError::Conflict("Database is already being rekeyed or replaced".to_string()));
+            }
+            count.draining = true;
+        }
+        // From here on, dropping the guard lets commands back in
+        let mut drained = Drained { db: self, pool: None };
+
+        let count = self.leases.count.lock().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))?;
+        let (count, _) = self.leases.returned
+            .wait_timeout_while(count, DRAIN_TIMEOUT, |count| count.active > 0)
+            .map_err(|e| RweError::Internal(format!("Lock error: {}", e)))?;
+        if count.active > 0 {
+            return Err(RweError::Locked("Database is busy; try again once other work has finished".to_string()));
+        }
+        drop(count);
+
+        drained.pool = self.replace(None)?;
+        let conn = drained.get()?;
+        let busy: i64 = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))
+            .map_err(|e| RweError::database("Checkpoint error", e))?;
+        if busy != 0 {
+            return Err(RweError::Locked("Database is busy; try again once other work has finished".to_string()));
+        }
+        drop(conn);
+        Ok(drained)
+    }
+}
+
+/// A pool no command can reach, from `DbPool::drain`.
+pub struct Drained<'a> {
+    db: &'a DbPool,
+    pool: Option<SqlitePool>,
+}
+
+impl Drained<'_> {
+    /// A connection for the draining command's own use.
+    pub fn get(&self) -> Result<PooledConn, RweError> {
+        self.pool.as_ref()
+            .ok_or_else(|| RweError::Locked("Database is locked".to_string()))?
+            .get()
+            .map_err(|e| RweError::Storage(format!("Connection error: {}", e)))
+    }
+
+    /// Closes every connection to the file, so it can be moved or replaced.
+    /// Connections from `get` must be dropped first. Unless `finish` follows,
+    /// commands see a locked database afterwards.
+    pub fn close(&mut self) {
+        self.pool = None;
+    }
+
+    /// Hands `pool` to commands in place of the drained one.
+    pub fn finish(mut self, pool: SqlitePool) {
+        self.pool = Some(pool);
+    }
+}
+
+impl Drop for Drained<'_> {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            *self.db.pool.write().unwrap_or_else(|e| e.into_inner()) = Some(pool);
+        }
+        self.db.leases.count.lock().unwrap_or_else(|e| e.into_inner()).draining = false;
+    }
+}
+
+/// Removes the `-wal` and `-shm` files SQLite keeps next to `path`. Only
+/// safe once every connection to `path` is closed, before a different file
+/// is moved into its place; a leftover WAL would be replayed into it.
+pub fn remove_wal_files(path: &Path) -> Result<(), RweError> {
+    for suffix in ["-wal", "-shm"] {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+        match std::fs::remove_file(&file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(RweError::io(&format!("Failed to remove {}", Path::new(&file).display()), e)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rwe_pool_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("main.db")
+    }
+
+    #[test]
+    fn drain_waits_for_connections_and_checkpoints() {
+        let path = temp_db("drain");
+        let db = DbPool::new(Some(DbPool::open(&path, None).unwrap()));
+        db.get().unwrap().execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1);").unwrap();
+
+        let held = db.get().unwrap();
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(held);
+        });
+        let started = Instant::now();
+        let drained = db.drain().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        release.join().unwrap();
+
+        assert!(matches!(db.get(), Err(RweError::Locked(_))));
+        assert!(matches!(db.drain(), Err(RweError::Conflict(_))));
+        let wal = std::fs::metadata(path.with_extension("db-wal")).map(|wal| wal.len()).unwrap_or(0);
+        assert_eq!(wal, 0);
+        let x: i64 = drained.get().unwrap().query_row("SELECT x FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(x, 1);
+
+        // Dropping the guard puts the drained pool back
+        drop(drained);
+        db.get().unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn closed_pool_stays_locked_until_finished() {
+        let path = temp_db("close");
+        let db = DbPool::new(Some(DbPool::open(&path, None).unwrap()));
+
+        let mut drained = db.drain().unwrap();
+        drained.close();
+        drained.finish(DbPool::open(&path, None).unwrap());
+        db.get().unwrap();
+
+        let mut drained = db.drain().unwrap();
+        drained.close();
+        drop(drained);
+        assert!(matches!(db.get(), Err(RweError::Locked(_))));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_holds_a_lease_until_it_finishes() {
+        let path = temp_db("run");
+        let db = std::sync::Arc::new(DbPool::new(Some(DbPool::open(&path, None).unwrap())));
+        let (started, wait) = std::sync::mpsc::channel();
+        let task = {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.run(move |_conn| {
+                    started.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(200));
+                    Ok::<_, RweError>(())
+                }).await
+            })
+        };
+        wait.recv().unwrap();
+        let started = Instant::now();
+        drop(db.drain().unwrap());
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        task.await.unwrap().unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,478 @@
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+use crate::branches;
+use crate::database;
+use crate::database_pool::DbPool;
+use crate::error::RweError;
+use crate::search_fuzzy::normalize_term;
+use crate::search_query::ContentType;
+
+/// Turns text into a fixed-size vector. Implementations must be deterministic
+/// for a given `model_id`, since stored vectors are only compared with query
+/// vectors produced by the same model.
+pub trait Embedder: Send + Sync {
+    fn model_id(&self) -> &str;
+    fn dimensions(&self) -> usize;
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+pub type SharedEmbedder = Arc<dyn Embedder>;
+
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "did", "do", "for", "from", "i", "in",
+    "is", "it", "of", "on", "or", "that", "the", "this", "to", "was", "we", "where", "with", "you",
+];
+
+/// A dependency-free CPU embedder based on feature hashing. Words and their
+/// character trigrams are hashed into signed buckets, so texts sharing words
+/// or word stems (`queue`/`queues`, `lock-free`/`lockfree`) land close together.
+/// It has no learned notion of synonyms; a model-backed `Embedder` can be
+/// managed in its place without touching the storage or search code.
+pub struct HashingEmbedder {
+    dimensions: usize,
+    model_id: String,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions,
+            model_id: format!("hashing-v1-{}", dimensions),
+        }
+    }
+
+    fn add_feature(&self, vector: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a(feature.as_bytes());
+        let bucket = (hash % self.dimensions as u64) as usize;
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[bucket] += sign * weight;
+    }
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+
+        let words: Vec<String> = text
+            .split(|c: char| c.is_whitespace() || (c.is_ascii_punctuation() && c != '-'))
+            .map(normalize_term)
+            .filter(|word| !word.is_empty() && !STOP_WORDS.contains(&word.as_str()))
+            .collect();
+
+        for word in &words {
+            self.add_feature(&mut vector, word, 1.0);
+
+            let padded: Vec<char> = format!("<{}>", word).chars().collect();
+            for trigram in padded.windows(3) {
+                let trigram: String = trigram.iter().collect();
+                self.add_feature(&mut vector, &trigram, 0.4);
+            }
+        }
+
+        normalize(&mut vector);
+        vector
+    }
+}
+
+/// 64-bit FNV-1a, used instead of `DefaultHasher` because stored vectors
+/// must hash identically across Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|v| v * v).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+// Rows without an embedding for the model. Triggers drop embeddings whose
+// source text changes, so a missing row is also how stale vectors show up.
+// Trashed conversations are left alone until they are restored, and messages
+// on inactive branches until their branch is switched to.
+const PENDING_MESSAGES_QUERY: &str = "
+    SELECT m.id, m.conversation_id, m.content
+    FROM messages m
+    JOIN conversations c ON c.id = m.conversation_id
+    LEFT JOIN embeddings e
+        ON e.source_type = 'message' AND e.source_id = m.id AND e.model = ?1
+    WHERE e.id IS NULL AND c.deleted_at IS NULL
+";
+
+const PENDING_NOTES_QUERY: &str = "
+    SELECT c.id, c.id, c.notes
+    FROM conversations c
+    LEFT JOIN embeddings e
+        ON e.source_type = 'notes' AND e.source_id = c.id AND e.model = ?1
+    WHERE c.notes IS NOT NULL AND c.notes != '' AND e.id IS NULL AND c.deleted_at IS NULL
+";
+
+const PENDING_SUMMARIES_QUERY: &str = "
+    SELECT c.id, c.id, c.summary
+    FROM conversations c
+    LEFT JOIN embeddings e
+        ON e.source_type = 'summary' AND e.source_id = c.id AND e.model = ?1
+    WHERE c.summary IS NOT NULL AND c.summary != '' AND e.id IS NULL AND c.deleted_at IS NULL
+";
+
+/// Rows embedded per transaction by the background indexer, so writers
+/// never wait long behind it.
+const INDEX_BATCH: usize = 256;
+
+/// How long the indexer waits before retrying while the database is locked.
+const LOCKED_RETRY: Duration = Duration::from_secs(2);
+
+/// Embeds up to `limit` messages, notes and summaries that have no vector
+/// for this embedder yet. Returns the number of rows embedded; fewer than
+/// `limit` means nothing is left.
+pub fn index_pending(conn: &Connection, embedder: &dyn Embedder, limit: usize) -> Result<usize, RweError> {
+    let pending_messages = format!("{} AND {}", PENDING_MESSAGES_QUERY, branches::ON_ACTIVE_BRANCH_SQL);
+    let sources = [
+        (ContentType::Message, pending_messages.as_str()),
+        (ContentType::Notes, PENDING_NOTES_QUERY),
+        (ContentType::Summary, PENDING_SUMMARIES_QUERY),
+    ];
+
+    let mut pending: Vec<(ContentType, i64, i64, String)> = Vec::new();
+    for (content_type, sql) in sources {
+        let remaining = limit - pending.len();
+        if remaining == 0 {
+            break;
+        }
+        let mut stmt = conn.prepare(&format!("{} LIMIT ?2", sql))
+            .map_err(|e| RweError::database("Prepare pending embeddings error", e))?;
+        let row_iter = stmt.query_map(rusqlite::params![embedder.model_id(), remaining as i64], |row| {
+            Ok((content_type, row.get(0)?, row.get(1)?, row.get(2)?))
+        }).map_err(|e| RweError::database("Query pending embeddings error", e))?;
+        for row in row_iter {
+            pending.push(row.map_err(|e| RweError::database("Row error", e))?);
+        }
+    }
+
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let vectors: Vec<Vec<f32>> = pending.iter().map(|(_, _, _, content)| embedder.embed(content)).collect();
+    database::with_transaction(conn, |tx| {
+        let mut insert = tx.prepare(
+            "INSERT OR REPLACE INTO embeddings (source_type, source_id, conversation_id, model, dimensions, vector)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        ).map_err(|e| RweError::database("Prepare insert embedding error", e))?;
+
+        for ((content_type, source_id, conversation_id, _), vector) in pending.iter().zip(&vectors) {
+            insert.execute(rusqlite::params![
+                content_type.as_str(),
+                source_id,
+                conversation_id,
+                embedder.model_id(),
+                embedder.dimensions() as i64,
+                encode_vector(vector),
+            ]).map_err(|e| RweError::database("Insert embedding error", e))?;
+        }
+        Ok(())
+    })?;
+
+    Ok(pending.len())
+}
+
+/// Wakes the background indexer. Commands that write messages, notes or
+/// summaries call `schedule` after committing, so searches never wait for
+/// text to be embedded.
+#[derive(Default)]
+pub struct EmbeddingIndexer {
+    wake: Notify,
+}
+
+impl EmbeddingIndexer {
+    pub fn schedule(&self) {
+        self.wake.notify_one();
+    }
+}
+
+/// Embeds pending rows in batches each time the indexer is scheduled. While
+/// the database is locked or still migrating it keeps retrying, so a single
+/// `schedule` at startup is enough to catch up on existing rows.
+pub async fn run_indexer(app: AppHandle) {
+    let indexer = app.state::<EmbeddingIndexer>();
+    let db = app.state::<DbPool>();
+    let embedder = app.state::<SharedEmbedder>().inner().clone();
+    loop {
+        indexer.wake.notified().await;
+        loop {
+            let embedder = embedder.clone();
+            match db.run(move |conn| index_pending(conn, embedder.as_ref(), INDEX_BATCH)).await {
+                Ok(count) if count == INDEX_BATCH => continue,
+                Ok(_) => break,
+                Err(RweError::Locked(_)) => tokio::time::sleep(LOCKED_RETRY).await,
+                Err(e) => {
+                    eprintln!("Embedding index error: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbour {
+    pub content_type: ContentType,
+    pub source_id: i64,
+    pub similarity: f32,
+}
+
+/// The `limit` stored vectors most similar to `query`, best first. Vectors of
+/// trashed conversations and of messages on inactive branches are skipped.
+pub fn nearest_neighbours(
+    conn: &Connection,
+    embedder: &dyn Embedder,
+    query: &[f32],
+    limit: usize,
+) -> Result<Vec<Neighbour>, RweError> {
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT e.source_type, e.source_id, e.vector
+        FROM embeddings e
+        JOIN conversations c ON c.id = e.conversation_id
+        LEFT JOIN messages m ON e.source_type = 'message' AND m.id = e.source_id
+        WHERE e.model = ?1 AND e.dimensions = ?2 AND c.deleted_at IS NULL
+          AND (e.source_type != 'message' OR {})
+        "#,
+        branches::ON_ACTIVE_BRANCH_SQL,
+    )).map_err(|e| RweError::database("Prepare embeddings error", e))?;
+
+    let row_iter = stmt.query_map(
+        rusqlite::params![embedder.model_id(), embedder.dimensions() as i64],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, Vec<u8>>(2)?)),
+    ).map_err(|e| RweError::database("Query embeddings error", e))?;
+
+    let mut neighbours = Vec::new();
+    for row in row_iter {
+        let (source_type, source_id, bytes) = row.map_err(|e| RweError::database("Row error", e))?;
+        let Some(content_type) = ContentType::from_source_type(&source_type) else {
+            continue;
+        };
+        neighbours.push(Neighbour {
+            content_type,
+            source_id,
+            similarity: cosine_similarity(query, &decode_vector(&bytes)),
+        });
+    }
+
+    neighbours.sort_by(|a, b| b.similarity.partial_cmp(&a.similarity).unwrap_or(std::cmp::Ordering::Equal));
+    neighbours.truncate(limit);
+
+    Ok(neighbours)
+}
+
+/// Reciprocal rank fusion: each ranked list contributes `1 / (k + rank)` for
+/// every item it contains, so items ranked well by several lists rise to the
+/// top without having to calibrate BM25 against cosine scores.
+pub fn reciprocal_rank_fusion<K>(rankings: &[Vec<K>], k: f64) -> Vec<(K, f64)>
+where
+    K: Clone + Eq + std::hash::Hash,
+{
+    let mut scores: HashMap<K, f64> = HashMap::new();
+    let mut order: Vec<K> = Vec::new();
+
+    for ranking in rankings {
+        for (rank, key) in ranking.iter().enumerate() {
+            let score = scores.entry(key.clone()).or_insert_with(|| {
+                order.push(key.clone());
+                0.0
+            });
+            *score += 1.0 / (k + rank as f64 + 1.0);
+        }
+    }
+
+    let mut fused: Vec<(K, f64)> = order
+        .into_iter()
+        .map(|key| {
+            let score = scores[&key];
+            (key, score)
+        })
+        .collect();
+    fused.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+
+    fn rank<'a>(embedder: &HashingEmbedder, query: &str, texts: &[&'a str]) -> Vec<&'a str> {
+        let query = embedder.embed(query);
+        let mut scored: Vec<(&str, f32)> = texts
+            .iter()
+            .map(|text| (*text, cosine_similarity(&query, &embedder.embed(text))))
+            .collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        scored.into_iter().map(|(text, _)| text).collect()
+    }
+
+    #[test]
+    fn hashing_embedder_is_deterministic_and_normalized() {
+        let embedder = HashingEmbedder::new(64);
+        let vector = embedder.embed("Lock-free queues with atomics");
+        assert_eq!(vector.len(), 64);
+        assert_eq!(vector, embedder.embed("Lock-free queues with atomics"));
+        assert_eq!(decode_vector(&encode_vector(&vector)), vector);
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+
+        // Only stop words: no features, and nothing is similar to it
+        let empty = embedder.embed("the and of");
+        assert!(empty.iter().all(|v| *v == 0.0));
+        assert_eq!(cosine_similarity(&empty, &vector), 0.0);
+        assert_eq!(embedder.model_id(), "hashing-v1-64");
+    }
+
+    #[test]
+    fn cosine_similarity_ranks_shared_words_and_stems_first() {
+        let embedder = HashingEmbedder::default();
+        let texts = [
+            "Cooking pasta requires boiling water",
+            "An atomic ring buffer avoids locks for queues",
+            "A lock-free queue built on atomics",
+        ];
+        assert_eq!(rank(&embedder, "lock-free queue", &texts), [texts[2], texts[1], texts[0]]);
+        assert_eq!(rank(&embedder, "boiling pasta", &texts)[0], texts[0]);
+
+        let same = embedder.embed("tokio runtime");
+        assert!((cosine_similarity(&same, &same) - 1.0).abs() < 1e-5);
+        assert!(cosine_similarity(&same, &embedder.embed("tokio runtimes")) > 0.5);
+    }
+
+    #[test]
+    fn reciprocal_rank_fusion_favours_items_in_both_lists() {
+        let fused = reciprocal_rank_fusion(&[vec!["a", "b", "c"], vec!["c", "a"]], 60.0);
+        let order: Vec<&str> = fused.iter().map(|(key, _)| *key).collect();
+        assert_eq!(order, ["a", "c", "b"]);
+        assert!((fused[0].1 - (1.0 / 61.0 + 1.0 / 62.0)).abs() < 1e-12);
+        assert!((fused[2].1 - 1.0 / 62.0).abs() < 1e-12);
+
+        // A top hit in one list beats a middling hit in one list
+        let fused = reciprocal_rank_fusion(&[vec!["x", "y"], vec!["z"]], 60.0);
+        assert_eq!(fused[2].0, "y");
+
+        // Ties keep the order items were first seen in
+        let fused = reciprocal_rank_fusion(&[vec!["p", "q"], vec!["q", "p"]], 60.0);
+        assert_eq!(fused.iter().map(|(key, _)| *key).collect::<Vec<_>>(), ["p", "q"]);
+        assert!(reciprocal_rank_fusion::<i64>(&[vec![], vec![]], 60.0).is_empty());
+    }
+
+    #[test]
+    fn index_pending_skips_trash_and_works_in_batches() {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO conversations (id, name, notes) VALUES (1, 'Kept', 'lock-free notes'), (2, 'Trashed', 'old notes');
+             UPDATE conversations SET deleted_at = CURRENT_TIMESTAMP WHERE id = 2;",
+        ).unwrap();
+        for content in ["lock-free queue", "atomic counters", "boiling pasta"] {
+            database::insert_message(&conn, 1, "user", content, false).unwrap();
+        }
+        database::insert_message(&conn, 2, "user", "lock-free queue in the trash", false).unwrap();
+
+        let embedder = HashingEmbedder::default();
+        assert_eq!(index_pending(&conn, &embedder, 2).unwrap(), 2);
+        assert_eq!(index_pending(&conn, &embedder, 2).unwrap(), 2);
+        assert_eq!(index_pending(&conn, &embedder, 2).unwrap(), 0);
+
+        let query = embedder.embed("lock-free queue");
+        let neighbours = nearest_neighbours(&conn, &embedder, &query, 10).unwrap();
+        assert_eq!(neighbours.len(), 4);
+        assert_eq!(neighbours[0].content_type, ContentType::Message);
+        assert!((neighbours[0].similarity - 1.0).abs() < 1e-5);
+
+        // Restoring brings the trashed conversation back into the index
+        conn.execute("UPDATE conversations SET deleted_at = NULL WHERE id = 2", []).unwrap();
+        assert_eq!(index_pending(&conn, &embedder, 10).unwrap(), 2);
+        assert_eq!(nearest_neighbours(&conn, &embedder, &query, 10).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn only_the_active_branch_is_indexed_and_searched() {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO conversations (id, name) VALUES (1, 'Queues')", []).unwrap();
+        let ids: Vec<i64> = ["lock-free queue", "atomic counters", "boiling pasta"]
+            .iter()
+            .map(|content| database::insert_message(&conn, 1, "user", content, false).unwrap())
+            .collect();
+
+        // Fork after the first message and continue on the new branch
+        conn.execute(
+            "INSERT INTO conversation_branches (conversation_id, name, head_message_id, active) VALUES (1, 'Main', ?1, 0)",
+            [ids[2]],
+        ).unwrap();
+        conn.execute(
+            "INSERT INTO conversation_branches (conversation_id, name, fork_message_id, head_message_id, active) VALUES (1, 'Branch 1', ?1, ?1, 1)",
+            [ids[0]],
+        ).unwrap();
+        database::insert_message(&conn, 1, "user", "lock-free ring buffer", false).unwrap();
+
+        let embedder = HashingEmbedder::default();
+        assert_eq!(index_pending(&conn, &embedder, 10).unwrap(), 2);
+        let query = embedder.embed("boiling pasta");
+        let neighbours = nearest_neighbours(&conn, &embedder, &query, 10).unwrap();
+        assert_eq!(neighbours.len(), 2);
+        assert!(neighbours.iter().all(|n| n.source_id != ids[2]));
+
+        // Switching back embeds Main's own messages and hides the fork's
+        conn.execute_batch(
+            "UPDATE conversation_branches SET active = 0 WHERE conversation_id = 1;
+             UPDATE conversation_branches SET active = 1 WHERE name = 'Main';",
+        ).unwrap();
+        assert_eq!(index_pending(&conn, &embedder, 10).unwrap(), 2);
+        let neighbours = nearest_neighbours(&conn, &embedder, &query, 10).unwrap();
+        assert_eq!(neighbours.len(), 3);
+        assert_eq!(neighbours[0].source_id, ids[2]);
+    }
+}
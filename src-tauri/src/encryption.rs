@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, AppHandle, State};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+use crate::database;
+use crate::database_pool::{self, DbPool, Drained, SqlitePool};
+use crate::error::RweError;
+use crate::migrations::{self, MigrationState};
+use crate::secrets::{self, SecretStore};
+
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptionStatus {
+    pub encrypted: bool,
+    pub locked: bool,
+}
+
+/// Tracks whether main.db is SQLCipher-encrypted and, once unlocked, keeps
+/// the passphrase so backups and exports can open their copies of the file.
+pub struct DatabaseEncryption {
+    path: PathBuf,
+    status: Mutex<EncryptionStatus>,
+    passphrase: Mutex<Option<Zeroizing<String>>>,
+}
+
+impl DatabaseEncryption {
+    pub fn new(path: PathBuf, status: EncryptionStatus) -> Self {
+        Self {
+            path,
+            status: Mutex::new(status),
+            passphrase: Mutex::new(None),
+        }
+    }
+
+    pub fn status(&self) -> Result<EncryptionStatus, RweError> {
+        self.status.lock()
+            .map(|status| *status)
+            .map_err(|e| RweError::Internal(format!("Lock error: {}", e)))
+    }
+
+    fn unlocked(&self, passphrase: &str) -> Result<(), RweError> {
+        *self.status.lock().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))? =
+            EncryptionStatus { encrypted: true, locked: false };
+        *self.passphrase.lock().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))? =
+            Some(Zeroizing::new(passphrase.to_string()));
+        Ok(())
+    }
+
+    /// The passphrase main.db is encrypted with, once unlocked.
+    pub fn passphrase(&self) -> Result<Option<Zeroizing<String>>, RweError> {
+        self.passphrase.lock()
+            .map(|passphrase| passphrase.clone())
+            .map_err(|e| RweError::Internal(format!("Lock error: {}", e)))
+    }
+
+    /// Opens another database file (a backup or export copy of main.db)
+    /// with the passphrase main.db is encrypted with, if any.
+    pub fn open(&self, path: &Path) -> Result<Connection, RweError> {
+        match self.passphrase()? {
+            Some(passphrase) => open_with_passphrase(path, &passphrase),
+            None => Connection::open(path).map_err(|e| RweError::database("Failed to open database", e)),
+        }
+    }
+
+    /// Opens a pool on `path`, keyed like main.db.
+    pub fn open_pool(&self, path: &Path) -> Result<SqlitePool, RweError> {
+        DbPool::open(path, self.passphrase()?.as_ref().map(|passphrase| passphrase.as_str()))
+    }
+}
+
+/// False when the file can't be read without a key (or isn't SQLite at all).
+pub fn is_readable(conn: &Connection) -> bool {
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+        .is_ok()
+}
+
+pub fn open_with_passphrase(path: &Path, passphrase: &str) -> Result<Connection, RweError> {
+    let conn = Connection::open(path)
+        .map_err(|e| RweError::database("Failed to open database", e))?;
+    // PRAGMA key must come before anything else touches the file
+    conn.pragma_update(None, "key", passphrase)
+        .map_err(|e| RweError::database("Failed to apply passphrase", e))?;
+    if !is_readable(&conn) {
+        return Err(RweError::Validation("Incorrect passphrase".to_string()));
+    }
+    database::configure_connection(&conn)
+        .map_err(|e| RweError::database("Failed to configure database", e))?;
+    Ok(conn)
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), RweError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(RweError::Validation(format!("Passphrase must be at least {} characters", MIN_PASSPHRASE_LEN)));
+    }
+    Ok(())
+}
+
+#[command]
+pub async fn get_encryption_status(encryption: State<'_, DatabaseEncryption>) -> Result<EncryptionStatus, RweError> {
+    encryption.status()
+}
+
+/// Opens an encrypted main.db that was left locked at startup, then runs the
+/// migrations that were skipped while it was unreadable.
+#[command]
+pub async fn unlock_database(
+    passphrase: String,
+    app: AppHandle,
+    db: State<'_, DbPool>,
+    encryption: State<'_, DatabaseEncryption>,
+    secrets: State<'_, SecretStore>,
+    migration_state: State<'_, MigrationState>,
+) -> Result<(), RweError> {
+    let passphrase = Zeroizing::new(passphrase);
+    if !encryption.status()?.locked {
+        return Ok(());
+    }
+
+    let pool = open_locked(&encryption, &passphrase)?;
+    // Data migrations can take a while; keep them off the async runtime
+    let path = encryption.path.clone();
+    let key = passphrase.clone();
+    let migrated = tauri::async_runtime::spawn_blocking(move || migrations::migrate_pool(pool, &path, Some(&key), &app))
+        .await
+        .map_err(|e| RweError::Internal(format!("Task error: {}", e)))?;
+    let pool = match migrated {
+        Ok(pool) => pool,
+        Err(failure) => {
+            let error = RweError::Storage(failure.message.clone());
+            migration_state.record(failure)?;
+            return Err(error);
+        }
+    };
+    {
+        let conn = pool.get().map_err(|e| RweError::Storage(format!("Connection error: {}", e)))?;
+        secrets::migrate_plaintext_keys(&conn, &secrets)?;
+    }
+
+    db.replace(Some(pool))?;
+    encryption.unlocked(&passphrase)
+}
+
+/// Checks `passphrase` against the locked main.db and opens a pool on it.
+/// A wrong passphrase leaves the database locked and the file untouched.
+fn open_locked(encryption: &DatabaseEncryption, passphrase: &str) -> Result<SqlitePool, RweError> {
+    open_with_passphrase(&encryption.path, passphrase).map_err(|e| match e {
+        RweError::Validation(message) => RweError::Locked(message),
+        e => e,
+    })?;
+    DbPool::open(&encryption.path, Some(passphrase))
+}
+
+/// Rewrites a plaintext main.db as a SQLCipher database keyed with
+/// `passphrase`. Earlier backups in the backups folder stay unencrypted.
+#[command]
+pub async fn enable_encryption(
+    passphrase: String,
+    db: State<'_, DbPool>,
+    encryption: State<'_, DatabaseEncryption>,
+) -> Result<(), RweError> {
+    enable(&db, &encryption, &Zeroizing::new(passphrase))
+}
+
+fn enable(db: &DbPool, encryption: &DatabaseEncryption, passphrase: &str) -> Result<(), RweError> {
+    validate_passphrase(passphrase)?;
+    if encryption.status()?.encrypted {
+        return Err(RweError::Conflict("Database is already encrypted".to_string()));
+    }
+
+    let path = &encryption.path;
+    let encrypted_path = path.with_extension("db.encrypting");
+    if encrypted_path.exists() {
+        std::fs::remove_file(&encrypted_path)
+            .map_err(|e| RweError::io("Failed to remove stale encrypted copy", e))?;
+    }
+
+    // Other commands see a locked database until the encrypted file is in place
+    let mut drained = db.drain()?;
+    let exported = export_encrypted(&drained, &encrypted_path, passphrase)
+        .and_then(|_| open_with_passphrase(&encrypted_path, passphrase).map(drop));
+    if let Err(e) = exported {
+        std::fs::remove_file(&encrypted_path).ok();
+        return Err(e);
+    }
+
+    // Every plaintext connection has to be closed before the file can be
+    // replaced. The plaintext file is kept aside until the encrypted one has
+    // opened, so a failure can put it back.
+    drained.close();
+    let plaintext_path = path.with_extension("db.plaintext");
+    let moved_aside = database_pool::remove_wal_files(path)
+        .and_then(|_| std::fs::rename(path, &plaintext_path).map_err(|e| RweError::io("Failed to replace database", e)));
+    if let Err(e) = moved_aside {
+        std::fs::remove_file(&encrypted_path).ok();
+        drained.finish(DbPool::open(path, None)?);
+        return Err(e);
+    }
+
+    let opened = std::fs::rename(&encrypted_path, path)
+        .map_err(|e| RweError::io("Failed to replace database", e))
+        .and_then(|_| DbPool::open(path, Some(passphrase)));
+    match opened {
+        Ok(pool) => {
+            drained.finish(pool);
+            std::fs::remove_file(&plaintext_path)
+                .map_err(|e| RweError::io("Failed to remove the unencrypted database", e))?;
+            encryption.unlocked(passphrase)
+        }
+        Err(e) => {
+            std::fs::remove_file(&encrypted_path).ok();
+            database_pool::remove_wal_files(path)?;
+            std::fs::rename(&plaintext_path, path)
+                .map_err(|e| RweError::io("Failed to restore the unencrypted database", e))?;
+            drained.finish(DbPool::open(path, None)?);
+            Err(e)
+        }
+    }
+}
+
+fn export_encrypted(drained: &Drained, encrypted_path: &Path, passphrase: &str) -> Result<(), RweError> {
+    let conn = drained.get()?;
+    let exported = conn
+        .execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            rusqlite::params![encrypted_path.to_string_lossy(), passphrase],
+        )
+        .and_then(|_| conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(())))
+        .and_then(|_| conn.execute("DETACH DATABASE encrypted", []));
+    if let Err(e) = exported {
+        conn.execute("DETACH DATABASE encrypted", []).ok();
+        return Err(RweError::database("Failed to encrypt database", e));
+    }
+    Ok(())
+}
+
+/// Re-encrypts main.db under `new_passphrase`. The pool is drained first and
+/// reopened afterwards, since its other connections were keyed with the old
+/// one; if the new pool can't be opened the old passphrase is put back.
+#[command]
+pub async fn change_passphrase(
+    current_passphrase: String,
+    new_passphrase: String,
+    db: State<'_, DbPool>,
+    encryption: State<'_, DatabaseEncryption>,
+) -> Result<(), RweError> {
+    change(&db, &encryption, &Zeroizing::new(current_passphrase), &Zeroizing::new(new_passphrase))
+}
+
+fn change(db: &DbPool, encryption: &DatabaseEncryption, current_passphrase: &str, new_passphrase: &str) -> Result<(), RweError> {
+    validate_passphrase(new_passphrase)?;
+
+    let status = encryption.status()?;
+    if !status.encrypted {
+        return Err(RweError::Conflict("Database is not encrypted".to_string()));
+    }
+    if status.locked {
+        return Err(RweError::Locked("Database is locked".to_string()));
+    }
+
+    open_with_passphrase(&encryption.path, current_passphrase)
+        .map_err(|_| RweError::Validation("Current passphrase is incorrect".to_string()))?;
+
+    let drained = db.drain()?;
+    rekey(&drained, new_passphrase)?;
+    match DbPool::open(&encryption.path, Some(new_passphrase)) {
+        Ok(pool) => {
+            drained.finish(pool);
+            encryption.unlocked(new_passphrase)
+        }
+        Err(e) => {
+            rekey(&drained, current_passphrase)?;
+            Err(e)
+        }
+    }
+}
+
+fn rekey(drained: &Drained, passphrase: &str) -> Result<(), RweError> {
+    drained.get()?
+        .pragma_update(None, "rekey", passphrase)
+        .map_err(|e| RweError::database("Failed to change passphrase", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rwe_encryption_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("main.db")
+    }
+
+    /// A plaintext main.db holding one note, with a pool open on it.
+    fn plaintext(name: &str) -> (PathBuf, DbPool, DatabaseEncryption) {
+        let path = temp_db(name);
+        let db = DbPool::new(Some(DbPool::open(&path, None).unwrap()));
+        db.get().unwrap().execute_batch("CREATE TABLE notes (body TEXT); INSERT INTO notes VALUES ('kept');").unwrap();
+        let encryption = DatabaseEncryption::new(path.clone(), EncryptionStatus { encrypted: false, locked: false });
+        (path, db, encryption)
+    }
+
+    fn note(conn: &Connection) -> String {
+        conn.query_row("SELECT body FROM notes", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn encrypted_database_reopens_only_with_its_passphrase() {
+        let (path, db, encryption) = plaintext("enable");
+        assert!(matches!(enable(&db, &encryption, "short"), Err(RweError::Validation(_))));
+        enable(&db, &encryption, "correct horse").unwrap();
+
+        assert_eq!(encryption.status().unwrap(), EncryptionStatus { encrypted: true, locked: false });
+        assert_eq!(note(&db.get().unwrap()), "kept");
+        assert!(!path.with_extension("db.plaintext").exists());
+        assert!(!path.with_extension("db.encrypting").exists());
+        assert!(matches!(enable(&db, &encryption, "correct horse"), Err(RweError::Conflict(_))));
+
+        drop(db);
+        assert!(!is_readable(&Connection::open(&path).unwrap()));
+        assert_eq!(note(&open_with_passphrase(&path, "correct horse").unwrap()), "kept");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn wrong_passphrase_leaves_the_database_locked_and_untouched() {
+        let (path, db, encryption) = plaintext("unlock");
+        enable(&db, &encryption, "correct horse").unwrap();
+        drop(db);
+        let before = std::fs::read(&path).unwrap();
+
+        let locked = DatabaseEncryption::new(path.clone(), EncryptionStatus { encrypted: true, locked: true });
+        assert_eq!(open_locked(&locked, "wrong horse").err(), Some(RweError::Locked("Incorrect passphrase".to_string())));
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        assert!(locked.status().unwrap().locked);
+        assert!(locked.passphrase().unwrap().is_none());
+
+        let pool = open_locked(&locked, "correct horse").unwrap();
+        assert_eq!(note(&pool.get().unwrap()), "kept");
+        drop(pool);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rekeying_replaces_the_passphrase() {
+        let (path, db, encryption) = plaintext("rekey");
+        enable(&db, &encryption, "correct horse").unwrap();
+
+        assert!(matches!(change(&db, &encryption, "wrong horse", "battery staple"), Err(RweError::Validation(_))));
+        change(&db, &encryption, "correct horse", "battery staple").unwrap();
+        assert_eq!(encryption.passphrase().unwrap().as_deref().map(String::as_str), Some("battery staple"));
+        assert_eq!(note(&db.get().unwrap()), "kept");
+
+        drop(db);
+        assert!(open_with_passphrase(&path, "correct horse").is_err());
+        assert_eq!(note(&open_with_passphrase(&path, "battery staple").unwrap()), "kept");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_export_keeps_the_plaintext_database() {
+        let (path, db, encryption) = plaintext("export_failure");
+        // A link into a missing directory: not a stale copy to remove, and
+        // nothing SQLite can create, so sqlcipher_export fails
+        let encrypted_path = path.with_extension("db.encrypting");
+        std::os::unix::fs::symlink(path.with_file_name("missing").join("main.db"), &encrypted_path).unwrap();
+
+        assert!(matches!(enable(&db, &encryption, "correct horse"), Err(RweError::Storage(_))));
+        assert_eq!(encryption.status().unwrap(), EncryptionStatus { encrypted: false, locked: false });
+        assert!(encryption.passphrase().unwrap().is_none());
+        assert!(std::fs::symlink_metadata(&encrypted_path).is_err());
+        assert_eq!(note(&db.get().unwrap()), "kept");
+
+        drop(db);
+        assert_eq!(note(&Connection::open(&path).unwrap()), "kept");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
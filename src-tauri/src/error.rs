@@ -0,0 +1,94 @@
+use serde::Serialize;
+use crate::mindmap_schema::MindMapError;
+use crate::search_query::QueryError;
+
+/// Errors returned to the frontend, tagged by `kind` so the UI can tell a
+/// missing row from a locked database or a full disk without parsing the
+/// message. Serialises as `{ "kind": "not_found", "detail": "..." }`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum RweError {
+    /// The conversation, message or file asked for doesn't exist.
+    NotFound(String),
+    /// The change clashes with data already stored, e.g. a unique or
+    /// foreign key constraint.
+    Conflict(String),
+    /// The arguments were rejected before anything was read or written.
+    Validation(String),
+    /// A search query that doesn't parse, with the position of the problem.
+    InvalidQuery(QueryError),
+    /// A mind map that breaks its schema, with the node or connection at
+    /// fault.
+    InvalidMindMap(MindMapError),
+    /// Reading or writing the database or a file failed: disk full,
+    /// permissions, I/O errors or a damaged file.
+    Storage(String),
+    /// A request to a remote service failed.
+    Network(String),
+    /// The database is encrypted and not unlocked yet, being replaced, or
+    /// held by another writer for longer than the busy timeout.
+    Locked(String),
+    /// Anything else; usually a bug rather than something the user can fix.
+    Internal(String),
+}
+
+impl RweError {
+    /// Wraps a SQLite error, prefixing it with `context` the way messages
+    /// always have been ("Query error: ...") and picking the kind from the
+    /// SQLite result code.
+    pub fn database(context: &str, e: rusqlite::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        match &e {
+            rusqlite::Error::QueryReturnedNoRows => RweError::NotFound(message),
+            rusqlite::Error::SqliteFailure(failure, _) => match failure.code {
+                rusqlite::ErrorCode::ConstraintViolation => RweError::Conflict(message),
+                rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked => RweError::Locked(message),
+                _ => RweError::Storage(message),
+            },
+            _ => RweError::Internal(message),
+        }
+    }
+
+    /// Wraps a filesystem error the same way; a missing file is `NotFound`,
+    /// anything else is `Storage`.
+    pub fn io(context: &str, e: std::io::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        match e.kind() {
+            std::io::ErrorKind::NotFound => RweError::NotFound(message),
+            _ => RweError::Storage(message),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            RweError::NotFound(message)
+            | RweError::Conflict(message)
+            | RweError::Validation(message)
+            | RweError::Storage(message)
+            | RweError::Network(message)
+            | RweError::Locked(message)
+            | RweError::Internal(message) => message,
+            RweError::InvalidQuery(error) => &error.message,
+            RweError::InvalidMindMap(error) => &error.message,
+        }
+    }
+}
+
+impl std::fmt::Display for RweError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RweError::InvalidQuery(error) => write!(f, "{}", error),
+            RweError::InvalidMindMap(error) => write!(f, "{}", error),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for RweError {}
+
+/// For setup code that reports plain strings, and the benches.
+impl From<RweError> for String {
+    fn from(error: RweError) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,604 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::HashMap;
+use crate::database_pool::DbPool;
+use crate::database::{self, Conversation, CONVERSATION_COLUMNS};
+use crate::error::RweError;
+
+/// What happens to the contents of a folder when it is deleted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FolderDeleteMode {
+    /// Subfolders and conversations move up into the deleted folder's parent
+    /// (or the top level), after whatever is already there.
+    #[default]
+    MoveToParent,
+    /// Every subfolder is deleted and every conversation in the subtree goes
+    /// to the trash (restoring one later puts it at the top level).
+    DeleteContents,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderNode {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub name: String,
+    pub position: i64,
+    pub created_at: String,
+    /// Conversations directly in this folder.
+    pub conversation_count: i64,
+    /// Conversations in this folder and all of its subfolders.
+    pub total_conversation_count: i64,
+    pub children: Vec<FolderNode>,
+}
+
+fn normalize_name(name: &str) -> Result<String, RweError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(RweError::Validation("Folder name must not be empty".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+fn parent_of(conn: &Connection, folder_id: i64) -> Result<Option<i64>, RweError> {
+    conn.query_row("SELECT parent_id FROM folders WHERE id = ?1", [folder_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| RweError::database("Query error", e))?
+        .ok_or_else(|| RweError::NotFound(format!("Folder {} not found", folder_id)))
+}
+
+fn ensure_folder(conn: &Connection, folder_id: Option<i64>) -> Result<(), RweError> {
+    match folder_id {
+        Some(id) => parent_of(conn, id).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// The folder and all of its descendants, deepest first.
+fn subtree_ids(conn: &Connection, folder_id: i64) -> Result<Vec<i64>, RweError> {
+    let mut stmt = conn
+        .prepare(
+            r#"
+            WITH RECURSIVE subtree(id, depth) AS (
+                SELECT id, 0 FROM folders WHERE id = ?1
+                UNION ALL
+                SELECT f.id, s.depth + 1 FROM folders f JOIN subtree s ON f.parent_id = s.id
+            )
+            SELECT id FROM subtree ORDER BY depth DESC
+            "#,
+        )
+        .map_err(|e| RweError::database("Prepare error", e))?;
+
+    let ids = stmt
+        .query_map([folder_id], |row| row.get(0))
+        .map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<i64>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+
+    Ok(ids)
+}
+
+fn query_ids(conn: &Connection, sql: &str, parent: Option<i64>) -> Result<Vec<i64>, RweError> {
+    let mut stmt = conn.prepare(sql).map_err(|e| RweError::database("Prepare error", e))?;
+    let ids = stmt
+        .query_map([parent], |row| row.get(0))
+        .map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<i64>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+    Ok(ids)
+}
+
+fn sibling_folder_ids(conn: &Connection, parent_id: Option<i64>) -> Result<Vec<i64>, RweError> {
+    query_ids(conn, "SELECT id FROM folders WHERE parent_id IS ?1 ORDER BY position, id", parent_id)
+}
+
+fn folder_conversation_ids(conn: &Connection, folder_id: Option<i64>) -> Result<Vec<i64>, RweError> {
+    query_ids(
+        conn,
+        r#"
+        SELECT id FROM conversations WHERE folder_id IS ?1 AND deleted_at IS NULL
+        ORDER BY folder_position, created_at DESC, id
+        "#,
+        folder_id,
+    )
+}
+
+fn set_folder_positions(conn: &Connection, ids: &[i64]) -> Result<(), RweError> {
+    for (position, id) in ids.iter().enumerate() {
+        conn.execute(
+            "UPDATE folders SET position = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+            rusqlite::params![position as i64, id],
+        ).map_err(|e| RweError::database("Update folder error", e))?;
+    }
+    Ok(())
+}
+
+fn set_conversation_positions(conn: &Connection, ids: &[i64]) -> Result<(), RweError> {
+    for (position, id) in ids.iter().enumerate() {
+        conn.execute(
+            "UPDATE conversations SET folder_position = ?1 WHERE id = ?2",
+            rusqlite::params![position as i64, id],
+        ).map_err(|e| RweError::database("Update conversation error", e))?;
+    }
+    Ok(())
+}
+
+/// Puts `requested` first, in that order, followed by the rest of `current`
+/// in their existing order. Every requested id must already be in `current`.
+fn apply_order(current: Vec<i64>, requested: &[i64]) -> Result<Vec<i64>, RweError> {
+    if let Some(id) = requested.iter().find(|id| !current.contains(id)) {
+        return Err(RweError::Validation(format!("Item {} is not in this folder", id)));
+    }
+    let mut ordered: Vec<i64> = Vec::with_capacity(current.len());
+    for id in requested {
+        if !ordered.contains(id) {
+            ordered.push(*id);
+        }
+    }
+    ordered.extend(current.into_iter().filter(|id| !requested.contains(id)));
+    Ok(ordered)
+}
+
+/// Inserts `id` into `siblings` at `position`, or at the end when no
+/// position is given.
+fn insert_at(mut siblings: Vec<i64>, id: i64, position: Option<usize>) -> Vec<i64> {
+    siblings.retain(|sibling| *sibling != id);
+    let index = position.unwrap_or(siblings.len()).min(siblings.len());
+    siblings.insert(index, id);
+    siblings
+}
+
+struct FolderRow {
+    id: i64,
+    parent_id: Option<i64>,
+    name: String,
+    position: i64,
+    created_at: String,
+    conversation_count: i64,
+}
+
+fn build_node(row: FolderRow, children_of: &mut HashMap<Option<i64>, Vec<FolderRow>>) -> FolderNode {
+    let children: Vec<FolderNode> = children_of
+        .remove(&Some(row.id))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|child| build_node(child, children_of))
+        .collect();
+    let total_conversation_count = row.conversation_count
+        + children.iter().map(|child| child.total_conversation_count).sum::<i64>();
+
+    FolderNode {
+        id: row.id,
+        parent_id: row.parent_id,
+        name: row.name,
+        position: row.position,
+        created_at: row.created_at,
+        conversation_count: row.conversation_count,
+        total_conversation_count,
+        children,
+    }
+}
+
+fn folder_tree(conn: &Connection, root_id: Option<i64>) -> Result<Vec<FolderNode>, RweError> {
+    if let Some(root_id) = root_id {
+        parent_of(conn, root_id)?;
+    }
+
+    let mut stmt = conn
+        .prepare(
+            r#"
+            WITH RECURSIVE subtree(id) AS (
+                SELECT id FROM folders WHERE (?1 IS NULL AND parent_id IS NULL) OR id = ?1
+                UNION ALL
+                SELECT f.id FROM folders f JOIN subtree s ON f.parent_id = s.id
+            )
+            SELECT f.id, f.parent_id, f.name, f.position, f.created_at,
+                   (SELECT COUNT(*) FROM conversations c WHERE c.folder_id = f.id AND c.deleted_at IS NULL)
+            FROM folders f JOIN subtree s ON s.id = f.id
+            ORDER BY f.position, f.id
+            "#,
+        )
+        .map_err(|e| RweError::database("Prepare error", e))?;
+
+    let rows = stmt
+        .query_map([root_id], |row| {
+            Ok(FolderRow {
+                id: row.get(0)?,
+                parent_id: row.get(1)?,
+                name: row.get(2)?,
+                position: row.get(3)?,
+                created_at: row.get(4)?,
+                conversation_count: row.get(5)?,
+            })
+        })
+        .map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+
+    let mut children_of: HashMap<Option<i64>, Vec<FolderRow>> = HashMap::new();
+    let mut roots = Vec::new();
+    for row in rows {
+        if Some(row.id) == root_id || (root_id.is_none() && row.parent_id.is_none()) {
+            roots.push(row);
+        } else {
+            children_of.entry(row.parent_id).or_default().push(row);
+        }
+    }
+
+    Ok(roots.into_iter().map(|row| build_node(row, &mut children_of)).collect())
+}
+
+/// Returns the folder tree with conversation counts: the whole tree when
+/// `root_id` is None, otherwise just that folder and its subfolders.
+#[command]
+pub async fn get_folder_tree(
+    root_id: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<Vec<FolderNode>, RweError> {
+    db.run(move |conn| folder_tree(conn, root_id)).await
+}
+
+#[command]
+pub async fn get_folder_conversations(
+    folder_id: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<Vec<Conversation>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn
+            .prepare(&format!(
+                r#"
+                SELECT {} FROM conversations WHERE folder_id IS ?1 AND deleted_at IS NULL
+                ORDER BY folder_position, created_at DESC, id
+                "#,
+                CONVERSATION_COLUMNS
+            ))
+            .map_err(|e| RweError::database("Prepare error", e))?;
+
+        let conversations = stmt
+            .query_map([folder_id], database::row_to_conversation)
+            .map_err(|e| RweError::database("Query error", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RweError::database("Row error", e))?;
+
+        Ok(conversations)
+    }).await
+}
+
+fn create(conn: &Connection, name: &str, parent_id: Option<i64>) -> Result<i64, RweError> {
+    database::with_transaction(conn, |tx| {
+        ensure_folder(tx, parent_id)?;
+
+        let position = sibling_folder_ids(tx, parent_id)?.len() as i64;
+        tx.execute(
+            "INSERT INTO folders (parent_id, name, position) VALUES (?1, ?2, ?3)",
+            rusqlite::params![parent_id, name, position],
+        ).map_err(|e| RweError::database("Insert folder error", e))?;
+
+        Ok(tx.last_insert_rowid())
+    })
+}
+
+#[command]
+pub async fn create_folder(
+    name: String,
+    parent_id: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<i64, RweError> {
+    let name = normalize_name(&name)?;
+    db.run(move |conn| create(conn, &name, parent_id)).await
+}
+
+#[command]
+pub async fn rename_folder(
+    folder_id: i64,
+    name: String,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    let name = normalize_name(&name)?;
+    db.run(move |conn| {
+        let affected = conn
+            .execute(
+                "UPDATE folders SET name = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                rusqlite::params![name, folder_id],
+            )
+            .map_err(|e| RweError::database("Update folder error", e))?;
+
+        Ok(affected > 0)
+    }).await
+}
+
+fn move_to(conn: &Connection, folder_id: i64, parent_id: Option<i64>, position: Option<usize>) -> Result<bool, RweError> {
+    // The cycle check and the move share one transaction, so a concurrent
+    // move can't slip a folder under its own subtree in between
+    database::with_transaction(conn, |tx| {
+        let old_parent = parent_of(tx, folder_id)?;
+        ensure_folder(tx, parent_id)?;
+
+        if let Some(parent_id) = parent_id {
+            if subtree_ids(tx, folder_id)?.contains(&parent_id) {
+                return Err(RweError::Conflict("Cannot move a folder into itself or one of its subfolders".to_string()));
+            }
+        }
+
+        tx.execute(
+            "UPDATE folders SET parent_id = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+            rusqlite::params![parent_id, folder_id],
+        ).map_err(|e| RweError::database("Update folder error", e))?;
+
+        let siblings = insert_at(sibling_folder_ids(tx, parent_id)?, folder_id, position);
+        set_folder_positions(tx, &siblings)?;
+        if old_parent != parent_id {
+            set_folder_positions(tx, &sibling_folder_ids(tx, old_parent)?)?;
+        }
+
+        Ok(true)
+    })
+}
+
+/// Moves a folder (with everything in it) under `parent_id`, or to the top
+/// level when None, at `position` among its new siblings.
+#[command]
+pub async fn move_folder(
+    folder_id: i64,
+    parent_id: Option<i64>,
+    position: Option<usize>,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| move_to(conn, folder_id, parent_id, position)).await
+}
+
+/// Reorders the subfolders of `parent_id`. Folders not listed keep their
+/// relative order after the listed ones.
+#[command]
+pub async fn reorder_folders(
+    parent_id: Option<i64>,
+    folder_ids: Vec<i64>,
+    db: State<'_, DbPool>,
+) -> Result<(), RweError> {
+    db.run(move |conn| {
+        database::with_transaction(conn, |tx| {
+            ensure_folder(tx, parent_id)?;
+
+            let ordered = apply_order(sibling_folder_ids(tx, parent_id)?, &folder_ids)?;
+            set_folder_positions(tx, &ordered)
+        })
+    }).await
+}
+
+/// Moves a conversation into `folder_id`, or out of any folder when None,
+/// at `position` within it. Conversations in the trash can't be moved.
+#[command]
+pub async fn move_conversation(
+    conversation_id: i64,
+    folder_id: Option<i64>,
+    position: Option<usize>,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        database::with_transaction(conn, |tx| {
+            ensure_folder(tx, folder_id)?;
+
+            let (old_folder, deleted_at): (Option<i64>, Option<String>) = tx
+                .query_row(
+                    "SELECT folder_id, deleted_at FROM conversations WHERE id = ?1",
+                    [conversation_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()
+                .map_err(|e| RweError::database("Query error", e))?
+                .ok_or_else(|| RweError::NotFound(format!("Conversation {} not found", conversation_id)))?;
+            if deleted_at.is_some() {
+                return Err(RweError::Conflict(format!("Conversation {} is in the trash", conversation_id)));
+            }
+
+            tx.execute(
+                "UPDATE conversations SET folder_id = ?1 WHERE id = ?2",
+                rusqlite::params![folder_id, conversation_id],
+            ).map_err(|e| RweError::database("Update conversation error", e))?;
+
+            let siblings = insert_at(folder_conversation_ids(tx, folder_id)?, conversation_id, position);
+            set_conversation_positions(tx, &siblings)?;
+            if old_folder != folder_id {
+                set_conversation_positions(tx, &folder_conversation_ids(tx, old_folder)?)?;
+            }
+
+            Ok(true)
+        })
+    }).await
+}
+
+/// Reorders the conversations in `folder_id`. Conversations not listed keep
+/// their relative order after the listed ones.
+#[command]
+pub async fn reorder_conversations(
+    folder_id: Option<i64>,
+    conversation_ids: Vec<i64>,
+    db: State<'_, DbPool>,
+) -> Result<(), RweError> {
+    db.run(move |conn| {
+        database::with_transaction(conn, |tx| {
+            ensure_folder(tx, folder_id)?;
+
+            let ordered = apply_order(folder_conversation_ids(tx, folder_id)?, &conversation_ids)?;
+            set_conversation_positions(tx, &ordered)
+        })
+    }).await
+}
+
+fn delete(conn: &Connection, folder_id: i64, mode: FolderDeleteMode) -> Result<bool, RweError> {
+    database::with_transaction(conn, |tx| {
+        let parent_id = parent_of(tx, folder_id)?;
+        match mode {
+            FolderDeleteMode::MoveToParent => {
+                let mut folders = sibling_folder_ids(tx, parent_id)?;
+                folders.retain(|id| *id != folder_id);
+                folders.extend(sibling_folder_ids(tx, Some(folder_id))?);
+                tx.execute(
+                    "UPDATE folders SET parent_id = ?1 WHERE parent_id = ?2",
+                    rusqlite::params![parent_id, folder_id],
+                ).map_err(|e| RweError::database("Update folder error", e))?;
+                set_folder_positions(tx, &folders)?;
+
+                let mut conversations = folder_conversation_ids(tx, parent_id)?;
+                conversations.extend(folder_conversation_ids(tx, Some(folder_id))?);
+                tx.execute(
+                    "UPDATE conversations SET folder_id = ?1 WHERE folder_id = ?2",
+                    rusqlite::params![parent_id, folder_id],
+                ).map_err(|e| RweError::database("Update conversation error", e))?;
+                set_conversation_positions(tx, &conversations)?;
+
+                tx.execute("DELETE FROM folders WHERE id = ?1", [folder_id])
+                    .map_err(|e| RweError::database("Delete folder error", e))?;
+            }
+            FolderDeleteMode::DeleteContents => {
+                for id in subtree_ids(tx, folder_id)? {
+                    tx.execute(
+                        r#"
+                        UPDATE conversations SET
+                            folder_id = NULL,
+                            deleted_at = COALESCE(deleted_at, CURRENT_TIMESTAMP)
+                        WHERE folder_id = ?1
+                        "#,
+                        [id],
+                    ).map_err(|e| RweError::database("Trash conversation error", e))?;
+                    tx.execute("DELETE FROM folders WHERE id = ?1", [id])
+                        .map_err(|e| RweError::database("Delete folder error", e))?;
+                }
+                set_folder_positions(tx, &sibling_folder_ids(tx, parent_id)?)?;
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(true)
+}
+
+/// Deletes a folder. By default its subfolders and conversations move up to
+/// its parent; with `DeleteContents` the subtree is deleted and its
+/// conversations trashed.
+#[command]
+pub async fn delete_folder(
+    folder_id: i64,
+    mode: Option<FolderDeleteMode>,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| delete(conn, folder_id, mode.unwrap_or_default())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+
+    /// Work > (Clients > Acme, Notes), Home; with a conversation in each of
+    /// Work, Clients and Acme, two in Notes and one at the top level.
+    fn library() -> (Connection, [i64; 5]) {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        let work = create(&conn, "Work", None).unwrap();
+        let clients = create(&conn, "Clients", Some(work)).unwrap();
+        let acme = create(&conn, "Acme", Some(clients)).unwrap();
+        let notes = create(&conn, "Notes", Some(work)).unwrap();
+        let home = create(&conn, "Home", None).unwrap();
+        for (name, folder) in [("Plan", Some(work)), ("Leads", Some(clients)), ("Contract", Some(acme)),
+                               ("Ideas", Some(notes)), ("Todo", Some(notes)), ("Loose", None)] {
+            conn.execute("INSERT INTO conversations (name, folder_id) VALUES (?1, ?2)", rusqlite::params![name, folder]).unwrap();
+        }
+        (conn, [work, clients, acme, notes, home])
+    }
+
+    /// (name, direct count, total count) for every folder, depth first.
+    fn outline(conn: &Connection) -> Vec<(String, i64, i64)> {
+        fn walk(nodes: &[FolderNode], out: &mut Vec<(String, i64, i64)>) {
+            for node in nodes {
+                out.push((node.name.clone(), node.conversation_count, node.total_conversation_count));
+                walk(&node.children, out);
+            }
+        }
+        let mut out = Vec::new();
+        walk(&folder_tree(conn, None).unwrap(), &mut out);
+        out
+    }
+
+    fn in_folder(conn: &Connection, folder_id: Option<i64>) -> Vec<String> {
+        folder_conversation_ids(conn, folder_id)
+            .unwrap()
+            .into_iter()
+            .map(|id| conn.query_row("SELECT name FROM conversations WHERE id = ?1", [id], |row| row.get(0)).unwrap())
+            .collect()
+    }
+
+    fn row(name: &str, direct: i64, total: i64) -> (String, i64, i64) {
+        (name.to_string(), direct, total)
+    }
+
+    #[test]
+    fn counts_include_every_subfolder_and_skip_the_trash() {
+        let (conn, [work, clients, ..]) = library();
+        assert_eq!(outline(&conn), [
+            row("Work", 1, 5), row("Clients", 1, 2), row("Acme", 1, 1), row("Notes", 2, 2), row("Home", 0, 0),
+        ]);
+
+        conn.execute("UPDATE conversations SET deleted_at = CURRENT_TIMESTAMP WHERE name = 'Contract'", []).unwrap();
+        let tree = folder_tree(&conn, Some(clients)).unwrap();
+        assert_eq!((tree.len(), tree[0].total_conversation_count, tree[0].children[0].conversation_count), (1, 1, 0));
+        assert_eq!(folder_tree(&conn, Some(work)).unwrap()[0].total_conversation_count, 4);
+    }
+
+    #[test]
+    fn moving_a_subtree_keeps_its_contents_and_rejects_cycles() {
+        let (conn, [work, clients, acme, _, home]) = library();
+        assert!(move_to(&conn, clients, Some(home), None).unwrap());
+        assert_eq!(outline(&conn), [
+            row("Work", 1, 3), row("Notes", 2, 2), row("Home", 0, 2), row("Clients", 1, 2), row("Acme", 1, 1),
+        ]);
+
+        // Into itself, its child or its grandchild
+        for target in [clients, acme] {
+            assert!(matches!(move_to(&conn, clients, Some(target), None), Err(RweError::Conflict(_))));
+        }
+        assert!(matches!(move_to(&conn, home, Some(acme), None), Err(RweError::Conflict(_))));
+        assert!(matches!(move_to(&conn, work, Some(999), None), Err(RweError::NotFound(_))));
+
+        // To the top level, first among the roots
+        move_to(&conn, acme, None, Some(0)).unwrap();
+        assert_eq!(sibling_folder_ids(&conn, None).unwrap(), [acme, work, home]);
+        assert_eq!(parent_of(&conn, clients).unwrap(), Some(home));
+    }
+
+    #[test]
+    fn deleting_a_folder_moves_its_contents_to_the_parent() {
+        let (conn, [work, clients, acme, notes, home]) = library();
+        assert!(delete(&conn, clients, FolderDeleteMode::MoveToParent).unwrap());
+
+        assert!(matches!(parent_of(&conn, clients), Err(RweError::NotFound(_))));
+        assert_eq!(sibling_folder_ids(&conn, Some(work)).unwrap(), [notes, acme]);
+        assert_eq!(in_folder(&conn, Some(work)), ["Plan", "Leads"]);
+        assert_eq!(outline(&conn)[0], row("Work", 2, 5));
+
+        // A top-level folder empties into the top level
+        delete(&conn, work, FolderDeleteMode::MoveToParent).unwrap();
+        assert_eq!(sibling_folder_ids(&conn, None).unwrap(), [home, notes, acme]);
+        assert_eq!(in_folder(&conn, None), ["Loose", "Plan", "Leads"]);
+    }
+
+    #[test]
+    fn deleting_a_folder_with_its_contents_trashes_the_subtree() {
+        let (conn, [work, clients, acme, notes, home]) = library();
+        delete(&conn, work, FolderDeleteMode::DeleteContents).unwrap();
+
+        for id in [work, clients, acme, notes] {
+            assert!(matches!(parent_of(&conn, id), Err(RweError::NotFound(_))));
+        }
+        assert_eq!(outline(&conn), [row("Home", 0, 0)]);
+        assert_eq!(in_folder(&conn, None), ["Loose"]);
+        let trashed: i64 = conn
+            .query_row("SELECT COUNT(*) FROM conversations WHERE deleted_at IS NOT NULL AND folder_id IS NULL", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(trashed, 5);
+        assert_eq!(sibling_folder_ids(&conn, None).unwrap(), [home]);
+        let position: i64 = conn.query_row("SELECT position FROM folders WHERE id = ?1", [home], |row| row.get(0)).unwrap();
+        assert_eq!(position, 0);
+    }
+}
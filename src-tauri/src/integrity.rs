@@ -0,0 +1,348 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+use rusqlite::Connection;
+use crate::database_pool::DbPool;
+use crate::database;
+use crate::error::RweError;
+
+/// A dangling reference or out-of-date search row, with the statement that
+/// removes it. Foreign keys stop new ones from appearing, but databases
+/// written before they were enforced can still contain them.
+struct Check {
+    kind: &'static str,
+    count_sql: &'static str,
+    repair_sql: &'static str,
+}
+
+const CHECKS: &[Check] = &[
+    Check {
+        kind: "orphaned_messages",
+        count_sql: "SELECT COUNT(*) FROM messages WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+        repair_sql: "DELETE FROM messages WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+    },
+    Check {
+        kind: "orphaned_mindmaps",
+        count_sql: "SELECT COUNT(*) FROM mindmaps WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+        repair_sql: "DELETE FROM mindmaps WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+    },
+    Check {
+        kind: "orphaned_conversation_tags",
+        count_sql: r#"
+            SELECT COUNT(*) FROM conversation_tags
+            WHERE conversation_id NOT IN (SELECT id FROM conversations)
+               OR tag_id NOT IN (SELECT id FROM tags)
+        "#,
+        repair_sql: r#"
+            DELETE FROM conversation_tags
+            WHERE conversation_id NOT IN (SELECT id FROM conversations)
+               OR tag_id NOT IN (SELECT id FROM tags)
+        "#,
+    },
+    Check {
+        kind: "orphaned_embeddings",
+        count_sql: r#"
+            SELECT COUNT(*) FROM embeddings
+            WHERE conversation_id NOT IN (SELECT id FROM conversations)
+               OR (source_type = 'message' AND source_id NOT IN (SELECT id FROM messages))
+        "#,
+        repair_sql: r#"
+            DELETE FROM embeddings
+            WHERE conversation_id NOT IN (SELECT id FROM conversations)
+               OR (source_type = 'message' AND source_id NOT IN (SELECT id FROM messages))
+        "#,
+    },
+    Check {
+        kind: "dangling_conversation_folders",
+        count_sql: r#"
+            SELECT COUNT(*) FROM conversations
+            WHERE folder_id IS NOT NULL AND folder_id NOT IN (SELECT id FROM folders)
+        "#,
+        repair_sql: r#"
+            UPDATE conversations SET folder_id = NULL
+            WHERE folder_id IS NOT NULL AND folder_id NOT IN (SELECT id FROM folders)
+        "#,
+    },
+    Check {
+        kind: "dangling_folder_parents",
+        count_sql: r#"
+            SELECT COUNT(*) FROM folders
+            WHERE parent_id IS NOT NULL AND parent_id NOT IN (SELECT id FROM folders)
+        "#,
+        repair_sql: r#"
+            UPDATE folders SET parent_id = NULL
+            WHERE parent_id IS NOT NULL AND parent_id NOT IN (SELECT id FROM folders)
+        "#,
+    },
+    Check {
+        kind: "stale_message_search_entries",
+        count_sql: "SELECT COUNT(*) FROM messages_fts WHERE rowid NOT IN (SELECT id FROM messages)",
+        repair_sql: "DELETE FROM messages_fts WHERE rowid NOT IN (SELECT id FROM messages)",
+    },
+    Check {
+        kind: "missing_message_search_entries",
+        count_sql: "SELECT COUNT(*) FROM messages WHERE id NOT IN (SELECT rowid FROM messages_fts)",
+        repair_sql: r#"
+            INSERT INTO messages_fts (rowid, content)
+            SELECT id, content FROM messages WHERE id NOT IN (SELECT rowid FROM messages_fts)
+        "#,
+    },
+    Check {
+        kind: "stale_notes_search_entries",
+        count_sql: r#"
+            SELECT COUNT(*) FROM conversation_notes_fts
+            WHERE rowid NOT IN (SELECT id FROM conversations WHERE notes IS NOT NULL AND notes != '')
+        "#,
+        repair_sql: r#"
+            DELETE FROM conversation_notes_fts
+            WHERE rowid NOT IN (SELECT id FROM conversations WHERE notes IS NOT NULL AND notes != '')
+        "#,
+    },
+    Check {
+        kind: "missing_notes_search_entries",
+        count_sql: r#"
+            SELECT COUNT(*) FROM conversations
+            WHERE notes IS NOT NULL AND notes != ''
+              AND id NOT IN (SELECT rowid FROM conversation_notes_fts)
+        "#,
+        repair_sql: r#"
+            INSERT INTO conversation_notes_fts (rowid, notes)
+            SELECT id, notes FROM conversations
+            WHERE notes IS NOT NULL AND notes != ''
+              AND id NOT IN (SELECT rowid FROM conversation_notes_fts)
+        "#,
+    },
+    Check {
+        kind: "stale_summary_search_entries",
+        count_sql: r#"
+            SELECT COUNT(*) FROM conversation_summaries_fts
+            WHERE rowid NOT IN (SELECT id FROM conversations WHERE summary IS NOT NULL AND summary != '')
+        "#,
+        repair_sql: r#"
+            DELETE FROM conversation_summaries_fts
+            WHERE rowid NOT IN (SELECT id FROM conversations WHERE summary IS NOT NULL AND summary != '')
+        "#,
+    },
+    Check {
+        kind: "missing_summary_search_entries",
+        count_sql: r#"
+            SELECT COUNT(*) FROM conversations
+            WHERE summary IS NOT NULL AND summary != ''
+              AND id NOT IN (SELECT rowid FROM conversation_summaries_fts)
+        "#,
+        repair_sql: r#"
+            INSERT INTO conversation_summaries_fts (rowid, summary)
+            SELECT id, summary FROM conversations
+            WHERE summary IS NOT NULL AND summary != ''
+              AND id NOT IN (SELECT rowid FROM conversation_summaries_fts)
+        "#,
+    },
+    Check {
+        kind: "stale_name_search_entries",
+        count_sql: "SELECT COUNT(*) FROM conversation_names_fts WHERE rowid NOT IN (SELECT id FROM conversations)",
+        repair_sql: "DELETE FROM conversation_names_fts WHERE rowid NOT IN (SELECT id FROM conversations)",
+    },
+    Check {
+        kind: "missing_name_search_entries",
+        count_sql: "SELECT COUNT(*) FROM conversations WHERE id NOT IN (SELECT rowid FROM conversation_names_fts)",
+        repair_sql: r#"
+            INSERT INTO conversation_names_fts (rowid, name)
+            SELECT id, name FROM conversations WHERE id NOT IN (SELECT rowid FROM conversation_names_fts)
+        "#,
+    },
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    pub kind: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub rowid: Option<i64>,
+    pub parent: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// Problems reported by `PRAGMA integrity_check`. These are damage to the
+    /// file itself and can't be repaired here; restore a backup instead.
+    pub integrity_errors: Vec<String>,
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+    /// Only checks that found something are listed.
+    pub issues: Vec<IntegrityIssue>,
+    /// True when the issues listed were fixed by this call.
+    pub repaired: bool,
+}
+
+fn integrity_errors(conn: &Connection) -> Result<Vec<String>, RweError> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")
+        .map_err(|e| RweError::database("Prepare error", e))?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+
+    Ok(rows.into_iter().filter(|row| row != "ok").collect())
+}
+
+fn foreign_key_violations(conn: &Connection) -> Result<Vec<ForeignKeyViolation>, RweError> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")
+        .map_err(|e| RweError::database("Prepare error", e))?;
+    let violations = stmt.query_map([], |row| {
+        Ok(ForeignKeyViolation {
+            table: row.get(0)?,
+            rowid: row.get(1)?,
+            parent: row.get(2)?,
+        })
+    }).map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+
+    Ok(violations)
+}
+
+fn find_issues(conn: &Connection) -> Result<Vec<IntegrityIssue>, RweError> {
+    let mut issues = Vec::new();
+    for check in CHECKS {
+        let count: i64 = conn.query_row(check.count_sql, [], |row| row.get(0))
+            .map_err(|e| RweError::database(&format!("Integrity check error ({})", check.kind), e))?;
+        if count > 0 {
+            issues.push(IntegrityIssue { kind: check.kind.to_string(), count });
+        }
+    }
+    Ok(issues)
+}
+
+fn run_checks(conn: &Connection, repair: bool) -> Result<IntegrityReport, RweError> {
+    let integrity_errors = integrity_errors(conn)?;
+    let foreign_key_violations = foreign_key_violations(conn)?;
+    let issues = find_issues(conn)?;
+
+    let repaired = repair && !issues.is_empty();
+    if repaired {
+        database::with_transaction(conn, |tx| {
+            for check in CHECKS.iter().filter(|check| issues.iter().any(|issue| issue.kind == check.kind)) {
+                tx.execute(check.repair_sql, [])
+                    .map_err(|e| RweError::database(&format!("Integrity repair error ({})", check.kind), e))?;
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(IntegrityReport {
+        integrity_errors,
+        foreign_key_violations,
+        issues,
+        repaired,
+    })
+}
+
+/// Checks the database for corruption, foreign key violations, orphaned rows
+/// and search index drift. With `repair`, every fixable issue found is fixed
+/// in one transaction; the report still lists what was found.
+#[command]
+pub async fn check_integrity(
+    repair: Option<bool>,
+    db: State<'_, DbPool>,
+) -> Result<IntegrityReport, RweError> {
+    db.run(move |conn| run_checks(conn, repair.unwrap_or(false))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+
+    /// A database written before foreign keys were enforced: conversation 2
+    /// was deleted without its message, and a tag link and folder reference
+    /// point at rows that don't exist. The search tables have drifted too.
+    fn damaged() -> (Connection, i64) {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO conversations (id, name, notes) VALUES (1, 'Retries', 'ask about jitter'), (2, 'Scratch', NULL)",
+        ).unwrap();
+        let message = database::insert_message(&conn, 1, "user", "how do I retry", false).unwrap();
+        database::insert_message(&conn, 2, "user", "left behind", false).unwrap();
+
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute_batch(&format!(
+            r#"
+            DELETE FROM conversations WHERE id = 2;
+            INSERT INTO conversation_tags (conversation_id, tag_id) VALUES (1, 42);
+            UPDATE conversations SET folder_id = 77 WHERE id = 1;
+
+            INSERT INTO messages_fts (rowid, content) VALUES (500, 'ghost');
+            DELETE FROM messages_fts WHERE rowid = {message};
+            INSERT INTO conversation_names_fts (rowid, name) VALUES (600, 'Ghost');
+            DELETE FROM conversation_names_fts WHERE rowid = 1;
+            DELETE FROM conversation_notes_fts WHERE rowid = 1;
+            "#
+        )).unwrap();
+        (conn, message)
+    }
+
+    fn kinds(report: &IntegrityReport) -> Vec<(&str, i64)> {
+        report.issues.iter().map(|issue| (issue.kind.as_str(), issue.count)).collect()
+    }
+
+    fn matches(conn: &Connection, table: &str, query: &str) -> Vec<i64> {
+        conn.prepare(&format!("SELECT rowid FROM {table} WHERE {table} MATCH ?1 ORDER BY rowid"))
+            .unwrap()
+            .query_map([query], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn orphans_and_search_drift_are_reported_without_repair() {
+        let (conn, _) = damaged();
+        let report = run_checks(&conn, false).unwrap();
+
+        assert_eq!(kinds(&report), [
+            ("orphaned_messages", 1),
+            ("orphaned_conversation_tags", 1),
+            ("dangling_conversation_folders", 1),
+            ("stale_message_search_entries", 1),
+            ("missing_message_search_entries", 1),
+            ("missing_notes_search_entries", 1),
+            ("stale_name_search_entries", 1),
+            ("missing_name_search_entries", 1),
+        ]);
+        assert!(!report.repaired);
+        assert!(report.integrity_errors.is_empty());
+        let mut tables: Vec<&str> = report.foreign_key_violations.iter().map(|v| v.table.as_str()).collect();
+        tables.sort();
+        assert_eq!(tables, ["conversation_tags", "conversations", "messages"]);
+
+        // Reporting changes nothing
+        assert_eq!(kinds(&run_checks(&conn, false).unwrap()), kinds(&report));
+    }
+
+    #[test]
+    fn repair_removes_orphans_and_rebuilds_search_entries() {
+        let (conn, message) = damaged();
+        let report = run_checks(&conn, true).unwrap();
+        assert!(report.repaired);
+        assert_eq!(report.issues.len(), 8);
+
+        let after = run_checks(&conn, false).unwrap();
+        assert!(after.issues.is_empty(), "{:?}", after.issues);
+        assert!(after.foreign_key_violations.is_empty());
+        assert!(!after.repaired);
+
+        let folder: Option<i64> = conn.query_row("SELECT folder_id FROM conversations WHERE id = 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(folder, None);
+        assert_eq!(matches(&conn, "messages_fts", "retry"), [message]);
+        assert!(matches(&conn, "messages_fts", "ghost OR behind").is_empty());
+        assert_eq!(matches(&conn, "conversation_names_fts", "retries"), [1]);
+        assert!(matches(&conn, "conversation_names_fts", "ghost").is_empty());
+        assert_eq!(matches(&conn, "conversation_notes_fts", "jitter"), [1]);
+
+        // A clean database has nothing to repair
+        assert!(!run_checks(&conn, true).unwrap().repaired);
+    }
+}
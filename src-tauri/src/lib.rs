@@ -1,36 +1,97 @@
 mod search;
+mod search_query;
+mod search_snippet;
+mod search_fuzzy;
+mod embeddings;
+mod llm;
+mod llm_gemini;
+mod llm_openai;
+mod llm_ollama;
+mod secrets;
+mod encryption;
+mod tags;
+mod folders;
+mod branches;
+mod whiteboards;
+mod mindmaps;
+mod mindmap_schema;
+mod mindmap_export;
+mod integrity;
 mod database;
+mod error;
+pub mod database_pool;
 mod migrations;
 mod updater;
 
 use rusqlite::Connection;
-use std::sync::Mutex;
-use tauri::Manager;
-use migrations::MigrationRunner;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager};
+use database_pool::{DbPool, SqlitePool};
+use migrations::MigrationState;
+use embeddings::{EmbeddingIndexer, HashingEmbedder, SharedEmbedder};
+use llm::ChatStreams;
+use secrets::SecretStore;
+use encryption::{DatabaseEncryption, EncryptionStatus};
+use error::RweError;
 
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-fn create_database_connection() -> Result<Connection, rusqlite::Error> {
+fn database_path() -> PathBuf {
     let app_data_dir = std::env::var("APPDATA")
         .or_else(|_| std::env::var("HOME"))
         .unwrap_or_else(|_| ".".to_string());
     
-    let db_path = std::path::Path::new(&app_data_dir).join("rwe_data").join("main.db");
-    
+    std::path::Path::new(&app_data_dir).join("rwe_data").join("main.db")
+}
+
+/// Opens the connection pool on main.db. An encrypted database can't be read
+/// until `unlock_database` supplies the passphrase, so in that case no pool is
+/// opened and migrations wait for the unlock.
+fn open_database_pool(db_path: &std::path::Path) -> Result<(Option<SqlitePool>, EncryptionStatus), String> {
     if let Some(parent) = db_path.parent() {
         std::fs::create_dir_all(parent).ok();
     }
     
-    let conn = Connection::open(db_path)?;
-    
-    let migration_runner = MigrationRunner::new();
-    migration_runner.run_migrations(&conn)
-        .expect("Failed to run database migrations");
+    let conn = Connection::open(db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+    if !encryption::is_readable(&conn) {
+        return Ok((None, EncryptionStatus { encrypted: true, locked: true }));
+    }
+    drop(conn);
     
-    Ok(conn)
+    let pool = DbPool::open(db_path, None)?;
+    Ok((Some(pool), EncryptionStatus { encrypted: false, locked: false }))
+}
+
+/// Runs pending migrations off the setup thread so the window can come up and
+/// show their progress; commands answer `Locked` until the migrated pool is
+/// handed over. A failed migration leaves the app without a database and the
+/// failure in `MigrationState` for the frontend to report.
+fn migrate_in_background(app: AppHandle, pool: SqlitePool, db_path: PathBuf) {
+    tauri::async_runtime::spawn_blocking(move || {
+        let pool = match migrations::migrate_pool(pool, &db_path, None, &app) {
+            Ok(pool) => pool,
+            Err(failure) => {
+                eprintln!("Migration error: {}", failure.message);
+                if let Err(e) = app.state::<MigrationState>().record(failure) {
+                    eprintln!("{}", e);
+                }
+                return;
+            }
+        };
+        let keys = pool.get()
+            .map_err(|e| RweError::Storage(format!("Connection error: {}", e)))
+            .and_then(|conn| secrets::migrate_plaintext_keys(&conn, &app.state::<SecretStore>()));
+        if let Err(e) = keys {
+            eprintln!("Failed to encrypt stored API keys: {}", e);
+        }
+        if let Err(e) = app.state::<DbPool>().replace(Some(pool)) {
+            eprintln!("{}", e);
+        }
+    });
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -40,14 +101,36 @@ pub fn run() {
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
-            let conn = create_database_connection()
-                .expect("Failed to create database connection");
-            app.manage(Mutex::new(conn));
+            let db_path = database_path();
+            let (pool, status) = open_database_pool(&db_path)?;
+            let secret_store = SecretStore::load_or_create(&updater::get_rwe_data_dir().join("secret.key"))?;
+            app.manage(DbPool::new(None));
+            app.manage(secret_store);
+            app.manage(DatabaseEncryption::new(db_path.clone(), status));
+            app.manage::<SharedEmbedder>(Arc::new(HashingEmbedder::default()));
+            app.manage(EmbeddingIndexer::default());
+            app.manage(ChatStreams::default());
+            app.manage(MigrationState::default());
+            if let Some(pool) = pool {
+                migrate_in_background(app.handle().clone(), pool, db_path);
+            }
+            // Catches up on rows written before this run once the database is available
+            app.state::<EmbeddingIndexer>().schedule();
+            tauri::async_runtime::spawn(embeddings::run_indexer(app.handle().clone()));
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             greet,
             search::search_content,
+            search::suggest_search_terms,
+            search::semantic_search,
+            llm::send_chat_message,
+            llm::generate_completion,
+            llm::stream_chat_message,
+            llm::cancel_chat_stream,
+            llm::get_llm_config,
+            llm::set_llm_config,
+            llm::list_models,
             database::init_database,
             database::create_conversation,
             database::get_conversations,
@@ -55,17 +138,68 @@ pub fn run() {
             database::get_conversations_count,
             database::get_conversation,
             database::delete_conversation,
+            database::list_trash,
+            database::restore_conversation,
+            database::purge_conversation,
+            database::purge_trash,
             database::save_message,
             database::get_messages,
+            database::edit_message,
+            database::get_message_versions,
             database::update_conversation_notes,
             database::update_conversation_summary,
-            database::get_mindmap_data,
-            database::save_mindmap_data,
+            mindmaps::get_mindmap_data,
+            mindmaps::list_mindmaps,
+            mindmaps::save_mindmap_data,
+            mindmaps::delete_mindmap,
+            mindmaps::list_mindmap_versions,
+            mindmaps::get_mindmap_version,
+            mindmaps::restore_mindmap_version,
+            mindmaps::export_mindmap,
+            whiteboards::get_whiteboard_data,
+            whiteboards::save_whiteboard_data,
+            whiteboards::import_local_whiteboards,
+            whiteboards::get_whiteboard_state,
+            whiteboards::append_whiteboard_ops,
+            whiteboards::undo_whiteboard,
+            whiteboards::redo_whiteboard,
+            whiteboards::compact_whiteboard,
             database::get_conversation_analytics,
             database::backup_database,
             database::get_database_info,
-            database::get_api_key,
             database::set_api_key,
+            encryption::get_encryption_status,
+            encryption::unlock_database,
+            encryption::enable_encryption,
+            encryption::change_passphrase,
+            secrets::list_secrets,
+            secrets::set_secret,
+            secrets::delete_secret,
+            secrets::activate_secret,
+            tags::get_tags,
+            tags::get_conversation_tags,
+            tags::create_tag,
+            tags::rename_tag,
+            tags::set_tag_color,
+            tags::merge_tags,
+            tags::delete_tag,
+            tags::tag_conversation,
+            tags::untag_conversation,
+            folders::get_folder_tree,
+            folders::get_folder_conversations,
+            folders::create_folder,
+            folders::rename_folder,
+            folders::move_folder,
+            folders::reorder_folders,
+            folders::move_conversation,
+            folders::reorder_conversations,
+            folders::delete_folder,
+            branches::get_branches,
+            branches::fork_conversation,
+            branches::switch_branch,
+            integrity::check_integrity,
+            migrations::get_migration_status,
+            migrations::migrate_database,
             updater::get_app_version,
             updater::open_url,
             updater::get_data_directory,
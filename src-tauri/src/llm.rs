@@ -0,0 +1,873 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, AppHandle, Emitter, State};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::watch;
+use tauri_plugin_http::reqwest;
+use crate::database_pool::DbPool;
+use crate::database::{self, Message, ReplyMetadata};
+use crate::embeddings::EmbeddingIndexer;
+use crate::error::RweError;
+use crate::llm_gemini::GeminiProvider;
+use crate::llm_ollama::OllamaProvider;
+use crate::llm_openai::OpenAiProvider;
+use crate::secrets::{self, SecretStore};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Flips to `true` when the frontend cancels a stream.
+pub type CancelSignal = watch::Receiver<bool>;
+
+/// Receives each chunk of text as a provider streams it.
+pub type DeltaSink<'a> = dyn FnMut(&str) + Send + 'a;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    User,
+    // Stored as 'ai' in `messages.role`
+    #[serde(alias = "ai", alias = "model")]
+    Assistant,
+}
+
+impl ChatRole {
+    pub fn from_db(role: &str) -> Self {
+        match role {
+            "user" => ChatRole::User,
+            _ => ChatRole::Assistant,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub messages: Vec<ChatMessage>,
+    pub temperature: f32,
+    pub max_output_tokens: u32,
+}
+
+impl ChatRequest {
+    pub fn new(messages: Vec<ChatMessage>, config: &LlmConfig) -> Self {
+        Self {
+            messages,
+            temperature: config.temperature,
+            max_output_tokens: config.max_output_tokens,
+        }
+    }
+
+    pub fn from_history(history: &[Message], config: &LlmConfig) -> Self {
+        Self::new(
+            history
+                .iter()
+                .map(|m| ChatMessage { role: ChatRole::from_db(&m.role), content: m.content.clone() })
+                .collect(),
+            config,
+        )
+    }
+}
+
+/// Token counts for one request, as reported by the provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatReply {
+    pub content: String,
+    pub model: String,
+    pub usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEnd {
+    /// `usage` is set when the provider reported token counts for the stream.
+    Completed { usage: Option<TokenUsage> },
+    Cancelled,
+}
+
+/// A chat completion backend. Implementations own their credentials, so API
+/// keys are read from the database on the Rust side and never reach the webview.
+/// Requests that never get an answer fail with `Network`, error statuses as
+/// `check_status` classifies them, and replies that don't parse with `Internal`.
+pub trait ChatProvider: Send + Sync {
+    /// The model requests go to, recorded with the replies it writes.
+    fn model(&self) -> &str;
+
+    fn send<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<ChatReply, RweError>>;
+
+    /// Model ids the backend reports as available for chat.
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, RweError>>;
+
+    /// Streams the reply into `on_delta`. Providers without a streaming API
+    /// fall back to delivering the whole reply as a single delta.
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_delta: &'a mut DeltaSink<'a>,
+        mut cancel: CancelSignal,
+    ) -> BoxFuture<'a, Result<StreamEnd, RweError>> {
+        Box::pin(async move {
+            tokio::select! {
+                reply = self.send(request) => {
+                    let reply = reply?;
+                    on_delta(&reply.content);
+                    Ok(StreamEnd::Completed { usage: reply.usage })
+                }
+                _ = cancelled(&mut cancel) => Ok(StreamEnd::Cancelled),
+            }
+        })
+    }
+}
+
+/// Resolves once the stream is cancelled; never resolves if it can't be.
+pub async fn cancelled(cancel: &mut CancelSignal) {
+    if cancel.wait_for(|cancelled| *cancelled).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Incremental decoder for `text/event-stream` bodies. Yields the `data`
+/// payload of every complete event; chunk boundaries may fall anywhere,
+/// including inside a multi-byte character.
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            self.handle_line(&String::from_utf8_lossy(&line), &mut events);
+        }
+        events
+    }
+
+    /// Flushes an event left open when the body ended without a blank line.
+    pub fn finish(&mut self) -> Vec<String> {
+        let mut events = Vec::new();
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            self.handle_line(&String::from_utf8_lossy(&line), &mut events);
+        }
+        self.handle_line("", &mut events);
+        events
+    }
+
+    fn handle_line(&mut self, line: &str, events: &mut Vec<String>) {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() {
+            if !self.data.is_empty() {
+                events.push(self.data.join("\n"));
+                self.data.clear();
+            }
+        } else if let Some(value) = line.strip_prefix("data:") {
+            self.data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+        }
+    }
+}
+
+/// Cancellation handles for in-flight streams, keyed by the request id the
+/// frontend picked.
+#[derive(Default)]
+pub struct ChatStreams {
+    active: Mutex<HashMap<String, watch::Sender<bool>>>,
+}
+
+impl ChatStreams {
+    fn register(&self, request_id: &str) -> Result<CancelSignal, RweError> {
+        let mut active = self.active.lock().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))?;
+        if active.contains_key(request_id) {
+            return Err(RweError::Conflict(format!("Stream {} is already running", request_id)));
+        }
+        let (sender, receiver) = watch::channel(false);
+        active.insert(request_id.to_string(), sender);
+        Ok(receiver)
+    }
+
+    fn finish(&self, request_id: &str) {
+        if let Ok(mut active) = self.active.lock() {
+            active.remove(request_id);
+        }
+    }
+
+    fn cancel(&self, request_id: &str) -> Result<bool, RweError> {
+        let active = self.active.lock().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))?;
+        Ok(match active.get(request_id) {
+            Some(sender) => sender.send(true).is_ok(),
+            None => false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatDelta {
+    pub request_id: String,
+    pub conversation_id: i64,
+    pub delta: String,
+}
+
+/// Turns a non-success response into `"{label} API error: {status} - {body}"`,
+/// the format the frontend matches on for 401 and 429 handling. A rejected
+/// key or request is `Validation` and an unknown model `NotFound`; rate
+/// limits and server errors stay `Network`, since retrying may help.
+pub async fn check_status(response: reqwest::Response, label: &str) -> Result<reqwest::Response, RweError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.bytes().await.unwrap_or_default();
+    let message = format!(
+        "{} API error: {} - {}",
+        label,
+        status.as_u16(),
+        String::from_utf8_lossy(&body)
+    );
+    Err(match status.as_u16() {
+        404 => RweError::NotFound(message),
+        429 => RweError::Network(message),
+        400..=499 => RweError::Validation(message),
+        _ => RweError::Network(message),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Gemini,
+    #[serde(rename = "openai")]
+    OpenAi,
+    Ollama,
+}
+
+impl ProviderKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::Gemini => "gemini",
+            ProviderKind::OpenAi => "openai",
+            ProviderKind::Ollama => "ollama",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "gemini" => Some(ProviderKind::Gemini),
+            "openai" => Some(ProviderKind::OpenAi),
+            "ollama" => Some(ProviderKind::Ollama),
+            _ => None,
+        }
+    }
+
+    /// Secrets provider whose active secret is the API key. Ollama has none.
+    fn secret_provider(&self) -> Option<&'static str> {
+        match self {
+            ProviderKind::Gemini => Some("gemini"),
+            ProviderKind::OpenAi => Some("openai"),
+            ProviderKind::Ollama => None,
+        }
+    }
+}
+
+/// Which backend chats go to and how, stored as `llm_*` rows in
+/// `user_preferences`. `None` for `base_url` or `model` means the provider's default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmConfig {
+    pub provider: ProviderKind,
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub temperature: f32,
+    pub max_output_tokens: u32,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            provider: ProviderKind::Gemini,
+            base_url: None,
+            model: None,
+            temperature: 0.7,
+            max_output_tokens: 1000,
+        }
+    }
+}
+
+const PROVIDER_PREFERENCE: &str = "llm_provider";
+const BASE_URL_PREFERENCE: &str = "llm_base_url";
+const MODEL_PREFERENCE: &str = "llm_model";
+const TEMPERATURE_PREFERENCE: &str = "llm_temperature";
+const MAX_TOKENS_PREFERENCE: &str = "llm_max_output_tokens";
+
+fn get_preference(conn: &Connection, key: &str) -> Result<Option<String>, RweError> {
+    match conn.query_row(
+        "SELECT value FROM user_preferences WHERE key = ?1",
+        [key],
+        |row| row.get::<_, Option<String>>(0),
+    ) {
+        Ok(value) => Ok(value.filter(|v| !v.trim().is_empty())),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(RweError::database(&format!("Failed to get preference {}", key), e)),
+    }
+}
+
+fn set_preference(conn: &Connection, key: &str, value: Option<&str>) -> Result<(), RweError> {
+    conn.execute(
+        r#"
+        INSERT INTO user_preferences (key, value, updated_at)
+        VALUES (?1, ?2, CURRENT_TIMESTAMP)
+        ON CONFLICT(key) DO UPDATE SET
+            value = excluded.value,
+            updated_at = excluded.updated_at
+        "#,
+        rusqlite::params![key, value],
+    ).map_err(|e| RweError::database(&format!("Failed to set preference {}", key), e))?;
+    Ok(())
+}
+
+impl LlmConfig {
+    pub fn load(conn: &Connection) -> Result<Self, RweError> {
+        let defaults = Self::default();
+        Ok(Self {
+            provider: get_preference(conn, PROVIDER_PREFERENCE)?
+                .and_then(|v| ProviderKind::parse(&v))
+                .unwrap_or(defaults.provider),
+            base_url: get_preference(conn, BASE_URL_PREFERENCE)?,
+            model: get_preference(conn, MODEL_PREFERENCE)?,
+            temperature: get_preference(conn, TEMPERATURE_PREFERENCE)?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.temperature),
+            max_output_tokens: get_preference(conn, MAX_TOKENS_PREFERENCE)?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.max_output_tokens),
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), RweError> {
+        if !(0.0..=2.0).contains(&self.temperature) {
+            return Err(RweError::Validation("Temperature must be between 0 and 2".to_string()));
+        }
+        if self.max_output_tokens == 0 {
+            return Err(RweError::Validation("Max output tokens must be greater than 0".to_string()));
+        }
+        if let Some(url) = &self.base_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(RweError::Validation(format!("Base URL must start with http:// or https://: {}", url)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, conn: &Connection) -> Result<(), RweError> {
+        self.validate()?;
+        database::with_transaction(conn, |tx| {
+            set_preference(tx, PROVIDER_PREFERENCE, Some(self.provider.as_str()))?;
+            set_preference(tx, BASE_URL_PREFERENCE, self.base_url.as_deref())?;
+            set_preference(tx, MODEL_PREFERENCE, self.model.as_deref())?;
+            set_preference(tx, TEMPERATURE_PREFERENCE, Some(&self.temperature.to_string()))?;
+            set_preference(tx, MAX_TOKENS_PREFERENCE, Some(&self.max_output_tokens.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn has_api_key(&self, conn: &Connection) -> Result<bool, RweError> {
+        match self.provider.secret_provider() {
+            Some(provider) => secrets::has_active(conn, provider),
+            None => Ok(false),
+        }
+    }
+
+    /// Builds the configured provider. Gemini needs an API key; the key is
+    /// optional for OpenAI-compatible servers, since local ones ignore it.
+    pub fn build_provider(&self, conn: &Connection, store: &SecretStore) -> Result<Box<dyn ChatProvider>, RweError> {
+        self.validate()?;
+        let base_url = self.base_url.as_deref().map(|url| url.trim_end_matches('/').to_string());
+        let api_key = match self.provider.secret_provider() {
+            Some(provider) => secrets::get_active(conn, store, provider)?,
+            None => None,
+        };
+
+        Ok(match self.provider {
+            ProviderKind::Gemini => {
+                let api_key = api_key.ok_or_else(|| RweError::Validation("API key not configured".to_string()))?;
+                Box::new(GeminiProvider::new(api_key, base_url, self.model.clone()))
+            }
+            ProviderKind::OpenAi => Box::new(OpenAiProvider::new(api_key, base_url, self.model.clone())),
+            ProviderKind::Ollama => Box::new(OllamaProvider::new(base_url, self.model.clone())),
+        })
+    }
+}
+
+/// The provider configured in `user_preferences`.
+pub fn load_provider(conn: &Connection, store: &SecretStore) -> Result<(Box<dyn ChatProvider>, LlmConfig), RweError> {
+    let config = LlmConfig::load(conn)?;
+    let provider = config.build_provider(conn, store)?;
+    Ok((provider, config))
+}
+
+async fn provider_from_state(
+    db: &State<'_, DbPool>,
+    secrets: &State<'_, SecretStore>,
+) -> Result<(Box<dyn ChatProvider>, LlmConfig), RweError> {
+    let secrets = secrets.inner().clone();
+    db.run(move |conn| load_provider(conn, &secrets)).await
+}
+
+/// Saves `content` as a user message, sends the conversation so far to the
+/// configured provider and persists the reply, which is returned.
+#[command]
+pub async fn send_chat_message(
+    conversation_id: i64,
+    content: String,
+    db: State<'_, DbPool>,
+    secrets: State<'_, SecretStore>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<Message, RweError> {
+    let (provider, config) = provider_from_state(&db, &secrets).await?;
+
+    database::save_message(conversation_id, "user".to_string(), content, db.clone(), indexer.clone()).await?;
+    let history = database::get_messages(conversation_id, db.clone()).await?;
+
+    let started = Instant::now();
+    let reply = provider.send(&ChatRequest::from_history(&history, &config)).await?;
+    let metadata = reply_metadata(reply.model, reply.usage, started);
+
+    let message = db.run(move |conn| {
+        let message_id = database::insert_message_with_metadata(conn, conversation_id, "ai", &reply.content, false, &metadata)?;
+        database::load_message(conn, message_id)
+    }).await?;
+    indexer.schedule();
+    Ok(message)
+}
+
+fn reply_metadata(model: String, usage: Option<TokenUsage>, started: Instant) -> ReplyMetadata {
+    ReplyMetadata {
+        model: Some(model),
+        prompt_tokens: usage.map(|usage| usage.prompt_tokens),
+        completion_tokens: usage.map(|usage| usage.completion_tokens),
+        latency_ms: Some(started.elapsed().as_millis() as u64),
+    }
+}
+
+/// One-off completion that isn't stored, e.g. for generating summaries.
+#[command]
+pub async fn generate_completion(
+    messages: Vec<ChatMessage>,
+    db: State<'_, DbPool>,
+    secrets: State<'_, SecretStore>,
+) -> Result<String, RweError> {
+    let (provider, config) = provider_from_state(&db, &secrets).await?;
+    let reply = provider.send(&ChatRequest::new(messages, &config)).await?;
+    Ok(reply.content)
+}
+
+/// Streaming variant of `send_chat_message`. Text is emitted as `chat://delta`
+/// events while it arrives; the reply is stored once the stream completes, or
+/// with `partial` set if it was cancelled or failed midway. Returns `None` when
+/// cancelled before any text arrived.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_chat_message(
+    request_id: String,
+    conversation_id: i64,
+    content: String,
+    app: AppHandle,
+    db: State<'_, DbPool>,
+    streams: State<'_, ChatStreams>,
+    secrets: State<'_, SecretStore>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<Option<Message>, RweError> {
+    // Registered before anything else, so a cancel that arrives while the
+    // provider is still being set up isn't lost
+    let cancel = streams.register(&request_id)?;
+
+    let emit = |delta: &str| {
+        let event = ChatDelta {
+            request_id: request_id.clone(),
+            conversation_id,
+            delta: delta.to_string(),
+        };
+        if let Err(e) = app.emit("chat://delta", event) {
+            eprintln!("Failed to emit chat delta: {}", e);
+        }
+    };
+    let result = match provider_from_state(&db, &secrets).await {
+        Ok((provider, config)) => stream_reply(provider.as_ref(), &config, conversation_id, content, &db, cancel, &emit).await,
+        Err(e) => Err(e),
+    };
+
+    streams.finish(&request_id);
+    indexer.schedule();
+    result
+}
+
+async fn stream_reply(
+    provider: &dyn ChatProvider,
+    config: &LlmConfig,
+    conversation_id: i64,
+    content: String,
+    db: &DbPool,
+    cancel: CancelSignal,
+    emit: &(dyn Fn(&str) + Sync),
+) -> Result<Option<Message>, RweError> {
+    db.run(move |conn| database::insert_message(conn, conversation_id, "user", &content, false)).await?;
+    let history = db.run(move |conn| database::load_messages(conn, conversation_id)).await?;
+    let request = ChatRequest::from_history(&history, config);
+
+    let started = Instant::now();
+    let mut reply = String::new();
+    let outcome = {
+        let mut on_delta = |delta: &str| {
+            reply.push_str(delta);
+            emit(delta);
+        };
+        provider.stream(&request, &mut on_delta, cancel).await
+    };
+
+    let (partial, usage) = match &outcome {
+        Ok(StreamEnd::Completed { usage }) => (false, *usage),
+        Ok(StreamEnd::Cancelled) | Err(_) => (true, None),
+    };
+    if reply.is_empty() {
+        return outcome.map(|_| None);
+    }
+    let metadata = reply_metadata(provider.model().to_string(), usage, started);
+
+    db.run(move |conn| {
+        let message_id = database::insert_message_with_metadata(conn, conversation_id, "ai", &reply, partial, &metadata)?;
+        outcome?;
+        database::load_message(conn, message_id).map(Some)
+    }).await
+}
+
+#[command]
+pub async fn cancel_chat_stream(
+    request_id: String,
+    streams: State<'_, ChatStreams>,
+) -> Result<bool, RweError> {
+    streams.cancel(&request_id)
+}
+
+#[derive(Debug, Serialize)]
+pub struct LlmStatus {
+    pub config: LlmConfig,
+    pub api_key_configured: bool,
+    pub ready: bool,
+}
+
+#[command]
+pub async fn get_llm_config(
+    db: State<'_, DbPool>,
+    secrets: State<'_, SecretStore>,
+) -> Result<LlmStatus, RweError> {
+    let secrets = secrets.inner().clone();
+    db.run(move |conn| {
+        let config = LlmConfig::load(conn)?;
+        let api_key_configured = config.has_api_key(conn)?;
+        let ready = config.build_provider(conn, &secrets).is_ok();
+        Ok(LlmStatus { config, api_key_configured, ready })
+    }).await
+}
+
+/// Saves the provider settings. `api_key`, when given, replaces the active
+/// secret of the selected provider; an empty string removes it.
+#[command]
+pub async fn set_llm_config(
+    config: LlmConfig,
+    api_key: Option<String>,
+    db: State<'_, DbPool>,
+    secrets: State<'_, SecretStore>,
+) -> Result<(), RweError> {
+    let secrets = secrets.inner().clone();
+    db.run(move |conn| {
+        database::with_transaction(conn, |tx| {
+            config.save(tx)?;
+
+            if let (Some(api_key), Some(provider)) = (api_key, config.provider.secret_provider()) {
+                match api_key.trim() {
+                    "" => secrets::delete_active(tx, provider)?,
+                    api_key => secrets::put_active(tx, &secrets, provider, api_key)?,
+                }
+            }
+
+            Ok(())
+        })
+    }).await
+}
+
+/// Models offered by the configured provider, or by `config` when given so
+/// the settings dialog can probe a server before saving it.
+#[command]
+pub async fn list_models(
+    config: Option<LlmConfig>,
+    db: State<'_, DbPool>,
+    secrets: State<'_, SecretStore>,
+) -> Result<Vec<String>, RweError> {
+    let secrets = secrets.inner().clone();
+    let provider = db.run(move |conn| {
+        let config = match config {
+            Some(config) => config,
+            None => LlmConfig::load(conn)?,
+        };
+        config.build_provider(conn, &secrets)
+    }).await?;
+    let mut models = provider.list_models().await?;
+    models.sort();
+    models.dedup();
+    Ok(models)
+}
+
+/// A minimal HTTP server for provider tests. Each route answers with a fixed
+/// status and body, written as separate chunked-encoding frames so clients
+/// see the same chunk boundaries a real stream would produce.
+#[cfg(test)]
+pub(crate) mod mock_server {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    pub struct Route {
+        pub method: &'static str,
+        pub path: &'static str,
+        pub status: u16,
+        pub content_type: &'static str,
+        pub chunks: Vec<Vec<u8>>,
+    }
+
+    impl Route {
+        pub fn new(method: &'static str, path: &'static str, status: u16, content_type: &'static str, chunks: &[&str]) -> Self {
+            Self {
+                method,
+                path,
+                status,
+                content_type,
+                chunks: chunks.iter().map(|c| c.as_bytes().to_vec()).collect(),
+            }
+        }
+    }
+
+    /// A request as the server received it: request line, headers and body.
+    pub struct Recorded {
+        pub head: String,
+        pub body: String,
+    }
+
+    pub struct MockServer {
+        pub base_url: String,
+        pub requests: Arc<Mutex<Vec<Recorded>>>,
+    }
+
+    pub async fn serve(routes: Vec<Route>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let routes = Arc::new(routes);
+        {
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    tokio::spawn(respond(socket, routes.clone(), requests.clone()));
+                }
+            });
+        }
+        MockServer { base_url, requests }
+    }
+
+    async fn respond(mut socket: TcpStream, routes: Arc<Vec<Route>>, requests: Arc<Mutex<Vec<Recorded>>>) {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 4096];
+        let head_end = loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            if n == 0 {
+                return;
+            }
+            received.extend_from_slice(&buf[..n]);
+            if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+        };
+        let head = String::from_utf8_lossy(&received[..head_end]).to_string();
+        let length = head
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse::<usize>().ok())?
+            })
+            .unwrap_or(0);
+        while received.len() < head_end + length {
+            let n = socket.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..n]);
+        }
+        let body = String::from_utf8_lossy(&received[head_end..]).to_string();
+
+        let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+        let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+        let route = routes.iter().find(|r| r.method == method && r.path == path);
+        requests.lock().unwrap().push(Recorded { head: head.clone(), body });
+
+        let Some(route) = route else {
+            let _ = socket
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await;
+            return;
+        };
+        let status = format!(
+            "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+            route.status, route.content_type
+        );
+        socket.write_all(status.as_bytes()).await.unwrap();
+        for chunk in &route.chunks {
+            socket.write_all(format!("{:x}\r\n", chunk.len()).as_bytes()).await.unwrap();
+            socket.write_all(chunk).await.unwrap();
+            socket.write_all(b"\r\n").await.unwrap();
+            socket.flush().await.unwrap();
+            // Give the client a chance to read each frame on its own
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let _ = socket.write_all(b"0\r\n\r\n").await;
+        let _ = socket.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+    use std::path::PathBuf;
+
+    fn decode(chunks: &[&str]) -> Vec<String> {
+        let mut decoder = SseDecoder::default();
+        let mut events: Vec<String> = chunks.iter().flat_map(|chunk| decoder.push(chunk.as_bytes())).collect();
+        events.extend(decoder.finish());
+        events
+    }
+
+    #[test]
+    fn sse_lines_split_inside_a_crlf() {
+        assert_eq!(decode(&["data: one\r", "\n\r", "\ndata: two\r\n", "\r\n"]), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn sse_data_lines_of_one_event_are_joined() {
+        let events = decode(&[": keep-alive\n", "event: message\ndata: first\ndata:second\nid: 7\n\n"]);
+        assert_eq!(events, vec!["first\nsecond"]);
+    }
+
+    #[test]
+    fn sse_done_marker_is_passed_through_even_without_a_blank_line() {
+        assert_eq!(decode(&["data: {\"a\":1}\n\n", "data: [DONE]"]), vec!["{\"a\":1}", "[DONE]"]);
+    }
+
+    /// Streams `deltas`, then waits until the stream is cancelled.
+    struct Stalling {
+        deltas: Vec<&'static str>,
+    }
+
+    impl ChatProvider for Stalling {
+        fn model(&self) -> &str {
+            "stalling"
+        }
+
+        fn send<'a>(&'a self, _request: &'a ChatRequest) -> BoxFuture<'a, Result<ChatReply, RweError>> {
+            Box::pin(std::future::pending())
+        }
+
+        fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, RweError>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn stream<'a>(
+            &'a self,
+            _request: &'a ChatRequest,
+            on_delta: &'a mut DeltaSink<'a>,
+            mut cancel: CancelSignal,
+        ) -> BoxFuture<'a, Result<StreamEnd, RweError>> {
+            Box::pin(async move {
+                for delta in &self.deltas {
+                    on_delta(delta);
+                }
+                cancelled(&mut cancel).await;
+                Ok(StreamEnd::Cancelled)
+            })
+        }
+    }
+
+    fn conversation(name: &str) -> (PathBuf, DbPool, i64) {
+        let dir = std::env::temp_dir().join(format!("rwe_llm_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.db");
+        let db = DbPool::new(Some(DbPool::open(&path, None).unwrap()));
+        let conn = db.get().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO conversations (name) VALUES ('Chat')", []).unwrap();
+        let conversation_id = conn.last_insert_rowid();
+        drop(conn);
+        (path, db, conversation_id)
+    }
+
+    #[tokio::test]
+    async fn cancelled_stream_keeps_the_partial_reply() {
+        let (path, db, conversation_id) = conversation("partial");
+        let streams = ChatStreams::default();
+        let cancel = streams.register("r1").unwrap();
+        // The frontend cancels as soon as the first text shows up
+        let emit = |_: &str| assert!(streams.cancel("r1").unwrap());
+        let provider = Stalling { deltas: vec!["Hel", "lo"] };
+
+        let reply = stream_reply(&provider, &LlmConfig::default(), conversation_id, "Hi".to_string(), &db, cancel, &emit)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.content, "Hello");
+        assert!(reply.partial);
+        assert_eq!(reply.model.as_deref(), Some("stalling"));
+        assert_eq!(reply.prompt_tokens, None);
+
+        let messages = database::load_messages(&db.get().unwrap(), conversation_id).unwrap();
+        let stored: Vec<_> = messages.iter().map(|m| (m.role.as_str(), m.content.as_str(), m.partial)).collect();
+        assert_eq!(stored, vec![("user", "Hi", false), ("ai", "Hello", true)]);
+        drop(db);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancel_before_any_text_stores_no_reply() {
+        let (path, db, conversation_id) = conversation("early_cancel");
+        let streams = ChatStreams::default();
+        let cancel = streams.register("r1").unwrap();
+        assert!(streams.cancel("r1").unwrap());
+        assert!(matches!(streams.register("r1"), Err(RweError::Conflict(_))));
+
+        let reply = stream_reply(&Stalling { deltas: Vec::new() }, &LlmConfig::default(), conversation_id, "Hi".to_string(), &db, cancel, &|_| {})
+            .await
+            .unwrap();
+        assert!(reply.is_none());
+        streams.finish("r1");
+        assert!(!streams.cancel("r1").unwrap());
+
+        let messages = database::load_messages(&db.get().unwrap(), conversation_id).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].role, "user");
+        drop(db);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,434 @@
+use serde::{Deserialize, Serialize};
+use tauri_plugin_http::reqwest;
+use crate::error::RweError;
+use crate::llm::{
+    cancelled, check_status, BoxFuture, CancelSignal, ChatProvider, ChatReply, ChatRequest, ChatRole,
+    DeltaSink, SseDecoder, StreamEnd, TokenUsage,
+};
+
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+const DEFAULT_MODEL: &str = "gemini-2.0-flash";
+
+#[derive(Serialize)]
+struct Part<'a> {
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct Content<'a> {
+    role: &'a str,
+    parts: Vec<Part<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    temperature: f32,
+    max_output_tokens: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentRequest<'a> {
+    contents: Vec<Content<'a>>,
+    generation_config: GenerationConfig,
+}
+
+#[derive(Deserialize)]
+struct ResponsePart {
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseContent {
+    #[serde(default)]
+    parts: Vec<ResponsePart>,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    content: Option<ResponseContent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+}
+
+impl From<UsageMetadata> for TokenUsage {
+    fn from(usage: UsageMetadata) -> Self {
+        TokenUsage { prompt_tokens: usage.prompt_token_count, completion_tokens: usage.candidates_token_count }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelEntry {
+    name: String,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelsResponse {
+    #[serde(default)]
+    models: Vec<ModelEntry>,
+    next_page_token: Option<String>,
+}
+
+pub struct GeminiProvider {
+    api_key: String,
+    model: String,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl GeminiProvider {
+    pub fn new(api_key: String, base_url: Option<String>, model: Option<String>) -> Self {
+        Self {
+            api_key,
+            model: model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn request_body<'a>(request: &'a ChatRequest) -> GenerateContentRequest<'a> {
+        GenerateContentRequest {
+            contents: request
+                .messages
+                .iter()
+                .map(|m| Content {
+                    role: match m.role {
+                        ChatRole::User => "user",
+                        ChatRole::Assistant => "model",
+                    },
+                    parts: vec![Part { text: &m.content }],
+                })
+                .collect(),
+            generation_config: GenerationConfig {
+                temperature: request.temperature,
+                max_output_tokens: request.max_output_tokens,
+            },
+        }
+    }
+
+    async fn post(&self, method: &str, request: &ChatRequest) -> Result<reqwest::Response, RweError> {
+        let url = format!("{}/models/{}:{}", self.base_url, self.model, method);
+        let body = serde_json::to_vec(&Self::request_body(request))
+            .map_err(|e| RweError::Internal(format!("Failed to encode Gemini request: {}", e)))?;
+
+        // The key goes in a header rather than the query string so it
+        // doesn't end up in proxy or error logs
+        let response = self.client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("x-goog-api-key", &self.api_key)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| RweError::Network(format!("Gemini request failed: {}", e)))?;
+
+        check_status(response, "Gemini").await
+    }
+}
+
+fn response_text(response: GenerateContentResponse) -> String {
+    response
+        .candidates
+        .into_iter()
+        .next()
+        .and_then(|c| c.content)
+        .map(|c| c.parts.into_iter().filter_map(|p| p.text).collect())
+        .unwrap_or_default()
+}
+
+impl ChatProvider for GeminiProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn send<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<ChatReply, RweError>> {
+        Box::pin(async move {
+            let bytes = self.post("generateContent", request).await?
+                .bytes()
+                .await
+                .map_err(|e| RweError::Network(format!("Failed to read Gemini response: {}", e)))?;
+
+            let mut parsed: GenerateContentResponse = serde_json::from_slice(&bytes)
+                .map_err(|e| RweError::Internal(format!("Invalid response from Gemini API: {}", e)))?;
+
+            let usage = parsed.usage_metadata.take().map(TokenUsage::from);
+            let content = response_text(parsed);
+            if content.is_empty() {
+                return Err(RweError::Internal("Invalid response from Gemini API".to_string()));
+            }
+
+            Ok(ChatReply { content, model: self.model.clone(), usage })
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, RweError>> {
+        Box::pin(async move {
+            let mut models = Vec::new();
+            let mut page_token: Option<String> = None;
+            loop {
+                let mut query = vec![("pageSize", "1000")];
+                if let Some(token) = &page_token {
+                    query.push(("pageToken", token));
+                }
+                let response = self.client
+                    .get(format!("{}/models", self.base_url))
+                    .query(&query)
+                    .header("x-goog-api-key", &self.api_key)
+                    .send()
+                    .await
+                    .map_err(|e| RweError::Network(format!("Gemini request failed: {}", e)))?;
+                let bytes = check_status(response, "Gemini").await?
+                    .bytes()
+                    .await
+                    .map_err(|e| RweError::Network(format!("Failed to read Gemini response: {}", e)))?;
+                let page: ModelsResponse = serde_json::from_slice(&bytes)
+                    .map_err(|e| RweError::Internal(format!("Invalid model list from Gemini API: {}", e)))?;
+
+                models.extend(
+                    page.models
+                        .into_iter()
+                        .filter(|m| m.supported_generation_methods.iter().any(|g| g == "generateContent"))
+                        .map(|m| m.name.trim_start_matches("models/").to_string()),
+                );
+
+                match page.next_page_token.filter(|t| !t.is_empty()) {
+                    Some(token) => page_token = Some(token),
+                    None => return Ok(models),
+                }
+            }
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_delta: &'a mut DeltaSink<'a>,
+        mut cancel: CancelSignal,
+    ) -> BoxFuture<'a, Result<StreamEnd, RweError>> {
+        Box::pin(async move {
+            let mut response = tokio::select! {
+                response = self.post("streamGenerateContent?alt=sse", request) => response?,
+                _ = cancelled(&mut cancel) => return Ok(StreamEnd::Cancelled),
+            };
+
+            let mut decoder = SseDecoder::default();
+            // Every event carries the running totals; the last one counts
+            let mut usage = None;
+            loop {
+                let chunk = tokio::select! {
+                    chunk = response.chunk() => chunk.map_err(|e| RweError::Network(format!("Gemini stream failed: {}", e)))?,
+                    _ = cancelled(&mut cancel) => return Ok(StreamEnd::Cancelled),
+                };
+                let events = match &chunk {
+                    Some(bytes) => decoder.push(bytes),
+                    None => decoder.finish(),
+                };
+                for data in events {
+                    let mut parsed: GenerateContentResponse = serde_json::from_str(&data)
+                        .map_err(|e| RweError::Internal(format!("Invalid stream event from Gemini API: {}", e)))?;
+                    if let Some(reported) = parsed.usage_metadata.take() {
+                        usage = Some(reported.into());
+                    }
+                    let text = response_text(parsed);
+                    if !text.is_empty() {
+                        on_delta(&text);
+                    }
+                }
+                if chunk.is_none() {
+                    return Ok(StreamEnd::Completed { usage });
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server::{serve, Route};
+    use crate::llm::ChatMessage;
+    use tokio::sync::watch;
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            messages: vec![
+                ChatMessage { role: ChatRole::User, content: "Hi".to_string() },
+                ChatMessage { role: ChatRole::Assistant, content: "Hello".to_string() },
+                ChatMessage { role: ChatRole::User, content: "Say more".to_string() },
+            ],
+            temperature: 0.4,
+            max_output_tokens: 256,
+        }
+    }
+
+    fn provider(base_url: &str) -> GeminiProvider {
+        GeminiProvider::new("key-test".to_string(), Some(format!("{}/v1beta", base_url)), None)
+    }
+
+    async fn stream_text(provider: &GeminiProvider) -> (Result<StreamEnd, RweError>, String) {
+        let (_keep, cancel) = watch::channel(false);
+        let mut text = String::new();
+        let request = request();
+        let mut sink = |delta: &str| text.push_str(delta);
+        let end = provider.stream(&request, &mut sink, cancel).await;
+        (end, text)
+    }
+
+    #[tokio::test]
+    async fn send_posts_contents_and_reads_reply() {
+        let server = serve(vec![Route::new(
+            "POST",
+            "/v1beta/models/gemini-2.0-flash:generateContent",
+            200,
+            "application/json",
+            &[r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Sure, "},{"text":"here."}]}}],"usageMetadata":{"promptTokenCount":11,"candidatesTokenCount":3}}"#],
+        )])
+        .await;
+
+        let reply = provider(&server.base_url).send(&request()).await.unwrap();
+        assert_eq!(reply.content, "Sure, here.");
+        assert_eq!(reply.model, DEFAULT_MODEL);
+        assert_eq!(reply.usage, Some(TokenUsage { prompt_tokens: 11, completion_tokens: 3 }));
+
+        let requests = server.requests.lock().unwrap();
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(body["contents"][2]["parts"][0]["text"], "Say more");
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 256);
+    }
+
+    #[tokio::test]
+    async fn key_goes_in_a_header_not_the_url() {
+        let server = serve(vec![
+            Route::new(
+                "POST",
+                "/v1beta/models/gemini-2.0-flash:generateContent",
+                200,
+                "application/json",
+                &[r#"{"candidates":[{"content":{"parts":[{"text":"ok"}]}}]}"#],
+            ),
+            Route::new(
+                "POST",
+                "/v1beta/models/gemini-2.0-flash:streamGenerateContent?alt=sse",
+                200,
+                "text/event-stream",
+                &["data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"ok\"}]}}]}\n\n"],
+            ),
+            Route::new("GET", "/v1beta/models?pageSize=1000", 200, "application/json", &[r#"{"models":[]}"#]),
+        ])
+        .await;
+        let provider = provider(&server.base_url);
+        provider.send(&request()).await.unwrap();
+        stream_text(&provider).await.0.unwrap();
+        provider.list_models().await.unwrap();
+
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        for recorded in requests.iter() {
+            let request_line = recorded.head.lines().next().unwrap();
+            assert!(!request_line.contains("key-test"), "{}", request_line);
+            assert!(recorded.head.to_ascii_lowercase().contains("x-goog-api-key: key-test"));
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_joins_events_and_keeps_the_last_usage() {
+        let server = serve(vec![Route::new(
+            "POST",
+            "/v1beta/models/gemini-2.0-flash:streamGenerateContent?alt=sse",
+            200,
+            "text/event-stream",
+            &[
+                "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hel\"}]}}],\"usageMetadata\":{\"promptTokenCount\":6,\"candidatesTokenCount\":1}}\r\n\r\n",
+                "data: {\"candidates\":[{\"content\":{\"parts\":[{\"te",
+                "xt\":\"lo\"}]}}],\"usageMetadata\":{\"promptTokenCount\":6,\"candidatesTokenCount\":2}}\r\n\r\n",
+            ],
+        )])
+        .await;
+
+        let (end, text) = stream_text(&provider(&server.base_url)).await;
+        assert_eq!(text, "Hello");
+        assert_eq!(
+            end.unwrap(),
+            StreamEnd::Completed { usage: Some(TokenUsage { prompt_tokens: 6, completion_tokens: 2 }) }
+        );
+    }
+
+    #[tokio::test]
+    async fn list_models_follows_page_tokens() {
+        let server = serve(vec![
+            Route::new(
+                "GET",
+                "/v1beta/models?pageSize=1000",
+                200,
+                "application/json",
+                &[r#"{"models":[{"name":"models/gemini-2.0-flash","supportedGenerationMethods":["generateContent","countTokens"]},{"name":"models/text-embedding-004","supportedGenerationMethods":["embedContent"]}],"nextPageToken":"page-2"}"#],
+            ),
+            Route::new(
+                "GET",
+                "/v1beta/models?pageSize=1000&pageToken=page-2",
+                200,
+                "application/json",
+                &[r#"{"models":[{"name":"models/gemini-1.5-pro","supportedGenerationMethods":["generateContent"]}],"nextPageToken":""}"#],
+            ),
+        ])
+        .await;
+
+        assert_eq!(
+            provider(&server.base_url).list_models().await.unwrap(),
+            vec!["gemini-2.0-flash", "gemini-1.5-pro"]
+        );
+        assert_eq!(server.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn errors_are_classified() {
+        let body = r#"{"error":{"code":401,"message":"API key not valid","status":"UNAUTHENTICATED"}}"#;
+        let server = serve(vec![
+            Route::new("POST", "/v1beta/models/gemini-2.0-flash:generateContent", 401, "application/json", &[body]),
+            Route::new(
+                "POST",
+                "/v1beta/models/gemini-2.0-flash:streamGenerateContent?alt=sse",
+                200,
+                "text/event-stream",
+                &["data: not json\n\n"],
+            ),
+            Route::new("GET", "/v1beta/models?pageSize=1000", 503, "text/plain", &["overloaded"]),
+        ])
+        .await;
+        let provider = provider(&server.base_url);
+
+        assert_eq!(
+            provider.send(&request()).await.unwrap_err(),
+            RweError::Validation(format!("Gemini API error: 401 - {}", body))
+        );
+        assert!(matches!(stream_text(&provider).await.0, Err(RweError::Internal(_))));
+        assert_eq!(
+            provider.list_models().await.unwrap_err(),
+            RweError::Network("Gemini API error: 503 - overloaded".to_string())
+        );
+
+        let unreachable = GeminiProvider::new("key-test".to_string(), Some("http://127.0.0.1:1".to_string()), None);
+        assert!(matches!(unreachable.send(&request()).await, Err(RweError::Network(_))));
+    }
+}
@@ -0,0 +1,351 @@
+use serde::{Deserialize, Serialize};
+use tauri_plugin_http::reqwest;
+use crate::error::RweError;
+use crate::llm::{
+    cancelled, check_status, BoxFuture, CancelSignal, ChatProvider, ChatReply, ChatRequest, ChatRole,
+    DeltaSink, StreamEnd, TokenUsage,
+};
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+const DEFAULT_MODEL: &str = "llama3.2";
+
+#[derive(Serialize)]
+struct RequestMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct Options {
+    temperature: f32,
+    num_predict: u32,
+}
+
+#[derive(Serialize)]
+struct ChatBody<'a> {
+    model: &'a str,
+    messages: Vec<RequestMessage<'a>>,
+    stream: bool,
+    options: Options,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    message: Option<ResponseMessage>,
+    model: Option<String>,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+    /// Token counts, sent with the final (`done`) response.
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+}
+
+impl ChatResponse {
+    fn usage(&self) -> Option<TokenUsage> {
+        match (self.prompt_eval_count, self.eval_count) {
+            (None, None) => None,
+            (prompt, completion) => Some(TokenUsage {
+                prompt_tokens: prompt.unwrap_or(0),
+                completion_tokens: completion.unwrap_or(0),
+            }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<ModelEntry>,
+}
+
+/// Ollama's native `/api/chat` endpoint, which streams newline-delimited
+/// JSON rather than server-sent events.
+pub struct OllamaProvider {
+    model: String,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl OllamaProvider {
+    pub fn new(base_url: Option<String>, model: Option<String>) -> Self {
+        Self {
+            model: model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn post(&self, request: &ChatRequest, stream: bool) -> Result<reqwest::Response, RweError> {
+        let body = ChatBody {
+            model: &self.model,
+            messages: request
+                .messages
+                .iter()
+                .map(|m| RequestMessage {
+                    role: match m.role {
+                        ChatRole::User => "user",
+                        ChatRole::Assistant => "assistant",
+                    },
+                    content: &m.content,
+                })
+                .collect(),
+            stream,
+            options: Options {
+                temperature: request.temperature,
+                num_predict: request.max_output_tokens,
+            },
+        };
+        let body = serde_json::to_vec(&body)
+            .map_err(|e| RweError::Internal(format!("Failed to encode Ollama request: {}", e)))?;
+
+        let response = self.client
+            .post(format!("{}/api/chat", self.base_url))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| RweError::Network(format!("Ollama request to {} failed: {}", self.base_url, e)))?;
+
+        check_status(response, "Ollama").await
+    }
+}
+
+fn parse_line(line: &[u8]) -> Result<Option<ChatResponse>, RweError> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let parsed: ChatResponse = serde_json::from_str(line)
+        .map_err(|e| RweError::Internal(format!("Invalid response from Ollama: {}", e)))?;
+    if let Some(error) = parsed.error {
+        return Err(RweError::Network(format!("Ollama error: {}", error)));
+    }
+    Ok(Some(parsed))
+}
+
+impl ChatProvider for OllamaProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn send<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<ChatReply, RweError>> {
+        Box::pin(async move {
+            let bytes = self.post(request, false).await?
+                .bytes()
+                .await
+                .map_err(|e| RweError::Network(format!("Failed to read Ollama response: {}", e)))?;
+
+            let parsed = parse_line(&bytes)?
+                .ok_or_else(|| RweError::Internal("Empty response from Ollama".to_string()))?;
+            let usage = parsed.usage();
+            let model = parsed.model.unwrap_or_else(|| self.model.clone());
+            let content = parsed.message.map(|m| m.content).unwrap_or_default();
+            if content.is_empty() {
+                return Err(RweError::Internal("Empty response from Ollama".to_string()));
+            }
+
+            Ok(ChatReply { content, model, usage })
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, RweError>> {
+        Box::pin(async move {
+            let response = self.client
+                .get(format!("{}/api/tags", self.base_url))
+                .send()
+                .await
+                .map_err(|e| RweError::Network(format!("Ollama request to {} failed: {}", self.base_url, e)))?;
+            let bytes = check_status(response, "Ollama").await?
+                .bytes()
+                .await
+                .map_err(|e| RweError::Network(format!("Failed to read Ollama model list: {}", e)))?;
+            let parsed: TagsResponse = serde_json::from_slice(&bytes)
+                .map_err(|e| RweError::Internal(format!("Invalid model list from Ollama: {}", e)))?;
+
+            Ok(parsed.models.into_iter().map(|m| m.name).collect())
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_delta: &'a mut DeltaSink<'a>,
+        mut cancel: CancelSignal,
+    ) -> BoxFuture<'a, Result<StreamEnd, RweError>> {
+        Box::pin(async move {
+            let mut response = tokio::select! {
+                response = self.post(request, true) => response?,
+                _ = cancelled(&mut cancel) => return Ok(StreamEnd::Cancelled),
+            };
+
+            let mut buffer: Vec<u8> = Vec::new();
+            loop {
+                let chunk = tokio::select! {
+                    chunk = response.chunk() => chunk.map_err(|e| RweError::Network(format!("Ollama stream failed: {}", e)))?,
+                    _ = cancelled(&mut cancel) => return Ok(StreamEnd::Cancelled),
+                };
+                let lines: Vec<Vec<u8>> = match &chunk {
+                    Some(bytes) => {
+                        buffer.extend_from_slice(bytes);
+                        let mut lines = Vec::new();
+                        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                            lines.push(buffer.drain(..=end).collect());
+                        }
+                        lines
+                    }
+                    None => vec![std::mem::take(&mut buffer)],
+                };
+                for line in lines {
+                    let Some(parsed) = parse_line(&line)? else {
+                        continue;
+                    };
+                    let usage = parsed.usage();
+                    if let Some(message) = parsed.message.filter(|m| !m.content.is_empty()) {
+                        on_delta(&message.content);
+                    }
+                    if parsed.done {
+                        return Ok(StreamEnd::Completed { usage });
+                    }
+                }
+                if chunk.is_none() {
+                    return Ok(StreamEnd::Completed { usage: None });
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server::{serve, Route};
+    use crate::llm::ChatMessage;
+    use tokio::sync::watch;
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            messages: vec![ChatMessage { role: ChatRole::User, content: "Hi".to_string() }],
+            temperature: 0.2,
+            max_output_tokens: 128,
+        }
+    }
+
+    async fn stream_text(provider: &OllamaProvider) -> (Result<StreamEnd, RweError>, String) {
+        let (_keep, cancel) = watch::channel(false);
+        let mut text = String::new();
+        let request = request();
+        let mut sink = |delta: &str| text.push_str(delta);
+        let end = provider.stream(&request, &mut sink, cancel).await;
+        (end, text)
+    }
+
+    #[tokio::test]
+    async fn send_posts_options_and_reads_reply() {
+        let server = serve(vec![Route::new(
+            "POST",
+            "/api/chat",
+            200,
+            "application/json",
+            &[r#"{"model":"llama3.2:latest","message":{"role":"assistant","content":"Hello there"},"done":true,"prompt_eval_count":9,"eval_count":4}"#],
+        )])
+        .await;
+        let provider = OllamaProvider::new(Some(server.base_url.clone()), None);
+
+        let reply = provider.send(&request()).await.unwrap();
+        assert_eq!(reply.content, "Hello there");
+        assert_eq!(reply.model, "llama3.2:latest");
+        assert_eq!(reply.usage, Some(TokenUsage { prompt_tokens: 9, completion_tokens: 4 }));
+
+        let body: serde_json::Value = serde_json::from_str(&server.requests.lock().unwrap()[0].body).unwrap();
+        assert_eq!(body["model"], DEFAULT_MODEL);
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["options"]["num_predict"], 128);
+        assert_eq!(body["messages"][0]["role"], "user");
+    }
+
+    #[tokio::test]
+    async fn list_models_reads_tags() {
+        let server = serve(vec![Route::new(
+            "GET",
+            "/api/tags",
+            200,
+            "application/json",
+            &[r#"{"models":[{"name":"llama3.2:latest","size":1},{"name":"qwen2.5:7b","size":2}]}"#],
+        )])
+        .await;
+        let provider = OllamaProvider::new(Some(server.base_url.clone()), None);
+
+        assert_eq!(provider.list_models().await.unwrap(), vec!["llama3.2:latest", "qwen2.5:7b"]);
+    }
+
+    #[tokio::test]
+    async fn stream_joins_lines_split_across_chunks() {
+        let server = serve(vec![Route::new(
+            "POST",
+            "/api/chat",
+            200,
+            "application/x-ndjson",
+            &[
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n{\"message\":{\"role\":",
+                "\"assistant\",\"content\":\"lo\"},\"done\":false}\n",
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":5,\"eval_count\":2}\n",
+            ],
+        )])
+        .await;
+        let provider = OllamaProvider::new(Some(server.base_url.clone()), None);
+
+        let (end, text) = stream_text(&provider).await;
+        assert_eq!(text, "Hello");
+        assert_eq!(
+            end.unwrap(),
+            StreamEnd::Completed { usage: Some(TokenUsage { prompt_tokens: 5, completion_tokens: 2 }) }
+        );
+        let body: serde_json::Value = serde_json::from_str(&server.requests.lock().unwrap()[0].body).unwrap();
+        assert_eq!(body["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn stream_reports_error_lines() {
+        let server = serve(vec![Route::new(
+            "POST",
+            "/api/chat",
+            200,
+            "application/x-ndjson",
+            &["{\"message\":{\"content\":\"Par\"},\"done\":false}\n", "{\"error\":\"model runner crashed\"}\n"],
+        )])
+        .await;
+        let provider = OllamaProvider::new(Some(server.base_url.clone()), None);
+
+        let (end, text) = stream_text(&provider).await;
+        assert_eq!(text, "Par");
+        assert_eq!(end.unwrap_err(), RweError::Network("Ollama error: model runner crashed".to_string()));
+    }
+
+    #[tokio::test]
+    async fn error_status_carries_code_and_body() {
+        let server = serve(vec![
+            Route::new("POST", "/api/chat", 404, "application/json", &[r#"{"error":"model \"nope\" not found"}"#]),
+            Route::new("GET", "/api/tags", 500, "text/plain", &["internal error"]),
+        ])
+        .await;
+        let provider = OllamaProvider::new(Some(server.base_url.clone()), Some("nope".to_string()));
+
+        let expected = RweError::NotFound(r#"Ollama API error: 404 - {"error":"model \"nope\" not found"}"#.to_string());
+        assert_eq!(provider.send(&request()).await.unwrap_err(), expected);
+        assert_eq!(stream_text(&provider).await.0.unwrap_err(), expected);
+        assert_eq!(provider.list_models().await.unwrap_err(), RweError::Network("Ollama API error: 500 - internal error".to_string()));
+    }
+}
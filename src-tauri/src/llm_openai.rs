@@ -0,0 +1,374 @@
+use serde::{Deserialize, Serialize};
+use tauri_plugin_http::reqwest;
+use crate::error::RweError;
+use crate::llm::{
+    cancelled, check_status, BoxFuture, CancelSignal, ChatProvider, ChatReply, ChatRequest, ChatRole,
+    DeltaSink, SseDecoder, StreamEnd, TokenUsage,
+};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+#[derive(Serialize)]
+struct RequestMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<RequestMessage<'a>>,
+    temperature: f32,
+    max_tokens: u32,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+/// Asks for a final stream event carrying the token counts.
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Option<ResponseMessage>,
+    delta: Option<ResponseMessage>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        TokenUsage { prompt_tokens: usage.prompt_tokens, completion_tokens: usage.completion_tokens }
+    }
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionResponse {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    model: Option<String>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    #[serde(default)]
+    data: Vec<ModelEntry>,
+}
+
+/// Any server speaking the OpenAI chat completions API: OpenAI itself,
+/// llama.cpp's `llama-server`, vLLM, LM Studio, Ollama's `/v1` endpoint, etc.
+pub struct OpenAiProvider {
+    api_key: Option<String>,
+    model: String,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl OpenAiProvider {
+    pub fn new(api_key: Option<String>, base_url: Option<String>, model: Option<String>) -> Self {
+        Self {
+            api_key,
+            model: model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn authorize(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+
+    async fn post(&self, request: &ChatRequest, stream: bool) -> Result<reqwest::Response, RweError> {
+        let body = ChatCompletionRequest {
+            model: &self.model,
+            messages: request
+                .messages
+                .iter()
+                .map(|m| RequestMessage {
+                    role: match m.role {
+                        ChatRole::User => "user",
+                        ChatRole::Assistant => "assistant",
+                    },
+                    content: &m.content,
+                })
+                .collect(),
+            temperature: request.temperature,
+            max_tokens: request.max_output_tokens,
+            stream,
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+        };
+        let body = serde_json::to_vec(&body)
+            .map_err(|e| RweError::Internal(format!("Failed to encode chat request: {}", e)))?;
+
+        let response = self
+            .authorize(self.client.post(format!("{}/chat/completions", self.base_url)))
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| RweError::Network(format!("Chat request to {} failed: {}", self.base_url, e)))?;
+
+        check_status(response, "OpenAI-compatible").await
+    }
+}
+
+fn choice_text(response: ChatCompletionResponse) -> String {
+    response
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|c| c.message.or(c.delta))
+        .and_then(|m| m.content)
+        .unwrap_or_default()
+}
+
+impl ChatProvider for OpenAiProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn send<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<ChatReply, RweError>> {
+        Box::pin(async move {
+            let bytes = self.post(request, false).await?
+                .bytes()
+                .await
+                .map_err(|e| RweError::Network(format!("Failed to read chat response: {}", e)))?;
+
+            let mut parsed: ChatCompletionResponse = serde_json::from_slice(&bytes)
+                .map_err(|e| RweError::Internal(format!("Invalid chat completion response: {}", e)))?;
+
+            let model = parsed.model.clone().unwrap_or_else(|| self.model.clone());
+            let usage = parsed.usage.take().map(TokenUsage::from);
+            let content = choice_text(parsed);
+            if content.is_empty() {
+                return Err(RweError::Internal("Invalid chat completion response: no content".to_string()));
+            }
+
+            Ok(ChatReply { content, model, usage })
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, RweError>> {
+        Box::pin(async move {
+            let response = self
+                .authorize(self.client.get(format!("{}/models", self.base_url)))
+                .send()
+                .await
+                .map_err(|e| RweError::Network(format!("Model list request to {} failed: {}", self.base_url, e)))?;
+            let bytes = check_status(response, "OpenAI-compatible").await?
+                .bytes()
+                .await
+                .map_err(|e| RweError::Network(format!("Failed to read model list: {}", e)))?;
+            let parsed: ModelsResponse = serde_json::from_slice(&bytes)
+                .map_err(|e| RweError::Internal(format!("Invalid model list: {}", e)))?;
+
+            Ok(parsed.data.into_iter().map(|m| m.id).collect())
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_delta: &'a mut DeltaSink<'a>,
+        mut cancel: CancelSignal,
+    ) -> BoxFuture<'a, Result<StreamEnd, RweError>> {
+        Box::pin(async move {
+            let mut response = tokio::select! {
+                response = self.post(request, true) => response?,
+                _ = cancelled(&mut cancel) => return Ok(StreamEnd::Cancelled),
+            };
+
+            let mut decoder = SseDecoder::default();
+            let mut usage = None;
+            loop {
+                let chunk = tokio::select! {
+                    chunk = response.chunk() => chunk.map_err(|e| RweError::Network(format!("Chat stream failed: {}", e)))?,
+                    _ = cancelled(&mut cancel) => return Ok(StreamEnd::Cancelled),
+                };
+                let events = match &chunk {
+                    Some(bytes) => decoder.push(bytes),
+                    None => decoder.finish(),
+                };
+                for data in events {
+                    if data.trim() == "[DONE]" {
+                        return Ok(StreamEnd::Completed { usage });
+                    }
+                    let mut parsed: ChatCompletionResponse = serde_json::from_str(&data)
+                        .map_err(|e| RweError::Internal(format!("Invalid chat stream event: {}", e)))?;
+                    if let Some(reported) = parsed.usage.take() {
+                        usage = Some(reported.into());
+                    }
+                    let text = choice_text(parsed);
+                    if !text.is_empty() {
+                        on_delta(&text);
+                    }
+                }
+                if chunk.is_none() {
+                    return Ok(StreamEnd::Completed { usage });
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock_server::{serve, Route};
+    use crate::llm::ChatMessage;
+    use tokio::sync::watch;
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            messages: vec![
+                ChatMessage { role: ChatRole::User, content: "Hi".to_string() },
+                ChatMessage { role: ChatRole::Assistant, content: "Hello".to_string() },
+                ChatMessage { role: ChatRole::User, content: "Say more".to_string() },
+            ],
+            temperature: 0.5,
+            max_output_tokens: 64,
+        }
+    }
+
+    async fn stream_text(provider: &OpenAiProvider) -> (Result<StreamEnd, RweError>, String) {
+        let (_keep, cancel) = watch::channel(false);
+        let mut text = String::new();
+        let request = request();
+        let mut sink = |delta: &str| text.push_str(delta);
+        let end = provider.stream(&request, &mut sink, cancel).await;
+        (end, text)
+    }
+
+    #[tokio::test]
+    async fn send_posts_history_and_reads_reply() {
+        let server = serve(vec![Route::new(
+            "POST",
+            "/v1/chat/completions",
+            200,
+            "application/json",
+            &[r#"{"model":"gpt-test-0613","choices":[{"message":{"role":"assistant","content":"Sure."}}],"usage":{"prompt_tokens":12,"completion_tokens":3}}"#],
+        )])
+        .await;
+        let provider = OpenAiProvider::new(
+            Some("sk-test".to_string()),
+            Some(format!("{}/v1", server.base_url)),
+            Some("gpt-test".to_string()),
+        );
+
+        let reply = provider.send(&request()).await.unwrap();
+        assert_eq!(reply.content, "Sure.");
+        assert_eq!(reply.model, "gpt-test-0613");
+        assert_eq!(reply.usage, Some(TokenUsage { prompt_tokens: 12, completion_tokens: 3 }));
+
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].head.to_ascii_lowercase().contains("authorization: bearer sk-test"));
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["model"], "gpt-test");
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["max_tokens"], 64);
+        assert_eq!(body["messages"][1]["role"], "assistant");
+        assert!(body.get("stream_options").is_none());
+    }
+
+    #[tokio::test]
+    async fn list_models_reads_ids() {
+        let server = serve(vec![Route::new(
+            "GET",
+            "/v1/models",
+            200,
+            "application/json",
+            &[r#"{"object":"list","data":[{"id":"gpt-a","object":"model"},{"id":"gpt-b","object":"model"}]}"#],
+        )])
+        .await;
+        let provider = OpenAiProvider::new(None, Some(format!("{}/v1", server.base_url)), None);
+
+        assert_eq!(provider.list_models().await.unwrap(), vec!["gpt-a", "gpt-b"]);
+        assert!(!server.requests.lock().unwrap()[0].head.to_ascii_lowercase().contains("authorization"));
+    }
+
+    #[tokio::test]
+    async fn stream_joins_frames_split_across_chunks() {
+        let server = serve(vec![Route::new(
+            "POST",
+            "/v1/chat/completions",
+            200,
+            "text/event-stream",
+            &[
+                "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hel",
+                "lo, \"}}]}\n",
+                "\ndata: {\"choices\":[{\"delta\":{\"content\":\"world\"}}]}\n\ndata: {\"choices\":[],",
+                "\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":2}}\n\n",
+                "data: [DONE]\n\n",
+            ],
+        )])
+        .await;
+        let provider = OpenAiProvider::new(None, Some(format!("{}/v1", server.base_url)), None);
+
+        let (end, text) = stream_text(&provider).await;
+        assert_eq!(text, "Hello, world");
+        assert_eq!(
+            end.unwrap(),
+            StreamEnd::Completed { usage: Some(TokenUsage { prompt_tokens: 7, completion_tokens: 2 }) }
+        );
+        let body: serde_json::Value = serde_json::from_str(&server.requests.lock().unwrap()[0].body).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    #[tokio::test]
+    async fn stream_splits_inside_a_multibyte_character() {
+        let event = "data: {\"choices\":[{\"delta\":{\"content\":\"caf\u{e9}\"}}]}\n\n".as_bytes();
+        let split = event.iter().position(|&b| b == 0xc3).unwrap() + 1;
+        let mut route = Route::new("POST", "/v1/chat/completions", 200, "text/event-stream", &[]);
+        route.chunks = vec![event[..split].to_vec(), event[split..].to_vec()];
+        let server = serve(vec![route]).await;
+        let provider = OpenAiProvider::new(None, Some(format!("{}/v1", server.base_url)), None);
+
+        // No [DONE]: the end of the body completes the stream
+        let (end, text) = stream_text(&provider).await;
+        assert_eq!(text, "caf\u{e9}");
+        assert_eq!(end.unwrap(), StreamEnd::Completed { usage: None });
+    }
+
+    #[tokio::test]
+    async fn error_status_carries_code_and_body() {
+        let body = r#"{"error":{"message":"Rate limit reached","type":"requests"}}"#;
+        let server = serve(vec![
+            Route::new("POST", "/v1/chat/completions", 429, "application/json", &[body]),
+            Route::new("GET", "/v1/models", 401, "application/json", &[r#"{"error":"bad key"}"#]),
+        ])
+        .await;
+        let provider = OpenAiProvider::new(Some("sk-x".to_string()), Some(format!("{}/v1", server.base_url)), None);
+
+        let expected = RweError::Network(format!("OpenAI-compatible API error: 429 - {}", body));
+        assert_eq!(provider.send(&request()).await.unwrap_err(), expected);
+        assert_eq!(stream_text(&provider).await.0.unwrap_err(), expected);
+        assert_eq!(
+            provider.list_models().await.unwrap_err(),
+            RweError::Validation(r#"OpenAI-compatible API error: 401 - {"error":"bad key"}"#.to_string())
+        );
+    }
+}
@@ -1,19 +1,258 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::cell::Cell;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{command, AppHandle, Emitter, State};
+use crate::database;
+use crate::database_pool::{self, DbPool, SqlitePool};
+use crate::encryption::{self, DatabaseEncryption};
+use crate::error::RweError;
+use crate::updater;
+use crate::mindmap_schema;
+use crate::mindmaps;
+use crate::whiteboards;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// A Rust migration step, run inside the migration's transaction.
+pub type MigrationFn = Box<dyn Fn(&Connection, &MigrationProgress) -> Result<(), RweError> + Send + Sync>;
+
+/// What a migration runs: SQL for schema changes, or Rust for data
+/// transformations SQL can't express, such as reshaping JSON columns or
+/// re-encrypting values.
+pub enum MigrationAction {
+    Sql(String),
+    Code(MigrationFn),
+}
 
 pub struct Migration {
     pub version: u32,
     pub name: String,
-    pub up_sql: String,
+    pub up: MigrationAction,
+    /// Undoes `up`, taking the schema back to the previous version.
+    pub down: MigrationAction,
+}
+
+impl Migration {
+    /// SHA-256 of the `up` SQL with runs of whitespace collapsed, so
+    /// re-indenting a migration doesn't count as rewriting it. Rust can't be
+    /// hashed, so a Rust migration is identified by its name alone; changing
+    /// what one does needs a new migration.
+    pub fn checksum(&self) -> String {
+        let normalized = match &self.up {
+            MigrationAction::Sql(sql) => sql.split_whitespace().collect::<Vec<_>>().join(" "),
+            MigrationAction::Code(_) => format!("rust:{}", self.name),
+        };
+        Sha256::digest(normalized.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Payload of `migration://progress` events. One is sent as each migration
+/// starts; Rust migrations send more with `done` and `total` filled in.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationProgressEvent {
+    pub version: u32,
+    pub name: String,
+    pub direction: Direction,
+    /// This migration's position in the run, from 1, out of `steps`.
+    pub step: usize,
+    pub steps: usize,
+    pub done: Option<u64>,
+    pub total: Option<u64>,
+}
+
+/// Where progress events go; `MigrationRunner::with_progress` sends them to
+/// the frontend.
+type ProgressSink = Box<dyn Fn(MigrationProgressEvent) + Send + Sync>;
+
+/// Lets a Rust migration report how far it has got through a long data
+/// transformation. Reports are throttled, so calling `report` per row is fine.
+pub struct MigrationProgress<'a> {
+    sink: Option<&'a ProgressSink>,
+    event: MigrationProgressEvent,
+    last_sent: Cell<Option<Instant>>,
+}
+
+impl MigrationProgress<'_> {
+    fn send(&self, event: MigrationProgressEvent) {
+        self.last_sent.set(Some(Instant::now()));
+        match self.sink {
+            Some(sink) => sink(event),
+            None => {
+                if let (Some(done), Some(total)) = (event.done, event.total) {
+                    eprintln!("Migration {}: {}/{}", event.version, done, total);
+                }
+            }
+        }
+    }
+
+    pub fn report(&self, done: u64, total: u64) {
+        let due = self.last_sent.get().is_none_or(|sent| sent.elapsed() >= PROGRESS_INTERVAL);
+        if due || done >= total {
+            self.send(MigrationProgressEvent { done: Some(done), total: Some(total), ..self.event.clone() });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStep {
+    pub version: u32,
+    pub name: String,
+    pub direction: Direction,
+}
+
+/// An applied migration whose SQL in this build differs from what ran.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChecksumMismatch {
+    pub version: u32,
+    pub name: String,
+    pub applied: String,
+    pub current: String,
+}
+
+/// What `migrate_to` would do, in the order it would do it.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationPlan {
+    pub current_version: u32,
+    pub target_version: u32,
+    pub steps: Vec<MigrationStep>,
+    pub checksum_mismatches: Vec<ChecksumMismatch>,
+}
+
+impl MigrationPlan {
+    /// Refuses to roll back a migration that changed since it was applied: its
+    /// down script was written against different SQL than what actually ran
+    /// and could leave a schema no release expects.
+    pub fn ensure_reversible(&self) -> Result<(), RweError> {
+        let rolled_back = |version: u32| self.steps.iter()
+            .any(|step| step.direction == Direction::Down && step.version == version);
+        match self.checksum_mismatches.iter().find(|mismatch| rolled_back(mismatch.version)) {
+            Some(mismatch) => Err(RweError::Conflict(format!(
+                "Migration {} ({}) has changed since it was applied; refusing to roll it back",
+                mismatch.version, mismatch.name
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The mind map tables once nodes and connections have their own.
+const MINDMAP_GRAPH_TABLES: &str = r#"
+    CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence WHERE name = 'mindmaps';
+
+    CREATE TABLE mindmaps_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        conversation_id INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        theme TEXT DEFAULT 'default',
+        acyclic INTEGER NOT NULL DEFAULT 0,
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+    );
+    INSERT INTO mindmaps_new (id, conversation_id, title, theme, created_at, updated_at)
+        SELECT id, conversation_id, title, theme, created_at, updated_at FROM mindmaps;
+    DROP TABLE mindmaps;
+    ALTER TABLE mindmaps_new RENAME TO mindmaps;
+
+    DELETE FROM sqlite_sequence WHERE name = 'mindmaps';
+    INSERT INTO sqlite_sequence (name, seq) SELECT name, seq FROM temp.saved_sequence;
+    DROP TABLE temp.saved_sequence;
+
+    CREATE INDEX IF NOT EXISTS idx_mindmaps_conversation ON mindmaps(conversation_id);
+
+    CREATE TABLE IF NOT EXISTS mindmap_nodes (
+        mindmap_id INTEGER NOT NULL,
+        node_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        x REAL NOT NULL,
+        y REAL NOT NULL,
+        text TEXT NOT NULL,
+        shape TEXT NOT NULL CHECK (shape IN ('square', 'circle', 'diamond')),
+        color TEXT NOT NULL,
+        PRIMARY KEY (mindmap_id, node_id),
+        FOREIGN KEY(mindmap_id) REFERENCES mindmaps(id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS mindmap_edges (
+        mindmap_id INTEGER NOT NULL,
+        from_node TEXT NOT NULL,
+        to_node TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (mindmap_id, from_node, to_node),
+        CHECK (from_node <> to_node),
+        FOREIGN KEY(mindmap_id, from_node) REFERENCES mindmap_nodes(mindmap_id, node_id) ON DELETE CASCADE,
+        FOREIGN KEY(mindmap_id, to_node) REFERENCES mindmap_nodes(mindmap_id, node_id) ON DELETE CASCADE
+    );
+    CREATE INDEX IF NOT EXISTS idx_mindmap_edges_to ON mindmap_edges(mindmap_id, to_node);
+"#;
+
+/// The mind map table as it was with nodes and connections stored as JSON;
+/// the caller fills those in.
+const MINDMAP_JSON_TABLE: &str = r#"
+    CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence WHERE name = 'mindmaps';
+
+    DROP TABLE IF EXISTS mindmap_edges;
+    DROP TABLE IF EXISTS mindmap_nodes;
+
+    CREATE TABLE mindmaps_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        conversation_id INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        nodes TEXT NOT NULL,
+        connections TEXT NOT NULL,
+        theme TEXT DEFAULT 'default',
+        created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+    );
+    INSERT INTO mindmaps_new (id, conversation_id, title, nodes, connections, theme, created_at, updated_at)
+        SELECT id, conversation_id, title, '[]', '[]', theme, created_at, updated_at FROM mindmaps;
+    DROP TABLE mindmaps;
+    ALTER TABLE mindmaps_new RENAME TO mindmaps;
+
+    DELETE FROM sqlite_sequence WHERE name = 'mindmaps';
+    INSERT INTO sqlite_sequence (name, seq) SELECT name, seq FROM temp.saved_sequence;
+    DROP TABLE temp.saved_sequence;
+
+    CREATE INDEX IF NOT EXISTS idx_mindmaps_conversation ON mindmaps(conversation_id);
+"#;
+
+/// Rows of (id, nodes, connections) for the mind map migrations.
+fn mindmap_json_rows(conn: &Connection, sql: &str) -> Result<Vec<(i64, String, String)>, RweError> {
+    let mut stmt = conn.prepare(sql)
+        .map_err(|e| RweError::database("Prepare error", e))?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| RweError::database("Query error", e))?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))
 }
 
 pub struct MigrationRunner {
     migrations: Vec<Migration>,
+    progress: Option<ProgressSink>,
 }
 
 impl MigrationRunner {
     pub fn new() -> Self {
         let mut runner = Self {
             migrations: Vec::new(),
+            progress: None,
         };
         runner.add_initial_migrations();
         runner
@@ -23,7 +262,7 @@ impl MigrationRunner {
         self.migrations.push(Migration {
             version: 1,
             name: "initial_schema".to_string(),
-            up_sql: r#"
+            up: MigrationAction::Sql(r#"
                 CREATE TABLE IF NOT EXISTS conversations (
                     id INTEGER PRIMARY KEY AUTOINCREMENT,
                     name TEXT NOT NULL,
@@ -56,13 +295,18 @@ impl MigrationRunner {
                 CREATE INDEX IF NOT EXISTS idx_messages_conversation ON messages(conversation_id);
                 CREATE INDEX IF NOT EXISTS idx_messages_seq ON messages(seq);
                 CREATE INDEX IF NOT EXISTS idx_mindmaps_conversation ON mindmaps(conversation_id);
-            "#.to_string(),
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TABLE IF EXISTS mindmaps;
+                DROP TABLE IF EXISTS messages;
+                DROP TABLE IF EXISTS conversations;
+            "#.to_string()),
         });
 
         self.migrations.push(Migration {
             version: 2,
             name: "add_user_preferences".to_string(),
-            up_sql: r#"
+            up: MigrationAction::Sql(r#"
                 CREATE TABLE IF NOT EXISTS user_preferences (
                     id INTEGER PRIMARY KEY AUTOINCREMENT,
                     key TEXT UNIQUE NOT NULL,
@@ -73,55 +317,1773 @@ impl MigrationRunner {
                 
                 INSERT OR IGNORE INTO user_preferences (key, value) VALUES ('theme', 'system');
                 INSERT OR IGNORE INTO user_preferences (key, value) VALUES ('app_version', '1.0.0');
-            "#.to_string(),
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TABLE IF EXISTS user_preferences;
+            "#.to_string()),
         });
-    }
 
-    pub fn setup_migration_table(&self, conn: &Connection) -> Result<()> {
-        conn.execute(
-            r#"
-            CREATE TABLE IF NOT EXISTS schema_migrations (
-                version INTEGER PRIMARY KEY,
-                name TEXT NOT NULL,
-                applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
-            [],
-        )?;
-        Ok(())
-    }
+        self.migrations.push(Migration {
+            version: 3,
+            name: "add_full_text_search".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+                    content,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
 
-    pub fn get_current_version(&self, conn: &Connection) -> Result<u32> {
-        match conn.query_row(
-            "SELECT MAX(version) FROM schema_migrations",
-            [],
-            |row| row.get::<_, Option<u32>>(0),
-        ) {
-            Ok(Some(version)) => Ok(version),
-            Ok(None) => Ok(0),
-            Err(_) => Ok(0),
-        }
-    }
+                CREATE VIRTUAL TABLE IF NOT EXISTS conversation_notes_fts USING fts5(
+                    notes,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
 
-    pub fn run_migrations(&self, conn: &Connection) -> Result<()> {
-        self.setup_migration_table(conn)?;
-        let current_version = self.get_current_version(conn)?;
+                CREATE VIRTUAL TABLE IF NOT EXISTS conversation_summaries_fts USING fts5(
+                    summary,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
 
-        for migration in &self.migrations {
-            if migration.version > current_version {
-                println!("Running migration {}: {}", migration.version, migration.name);
-                
-                conn.execute_batch(&migration.up_sql)?;
-                
-                conn.execute(
-                    "INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)",
-                    [&migration.version.to_string(), &migration.name],
-                )?;
-                
-                println!("Migration {} completed", migration.version);
-            }
-        }
+                INSERT INTO messages_fts (rowid, content)
+                    SELECT id, content FROM messages;
+                INSERT INTO conversation_notes_fts (rowid, notes)
+                    SELECT id, notes FROM conversations WHERE notes IS NOT NULL AND notes != '';
+                INSERT INTO conversation_summaries_fts (rowid, summary)
+                    SELECT id, summary FROM conversations WHERE summary IS NOT NULL AND summary != '';
 
-        Ok(())
+                CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_fts_insert AFTER INSERT ON conversations BEGIN
+                    INSERT INTO conversation_notes_fts (rowid, notes)
+                        SELECT new.id, new.notes WHERE new.notes IS NOT NULL AND new.notes != '';
+                    INSERT INTO conversation_summaries_fts (rowid, summary)
+                        SELECT new.id, new.summary WHERE new.summary IS NOT NULL AND new.summary != '';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_fts_notes_update AFTER UPDATE OF notes ON conversations BEGIN
+                    DELETE FROM conversation_notes_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_notes_fts (rowid, notes)
+                        SELECT new.id, new.notes WHERE new.notes IS NOT NULL AND new.notes != '';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_fts_summary_update AFTER UPDATE OF summary ON conversations BEGIN
+                    DELETE FROM conversation_summaries_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_summaries_fts (rowid, summary)
+                        SELECT new.id, new.summary WHERE new.summary IS NOT NULL AND new.summary != '';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_fts_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM conversation_notes_fts WHERE rowid = old.id;
+                    DELETE FROM conversation_summaries_fts WHERE rowid = old.id;
+                END;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TRIGGER IF EXISTS messages_fts_insert;
+                DROP TRIGGER IF EXISTS messages_fts_delete;
+                DROP TRIGGER IF EXISTS messages_fts_update;
+                DROP TRIGGER IF EXISTS conversations_fts_insert;
+                DROP TRIGGER IF EXISTS conversations_fts_notes_update;
+                DROP TRIGGER IF EXISTS conversations_fts_summary_update;
+                DROP TRIGGER IF EXISTS conversations_fts_delete;
+
+                DROP TABLE IF EXISTS messages_fts;
+                DROP TABLE IF EXISTS conversation_notes_fts;
+                DROP TABLE IF EXISTS conversation_summaries_fts;
+            "#.to_string()),
+        });
+
+        // Rebuilds the FTS tables with prefix indexes for search-as-you-type and
+        // exposes their term dictionaries for typo correction and completion
+        self.migrations.push(Migration {
+            version: 4,
+            name: "add_search_vocabulary".to_string(),
+            up: MigrationAction::Sql(r#"
+                DROP TABLE IF EXISTS messages_fts;
+                DROP TABLE IF EXISTS conversation_notes_fts;
+                DROP TABLE IF EXISTS conversation_summaries_fts;
+
+                CREATE VIRTUAL TABLE messages_fts USING fts5(
+                    content,
+                    tokenize = 'unicode61 remove_diacritics 2',
+                    prefix = '2 3'
+                );
+
+                CREATE VIRTUAL TABLE conversation_notes_fts USING fts5(
+                    notes,
+                    tokenize = 'unicode61 remove_diacritics 2',
+                    prefix = '2 3'
+                );
+
+                CREATE VIRTUAL TABLE conversation_summaries_fts USING fts5(
+                    summary,
+                    tokenize = 'unicode61 remove_diacritics 2',
+                    prefix = '2 3'
+                );
+
+                INSERT INTO messages_fts (rowid, content)
+                    SELECT id, content FROM messages;
+                INSERT INTO conversation_notes_fts (rowid, notes)
+                    SELECT id, notes FROM conversations WHERE notes IS NOT NULL AND notes != '';
+                INSERT INTO conversation_summaries_fts (rowid, summary)
+                    SELECT id, summary FROM conversations WHERE summary IS NOT NULL AND summary != '';
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts_vocab
+                    USING fts5vocab(messages_fts, 'row');
+                CREATE VIRTUAL TABLE IF NOT EXISTS conversation_notes_fts_vocab
+                    USING fts5vocab(conversation_notes_fts, 'row');
+                CREATE VIRTUAL TABLE IF NOT EXISTS conversation_summaries_fts_vocab
+                    USING fts5vocab(conversation_summaries_fts, 'row');
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TABLE IF EXISTS messages_fts_vocab;
+                DROP TABLE IF EXISTS conversation_notes_fts_vocab;
+                DROP TABLE IF EXISTS conversation_summaries_fts_vocab;
+
+                DROP TABLE IF EXISTS messages_fts;
+                DROP TABLE IF EXISTS conversation_notes_fts;
+                DROP TABLE IF EXISTS conversation_summaries_fts;
+
+                CREATE VIRTUAL TABLE messages_fts USING fts5(
+                    content,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
+
+                CREATE VIRTUAL TABLE conversation_notes_fts USING fts5(
+                    notes,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
+
+                CREATE VIRTUAL TABLE conversation_summaries_fts USING fts5(
+                    summary,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
+
+                INSERT INTO messages_fts (rowid, content)
+                    SELECT id, content FROM messages;
+                INSERT INTO conversation_notes_fts (rowid, notes)
+                    SELECT id, notes FROM conversations WHERE notes IS NOT NULL AND notes != '';
+                INSERT INTO conversation_summaries_fts (rowid, summary)
+                    SELECT id, summary FROM conversations WHERE summary IS NOT NULL AND summary != '';
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 5,
+            name: "add_embeddings".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE TABLE IF NOT EXISTS embeddings (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    source_type TEXT NOT NULL,
+                    source_id INTEGER NOT NULL,
+                    conversation_id INTEGER NOT NULL,
+                    model TEXT NOT NULL,
+                    dimensions INTEGER NOT NULL,
+                    vector BLOB NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE(source_type, source_id, model)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_embeddings_model ON embeddings(model);
+
+                CREATE TRIGGER IF NOT EXISTS messages_embeddings_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS messages_embeddings_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_embeddings_notes_update AFTER UPDATE OF notes ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'notes' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_embeddings_summary_update AFTER UPDATE OF summary ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'summary' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_embeddings_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type IN ('notes', 'summary') AND source_id = old.id;
+                END;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TRIGGER IF EXISTS messages_embeddings_update;
+                DROP TRIGGER IF EXISTS messages_embeddings_delete;
+                DROP TRIGGER IF EXISTS conversations_embeddings_notes_update;
+                DROP TRIGGER IF EXISTS conversations_embeddings_summary_update;
+                DROP TRIGGER IF EXISTS conversations_embeddings_delete;
+
+                DROP TABLE IF EXISTS embeddings;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 6,
+            name: "add_message_partial_flag".to_string(),
+            up: MigrationAction::Sql(r#"
+                ALTER TABLE messages ADD COLUMN partial INTEGER NOT NULL DEFAULT 0;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                ALTER TABLE messages DROP COLUMN partial;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 7,
+            name: "add_secrets".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE TABLE IF NOT EXISTS secrets (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    provider TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    nonce BLOB NOT NULL,
+                    ciphertext BLOB NOT NULL,
+                    is_active INTEGER NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE(provider, name)
+                );
+
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_secrets_active
+                    ON secrets(provider) WHERE is_active = 1;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TABLE IF EXISTS secrets;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 8,
+            name: "add_tags".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE TABLE IF NOT EXISTS tags (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    color TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+
+                CREATE TABLE IF NOT EXISTS conversation_tags (
+                    conversation_id INTEGER NOT NULL,
+                    tag_id INTEGER NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    PRIMARY KEY(conversation_id, tag_id),
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE,
+                    FOREIGN KEY(tag_id) REFERENCES tags(id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_conversation_tags_tag ON conversation_tags(tag_id);
+
+                CREATE TRIGGER IF NOT EXISTS conversations_tags_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM conversation_tags WHERE conversation_id = old.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS tags_links_delete AFTER DELETE ON tags BEGIN
+                    DELETE FROM conversation_tags WHERE tag_id = old.id;
+                END;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TRIGGER IF EXISTS conversations_tags_delete;
+                DROP TRIGGER IF EXISTS tags_links_delete;
+
+                DROP TABLE IF EXISTS conversation_tags;
+                DROP TABLE IF EXISTS tags;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 9,
+            name: "add_folders".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE TABLE IF NOT EXISTS folders (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    parent_id INTEGER,
+                    name TEXT NOT NULL,
+                    position INTEGER NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(parent_id) REFERENCES folders(id)
+                );
+
+                ALTER TABLE conversations ADD COLUMN folder_id INTEGER REFERENCES folders(id);
+                ALTER TABLE conversations ADD COLUMN folder_position INTEGER NOT NULL DEFAULT 0;
+
+                CREATE INDEX IF NOT EXISTS idx_folders_parent ON folders(parent_id, position);
+                CREATE INDEX IF NOT EXISTS idx_conversations_folder ON conversations(folder_id, folder_position);
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP INDEX IF EXISTS idx_conversations_folder;
+
+                CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence;
+
+                CREATE TABLE conversations_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    summary TEXT,
+                    notes TEXT
+                );
+                INSERT INTO conversations_new (id, name, created_at, summary, notes)
+                    SELECT id, name, created_at, summary, notes FROM conversations;
+                DROP TABLE conversations;
+                ALTER TABLE conversations_new RENAME TO conversations;
+
+                DELETE FROM sqlite_sequence WHERE name = 'conversations';
+                INSERT INTO sqlite_sequence (name, seq)
+                    SELECT name, seq FROM temp.saved_sequence WHERE name = 'conversations';
+                DROP TABLE temp.saved_sequence;
+
+                DROP TABLE IF EXISTS folders;
+
+                CREATE TRIGGER conversations_fts_insert AFTER INSERT ON conversations BEGIN
+                    INSERT INTO conversation_notes_fts (rowid, notes)
+                        SELECT new.id, new.notes WHERE new.notes IS NOT NULL AND new.notes != '';
+                    INSERT INTO conversation_summaries_fts (rowid, summary)
+                        SELECT new.id, new.summary WHERE new.summary IS NOT NULL AND new.summary != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_notes_update AFTER UPDATE OF notes ON conversations BEGIN
+                    DELETE FROM conversation_notes_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_notes_fts (rowid, notes)
+                        SELECT new.id, new.notes WHERE new.notes IS NOT NULL AND new.notes != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_summary_update AFTER UPDATE OF summary ON conversations BEGIN
+                    DELETE FROM conversation_summaries_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_summaries_fts (rowid, summary)
+                        SELECT new.id, new.summary WHERE new.summary IS NOT NULL AND new.summary != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM conversation_notes_fts WHERE rowid = old.id;
+                    DELETE FROM conversation_summaries_fts WHERE rowid = old.id;
+                END;
+
+                CREATE TRIGGER conversations_embeddings_notes_update AFTER UPDATE OF notes ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'notes' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_embeddings_summary_update AFTER UPDATE OF summary ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'summary' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_embeddings_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type IN ('notes', 'summary') AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_tags_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM conversation_tags WHERE conversation_id = old.id;
+                END;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 10,
+            name: "add_conversation_trash".to_string(),
+            up: MigrationAction::Sql(r#"
+                ALTER TABLE conversations ADD COLUMN deleted_at DATETIME;
+
+                CREATE INDEX IF NOT EXISTS idx_conversations_deleted ON conversations(deleted_at);
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP INDEX IF EXISTS idx_conversations_deleted;
+                ALTER TABLE conversations DROP COLUMN deleted_at;
+            "#.to_string()),
+        });
+
+        // SQLite can't add ON DELETE actions to existing columns, so every
+        // table that points at a conversation or folder is rebuilt. Orphans
+        // left behind while foreign keys were unenforced are removed first,
+        // and the triggers are dropped up front because renaming a table
+        // re-checks every trigger that mentions a table that is gone.
+        self.migrations.push(Migration {
+            version: 11,
+            name: "enforce_foreign_keys".to_string(),
+            up: MigrationAction::Sql(r#"
+                DELETE FROM messages WHERE conversation_id NOT IN (SELECT id FROM conversations);
+                DELETE FROM mindmaps WHERE conversation_id NOT IN (SELECT id FROM conversations);
+                DELETE FROM conversation_tags
+                    WHERE conversation_id NOT IN (SELECT id FROM conversations)
+                       OR tag_id NOT IN (SELECT id FROM tags);
+                DELETE FROM embeddings
+                    WHERE conversation_id NOT IN (SELECT id FROM conversations)
+                       OR (source_type = 'message' AND source_id NOT IN (SELECT id FROM messages));
+                UPDATE conversations SET folder_id = NULL
+                    WHERE folder_id IS NOT NULL AND folder_id NOT IN (SELECT id FROM folders);
+                UPDATE folders SET parent_id = NULL
+                    WHERE parent_id IS NOT NULL AND parent_id NOT IN (SELECT id FROM folders);
+
+                DROP TRIGGER IF EXISTS messages_fts_insert;
+                DROP TRIGGER IF EXISTS messages_fts_delete;
+                DROP TRIGGER IF EXISTS messages_fts_update;
+                DROP TRIGGER IF EXISTS messages_embeddings_update;
+                DROP TRIGGER IF EXISTS messages_embeddings_delete;
+                DROP TRIGGER IF EXISTS conversations_fts_insert;
+                DROP TRIGGER IF EXISTS conversations_fts_notes_update;
+                DROP TRIGGER IF EXISTS conversations_fts_summary_update;
+                DROP TRIGGER IF EXISTS conversations_fts_delete;
+                DROP TRIGGER IF EXISTS conversations_embeddings_notes_update;
+                DROP TRIGGER IF EXISTS conversations_embeddings_summary_update;
+                DROP TRIGGER IF EXISTS conversations_embeddings_delete;
+                DROP TRIGGER IF EXISTS conversations_tags_delete;
+                DROP TRIGGER IF EXISTS tags_links_delete;
+
+                CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence;
+
+                CREATE TABLE folders_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    parent_id INTEGER,
+                    name TEXT NOT NULL,
+                    position INTEGER NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(parent_id) REFERENCES folders(id) ON DELETE CASCADE
+                );
+                INSERT INTO folders_new (id, parent_id, name, position, created_at, updated_at)
+                    SELECT id, parent_id, name, position, created_at, updated_at FROM folders;
+                DROP TABLE folders;
+                ALTER TABLE folders_new RENAME TO folders;
+
+                CREATE TABLE conversations_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    summary TEXT,
+                    notes TEXT,
+                    folder_id INTEGER,
+                    folder_position INTEGER NOT NULL DEFAULT 0,
+                    deleted_at DATETIME,
+                    FOREIGN KEY(folder_id) REFERENCES folders(id) ON DELETE SET NULL
+                );
+                INSERT INTO conversations_new (id, name, created_at, summary, notes, folder_id, folder_position, deleted_at)
+                    SELECT id, name, created_at, summary, notes, folder_id, folder_position, deleted_at FROM conversations;
+                DROP TABLE conversations;
+                ALTER TABLE conversations_new RENAME TO conversations;
+
+                CREATE TABLE messages_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL,
+                    role TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    seq INTEGER NOT NULL,
+                    partial INTEGER NOT NULL DEFAULT 0,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+                INSERT INTO messages_new (id, conversation_id, role, content, seq, partial)
+                    SELECT id, conversation_id, role, content, seq, partial FROM messages;
+                DROP TABLE messages;
+                ALTER TABLE messages_new RENAME TO messages;
+
+                CREATE TABLE mindmaps_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL UNIQUE,
+                    title TEXT NOT NULL,
+                    nodes TEXT NOT NULL,
+                    connections TEXT NOT NULL,
+                    theme TEXT DEFAULT 'default',
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+                INSERT INTO mindmaps_new (id, conversation_id, title, nodes, connections, theme, created_at, updated_at)
+                    SELECT id, conversation_id, title, nodes, connections, theme, created_at, updated_at FROM mindmaps;
+                DROP TABLE mindmaps;
+                ALTER TABLE mindmaps_new RENAME TO mindmaps;
+
+                CREATE TABLE embeddings_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    source_type TEXT NOT NULL,
+                    source_id INTEGER NOT NULL,
+                    conversation_id INTEGER NOT NULL,
+                    model TEXT NOT NULL,
+                    dimensions INTEGER NOT NULL,
+                    vector BLOB NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE(source_type, source_id, model),
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+                INSERT INTO embeddings_new (id, source_type, source_id, conversation_id, model, dimensions, vector, created_at)
+                    SELECT id, source_type, source_id, conversation_id, model, dimensions, vector, created_at FROM embeddings;
+                DROP TABLE embeddings;
+                ALTER TABLE embeddings_new RENAME TO embeddings;
+
+                DELETE FROM sqlite_sequence
+                    WHERE name IN ('folders', 'conversations', 'messages', 'mindmaps', 'embeddings');
+                INSERT INTO sqlite_sequence (name, seq)
+                    SELECT name, seq FROM temp.saved_sequence
+                    WHERE name IN ('folders', 'conversations', 'messages', 'mindmaps', 'embeddings');
+                DROP TABLE temp.saved_sequence;
+
+                CREATE INDEX IF NOT EXISTS idx_folders_parent ON folders(parent_id, position);
+                CREATE INDEX IF NOT EXISTS idx_conversations_folder ON conversations(folder_id, folder_position);
+                CREATE INDEX IF NOT EXISTS idx_conversations_deleted ON conversations(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_messages_conversation ON messages(conversation_id);
+                CREATE INDEX IF NOT EXISTS idx_messages_seq ON messages(seq);
+                CREATE INDEX IF NOT EXISTS idx_mindmaps_conversation ON mindmaps(conversation_id);
+                CREATE INDEX IF NOT EXISTS idx_embeddings_model ON embeddings(model);
+                CREATE INDEX IF NOT EXISTS idx_embeddings_conversation ON embeddings(conversation_id);
+
+                CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                END;
+
+                CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER messages_embeddings_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER messages_embeddings_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_fts_insert AFTER INSERT ON conversations BEGIN
+                    INSERT INTO conversation_notes_fts (rowid, notes)
+                        SELECT new.id, new.notes WHERE new.notes IS NOT NULL AND new.notes != '';
+                    INSERT INTO conversation_summaries_fts (rowid, summary)
+                        SELECT new.id, new.summary WHERE new.summary IS NOT NULL AND new.summary != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_notes_update AFTER UPDATE OF notes ON conversations BEGIN
+                    DELETE FROM conversation_notes_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_notes_fts (rowid, notes)
+                        SELECT new.id, new.notes WHERE new.notes IS NOT NULL AND new.notes != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_summary_update AFTER UPDATE OF summary ON conversations BEGIN
+                    DELETE FROM conversation_summaries_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_summaries_fts (rowid, summary)
+                        SELECT new.id, new.summary WHERE new.summary IS NOT NULL AND new.summary != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM conversation_notes_fts WHERE rowid = old.id;
+                    DELETE FROM conversation_summaries_fts WHERE rowid = old.id;
+                END;
+
+                CREATE TRIGGER conversations_embeddings_notes_update AFTER UPDATE OF notes ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'notes' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_embeddings_summary_update AFTER UPDATE OF summary ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'summary' AND source_id = old.id;
+                END;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TRIGGER IF EXISTS messages_fts_insert;
+                DROP TRIGGER IF EXISTS messages_fts_delete;
+                DROP TRIGGER IF EXISTS messages_fts_update;
+                DROP TRIGGER IF EXISTS messages_embeddings_update;
+                DROP TRIGGER IF EXISTS messages_embeddings_delete;
+                DROP TRIGGER IF EXISTS conversations_fts_insert;
+                DROP TRIGGER IF EXISTS conversations_fts_notes_update;
+                DROP TRIGGER IF EXISTS conversations_fts_summary_update;
+                DROP TRIGGER IF EXISTS conversations_fts_delete;
+                DROP TRIGGER IF EXISTS conversations_embeddings_notes_update;
+                DROP TRIGGER IF EXISTS conversations_embeddings_summary_update;
+                DROP TRIGGER IF EXISTS conversations_embeddings_delete;
+                DROP TRIGGER IF EXISTS conversations_tags_delete;
+                DROP TRIGGER IF EXISTS tags_links_delete;
+
+                CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence;
+
+                CREATE TABLE folders_old (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    parent_id INTEGER,
+                    name TEXT NOT NULL,
+                    position INTEGER NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(parent_id) REFERENCES folders(id)
+                );
+                INSERT INTO folders_old (id, parent_id, name, position, created_at, updated_at)
+                    SELECT id, parent_id, name, position, created_at, updated_at FROM folders;
+                DROP TABLE folders;
+                ALTER TABLE folders_old RENAME TO folders;
+
+                CREATE TABLE conversations_old (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    summary TEXT,
+                    notes TEXT,
+                    folder_id INTEGER REFERENCES folders(id),
+                    folder_position INTEGER NOT NULL DEFAULT 0,
+                    deleted_at DATETIME
+                );
+                INSERT INTO conversations_old (id, name, created_at, summary, notes, folder_id, folder_position, deleted_at)
+                    SELECT id, name, created_at, summary, notes, folder_id, folder_position, deleted_at FROM conversations;
+                DROP TABLE conversations;
+                ALTER TABLE conversations_old RENAME TO conversations;
+
+                CREATE TABLE messages_old (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL,
+                    role TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    seq INTEGER NOT NULL,
+                    partial INTEGER NOT NULL DEFAULT 0,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id)
+                );
+                INSERT INTO messages_old (id, conversation_id, role, content, seq, partial)
+                    SELECT id, conversation_id, role, content, seq, partial FROM messages;
+                DROP TABLE messages;
+                ALTER TABLE messages_old RENAME TO messages;
+
+                CREATE TABLE mindmaps_old (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL UNIQUE,
+                    title TEXT NOT NULL,
+                    nodes TEXT NOT NULL,
+                    connections TEXT NOT NULL,
+                    theme TEXT DEFAULT 'default',
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id)
+                );
+                INSERT INTO mindmaps_old (id, conversation_id, title, nodes, connections, theme, created_at, updated_at)
+                    SELECT id, conversation_id, title, nodes, connections, theme, created_at, updated_at FROM mindmaps;
+                DROP TABLE mindmaps;
+                ALTER TABLE mindmaps_old RENAME TO mindmaps;
+
+                CREATE TABLE embeddings_old (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    source_type TEXT NOT NULL,
+                    source_id INTEGER NOT NULL,
+                    conversation_id INTEGER NOT NULL,
+                    model TEXT NOT NULL,
+                    dimensions INTEGER NOT NULL,
+                    vector BLOB NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE(source_type, source_id, model)
+                );
+                INSERT INTO embeddings_old (id, source_type, source_id, conversation_id, model, dimensions, vector, created_at)
+                    SELECT id, source_type, source_id, conversation_id, model, dimensions, vector, created_at FROM embeddings;
+                DROP TABLE embeddings;
+                ALTER TABLE embeddings_old RENAME TO embeddings;
+
+                DELETE FROM sqlite_sequence
+                    WHERE name IN ('folders', 'conversations', 'messages', 'mindmaps', 'embeddings');
+                INSERT INTO sqlite_sequence (name, seq)
+                    SELECT name, seq FROM temp.saved_sequence
+                    WHERE name IN ('folders', 'conversations', 'messages', 'mindmaps', 'embeddings');
+                DROP TABLE temp.saved_sequence;
+
+                CREATE INDEX IF NOT EXISTS idx_folders_parent ON folders(parent_id, position);
+                CREATE INDEX IF NOT EXISTS idx_conversations_folder ON conversations(folder_id, folder_position);
+                CREATE INDEX IF NOT EXISTS idx_conversations_deleted ON conversations(deleted_at);
+                CREATE INDEX IF NOT EXISTS idx_messages_conversation ON messages(conversation_id);
+                CREATE INDEX IF NOT EXISTS idx_messages_seq ON messages(seq);
+                CREATE INDEX IF NOT EXISTS idx_mindmaps_conversation ON mindmaps(conversation_id);
+                CREATE INDEX IF NOT EXISTS idx_embeddings_model ON embeddings(model);
+
+                CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                END;
+
+                CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER messages_embeddings_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER messages_embeddings_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_fts_insert AFTER INSERT ON conversations BEGIN
+                    INSERT INTO conversation_notes_fts (rowid, notes)
+                        SELECT new.id, new.notes WHERE new.notes IS NOT NULL AND new.notes != '';
+                    INSERT INTO conversation_summaries_fts (rowid, summary)
+                        SELECT new.id, new.summary WHERE new.summary IS NOT NULL AND new.summary != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_notes_update AFTER UPDATE OF notes ON conversations BEGIN
+                    DELETE FROM conversation_notes_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_notes_fts (rowid, notes)
+                        SELECT new.id, new.notes WHERE new.notes IS NOT NULL AND new.notes != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_summary_update AFTER UPDATE OF summary ON conversations BEGIN
+                    DELETE FROM conversation_summaries_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_summaries_fts (rowid, summary)
+                        SELECT new.id, new.summary WHERE new.summary IS NOT NULL AND new.summary != '';
+                END;
+
+                CREATE TRIGGER conversations_fts_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM conversation_notes_fts WHERE rowid = old.id;
+                    DELETE FROM conversation_summaries_fts WHERE rowid = old.id;
+                END;
+
+                CREATE TRIGGER conversations_embeddings_notes_update AFTER UPDATE OF notes ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'notes' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_embeddings_summary_update AFTER UPDATE OF summary ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'summary' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_embeddings_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM embeddings WHERE source_type IN ('notes', 'summary') AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER conversations_tags_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM conversation_tags WHERE conversation_id = old.id;
+                END;
+
+                CREATE TRIGGER tags_links_delete AFTER DELETE ON tags BEGIN
+                    DELETE FROM conversation_tags WHERE tag_id = old.id;
+                END;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 12,
+            name: "add_message_metadata_and_versions".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence WHERE name = 'messages';
+
+                -- seq was the insert time in milliseconds, which two quick inserts can
+                -- share; it becomes the message's position in its conversation. The old
+                -- value is the best record of when a message was written, and the
+                -- message before it is its parent.
+                CREATE TABLE messages_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL,
+                    role TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    seq INTEGER NOT NULL,
+                    partial INTEGER NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    model TEXT,
+                    prompt_tokens INTEGER,
+                    completion_tokens INTEGER,
+                    latency_ms INTEGER,
+                    parent_id INTEGER,
+                    edited_at DATETIME,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE,
+                    FOREIGN KEY(parent_id) REFERENCES messages(id) ON DELETE SET NULL
+                );
+                INSERT INTO messages_new (id, conversation_id, role, content, seq, partial, created_at, parent_id)
+                    SELECT
+                        id,
+                        conversation_id,
+                        role,
+                        content,
+                        ROW_NUMBER() OVER (PARTITION BY conversation_id ORDER BY seq, id),
+                        partial,
+                        CASE WHEN seq > 100000000000 THEN datetime(seq / 1000, 'unixepoch') END,
+                        LAG(id) OVER (PARTITION BY conversation_id ORDER BY seq, id)
+                    FROM messages;
+                DROP TABLE messages;
+                ALTER TABLE messages_new RENAME TO messages;
+
+                DELETE FROM sqlite_sequence WHERE name = 'messages';
+                INSERT INTO sqlite_sequence (name, seq) SELECT name, seq FROM temp.saved_sequence;
+                DROP TABLE temp.saved_sequence;
+
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_conversation_seq ON messages(conversation_id, seq);
+                CREATE INDEX IF NOT EXISTS idx_messages_parent ON messages(parent_id);
+
+                CREATE TABLE IF NOT EXISTS message_versions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    message_id INTEGER NOT NULL,
+                    version INTEGER NOT NULL,
+                    content TEXT NOT NULL,
+                    replaced_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE(message_id, version),
+                    FOREIGN KEY(message_id) REFERENCES messages(id) ON DELETE CASCADE
+                );
+
+                CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                END;
+
+                CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER messages_embeddings_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER messages_embeddings_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TABLE IF EXISTS message_versions;
+
+                CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence WHERE name = 'messages';
+
+                CREATE TABLE messages_old (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL,
+                    role TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    seq INTEGER NOT NULL,
+                    partial INTEGER NOT NULL DEFAULT 0,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+                INSERT INTO messages_old (id, conversation_id, role, content, seq, partial)
+                    SELECT id, conversation_id, role, content, seq, partial FROM messages;
+                DROP TABLE messages;
+                ALTER TABLE messages_old RENAME TO messages;
+
+                DELETE FROM sqlite_sequence WHERE name = 'messages';
+                INSERT INTO sqlite_sequence (name, seq) SELECT name, seq FROM temp.saved_sequence;
+                DROP TABLE temp.saved_sequence;
+
+                CREATE INDEX IF NOT EXISTS idx_messages_conversation ON messages(conversation_id);
+                CREATE INDEX IF NOT EXISTS idx_messages_seq ON messages(seq);
+
+                CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages BEGIN
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                END;
+
+                CREATE TRIGGER messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM messages_fts WHERE rowid = old.id;
+                    INSERT INTO messages_fts (rowid, content) VALUES (new.id, new.content);
+                END;
+
+                CREATE TRIGGER messages_embeddings_update AFTER UPDATE OF content ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+
+                CREATE TRIGGER messages_embeddings_delete AFTER DELETE ON messages BEGIN
+                    DELETE FROM embeddings WHERE source_type = 'message' AND source_id = old.id;
+                END;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 13,
+            name: "add_conversation_branches".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE TABLE IF NOT EXISTS conversation_branches (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL,
+                    name TEXT NOT NULL,
+                    fork_message_id INTEGER,
+                    head_message_id INTEGER NOT NULL,
+                    active INTEGER NOT NULL DEFAULT 0,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE,
+                    FOREIGN KEY(fork_message_id) REFERENCES messages(id) ON DELETE CASCADE,
+                    FOREIGN KEY(head_message_id) REFERENCES messages(id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_branches_conversation ON conversation_branches(conversation_id);
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_branches_active ON conversation_branches(conversation_id) WHERE active = 1;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TABLE IF EXISTS conversation_branches;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 14,
+            name: "add_whiteboards".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE TABLE IF NOT EXISTS whiteboards (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL UNIQUE,
+                    data TEXT NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TABLE IF EXISTS whiteboards;
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 15,
+            name: "add_whiteboard_operations".to_string(),
+            up: MigrationAction::Sql(r#"
+                ALTER TABLE whiteboards ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE whiteboards ADD COLUMN snapshot_revision INTEGER NOT NULL DEFAULT 0;
+
+                CREATE TABLE IF NOT EXISTS whiteboard_operations (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    whiteboard_id INTEGER NOT NULL,
+                    revision INTEGER NOT NULL,
+                    op TEXT NOT NULL,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE(whiteboard_id, revision),
+                    FOREIGN KEY(whiteboard_id) REFERENCES whiteboards(id) ON DELETE CASCADE
+                );
+            "#.to_string()),
+            // Boards go back to being a single blob, so each one's log is
+            // folded into it first; undone operations are dropped
+            down: MigrationAction::Code(Box::new(|conn, progress| {
+                let boards = {
+                    let mut stmt = conn.prepare("SELECT id, revision FROM whiteboards WHERE revision > snapshot_revision")
+                        .map_err(|e| RweError::database("Prepare error", e))?;
+                    let board_iter = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))
+                        .map_err(|e| RweError::database("Query error", e))?;
+                    board_iter.collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| RweError::database("Row error", e))?
+                };
+                for (done, (id, revision)) in boards.iter().enumerate() {
+                    whiteboards::compact(conn, *id, *revision)?;
+                    progress.report(done as u64 + 1, boards.len() as u64);
+                }
+                conn.execute_batch(r#"
+                    DROP TABLE IF EXISTS whiteboard_operations;
+                    ALTER TABLE whiteboards DROP COLUMN snapshot_revision;
+                    ALTER TABLE whiteboards DROP COLUMN revision;
+                "#).map_err(|e| RweError::database("Failed to drop whiteboard operations", e))
+            })),
+        });
+
+        self.migrations.push(Migration {
+            version: 16,
+            name: "add_mindmap_versions".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence WHERE name = 'mindmaps';
+
+                -- A conversation can have any number of maps
+                CREATE TABLE mindmaps_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL,
+                    title TEXT NOT NULL,
+                    nodes TEXT NOT NULL,
+                    connections TEXT NOT NULL,
+                    theme TEXT DEFAULT 'default',
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+                INSERT INTO mindmaps_new (id, conversation_id, title, nodes, connections, theme, created_at, updated_at)
+                    SELECT id, conversation_id, title, nodes, connections, theme, created_at, updated_at FROM mindmaps;
+                DROP TABLE mindmaps;
+                ALTER TABLE mindmaps_new RENAME TO mindmaps;
+
+                DELETE FROM sqlite_sequence WHERE name = 'mindmaps';
+                INSERT INTO sqlite_sequence (name, seq) SELECT name, seq FROM temp.saved_sequence;
+                DROP TABLE temp.saved_sequence;
+
+                CREATE INDEX IF NOT EXISTS idx_mindmaps_conversation ON mindmaps(conversation_id);
+
+                CREATE TABLE IF NOT EXISTS mindmap_versions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    mindmap_id INTEGER NOT NULL,
+                    version INTEGER NOT NULL,
+                    title TEXT NOT NULL,
+                    nodes TEXT NOT NULL,
+                    connections TEXT NOT NULL,
+                    theme TEXT,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE(mindmap_id, version),
+                    FOREIGN KEY(mindmap_id) REFERENCES mindmaps(id) ON DELETE CASCADE
+                );
+
+                -- Existing maps start their history at what they hold now
+                INSERT INTO mindmap_versions (mindmap_id, version, title, nodes, connections, theme, created_at)
+                    SELECT id, 1, title, nodes, connections, theme, updated_at FROM mindmaps;
+            "#.to_string()),
+            // Only one map per conversation fits the old table; the most
+            // recently saved one is kept
+            down: MigrationAction::Sql(r#"
+                CREATE TEMP TABLE saved_sequence AS SELECT name, seq FROM sqlite_sequence WHERE name = 'mindmaps';
+
+                DROP TABLE IF EXISTS mindmap_versions;
+
+                CREATE TABLE mindmaps_old (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    conversation_id INTEGER NOT NULL UNIQUE,
+                    title TEXT NOT NULL,
+                    nodes TEXT NOT NULL,
+                    connections TEXT NOT NULL,
+                    theme TEXT DEFAULT 'default',
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+                );
+                INSERT INTO mindmaps_old (id, conversation_id, title, nodes, connections, theme, created_at, updated_at)
+                    SELECT id, conversation_id, title, nodes, connections, theme, created_at, updated_at FROM mindmaps m
+                    WHERE m.id = (
+                        SELECT latest.id FROM mindmaps latest WHERE latest.conversation_id = m.conversation_id
+                        ORDER BY latest.updated_at DESC, latest.id DESC LIMIT 1
+                    );
+                DROP TABLE mindmaps;
+                ALTER TABLE mindmaps_old RENAME TO mindmaps;
+
+                DELETE FROM sqlite_sequence WHERE name = 'mindmaps';
+                INSERT INTO sqlite_sequence (name, seq) SELECT name, seq FROM temp.saved_sequence;
+                DROP TABLE temp.saved_sequence;
+
+                CREATE INDEX IF NOT EXISTS idx_mindmaps_conversation ON mindmaps(conversation_id);
+            "#.to_string()),
+        });
+
+        self.migrations.push(Migration {
+            version: 17,
+            name: "normalize_mindmap_graphs".to_string(),
+            // Nodes and connections move out of JSON columns into their own
+            // tables. Whatever in the old JSON breaks the schema is dropped,
+            // and versions are rewritten in the schema's JSON
+            up: MigrationAction::Code(Box::new(|conn, progress| {
+                let mindmaps = mindmap_json_rows(conn, "SELECT id, nodes, connections FROM mindmaps")?;
+                let versions = mindmap_json_rows(conn, "SELECT id, nodes, connections FROM mindmap_versions")?;
+                conn.execute_batch(MINDMAP_GRAPH_TABLES)
+                    .map_err(|e| RweError::database("Failed to create mind map tables", e))?;
+
+                let total = (mindmaps.len() + versions.len()) as u64;
+                for (done, (id, nodes, connections)) in mindmaps.iter().enumerate() {
+                    let (nodes, edges) = mindmap_schema::salvage(nodes, connections);
+                    mindmaps::write_graph(conn, *id, &nodes, &edges)?;
+                    progress.report(done as u64 + 1, total);
+                }
+                for (done, (id, nodes, connections)) in versions.iter().enumerate() {
+                    let (nodes, edges) = mindmap_schema::salvage(nodes, connections);
+                    let (nodes, connections) = mindmap_schema::to_json(&nodes, &edges)
+                        .map_err(|e| RweError::Internal(format!("Failed to encode mind map: {}", e)))?;
+                    conn.execute("UPDATE mindmap_versions SET nodes = ?1, connections = ?2 WHERE id = ?3", params![nodes, connections, id])
+                        .map_err(|e| RweError::database("Failed to rewrite mind map version", e))?;
+                    progress.report((mindmaps.len() + done) as u64 + 1, total);
+                }
+                Ok(())
+            })),
+            down: MigrationAction::Code(Box::new(|conn, progress| {
+                let ids = {
+                    let mut stmt = conn.prepare("SELECT id FROM mindmaps")
+                        .map_err(|e| RweError::database("Prepare error", e))?;
+                    let id_iter = stmt.query_map([], |row| row.get::<_, i64>(0))
+                        .map_err(|e| RweError::database("Query error", e))?;
+                    id_iter.collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| RweError::database("Row error", e))?
+                };
+                let mut graphs = Vec::new();
+                for id in ids {
+                    let (nodes, edges) = mindmaps::load_graph(conn, id)?;
+                    let json = mindmap_schema::legacy_json(&nodes, &edges)
+                        .map_err(|e| RweError::Internal(format!("Failed to encode mind map: {}", e)))?;
+                    graphs.push((id, json));
+                }
+                let versions = mindmap_json_rows(conn, "SELECT id, nodes, connections FROM mindmap_versions")?;
+
+                conn.execute_batch(MINDMAP_JSON_TABLE)
+                    .map_err(|e| RweError::database("Failed to restore mind map table", e))?;
+                let total = (graphs.len() + versions.len()) as u64;
+                for (done, (id, (nodes, connections))) in graphs.iter().enumerate() {
+                    conn.execute("UPDATE mindmaps SET nodes = ?1, connections = ?2 WHERE id = ?3", params![nodes, connections, id])
+                        .map_err(|e| RweError::database("Failed to restore mind map", e))?;
+                    progress.report(done as u64 + 1, total);
+                }
+                for (done, (id, nodes, connections)) in versions.iter().enumerate() {
+                    let (nodes, edges) = mindmap_schema::salvage(nodes, connections);
+                    let (nodes, connections) = mindmap_schema::legacy_json(&nodes, &edges)
+                        .map_err(|e| RweError::Internal(format!("Failed to encode mind map: {}", e)))?;
+                    conn.execute("UPDATE mindmap_versions SET nodes = ?1, connections = ?2 WHERE id = ?3", params![nodes, connections, id])
+                        .map_err(|e| RweError::database("Failed to rewrite mind map version", e))?;
+                    progress.report((graphs.len() + done) as u64 + 1, total);
+                }
+                Ok(())
+            })),
+        });
+
+        // Conversation names become a search source next to messages, notes
+        // and summaries, with a vocabulary for typo correction
+        self.migrations.push(Migration {
+            version: 18,
+            name: "add_conversation_name_search".to_string(),
+            up: MigrationAction::Sql(r#"
+                CREATE VIRTUAL TABLE IF NOT EXISTS conversation_names_fts USING fts5(
+                    name,
+                    tokenize = 'unicode61 remove_diacritics 2',
+                    prefix = '2 3'
+                );
+
+                INSERT INTO conversation_names_fts (rowid, name)
+                    SELECT id, name FROM conversations;
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS conversation_names_fts_vocab
+                    USING fts5vocab(conversation_names_fts, 'row');
+
+                CREATE TRIGGER IF NOT EXISTS conversations_fts_name_insert AFTER INSERT ON conversations BEGIN
+                    INSERT INTO conversation_names_fts (rowid, name) VALUES (new.id, new.name);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_fts_name_update AFTER UPDATE OF name ON conversations BEGIN
+                    DELETE FROM conversation_names_fts WHERE rowid = old.id;
+                    INSERT INTO conversation_names_fts (rowid, name) VALUES (new.id, new.name);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS conversations_fts_name_delete AFTER DELETE ON conversations BEGIN
+                    DELETE FROM conversation_names_fts WHERE rowid = old.id;
+                END;
+            "#.to_string()),
+            down: MigrationAction::Sql(r#"
+                DROP TRIGGER IF EXISTS conversations_fts_name_insert;
+                DROP TRIGGER IF EXISTS conversations_fts_name_update;
+                DROP TRIGGER IF EXISTS conversations_fts_name_delete;
+
+                DROP TABLE IF EXISTS conversation_names_fts_vocab;
+                DROP TABLE IF EXISTS conversation_names_fts;
+            "#.to_string()),
+        });
+    }
+
+    /// Sends `migration://progress` events to the frontend while migrating.
+    pub fn with_progress(self, app: AppHandle) -> Self {
+        self.on_progress(Box::new(move |event| {
+            if let Err(e) = app.emit("migration://progress", event) {
+                eprintln!("Failed to emit migration progress: {}", e);
+            }
+        }))
+    }
+
+    fn on_progress(mut self, sink: ProgressSink) -> Self {
+        self.progress = Some(sink);
+        self
+    }
+
+    pub fn latest_version(&self) -> u32 {
+        self.migrations.last().map_or(0, |migration| migration.version)
+    }
+
+    pub fn setup_migration_table(&self, conn: &Connection) -> Result<(), RweError> {
+        conn.execute(
+            r#"
+            CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                checksum TEXT
+            )
+            "#,
+            [],
+        ).map_err(|e| RweError::database("Migration error", e))?;
+
+        // Tables created before checksums were recorded
+        let has_checksum: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('schema_migrations') WHERE name = 'checksum')",
+            [],
+            |row| row.get(0),
+        ).map_err(|e| RweError::database("Migration error", e))?;
+        if !has_checksum {
+            conn.execute("ALTER TABLE schema_migrations ADD COLUMN checksum TEXT", [])
+                .map_err(|e| RweError::database("Migration error", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_current_version(&self, conn: &Connection) -> Result<u32, RweError> {
+        match conn.query_row(
+            "SELECT MAX(version) FROM schema_migrations",
+            [],
+            |row| row.get::<_, Option<u32>>(0),
+        ) {
+            Ok(Some(version)) => Ok(version),
+            Ok(None) => Ok(0),
+            Err(e) => Err(RweError::database("Failed to read schema version", e)),
+        }
+    }
+
+    fn applied_checksums(&self, conn: &Connection) -> Result<Vec<(u32, Option<String>)>, RweError> {
+        let mut stmt = conn.prepare("SELECT version, checksum FROM schema_migrations ORDER BY version")
+            .map_err(|e| RweError::database("Migration error", e))?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| RweError::database("Migration error", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RweError::database("Migration error", e))
+    }
+
+    /// Records checksums for migrations applied before they were kept,
+    /// trusting that what ran then is what this build has.
+    pub fn backfill_checksums(&self, conn: &Connection) -> Result<(), RweError> {
+        for (version, checksum) in self.applied_checksums(conn)? {
+            let Some(migration) = self.find(version) else { continue };
+            if checksum.is_none() {
+                conn.execute(
+                    "UPDATE schema_migrations SET checksum = ?1 WHERE version = ?2",
+                    params![migration.checksum(), version],
+                ).map_err(|e| RweError::database("Migration error", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applied migrations whose SQL has been edited since they ran.
+    pub fn checksum_mismatches(&self, conn: &Connection) -> Result<Vec<ChecksumMismatch>, RweError> {
+        let mut mismatches = Vec::new();
+        for (version, checksum) in self.applied_checksums(conn)? {
+            let (Some(migration), Some(applied)) = (self.find(version), checksum) else { continue };
+            let current = migration.checksum();
+            if applied != current {
+                mismatches.push(ChecksumMismatch {
+                    version,
+                    name: migration.name.clone(),
+                    applied,
+                    current,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+
+    fn find(&self, version: u32) -> Option<&Migration> {
+        self.migrations.iter().find(|migration| migration.version == version)
+    }
+
+    fn steps(&self, current_version: u32, target_version: u32) -> Vec<(&Migration, Direction)> {
+        if target_version >= current_version {
+            self.migrations.iter()
+                .filter(|m| m.version > current_version && m.version <= target_version)
+                .map(|m| (m, Direction::Up))
+                .collect()
+        } else {
+            self.migrations.iter()
+                .rev()
+                .filter(|m| m.version > target_version && m.version <= current_version)
+                .map(|m| (m, Direction::Down))
+                .collect()
+        }
+    }
+
+    /// Works out the steps from the database's current version to
+    /// `target_version` without changing anything.
+    pub fn plan(&self, conn: &Connection, target_version: u32) -> Result<MigrationPlan, RweError> {
+        self.setup_migration_table(conn)?;
+        let latest_version = self.latest_version();
+        if target_version > latest_version {
+            return Err(RweError::Validation(format!(
+                "Unknown schema version {}; the latest is {}", target_version, latest_version
+            )));
+        }
+        let current_version = self.get_current_version(conn)?;
+        if current_version > latest_version {
+            return Err(RweError::Conflict(format!(
+                "Database schema version {} is newer than this app supports ({})", current_version, latest_version
+            )));
+        }
+
+        Ok(MigrationPlan {
+            current_version,
+            target_version,
+            steps: self.steps(current_version, target_version)
+                .into_iter()
+                .map(|(migration, direction)| MigrationStep {
+                    version: migration.version,
+                    name: migration.name.clone(),
+                    direction,
+                })
+                .collect(),
+            checksum_mismatches: self.checksum_mismatches(conn)?,
+        })
+    }
+
+    pub fn run_migrations(&self, conn: &Connection) -> Result<(), RweError> {
+        self.setup_migration_table(conn)?;
+        self.backfill_checksums(conn)?;
+        if self.get_current_version(conn)? >= self.latest_version() {
+            return Ok(());
+        }
+        self.migrate_to(conn, self.latest_version()).map(|_| ())
+    }
+
+    /// Migrates up or down to `target_version`, each step in its own
+    /// transaction with its schema_migrations row, so a failure leaves the
+    /// database at the last version that completed.
+    pub fn migrate_to(&self, conn: &Connection, target_version: u32) -> Result<MigrationPlan, RweError> {
+        let plan = self.plan(conn, target_version)?;
+        plan.ensure_reversible()?;
+        let steps = self.steps(plan.current_version, target_version);
+
+        // Table rebuilds drop and recreate parent tables, which would cascade
+        // into their children if foreign keys were enforced while they ran
+        let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .map_err(|e| RweError::database("Migration error", e))?;
+        conn.pragma_update(None, "foreign_keys", false)
+            .map_err(|e| RweError::database("Migration error", e))?;
+        let count = steps.len();
+        let result = steps.into_iter().enumerate()
+            .try_for_each(|(index, (migration, direction))| self.apply(conn, migration, direction, index + 1, count));
+        conn.pragma_update(None, "foreign_keys", foreign_keys)
+            .map_err(|e| RweError::database("Migration error", e))?;
+        result.map(|()| plan)
+    }
+
+    fn apply(&self, conn: &Connection, migration: &Migration, direction: Direction, step: usize, steps: usize) -> Result<(), RweError> {
+        let label = match direction {
+            Direction::Up => "Running",
+            Direction::Down => "Reverting",
+        };
+        println!("{} migration {}: {}", label, migration.version, migration.name);
+
+        let progress = MigrationProgress {
+            sink: self.progress.as_ref(),
+            event: MigrationProgressEvent {
+                version: migration.version,
+                name: migration.name.clone(),
+                direction,
+                step,
+                steps,
+                done: None,
+                total: None,
+            },
+            last_sent: Cell::new(None),
+        };
+        progress.send(progress.event.clone());
+
+        database::with_transaction(conn, |conn| {
+            let failed = format!("Migration {} ({}) failed", migration.version, migration.name);
+            let action = match direction {
+                Direction::Up => &migration.up,
+                Direction::Down => &migration.down,
+            };
+            let orphans_before = foreign_key_violations(conn)?;
+            match action {
+                MigrationAction::Sql(sql) => conn.execute_batch(sql)
+                    .map_err(|e| RweError::database(&failed, e))?,
+                MigrationAction::Code(run) => run(conn, &progress)?,
+            }
+            // Foreign keys are off while migrations run, so check by hand that
+            // this step didn't leave rows pointing at rows that are gone.
+            // Orphans that were already there aren't this step's doing.
+            if let Some(orphan) = foreign_key_violations(conn)?.difference(&orphans_before).next() {
+                return Err(RweError::Storage(format!(
+                    "{}: {} row {} refers to a missing {} row",
+                    failed,
+                    orphan.table,
+                    orphan.rowid.map_or_else(|| "?".to_string(), |rowid| rowid.to_string()),
+                    orphan.parent,
+                )));
+            }
+            match direction {
+                Direction::Up => conn.execute(
+                    "INSERT INTO schema_migrations (version, name, checksum) VALUES (?1, ?2, ?3)",
+                    params![migration.version, migration.name, migration.checksum()],
+                ),
+                Direction::Down => conn.execute("DELETE FROM schema_migrations WHERE version = ?1", [migration.version]),
+            }.map_err(|e| RweError::database(&failed, e))?;
+            Ok(())
+        })?;
+
+        println!("Migration {} completed", migration.version);
+        Ok(())
+    }
+}
+
+/// A row of `PRAGMA foreign_key_check`: a row in `table` whose foreign key
+/// names a row of `parent` that doesn't exist.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ForeignKeyViolation {
+    table: String,
+    rowid: Option<i64>,
+    parent: String,
+    key: i64,
+}
+
+fn foreign_key_violations(conn: &Connection) -> Result<HashSet<ForeignKeyViolation>, RweError> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")
+        .map_err(|e| RweError::database("Foreign key check error", e))?;
+    let rows = stmt.query_map([], |row| {
+        Ok(ForeignKeyViolation { table: row.get(0)?, rowid: row.get(1)?, parent: row.get(2)?, key: row.get(3)? })
+    }).map_err(|e| RweError::database("Foreign key check error", e))?;
+    rows.collect::<Result<HashSet<_>, _>>()
+        .map_err(|e| RweError::database("Foreign key check error", e))
+}
+
+/// Why the database isn't open after a migration failed at startup or unlock.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationFailure {
+    pub message: String,
+    /// The pre-migration backup copied back over main.db, if there was one.
+    pub restored_backup: Option<String>,
+    /// Applied migrations that had changed since they ran, found before
+    /// migrating. `get_migration_status` can't read them itself while the
+    /// database is closed.
+    pub checksum_mismatches: Vec<ChecksumMismatch>,
+}
+
+impl From<RweError> for MigrationFailure {
+    fn from(error: RweError) -> Self {
+        Self { message: error.to_string(), restored_backup: None, checksum_mismatches: Vec::new() }
+    }
+}
+
+/// Keeps the last startup or unlock migration failure for
+/// `get_migration_status`, since the app comes up without a database then.
+#[derive(Default)]
+pub struct MigrationState {
+    failure: Mutex<Option<MigrationFailure>>,
+}
+
+impl MigrationState {
+    pub fn record(&self, failure: MigrationFailure) -> Result<(), RweError> {
+        *self.failure.lock().map_err(|e| RweError::Internal(format!("Lock error: {}", e)))? = Some(failure);
+        Ok(())
+    }
+
+    pub fn failure(&self) -> Result<Option<MigrationFailure>, RweError> {
+        self.failure.lock()
+            .map(|failure| failure.clone())
+            .map_err(|e| RweError::Internal(format!("Lock error: {}", e)))
+    }
+}
+
+fn backup_path(dir: &Path, version: u32) -> PathBuf {
+    dir.join(format!("pre_migration_v{}_{}.db", version, chrono::Utc::now().format("%Y%m%d_%H%M%S")))
+}
+
+fn backup_before_migrating(conn: &Connection, db_path: &Path, version: u32, passphrase: Option<&str>) -> Result<PathBuf, RweError> {
+    let dir = db_path.with_file_name("backups");
+    std::fs::create_dir_all(&dir).map_err(|e| RweError::io("Failed to create backup directory", e))?;
+    let path = backup_path(&dir, version);
+    let mut target = match passphrase {
+        Some(passphrase) => encryption::open_with_passphrase(&path, passphrase)?,
+        None => Connection::open(&path).map_err(|e| RweError::database("Failed to create backup", e))?,
+    };
+    database::copy_database(conn, &mut target)?;
+    Ok(path)
+}
+
+/// Brings a freshly opened pool up to date. When anything is pending, main.db
+/// is backed up first; if a migration then fails, the pool is closed and the
+/// backup copied back over main.db, so the file is left as the previous
+/// release wrote it rather than half migrated.
+pub fn migrate_pool(pool: SqlitePool, db_path: &Path, passphrase: Option<&str>, app: &AppHandle) -> Result<SqlitePool, MigrationFailure> {
+    let runner = MigrationRunner::new().with_progress(app.clone());
+    let (error, backup, checksum_mismatches) = {
+        let conn = pool.get().map_err(|e| RweError::Storage(format!("Connection error: {}", e)))?;
+        runner.setup_migration_table(&conn)?;
+        runner.backfill_checksums(&conn)?;
+        let checksum_mismatches = runner.checksum_mismatches(&conn)?;
+
+        let current_version = runner.get_current_version(&conn)?;
+        if current_version >= runner.latest_version() {
+            drop(conn);
+            return Ok(pool);
+        }
+        // A new database has nothing worth restoring
+        let backup = match current_version {
+            0 => None,
+            version => match backup_before_migrating(&conn, db_path, version, passphrase) {
+                Ok(backup) => Some(backup),
+                Err(error) => return Err(MigrationFailure { checksum_mismatches, ..error.into() }),
+            },
+        };
+        match runner.run_migrations(&conn) {
+            Ok(()) => {
+                drop(conn);
+                return Ok(pool);
+            }
+            Err(error) => (error, backup, checksum_mismatches),
+        }
+    };
+    drop(pool);
+
+    let Some(backup) = backup else {
+        return Err(MigrationFailure { checksum_mismatches, ..error.into() });
+    };
+    if let Err(restore_error) = database_pool::remove_wal_files(db_path)
+        .and_then(|()| std::fs::copy(&backup, db_path).map_err(|e| RweError::io("Failed to restore backup", e)))
+    {
+        return Err(MigrationFailure {
+            message: format!("{}; restoring {} also failed: {}", error, backup.display(), restore_error),
+            restored_backup: None,
+            checksum_mismatches,
+        });
+    }
+    Err(MigrationFailure {
+        message: error.to_string(),
+        restored_backup: Some(backup.to_string_lossy().to_string()),
+        checksum_mismatches,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationStatus {
+    /// None while the database is locked or failed to open.
+    pub current_version: Option<u32>,
+    pub latest_version: u32,
+    pub checksum_mismatches: Vec<ChecksumMismatch>,
+    pub failure: Option<MigrationFailure>,
+}
+
+#[command]
+pub async fn get_migration_status(
+    db: State<'_, DbPool>,
+    migrations: State<'_, MigrationState>,
+) -> Result<MigrationStatus, RweError> {
+    let failure = migrations.failure()?;
+    let applied = db.run(|conn| {
+        let runner = MigrationRunner::new();
+        runner.setup_migration_table(conn)?;
+        Ok::<_, RweError>((runner.get_current_version(conn)?, runner.checksum_mismatches(conn)?))
+    }).await;
+    let (current_version, checksum_mismatches) = match applied {
+        Ok((version, mismatches)) => (Some(version), mismatches),
+        Err(RweError::Locked(_)) => {
+            let mismatches = failure.as_ref().map(|failure| failure.checksum_mismatches.clone()).unwrap_or_default();
+            (None, mismatches)
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(MigrationStatus {
+        current_version,
+        latest_version: MigrationRunner::new().latest_version(),
+        checksum_mismatches,
+        failure,
+    })
+}
+
+/// Migrates main.db to `target_version`, or with `dry_run` only reports the
+/// steps it would take. Rolling back is for going back to an older release;
+/// this build migrates up again the next time it starts. A backup is taken
+/// into the backups folder before anything changes. Version 0 drops every
+/// table, so it needs `confirm_data_loss`.
+#[command]
+pub async fn migrate_database(
+    target_version: u32,
+    dry_run: Option<bool>,
+    confirm_data_loss: Option<bool>,
+    app: AppHandle,
+    db: State<'_, DbPool>,
+    encryption: State<'_, DatabaseEncryption>,
+) -> Result<MigrationPlan, RweError> {
+    let plan = db.run(move |conn| MigrationRunner::new().plan(conn, target_version)).await?;
+    if dry_run.unwrap_or(false) || plan.steps.is_empty() {
+        return Ok(plan);
+    }
+    if target_version == 0 && !confirm_data_loss.unwrap_or(false) {
+        return Err(RweError::Validation(
+            "Rolling back to version 0 deletes every conversation; confirm the data loss to continue".to_string(),
+        ));
+    }
+    plan.ensure_reversible()?;
+
+    let dir = updater::get_rwe_data_dir().join("backups");
+    std::fs::create_dir_all(&dir).map_err(|e| RweError::io("Failed to create backup directory", e))?;
+    let path = backup_path(&dir, plan.current_version);
+    let mut backup = encryption.open(&path)?;
+
+    db.run(move |conn| {
+        database::copy_database(conn, &mut backup)?;
+        MigrationRunner::new().with_progress(app).migrate_to(conn, target_version).map_err(|e| {
+            RweError::Storage(format!("{}. The database was backed up to {} first", e, path.display()))
+        })
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrated() -> (Connection, MigrationRunner) {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        let runner = MigrationRunner::new();
+        runner.run_migrations(&conn).unwrap();
+        (conn, runner)
+    }
+
+    fn push_sql(runner: &mut MigrationRunner, up: &str) -> u32 {
+        let version = runner.latest_version() + 1;
+        runner.migrations.push(Migration {
+            version,
+            name: format!("test_{}", version),
+            up: MigrationAction::Sql(up.to_string()),
+            down: MigrationAction::Sql(String::new()),
+        });
+        version
+    }
+
+    /// Every table, index and trigger with its SQL, which includes the
+    /// foreign keys.
+    fn schema(conn: &Connection) -> Vec<(String, String, Option<String>)> {
+        let mut stmt = conn.prepare(
+            "SELECT type, name, sql FROM sqlite_master WHERE name NOT LIKE 'sqlite_%' ORDER BY type, name",
+        ).unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn every_migration_rolls_back_and_reapplies() {
+        let (conn, runner) = migrated();
+        let latest = runner.latest_version();
+        let fresh = schema(&conn);
+        conn.execute(
+            "INSERT INTO conversations (id, name, notes, summary) VALUES (1, 'Queues', 'tokio notes', 'A summary')",
+            [],
+        ).unwrap();
+        database::insert_message(&conn, 1, "user", "lock-free queues", false).unwrap();
+        database::insert_message(&conn, 1, "assistant", "use atomics", false).unwrap();
+
+        // Rolling back to the first version keeps the rows it has tables for,
+        // and migrating up again re-indexes them
+        runner.migrate_to(&conn, 1).unwrap();
+        assert_eq!(runner.get_current_version(&conn).unwrap(), 1);
+        runner.migrate_to(&conn, latest).unwrap();
+        assert_eq!(schema(&conn), fresh);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages WHERE conversation_id = 1"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'queues'"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM conversation_notes_fts WHERE conversation_notes_fts MATCH 'tokio'"), 1);
+        assert!(foreign_key_violations(&conn).unwrap().is_empty());
+
+        // Version 0 has no tables left; migrating up gives a fresh schema
+        runner.migrate_to(&conn, 0).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name != 'schema_migrations' AND name NOT LIKE 'sqlite_%'"), 0);
+        runner.migrate_to(&conn, latest).unwrap();
+        assert_eq!(schema(&conn), fresh);
+
+        // The FTS triggers and foreign keys work again
+        conn.execute("INSERT INTO conversations (id, name, notes) VALUES (1, 'Again', 'tokio notes')", []).unwrap();
+        database::insert_message(&conn, 1, "user", "lock-free queues", false).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'queues'"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM conversation_notes_fts WHERE conversation_notes_fts MATCH 'tokio'"), 1);
+        assert!(conn.execute("INSERT INTO messages (conversation_id, role, content, seq) VALUES (42, 'user', 'stray', 1)", []).is_err());
+        conn.execute("DELETE FROM conversations WHERE id = 1", []).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages"), 0);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'queues'"), 0);
+    }
+
+    #[test]
+    fn a_corrupt_migration_table_is_an_error_not_version_0() {
+        let (conn, runner) = migrated();
+        conn.execute_batch(
+            "DROP TABLE schema_migrations;
+             CREATE TABLE schema_migrations (version TEXT, name TEXT, applied_at TEXT, checksum TEXT);
+             INSERT INTO schema_migrations VALUES ('not a version', 'x', NULL, NULL);",
+        ).unwrap();
+        assert!(runner.get_current_version(&conn).is_err());
+        assert!(runner.run_migrations(&conn).is_err());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM schema_migrations"), 1);
+    }
+
+    #[test]
+    fn a_step_that_orphans_rows_is_rolled_back() {
+        let (conn, mut runner) = migrated();
+        conn.execute_batch(
+            "INSERT INTO conversations (id, name) VALUES (1, 'Kept');
+             INSERT INTO messages (conversation_id, role, content, seq) VALUES (1, 'user', 'hello', 1);",
+        ).unwrap();
+        let before = runner.get_current_version(&conn).unwrap();
+
+        // Foreign keys are off while migrating, so this delete succeeds on its own
+        let version = push_sql(&mut runner, "DELETE FROM conversations WHERE id = 1;");
+        let error = runner.run_migrations(&conn).unwrap_err();
+        assert!(matches!(&error, RweError::Storage(message) if message.contains("messages row") && message.contains("conversations")), "{:?}", error);
+
+        assert_eq!(runner.get_current_version(&conn).unwrap(), before);
+        let kept: i64 = conn.query_row("SELECT COUNT(*) FROM conversations", [], |row| row.get(0)).unwrap();
+        assert_eq!(kept, 1);
+        let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |row| row.get(0)).unwrap();
+        assert!(foreign_keys);
+        assert!(version > before);
+    }
+
+    #[test]
+    fn a_failing_rust_step_is_rolled_back_after_reporting_progress() {
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let sent = events.clone();
+        let mut runner = MigrationRunner::new().on_progress(Box::new(move |event| sent.lock().unwrap().push(event)));
+        let conn = Connection::open_in_memory().unwrap();
+        runner.run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO conversations (id, name) VALUES (1, 'Before')", []).unwrap();
+        let applied = count(&conn, "SELECT COUNT(*) FROM schema_migrations");
+        events.lock().unwrap().clear();
+
+        let version = runner.latest_version() + 1;
+        runner.migrations.push(Migration {
+            version,
+            name: "test_rename_all".to_string(),
+            up: MigrationAction::Code(Box::new(|conn, progress| {
+                for done in 1..=3 {
+                    conn.execute("UPDATE conversations SET name = 'Renamed'", [])
+                        .map_err(|e| RweError::database("Rename error", e))?;
+                    progress.report(done, 3);
+                }
+                Err(RweError::Validation("Refusing to finish".to_string()))
+            })),
+            down: MigrationAction::Sql(String::new()),
+        });
+        let error = runner.run_migrations(&conn).unwrap_err();
+        assert_eq!(error, RweError::Validation("Refusing to finish".to_string()));
+
+        assert_eq!(runner.get_current_version(&conn).unwrap(), version - 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM schema_migrations"), applied);
+        let name: String = conn.query_row("SELECT name FROM conversations WHERE id = 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(name, "Before");
+
+        // The start of the step, then the throttled reports, of which the
+        // last always goes out
+        let events = events.lock().unwrap();
+        let reported: Vec<_> = events.iter().map(|event| (event.version, event.step, event.steps, event.done, event.total)).collect();
+        assert_eq!(reported.first(), Some(&(version, 1, 1, None, None)));
+        assert_eq!(reported.last(), Some(&(version, 1, 1, Some(3), Some(3))));
+        assert!(reported.len() <= 4);
+        assert_eq!(events[0].direction, Direction::Up);
+        assert_eq!(events[0].name, "test_rename_all");
+    }
+
+    #[test]
+    fn orphans_from_before_the_step_do_not_block_it() {
+        let (conn, mut runner) = migrated();
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute("INSERT INTO messages (conversation_id, role, content, seq) VALUES (42, 'user', 'stray', 1)", []).unwrap();
+
+        let version = push_sql(&mut runner, "CREATE TABLE test_extra (id INTEGER PRIMARY KEY);");
+        runner.run_migrations(&conn).unwrap();
+        assert_eq!(runner.get_current_version(&conn).unwrap(), version);
+        assert_eq!(foreign_key_violations(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn plan_reports_edited_migrations() {
+        let (conn, runner) = migrated();
+        assert!(runner.plan(&conn, runner.latest_version()).unwrap().checksum_mismatches.is_empty());
+
+        conn.execute("UPDATE schema_migrations SET checksum = 'edited' WHERE version = 2", []).unwrap();
+        let plan = runner.plan(&conn, runner.latest_version()).unwrap();
+        assert_eq!(plan.checksum_mismatches.len(), 1);
+        let mismatch = &plan.checksum_mismatches[0];
+        assert_eq!((mismatch.version, mismatch.applied.as_str()), (2, "edited"));
+        assert_eq!(mismatch.current, runner.find(2).unwrap().checksum());
+        assert!(plan.steps.is_empty());
     }
 }
@@ -0,0 +1,658 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::mindmap_schema::{MindMapEdge, MindMapNode, NodeShape};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MindMapExportFormat {
+    Opml,
+    /// FreeMind's XML, also read by Freeplane and most desktop mind mappers.
+    #[serde(alias = "mm")]
+    FreeMind,
+    /// A Mermaid `mindmap` diagram.
+    Mermaid,
+    /// A Graphviz `digraph`.
+    Dot,
+}
+
+impl MindMapExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MindMapExportFormat::Opml => "opml",
+            MindMapExportFormat::FreeMind => "mm",
+            MindMapExportFormat::Mermaid => "mmd",
+            MindMapExportFormat::Dot => "dot",
+        }
+    }
+}
+
+/// Writes a map out in `format`. DOT keeps every connection and position.
+/// The other formats are outlines, so each node appears once, under the
+/// first connection that reaches it; FreeMind keeps the connections left
+/// over as arrow links, while OPML and Mermaid have nowhere to put them.
+/// Node text and shapes survive in all four.
+pub fn export(format: MindMapExportFormat, title: &str, nodes: &[MindMapNode], edges: &[MindMapEdge]) -> String {
+    match format {
+        MindMapExportFormat::Opml => to_opml(title, &Outline::new(nodes, edges)),
+        MindMapExportFormat::FreeMind => to_freemind(title, &Outline::new(nodes, edges)),
+        MindMapExportFormat::Mermaid => to_mermaid(title, &Outline::new(nodes, edges)),
+        MindMapExportFormat::Dot => to_dot(title, nodes, edges),
+    }
+}
+
+/// A name to save the export under, made from the map's title.
+pub fn file_name(title: &str, format: MindMapExportFormat) -> String {
+    let stem: String = title
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let stem = stem.trim_matches('_');
+    format!("{}.{}", if stem.is_empty() { "mindmap" } else { stem }, format.extension())
+}
+
+/// The map as a forest. Nodes nothing points at start a tree, in the order
+/// they were saved; a loop nothing leads into starts from its first node.
+struct Outline<'a> {
+    nodes: &'a [MindMapNode],
+    roots: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    /// Connections that aren't part of the outline, as (from, to).
+    links: Vec<(usize, usize)>,
+}
+
+impl<'a> Outline<'a> {
+    fn new(nodes: &'a [MindMapNode], edges: &[MindMapEdge]) -> Self {
+        let index: HashMap<&str, usize> = nodes.iter().enumerate().map(|(i, node)| (node.id.as_str(), i)).collect();
+        let mut outgoing = vec![Vec::new(); nodes.len()];
+        let mut has_parent = vec![false; nodes.len()];
+        for edge in edges {
+            // Saved maps are validated, so both ends are there
+            if let (Some(&from), Some(&to)) = (index.get(edge.from.as_str()), index.get(edge.to.as_str())) {
+                outgoing[from].push(to);
+                has_parent[to] = true;
+            }
+        }
+
+        let mut outline = Outline {
+            nodes,
+            roots: Vec::new(),
+            children: vec![Vec::new(); nodes.len()],
+            links: Vec::new(),
+        };
+        let mut placed = vec![false; nodes.len()];
+        let starts = (0..nodes.len()).filter(|&i| !has_parent[i]).chain(0..nodes.len());
+        for start in starts {
+            if placed[start] {
+                continue;
+            }
+            placed[start] = true;
+            outline.roots.push(start);
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                for &child in &outgoing[node] {
+                    if placed[child] {
+                        outline.links.push((node, child));
+                    } else {
+                        placed[child] = true;
+                        outline.children[node].push(child);
+                    }
+                }
+                stack.extend(outline.children[node].iter().rev());
+            }
+        }
+        outline
+    }
+
+    /// The single tree's root, if the map is one tree.
+    fn single_root(&self) -> Option<usize> {
+        match self.roots.as_slice() {
+            [root] => Some(*root),
+            _ => None,
+        }
+    }
+}
+
+/// Escapes text for an XML attribute value. Control characters XML can't
+/// hold are dropped.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' => escaped.push_str("&#9;"),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `#rrggbb` for the colours other tools understand; `#rgb` is widened.
+fn hex_color(color: &str) -> Option<String> {
+    let digits = color.strip_prefix('#')?;
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match digits.len() {
+        6 => Some(format!("#{}", digits.to_ascii_lowercase())),
+        3 => Some(digits.to_ascii_lowercase().chars().fold(String::from("#"), |mut color, c| {
+            color.push(c);
+            color.push(c);
+            color
+        })),
+        _ => None,
+    }
+}
+
+fn to_opml(title: &str, outline: &Outline) -> String {
+    fn write(out: &mut String, outline: &Outline, node: usize, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let node_data = &outline.nodes[node];
+        // OPML lets outlines carry attributes of their own
+        let open = format!("{}<outline text=\"{}\" shape=\"{}\"", indent, xml_escape(&node_data.text), node_data.shape.as_str());
+        if outline.children[node].is_empty() {
+            out.push_str(&format!("{}/>\n", open));
+            return;
+        }
+        out.push_str(&format!("{}>\n", open));
+        for &child in &outline.children[node] {
+            write(out, outline, child, depth + 1);
+        }
+        out.push_str(&format!("{}</outline>\n", indent));
+    }
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n");
+    out.push_str(&format!("  <head>\n    <title>{}</title>\n  </head>\n  <body>\n", xml_escape(title)));
+    for &root in &outline.roots {
+        write(&mut out, outline, root, 2);
+    }
+    out.push_str("  </body>\n</opml>\n");
+    out
+}
+
+fn to_freemind(title: &str, outline: &Outline) -> String {
+    fn attributes(node: &MindMapNode, id: usize) -> String {
+        let mut attributes = format!("ID=\"ID_{}\" TEXT=\"{}\"", id, xml_escape(&node.text));
+        if let Some(color) = hex_color(&node.color) {
+            attributes.push_str(&format!(" COLOR=\"{}\"", color));
+        }
+        let style = if node.shape == NodeShape::Circle { "bubble" } else { "fork" };
+        attributes.push_str(&format!(" STYLE=\"{}\"", style));
+        attributes
+    }
+
+    fn write(out: &mut String, outline: &Outline, node: usize, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let links: Vec<usize> = outline.links.iter().filter(|(from, _)| *from == node).map(|(_, to)| *to).collect();
+        out.push_str(&format!("{}<node {}>\n", indent, attributes(&outline.nodes[node], node)));
+        // FreeMind only tells bubbles from forks, so the shape goes along as
+        // a node attribute too
+        out.push_str(&format!(
+            "{}  <attribute NAME=\"shape\" VALUE=\"{}\"/>\n",
+            indent,
+            outline.nodes[node].shape.as_str()
+        ));
+        for to in links {
+            out.push_str(&format!("{}  <arrowlink DESTINATION=\"ID_{}\" ENDARROW=\"Default\"/>\n", indent, to));
+        }
+        for &child in &outline.children[node] {
+            write(out, outline, child, depth + 1);
+        }
+        out.push_str(&format!("{}</node>\n", indent));
+    }
+
+    let mut out = String::from("<map version=\"1.0.1\">\n");
+    match outline.single_root() {
+        Some(root) => write(&mut out, outline, root, 0),
+        None => {
+            // FreeMind needs one root; the title stands in for it
+            out.push_str(&format!("<node ID=\"ID_root\" TEXT=\"{}\">\n", xml_escape(title)));
+            for &root in &outline.roots {
+                write(&mut out, outline, root, 1);
+            }
+            out.push_str("</node>\n");
+        }
+    }
+    out.push_str("</map>\n");
+    out
+}
+
+/// Mermaid reads node text as a quoted string. Characters it would read as
+/// syntax or markup are written as its `#code;` entities, and line breaks
+/// as `<br>`.
+fn mermaid_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("#quot;"),
+            '#' => escaped.push_str("#35;"),
+            '&' => escaped.push_str("#38;"),
+            '<' => escaped.push_str("#lt;"),
+            '>' => escaped.push_str("#gt;"),
+            '\n' => escaped.push_str("<br>"),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn to_mermaid(title: &str, outline: &Outline) -> String {
+    fn write(out: &mut String, outline: &Outline, node: usize, depth: usize) {
+        let text = mermaid_text(&outline.nodes[node].text);
+        let shape = match outline.nodes[node].shape {
+            NodeShape::Square => format!("[\"{}\"]", text),
+            NodeShape::Circle => format!("((\"{}\"))", text),
+            // Mermaid mind maps have no diamond; the hexagon is closest
+            NodeShape::Diamond => format!("{{{{\"{}\"}}}}", text),
+        };
+        out.push_str(&format!("{}n{}{}\n", "  ".repeat(depth), node, shape));
+        for &child in &outline.children[node] {
+            write(out, outline, child, depth + 1);
+        }
+    }
+
+    let mut out = String::from("mindmap\n");
+    match outline.single_root() {
+        Some(root) => write(&mut out, outline, root, 1),
+        None => {
+            out.push_str(&format!("  root[\"{}\"]\n", mermaid_text(title)));
+            for &root in &outline.roots {
+                write(&mut out, outline, root, 2);
+            }
+        }
+    }
+    out
+}
+
+fn dot_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {}
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn to_dot(title: &str, nodes: &[MindMapNode], edges: &[MindMapEdge]) -> String {
+    let index: HashMap<&str, usize> = nodes.iter().enumerate().map(|(i, node)| (node.id.as_str(), i)).collect();
+    let mut out = format!("digraph {} {{\n", dot_string(title));
+    for (i, node) in nodes.iter().enumerate() {
+        let shape = match node.shape {
+            NodeShape::Square => "box",
+            NodeShape::Circle => "circle",
+            NodeShape::Diamond => "diamond",
+        };
+        // Graphviz puts y upwards; the canvas puts it downwards
+        let mut attributes = format!("label={}, shape={}, pos=\"{},{}!\"", dot_string(&node.text), shape, node.x, 0.0 - node.y);
+        if let Some(color) = hex_color(&node.color) {
+            attributes.push_str(&format!(", color=\"{}\"", color));
+        }
+        out.push_str(&format!("  n{} [{}];\n", i, attributes));
+    }
+    for edge in edges {
+        if let (Some(from), Some(to)) = (index.get(edge.from.as_str()), index.get(edge.to.as_str())) {
+            out.push_str(&format!("  n{} -> n{};\n", from, to));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::events::{BytesStart, Event};
+    use std::collections::BTreeSet;
+
+    const FORMATS: [MindMapExportFormat; 4] = [
+        MindMapExportFormat::Opml,
+        MindMapExportFormat::FreeMind,
+        MindMapExportFormat::Mermaid,
+        MindMapExportFormat::Dot,
+    ];
+
+    fn node(id: &str, text: &str, shape: NodeShape) -> MindMapNode {
+        MindMapNode { id: id.to_string(), x: 10.0, y: 20.5, text: text.to_string(), shape, color: "#0f0".to_string() }
+    }
+
+    fn edge(from: &str, to: &str) -> MindMapEdge {
+        MindMapEdge { from: from.to_string(), to: to.to_string() }
+    }
+
+    fn tree() -> (Vec<MindMapNode>, Vec<MindMapEdge>) {
+        let nodes = vec![
+            node("r", "Root", NodeShape::Circle),
+            node("a", "A", NodeShape::Square),
+            node("b", "B", NodeShape::Diamond),
+            node("c", "C", NodeShape::Square),
+        ];
+        (nodes, vec![edge("r", "a"), edge("r", "b"), edge("a", "c")])
+    }
+
+    fn forest() -> (Vec<MindMapNode>, Vec<MindMapEdge>) {
+        let nodes = vec![
+            node("x", "X", NodeShape::Square),
+            node("y", "Y", NodeShape::Circle),
+            node("z", "Z", NodeShape::Diamond),
+            node("w", "W", NodeShape::Square),
+            node("v", "V", NodeShape::Square),
+        ];
+        (nodes, vec![edge("x", "y"), edge("w", "v")])
+    }
+
+    /// T leads into the loop P -> Q -> S -> P.
+    fn cycle() -> (Vec<MindMapNode>, Vec<MindMapEdge>) {
+        let nodes = vec![
+            node("p", "P", NodeShape::Square),
+            node("q", "Q", NodeShape::Circle),
+            node("s", "S", NodeShape::Diamond),
+            node("t", "T", NodeShape::Square),
+        ];
+        (nodes, vec![edge("p", "q"), edge("q", "s"), edge("s", "p"), edge("t", "q")])
+    }
+
+    fn awkward_text() -> (Vec<MindMapNode>, Vec<MindMapEdge>) {
+        let nodes = vec![
+            node("1", "Say \"hi\"", NodeShape::Circle),
+            node("2", "a < b && c > d", NodeShape::Square),
+            node("3", "line one\nline two", NodeShape::Diamond),
+            node("4", "#35; isn't \\ a <br>", NodeShape::Square),
+        ];
+        (nodes, vec![edge("1", "2"), edge("1", "3"), edge("3", "4")])
+    }
+
+    /// What an export reads back as: each node's text and shape, the
+    /// outline's parent/child pairs and any other links, by node text.
+    #[derive(Debug, Default)]
+    struct Parsed {
+        nodes: Vec<(String, NodeShape)>,
+        children: BTreeSet<(String, String)>,
+        links: BTreeSet<(String, String)>,
+    }
+
+    fn by_text(nodes: &[MindMapNode], edges: &[MindMapEdge]) -> BTreeSet<(String, String)> {
+        let text: HashMap<&str, &str> = nodes.iter().map(|node| (node.id.as_str(), node.text.as_str())).collect();
+        edges.iter().map(|edge| (text[edge.from.as_str()].to_string(), text[edge.to.as_str()].to_string())).collect()
+    }
+
+    fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+        element
+            .attributes()
+            .map(|attribute| attribute.unwrap())
+            .find(|attribute| attribute.key.as_ref() == name.as_bytes())
+            .map(|attribute| attribute.unescape_value().unwrap().into_owned())
+    }
+
+    fn shape(value: &str) -> NodeShape {
+        NodeShape::from_stored(value).unwrap_or_else(|| panic!("unknown shape {}", value))
+    }
+
+    fn parse_opml(xml: &str) -> Parsed {
+        let mut reader = quick_xml::Reader::from_str(xml);
+        let mut parsed = Parsed::default();
+        let mut path: Vec<String> = Vec::new();
+        loop {
+            let (element, has_children) = match reader.read_event().unwrap() {
+                Event::Start(element) if element.name().as_ref() == b"outline" => (element, true),
+                Event::Empty(element) if element.name().as_ref() == b"outline" => (element, false),
+                Event::End(element) if element.name().as_ref() == b"outline" => {
+                    path.pop();
+                    continue;
+                }
+                Event::Eof => break,
+                _ => continue,
+            };
+            let text = attribute(&element, "text").unwrap();
+            parsed.nodes.push((text.clone(), shape(&attribute(&element, "shape").unwrap())));
+            if let Some(parent) = path.last() {
+                parsed.children.insert((parent.clone(), text.clone()));
+            }
+            if has_children {
+                path.push(text);
+            }
+        }
+        parsed
+    }
+
+    fn parse_freemind(xml: &str) -> Parsed {
+        let mut reader = quick_xml::Reader::from_str(xml);
+        let mut parsed = Parsed::default();
+        let mut text_of: HashMap<String, String> = HashMap::new();
+        let mut path: Vec<String> = Vec::new();
+        let mut links = Vec::new();
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(element) if element.name().as_ref() == b"node" => {
+                    let id = attribute(&element, "ID").unwrap();
+                    text_of.insert(id.clone(), attribute(&element, "TEXT").unwrap());
+                    if let Some(parent) = path.last().filter(|parent| *parent != "ID_root") {
+                        parsed.children.insert((parent.clone(), id.clone()));
+                    }
+                    path.push(id);
+                }
+                Event::Empty(element) if element.name().as_ref() == b"attribute" => {
+                    assert_eq!(attribute(&element, "NAME").as_deref(), Some("shape"));
+                    let id = path.last().unwrap();
+                    parsed.nodes.push((text_of[id].clone(), shape(&attribute(&element, "VALUE").unwrap())));
+                }
+                Event::Empty(element) if element.name().as_ref() == b"arrowlink" => {
+                    links.push((path.last().unwrap().clone(), attribute(&element, "DESTINATION").unwrap()));
+                }
+                Event::End(element) if element.name().as_ref() == b"node" => {
+                    path.pop();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        let text = |pair: (String, String)| (text_of[&pair.0].clone(), text_of[&pair.1].clone());
+        parsed.children = std::mem::take(&mut parsed.children).into_iter().map(text).collect();
+        parsed.links = links.into_iter().map(text).collect();
+        parsed
+    }
+
+    fn mermaid_unescape(text: &str) -> String {
+        let text = text.replace("<br>", "\n");
+        let mut unescaped = String::new();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find('#') {
+            unescaped.push_str(&rest[..start]);
+            let end = start + rest[start..].find(';').unwrap();
+            unescaped.push_str(match &rest[start + 1..end] {
+                "quot" => "\"",
+                "35" => "#",
+                "38" => "&",
+                "lt" => "<",
+                "gt" => ">",
+                other => panic!("unknown entity {}", other),
+            });
+            rest = &rest[end + 1..];
+        }
+        unescaped.push_str(rest);
+        unescaped
+    }
+
+    fn parse_mermaid(source: &str) -> Parsed {
+        let mut lines = source.lines();
+        assert_eq!(lines.next(), Some("mindmap"));
+        let mut parsed = Parsed::default();
+        let mut path: Vec<(usize, String)> = Vec::new();
+        for line in lines {
+            let depth = line.len() - line.trim_start().len();
+            let line = line.trim_start();
+            let (id, label) = line.split_at(line.find(['[', '(', '{']).unwrap());
+            let (node_shape, text) = [("((\"", "\"))", NodeShape::Circle), ("{{\"", "\"}}", NodeShape::Diamond), ("[\"", "\"]", NodeShape::Square)]
+                .into_iter()
+                .find_map(|(open, close, node_shape)| {
+                    label.strip_prefix(open).and_then(|label| label.strip_suffix(close)).map(|text| (node_shape, text))
+                })
+                .unwrap();
+            assert!(!text.contains('"'), "{}", text);
+            let text = mermaid_unescape(text);
+            while path.last().is_some_and(|(parent_depth, _)| *parent_depth >= depth) {
+                path.pop();
+            }
+            if id != "root" {
+                parsed.nodes.push((text.clone(), node_shape));
+                if let Some((_, parent)) = path.last().filter(|(_, parent)| !parent.is_empty()) {
+                    parsed.children.insert((parent.clone(), text.clone()));
+                }
+            }
+            // The stand-in root has no text of its own to link from
+            path.push((depth, if id == "root" { String::new() } else { text }));
+        }
+        parsed
+    }
+
+    /// A quoted DOT string and what follows it.
+    fn dot_unquote(source: &str) -> (String, &str) {
+        let mut chars = source.strip_prefix('"').unwrap().char_indices();
+        let mut text = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next().unwrap().1 {
+                    'n' => text.push('\n'),
+                    c => text.push(c),
+                },
+                '"' => return (text, &source[i + 2..]),
+                c => text.push(c),
+            }
+        }
+        panic!("unterminated string in {}", source)
+    }
+
+    fn parse_dot(source: &str) -> (String, Parsed) {
+        let mut lines = source.lines();
+        let (title, rest) = dot_unquote(lines.next().unwrap().strip_prefix("digraph ").unwrap());
+        assert_eq!(rest, " {");
+        let mut parsed = Parsed::default();
+        let mut text_of: HashMap<String, String> = HashMap::new();
+        for line in lines.map(str::trim).filter(|line| *line != "}") {
+            let line = line.strip_suffix(';').unwrap();
+            if let Some((from, to)) = line.split_once(" -> ") {
+                parsed.children.insert((text_of[from].clone(), text_of[to].clone()));
+                continue;
+            }
+            let (id, rest) = line.split_once(" [label=").unwrap();
+            let (text, rest) = dot_unquote(rest);
+            let node_shape = match rest.split("shape=").nth(1).unwrap().split(',').next().unwrap() {
+                "box" => NodeShape::Square,
+                "circle" => NodeShape::Circle,
+                "diamond" => NodeShape::Diamond,
+                other => panic!("unknown shape {}", other),
+            };
+            assert!(rest.contains("pos=\"10,-20.5!\""), "{}", rest);
+            assert!(rest.contains("color=\"#00ff00\""), "{}", rest);
+            text_of.insert(id.to_string(), text.clone());
+            parsed.nodes.push((text, node_shape));
+        }
+        (title, parsed)
+    }
+
+    fn parse(format: MindMapExportFormat, output: &str) -> Parsed {
+        match format {
+            MindMapExportFormat::Opml => parse_opml(output),
+            MindMapExportFormat::FreeMind => parse_freemind(output),
+            MindMapExportFormat::Mermaid => parse_mermaid(output),
+            MindMapExportFormat::Dot => parse_dot(output).1,
+        }
+    }
+
+    fn sorted(mut nodes: Vec<(String, NodeShape)>) -> Vec<(String, NodeShape)> {
+        nodes.sort_by(|a, b| a.0.cmp(&b.0));
+        nodes
+    }
+
+    #[test]
+    fn trees_and_forests_survive_every_format() {
+        for (nodes, edges) in [tree(), forest(), awkward_text()] {
+            let expected = sorted(nodes.iter().map(|node| (node.text.clone(), node.shape)).collect());
+            for format in FORMATS {
+                let output = export(format, "Map", &nodes, &edges);
+                let parsed = parse(format, &output);
+                assert_eq!(sorted(parsed.nodes), expected, "{:?}:\n{}", format, output);
+                assert_eq!(parsed.children, by_text(&nodes, &edges), "{:?}:\n{}", format, output);
+                assert!(parsed.links.is_empty(), "{:?}:\n{}", format, output);
+            }
+        }
+    }
+
+    #[test]
+    fn cycles_keep_every_node_once() {
+        let (nodes, edges) = cycle();
+        let all = by_text(&nodes, &edges);
+        let expected = sorted(nodes.iter().map(|node| (node.text.clone(), node.shape)).collect());
+        for format in FORMATS {
+            let output = export(format, "Map", &nodes, &edges);
+            let parsed = parse(format, &output);
+            assert_eq!(sorted(parsed.nodes), expected, "{:?}:\n{}", format, output);
+            match format {
+                MindMapExportFormat::Dot => assert_eq!(parsed.children, all),
+                _ => {
+                    // T is the only node nothing points at, so the outline is T -> Q -> S -> P
+                    let outline: BTreeSet<_> = [("T", "Q"), ("Q", "S"), ("S", "P")]
+                        .into_iter()
+                        .map(|(from, to)| (from.to_string(), to.to_string()))
+                        .collect();
+                    assert_eq!(parsed.children, outline, "{:?}:\n{}", format, output);
+                }
+            }
+            if format == MindMapExportFormat::FreeMind {
+                assert_eq!(parsed.links, BTreeSet::from([("P".to_string(), "Q".to_string())]));
+            }
+        }
+    }
+
+    #[test]
+    fn loop_with_no_way_in_starts_at_its_first_node() {
+        let nodes = vec![node("a", "A", NodeShape::Square), node("b", "B", NodeShape::Square)];
+        let edges = vec![edge("a", "b"), edge("b", "a")];
+        let parsed = parse_freemind(&export(MindMapExportFormat::FreeMind, "Map", &nodes, &edges));
+        assert_eq!(parsed.children, BTreeSet::from([("A".to_string(), "B".to_string())]));
+        assert_eq!(parsed.links, BTreeSet::from([("B".to_string(), "A".to_string())]));
+    }
+
+    #[test]
+    fn forests_hang_from_the_title() {
+        let (nodes, edges) = forest();
+        let freemind = export(MindMapExportFormat::FreeMind, "Two & more", &nodes, &edges);
+        assert!(freemind.contains("<node ID=\"ID_root\" TEXT=\"Two &amp; more\">"), "{}", freemind);
+        let mermaid = export(MindMapExportFormat::Mermaid, "Two \"trees\"", &nodes, &edges);
+        assert!(mermaid.starts_with("mindmap\n  root[\"Two #quot;trees#quot;\"]\n"), "{}", mermaid);
+        let opml = export(MindMapExportFormat::Opml, "A <b>", &nodes, &edges);
+        assert!(opml.contains("<title>A &lt;b&gt;</title>"), "{}", opml);
+        let (title, _) = parse_dot(&export(MindMapExportFormat::Dot, "say \"dot\"", &nodes, &edges));
+        assert_eq!(title, "say \"dot\"");
+    }
+
+    #[test]
+    fn empty_maps_export() {
+        for format in FORMATS {
+            let parsed = parse(format, &export(format, "Empty", &[], &[]));
+            assert!(parsed.nodes.is_empty() && parsed.children.is_empty());
+        }
+    }
+
+    #[test]
+    fn file_names_come_from_the_title() {
+        assert_eq!(file_name("Project plan", MindMapExportFormat::Opml), "Project_plan.opml");
+        assert_eq!(file_name("  Q3/Q4: ideas? ", MindMapExportFormat::FreeMind), "Q3_Q4__ideas.mm");
+        assert_eq!(file_name("naïve-map_2", MindMapExportFormat::Mermaid), "naïve-map_2.mmd");
+        assert_eq!(file_name("???", MindMapExportFormat::Dot), "mindmap.dot");
+        assert_eq!(file_name("", MindMapExportFormat::Dot), "mindmap.dot");
+    }
+}
@@ -0,0 +1,416 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeShape {
+    Square,
+    Circle,
+    Diamond,
+}
+
+impl NodeShape {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeShape::Square => "square",
+            NodeShape::Circle => "circle",
+            NodeShape::Diamond => "diamond",
+        }
+    }
+
+    /// The inverse of `as_str`, for values read back from the database.
+    pub fn from_stored(value: &str) -> Option<Self> {
+        match value {
+            "square" => Some(NodeShape::Square),
+            "circle" => Some(NodeShape::Circle),
+            "diamond" => Some(NodeShape::Diamond),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MindMapNode {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub text: String,
+    pub shape: NodeShape,
+    pub color: String,
+}
+
+/// A link drawn from one node to another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MindMapEdge {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MindMapErrorKind {
+    /// The nodes or connections don't have the expected shape.
+    Malformed,
+    EmptyNodeId,
+    DuplicateNodeId,
+    InvalidPosition,
+    /// A connection names a node that isn't in the map.
+    DanglingEdge,
+    SelfLoop,
+    DuplicateEdge,
+    /// A connection closes a loop in a map that doesn't allow them.
+    Cycle,
+}
+
+/// Why a mind map was rejected, with the node or connection at fault.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MindMapError {
+    pub kind: MindMapErrorKind,
+    pub message: String,
+    pub node_id: Option<String>,
+    pub edge: Option<MindMapEdge>,
+}
+
+impl MindMapError {
+    fn node(kind: MindMapErrorKind, node_id: &str, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into(), node_id: Some(node_id.to_string()), edge: None }
+    }
+
+    fn edge(kind: MindMapErrorKind, edge: &MindMapEdge, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into(), node_id: None, edge: Some(edge.clone()) }
+    }
+}
+
+impl std::fmt::Display for MindMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Reads the nodes and connections the UI sends. Fields the schema doesn't
+/// know about, such as the per-node `connections` list older saves carry,
+/// are ignored.
+pub fn parse(nodes: Value, connections: Value) -> Result<(Vec<MindMapNode>, Vec<MindMapEdge>), MindMapError> {
+    let malformed = |what: &str, e: serde_json::Error| MindMapError {
+        kind: MindMapErrorKind::Malformed,
+        message: format!("Invalid mind map {}: {}", what, e),
+        node_id: None,
+        edge: None,
+    };
+    let nodes = serde_json::from_value(nodes).map_err(|e| malformed("nodes", e))?;
+    let connections = serde_json::from_value(connections).map_err(|e| malformed("connections", e))?;
+    Ok((nodes, connections))
+}
+
+pub fn validate(nodes: &[MindMapNode], edges: &[MindMapEdge], acyclic: bool) -> Result<(), MindMapError> {
+    let mut ids = HashSet::new();
+    for node in nodes {
+        if node.id.trim().is_empty() {
+            return Err(MindMapError::node(MindMapErrorKind::EmptyNodeId, &node.id, "Every node needs an id"));
+        }
+        if !ids.insert(node.id.as_str()) {
+            return Err(MindMapError::node(
+                MindMapErrorKind::DuplicateNodeId,
+                &node.id,
+                format!("More than one node has id \"{}\"", node.id),
+            ));
+        }
+        if !node.x.is_finite() || !node.y.is_finite() {
+            return Err(MindMapError::node(
+                MindMapErrorKind::InvalidPosition,
+                &node.id,
+                format!("Node \"{}\" has no valid position", node.id),
+            ));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for edge in edges {
+        for end in [&edge.from, &edge.to] {
+            if !ids.contains(end.as_str()) {
+                return Err(MindMapError::edge(
+                    MindMapErrorKind::DanglingEdge,
+                    edge,
+                    format!("Connection {} -> {} refers to missing node \"{}\"", edge.from, edge.to, end),
+                ));
+            }
+        }
+        if edge.from == edge.to {
+            return Err(MindMapError::edge(
+                MindMapErrorKind::SelfLoop,
+                edge,
+                format!("Node \"{}\" is connected to itself", edge.from),
+            ));
+        }
+        if !seen.insert(edge) {
+            return Err(MindMapError::edge(
+                MindMapErrorKind::DuplicateEdge,
+                edge,
+                format!("Connection {} -> {} appears more than once", edge.from, edge.to),
+            ));
+        }
+    }
+
+    if acyclic {
+        if let Some(edge) = find_cycle(nodes, edges) {
+            return Err(MindMapError::edge(
+                MindMapErrorKind::Cycle,
+                edge,
+                format!("Connection {} -> {} makes a loop, which this map doesn't allow", edge.from, edge.to),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The first connection found that leads back to a node already on the
+/// current path, if there is one.
+fn find_cycle<'a>(nodes: &'a [MindMapNode], edges: &'a [MindMapEdge]) -> Option<&'a MindMapEdge> {
+    let mut outgoing: HashMap<&str, Vec<&MindMapEdge>> = HashMap::new();
+    for edge in edges {
+        outgoing.entry(edge.from.as_str()).or_default().push(edge);
+    }
+
+    // 1 = on the current path, 2 = fully explored
+    let mut state: HashMap<&str, u8> = HashMap::new();
+    for node in nodes {
+        if state.contains_key(node.id.as_str()) {
+            continue;
+        }
+        state.insert(&node.id, 1);
+        let mut stack = vec![(node.id.as_str(), 0)];
+        while let Some(top) = stack.last_mut() {
+            let (id, next) = *top;
+            top.1 += 1;
+            let Some(edge) = outgoing.get(id).and_then(|edges| edges.get(next)).copied() else {
+                state.insert(id, 2);
+                stack.pop();
+                continue;
+            };
+            match state.get(edge.to.as_str()) {
+                Some(1) => return Some(edge),
+                Some(_) => {}
+                None => {
+                    state.insert(&edge.to, 1);
+                    stack.push((&edge.to, 0));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Keeps what can be kept from a map saved before saves were validated:
+/// nodes that don't parse or repeat an id are dropped, and so are
+/// connections that dangle, loop back on their node or repeat.
+pub fn salvage(nodes: &str, connections: &str) -> (Vec<MindMapNode>, Vec<MindMapEdge>) {
+    let items = |json: &str| match serde_json::from_str::<Value>(json) {
+        Ok(Value::Array(items)) => items,
+        _ => Vec::new(),
+    };
+
+    let mut ids = HashSet::new();
+    let nodes: Vec<MindMapNode> = items(nodes)
+        .into_iter()
+        .filter_map(|node| serde_json::from_value::<MindMapNode>(node).ok())
+        .filter(|node| !node.id.trim().is_empty() && node.x.is_finite() && node.y.is_finite())
+        .filter(|node| ids.insert(node.id.clone()))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let edges = items(connections)
+        .into_iter()
+        .filter_map(|edge| serde_json::from_value::<MindMapEdge>(edge).ok())
+        .filter(|edge| edge.from != edge.to && ids.contains(&edge.from) && ids.contains(&edge.to))
+        .filter(|edge| seen.insert(edge.clone()))
+        .collect();
+    (nodes, edges)
+}
+
+/// The JSON a map's versions are stored as.
+pub fn to_json(nodes: &[MindMapNode], edges: &[MindMapEdge]) -> Result<(String, String), serde_json::Error> {
+    Ok((serde_json::to_string(nodes)?, serde_json::to_string(edges)?))
+}
+
+/// The JSON maps were stored as before they were normalised, where each
+/// node also lists the nodes it links to.
+pub fn legacy_json(nodes: &[MindMapNode], edges: &[MindMapEdge]) -> Result<(String, String), serde_json::Error> {
+    let nodes = nodes
+        .iter()
+        .map(|node| {
+            let mut value = serde_json::to_value(node)?;
+            let targets: Vec<&str> = edges.iter().filter(|edge| edge.from == node.id).map(|edge| edge.to.as_str()).collect();
+            value["connections"] = serde_json::to_value(targets)?;
+            Ok(value)
+        })
+        .collect::<Result<Vec<_>, serde_json::Error>>()?;
+    Ok((serde_json::to_string(&nodes)?, serde_json::to_string(edges)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(id: &str) -> MindMapNode {
+        MindMapNode { id: id.to_string(), x: 10.0, y: 20.0, text: id.to_uppercase(), shape: NodeShape::Square, color: "#fff".to_string() }
+    }
+
+    fn edge(from: &str, to: &str) -> MindMapEdge {
+        MindMapEdge { from: from.to_string(), to: to.to_string() }
+    }
+
+    fn nodes(ids: &[&str]) -> Vec<MindMapNode> {
+        ids.iter().map(|id| node(id)).collect()
+    }
+
+    fn kind(nodes: &[MindMapNode], edges: &[MindMapEdge], acyclic: bool) -> MindMapErrorKind {
+        validate(nodes, edges, acyclic).unwrap_err().kind
+    }
+
+    #[test]
+    fn parse_reads_ui_json_and_ignores_legacy_fields() {
+        let (nodes, edges) = parse(
+            json!([{ "id": "a", "x": 1, "y": 2.5, "text": "A", "shape": "diamond", "color": "#f00", "connections": ["b"] }]),
+            json!([{ "from": "a", "to": "b" }]),
+        ).unwrap();
+        assert_eq!(nodes[0].shape, NodeShape::Diamond);
+        assert_eq!((nodes[0].x, nodes[0].y), (1.0, 2.5));
+        assert_eq!(edges, [edge("a", "b")]);
+    }
+
+    #[test]
+    fn parse_reports_malformed_nodes_and_connections() {
+        let bad_shape = json!([{ "id": "a", "x": 0, "y": 0, "text": "", "shape": "hexagon", "color": "" }]);
+        let error = parse(bad_shape, json!([])).unwrap_err();
+        assert_eq!(error.kind, MindMapErrorKind::Malformed);
+        assert!(error.message.starts_with("Invalid mind map nodes"));
+
+        let error = parse(json!([]), json!({ "from": "a" })).unwrap_err();
+        assert_eq!(error.kind, MindMapErrorKind::Malformed);
+        assert!(error.message.starts_with("Invalid mind map connections"));
+        assert_eq!((error.node_id, error.edge), (None, None));
+    }
+
+    #[test]
+    fn validate_reports_each_node_problem() {
+        let mut blank = nodes(&["a", " "]);
+        assert_eq!(kind(&blank, &[], false), MindMapErrorKind::EmptyNodeId);
+        blank[1].id = "a".to_string();
+        let error = validate(&blank, &[], false).unwrap_err();
+        assert_eq!(error.kind, MindMapErrorKind::DuplicateNodeId);
+        assert_eq!(error.node_id.as_deref(), Some("a"));
+
+        for (x, y) in [(f64::NAN, 0.0), (0.0, f64::INFINITY)] {
+            let mut lost = nodes(&["a"]);
+            lost[0].x = x;
+            lost[0].y = y;
+            assert_eq!(kind(&lost, &[], false), MindMapErrorKind::InvalidPosition);
+        }
+    }
+
+    #[test]
+    fn validate_reports_each_connection_problem() {
+        let map = nodes(&["a", "b"]);
+        let error = validate(&map, &[edge("a", "b"), edge("b", "ghost")], false).unwrap_err();
+        assert_eq!(error.kind, MindMapErrorKind::DanglingEdge);
+        assert_eq!(error.edge, Some(edge("b", "ghost")));
+        assert!(error.message.contains("\"ghost\""));
+        assert_eq!(kind(&map, &[edge("ghost", "a")], false), MindMapErrorKind::DanglingEdge);
+        assert_eq!(kind(&map, &[edge("a", "a")], false), MindMapErrorKind::SelfLoop);
+        assert_eq!(kind(&map, &[edge("a", "b"), edge("a", "b")], false), MindMapErrorKind::DuplicateEdge);
+
+        // The reverse direction is a different connection
+        validate(&map, &[edge("a", "b"), edge("b", "a")], false).unwrap();
+    }
+
+    #[test]
+    fn cycles_are_only_rejected_in_acyclic_maps() {
+        let map = nodes(&["a", "b", "c"]);
+        let loop_edges = [edge("a", "b"), edge("b", "c"), edge("c", "a")];
+        validate(&map, &loop_edges, false).unwrap();
+        let error = validate(&map, &loop_edges, true).unwrap_err();
+        assert_eq!(error.kind, MindMapErrorKind::Cycle);
+        assert_eq!(error.edge, Some(edge("c", "a")));
+
+        // A loop that is only reachable from a later node is still found
+        let map = nodes(&["root", "x", "y"]);
+        assert_eq!(kind(&map, &[edge("x", "y"), edge("y", "x")], true), MindMapErrorKind::Cycle);
+        assert_eq!(kind(&map, &[edge("root", "x"), edge("x", "y"), edge("y", "x")], true), MindMapErrorKind::Cycle);
+    }
+
+    #[test]
+    fn diamond_shaped_maps_are_not_cycles() {
+        //   top
+        //  /   \
+        // left right
+        //  \   /
+        //  bottom -> tail
+        let map = nodes(&["top", "left", "right", "bottom", "tail"]);
+        let edges = [
+            edge("top", "left"),
+            edge("top", "right"),
+            edge("left", "bottom"),
+            edge("right", "bottom"),
+            edge("bottom", "tail"),
+            edge("top", "tail"),
+        ];
+        validate(&map, &edges, true).unwrap();
+
+        // Visiting the shared node first from either side makes no difference
+        let mut reversed = edges.to_vec();
+        reversed.reverse();
+        let mut reordered = map.clone();
+        reordered.reverse();
+        validate(&reordered, &reversed, true).unwrap();
+    }
+
+    #[test]
+    fn salvage_keeps_what_legacy_json_can_still_give() {
+        let legacy_nodes = r##"[
+            { "id": "a", "x": 0, "y": 0, "text": "A", "shape": "circle", "color": "#000", "connections": ["b"] },
+            { "id": "b", "x": 5, "y": 5, "text": "B", "shape": "square", "color": "#111" },
+            { "id": "a", "x": 9, "y": 9, "text": "Again", "shape": "square", "color": "#222" },
+            { "id": "", "x": 1, "y": 1, "text": "No id", "shape": "square", "color": "#333" },
+            { "id": "c", "x": "left", "y": 0, "text": "Bad x", "shape": "square", "color": "#444" },
+            { "id": "d", "x": 0, "y": 0, "text": "Bad shape", "shape": "star", "color": "#555" },
+            "not a node",
+            { "id": "e", "x": 2, "y": 3, "text": "E", "shape": "diamond", "color": "#666" }
+        ]"##;
+        let legacy_edges = r#"[
+            { "from": "a", "to": "b" },
+            { "from": "a", "to": "b" },
+            { "from": "b", "to": "b" },
+            { "from": "b", "to": "c" },
+            { "from": "a" },
+            { "from": "e", "to": "a" }
+        ]"#;
+
+        let (nodes, edges) = salvage(legacy_nodes, legacy_edges);
+        assert_eq!(nodes.iter().map(|n| n.id.as_str()).collect::<Vec<_>>(), ["a", "b", "e"]);
+        assert_eq!(nodes[0].text, "A");
+        assert_eq!(edges, [edge("a", "b"), edge("e", "a")]);
+        validate(&nodes, &edges, false).unwrap();
+
+        // Not JSON at all, or not arrays: nothing survives
+        assert_eq!(salvage("{{", "[]"), (vec![], vec![]));
+        assert_eq!(salvage(r#"{"id":"a"}"#, "null"), (vec![], vec![]));
+        let (nodes, edges) = salvage(legacy_nodes, "oops");
+        assert_eq!((nodes.len(), edges.len()), (3, 0));
+    }
+
+    #[test]
+    fn legacy_json_round_trips_through_salvage() {
+        let map = nodes(&["a", "b", "c"]);
+        let edges = [edge("a", "b"), edge("a", "c")];
+        let (nodes_json, edges_json) = legacy_json(&map, &edges).unwrap();
+        let stored: Value = serde_json::from_str(&nodes_json).unwrap();
+        assert_eq!(stored[0]["connections"], json!(["b", "c"]));
+        assert_eq!(stored[2]["connections"], json!([]));
+        assert_eq!(salvage(&nodes_json, &edges_json), (map.clone(), edges.to_vec()));
+
+        let (nodes_json, edges_json) = to_json(&map, &edges).unwrap();
+        assert_eq!(parse(serde_json::from_str(&nodes_json).unwrap(), serde_json::from_str(&edges_json).unwrap()).unwrap(), (map, edges.to_vec()));
+    }
+}
@@ -0,0 +1,573 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::database::with_transaction;
+use crate::database_pool::DbPool;
+use crate::error::RweError;
+use crate::mindmap_export::{self, MindMapExportFormat};
+use crate::mindmap_schema::{self, MindMapEdge, MindMapNode, NodeShape};
+
+/// How many of a map's newest versions are always kept.
+const KEEP_RECENT_VERSIONS: i64 = 20;
+/// Older versions are thinned to the last one of each day for this long,
+/// then dropped.
+const KEEP_DAILY_VERSIONS_DAYS: i64 = 30;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MindMapData {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub title: String,
+    pub nodes: Vec<MindMapNode>,
+    pub connections: Vec<MindMapEdge>,
+    pub theme: String,
+    /// Whether connections may form loops; trees and flowcharts say no.
+    pub acyclic: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A saved version as listed, without its nodes and connections.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MindMapVersionInfo {
+    pub mindmap_id: i64,
+    pub version: i64,
+    pub title: String,
+    pub theme: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MindMapVersion {
+    pub mindmap_id: i64,
+    pub version: i64,
+    pub title: String,
+    pub nodes: Vec<MindMapNode>,
+    pub connections: Vec<MindMapEdge>,
+    pub theme: String,
+    pub created_at: String,
+}
+
+const MINDMAP_COLUMNS: &str = "id, conversation_id, title, theme, acyclic, created_at, updated_at";
+
+fn row_to_mindmap(row: &rusqlite::Row) -> rusqlite::Result<MindMapData> {
+    Ok(MindMapData {
+        id: row.get(0)?,
+        conversation_id: row.get(1)?,
+        title: row.get(2)?,
+        nodes: Vec::new(),
+        connections: Vec::new(),
+        theme: row.get(3)?,
+        acyclic: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+/// Reads a map's nodes and connections in the order they were saved.
+pub fn load_graph(conn: &Connection, mindmap_id: i64) -> Result<(Vec<MindMapNode>, Vec<MindMapEdge>), RweError> {
+    let mut stmt = conn.prepare(
+        "SELECT node_id, x, y, text, shape, color FROM mindmap_nodes WHERE mindmap_id = ?1 ORDER BY position",
+    ).map_err(|e| RweError::database("Prepare error", e))?;
+    let nodes = stmt.query_map([mindmap_id], |row| {
+        let shape: String = row.get(4)?;
+        Ok(MindMapNode {
+            id: row.get(0)?,
+            x: row.get(1)?,
+            y: row.get(2)?,
+            text: row.get(3)?,
+            // The table only admits known shapes
+            shape: NodeShape::from_stored(&shape).unwrap_or(NodeShape::Square),
+            color: row.get(5)?,
+        })
+    }).map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT from_node, to_node FROM mindmap_edges WHERE mindmap_id = ?1 ORDER BY position",
+    ).map_err(|e| RweError::database("Prepare error", e))?;
+    let edges = stmt.query_map([mindmap_id], |row| {
+        Ok(MindMapEdge { from: row.get(0)?, to: row.get(1)? })
+    }).map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+    Ok((nodes, edges))
+}
+
+/// Replaces a map's nodes and connections. Callers validate them first.
+pub fn write_graph(conn: &Connection, mindmap_id: i64, nodes: &[MindMapNode], edges: &[MindMapEdge]) -> Result<(), RweError> {
+    conn.execute("DELETE FROM mindmap_edges WHERE mindmap_id = ?1", [mindmap_id])
+        .map_err(|e| RweError::database("Delete connections error", e))?;
+    conn.execute("DELETE FROM mindmap_nodes WHERE mindmap_id = ?1", [mindmap_id])
+        .map_err(|e| RweError::database("Delete nodes error", e))?;
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO mindmap_nodes (mindmap_id, node_id, position, x, y, text, shape, color) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    ).map_err(|e| RweError::database("Prepare error", e))?;
+    for (position, node) in nodes.iter().enumerate() {
+        stmt.execute(params![mindmap_id, node.id, position as i64, node.x, node.y, node.text, node.shape.as_str(), node.color])
+            .map_err(|e| RweError::database("Insert node error", e))?;
+    }
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO mindmap_edges (mindmap_id, from_node, to_node, position) VALUES (?1, ?2, ?3, ?4)",
+    ).map_err(|e| RweError::database("Prepare error", e))?;
+    for (position, edge) in edges.iter().enumerate() {
+        stmt.execute(params![mindmap_id, edge.from, edge.to, position as i64])
+            .map_err(|e| RweError::database("Insert connection error", e))?;
+    }
+    Ok(())
+}
+
+fn with_graph(conn: &Connection, mut mindmap: MindMapData) -> Result<MindMapData, RweError> {
+    (mindmap.nodes, mindmap.connections) = load_graph(conn, mindmap.id)?;
+    Ok(mindmap)
+}
+
+fn load_mindmap(conn: &Connection, mindmap_id: i64) -> Result<MindMapData, RweError> {
+    let mindmap = conn.query_row(
+        &format!("SELECT {} FROM mindmaps WHERE id = ?1", MINDMAP_COLUMNS),
+        [mindmap_id],
+        row_to_mindmap,
+    )
+    .optional()
+    .map_err(|e| RweError::database("Query mindmap error", e))?
+    .ok_or_else(|| RweError::NotFound(format!("Mind map {} not found", mindmap_id)))?;
+    with_graph(conn, mindmap)
+}
+
+fn normalize_title(title: &str) -> Result<String, RweError> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(RweError::Validation("Mind map title must not be empty".to_string()));
+    }
+    Ok(title.to_string())
+}
+
+/// Records the map as it is now as its next version, unless that would
+/// repeat the latest one, then applies the retention policy.
+fn snapshot(conn: &Connection, mindmap: &MindMapData) -> Result<(), RweError> {
+    let (nodes, connections) = mindmap_schema::to_json(&mindmap.nodes, &mindmap.connections)
+        .map_err(|e| RweError::Internal(format!("Failed to encode mind map: {}", e)))?;
+    let latest: Option<(i64, String, String, String, Option<String>)> = conn.query_row(
+        r#"
+        SELECT version, title, nodes, connections, theme FROM mindmap_versions
+        WHERE mindmap_id = ?1 ORDER BY version DESC LIMIT 1
+        "#,
+        [mindmap.id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    )
+    .optional()
+    .map_err(|e| RweError::database("Query error", e))?;
+
+    let version = match latest {
+        Some((version, title, latest_nodes, latest_connections, theme)) => {
+            if title == mindmap.title && latest_nodes == nodes && latest_connections == connections
+                && theme.as_deref() == Some(mindmap.theme.as_str()) {
+                return Ok(());
+            }
+            version + 1
+        }
+        None => 1,
+    };
+    conn.execute(
+        "INSERT INTO mindmap_versions (mindmap_id, version, title, nodes, connections, theme) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![mindmap.id, version, mindmap.title, nodes, connections, mindmap.theme],
+    ).map_err(|e| RweError::database("Snapshot mindmap error", e))?;
+
+    conn.execute(
+        r#"
+        DELETE FROM mindmap_versions
+        WHERE mindmap_id = ?1
+          AND version NOT IN (
+              SELECT version FROM mindmap_versions WHERE mindmap_id = ?1
+              ORDER BY version DESC LIMIT ?2
+          )
+          AND version NOT IN (
+              SELECT MAX(version) FROM mindmap_versions
+              WHERE mindmap_id = ?1 AND created_at >= datetime('now', ?3)
+              GROUP BY date(created_at)
+          )
+        "#,
+        params![mindmap.id, KEEP_RECENT_VERSIONS, format!("-{} days", KEEP_DAILY_VERSIONS_DAYS)],
+    ).map_err(|e| RweError::database("Prune mindmap versions error", e))?;
+    Ok(())
+}
+
+fn latest_mindmap(conn: &Connection, conversation_id: i64) -> Result<Option<MindMapData>, RweError> {
+    let mindmap = conn.query_row(
+        &format!(
+            "SELECT {} FROM mindmaps WHERE conversation_id = ?1 ORDER BY updated_at DESC, id DESC LIMIT 1",
+            MINDMAP_COLUMNS
+        ),
+        [conversation_id],
+        row_to_mindmap,
+    )
+    .optional()
+    .map_err(|e| RweError::database("Query error", e))?;
+    mindmap.map(|mindmap| with_graph(conn, mindmap)).transpose()
+}
+
+/// The conversation's most recently saved mind map.
+#[command]
+pub async fn get_mindmap_data(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<Option<MindMapData>, RweError> {
+    db.run(move |conn| latest_mindmap(conn, conversation_id)).await
+}
+
+#[command]
+pub async fn list_mindmaps(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<Vec<MindMapData>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM mindmaps WHERE conversation_id = ?1 ORDER BY updated_at DESC, id DESC",
+            MINDMAP_COLUMNS
+        )).map_err(|e| RweError::database("Prepare error", e))?;
+        let mindmaps = stmt.query_map([conversation_id], row_to_mindmap)
+            .map_err(|e| RweError::database("Query error", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RweError::database("Row error", e))?;
+        mindmaps.into_iter().map(|mindmap| with_graph(conn, mindmap)).collect()
+    }).await
+}
+
+/// Saves over `mindmap_id`, or starts a new map in the conversation when it
+/// is not given. The map is checked against the schema first and rejected
+/// with `InvalidMindMap` if it breaks it. `acyclic` is left as it was when
+/// not given. Every save that changes the map is kept as a version.
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_mindmap_data(
+    conversation_id: i64,
+    mindmap_id: Option<i64>,
+    title: String,
+    nodes: serde_json::Value,
+    connections: serde_json::Value,
+    theme: String,
+    acyclic: Option<bool>,
+    db: State<'_, DbPool>,
+) -> Result<i64, RweError> {
+    let title = normalize_title(&title)?;
+    let (nodes, connections) = mindmap_schema::parse(nodes, connections).map_err(RweError::InvalidMindMap)?;
+    db.run(move |conn| save(conn, conversation_id, mindmap_id, &title, &nodes, &connections, &theme, acyclic)).await
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save(
+    conn: &Connection,
+    conversation_id: i64,
+    mindmap_id: Option<i64>,
+    title: &str,
+    nodes: &[MindMapNode],
+    connections: &[MindMapEdge],
+    theme: &str,
+    acyclic: Option<bool>,
+) -> Result<i64, RweError> {
+    with_transaction(conn, |tx| {
+        let mindmap_id = match mindmap_id {
+            Some(mindmap_id) => {
+                let existing = load_mindmap(tx, mindmap_id)?;
+                if existing.conversation_id != conversation_id {
+                    return Err(RweError::NotFound(format!("Mind map {} not found", mindmap_id)));
+                }
+                let acyclic = acyclic.unwrap_or(existing.acyclic);
+                mindmap_schema::validate(nodes, connections, acyclic).map_err(RweError::InvalidMindMap)?;
+                tx.execute(
+                    "UPDATE mindmaps SET title = ?1, theme = ?2, acyclic = ?3, updated_at = CURRENT_TIMESTAMP WHERE id = ?4",
+                    params![title, theme, acyclic, mindmap_id],
+                ).map_err(|e| RweError::database("Update mindmap error", e))?;
+                mindmap_id
+            }
+            None => {
+                let acyclic = acyclic.unwrap_or(false);
+                mindmap_schema::validate(nodes, connections, acyclic).map_err(RweError::InvalidMindMap)?;
+                tx.execute(
+                    "INSERT INTO mindmaps (conversation_id, title, theme, acyclic) VALUES (?1, ?2, ?3, ?4)",
+                    params![conversation_id, title, theme, acyclic],
+                ).map_err(|e| RweError::database("Insert mindmap error", e))?;
+                tx.last_insert_rowid()
+            }
+        };
+        write_graph(tx, mindmap_id, nodes, connections)?;
+        snapshot(tx, &load_mindmap(tx, mindmap_id)?)?;
+        Ok(mindmap_id)
+    })
+}
+
+/// Deletes a mind map along with its versions.
+#[command]
+pub async fn delete_mindmap(
+    mindmap_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        let affected = conn.execute("DELETE FROM mindmaps WHERE id = ?1", [mindmap_id])
+            .map_err(|e| RweError::database("Delete mindmap error", e))?;
+        Ok(affected > 0)
+    }).await
+}
+
+/// The map's saved versions, newest first.
+#[command]
+pub async fn list_mindmap_versions(
+    mindmap_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<Vec<MindMapVersionInfo>, RweError> {
+    db.run(move |conn| list_versions(conn, mindmap_id)).await
+}
+
+fn list_versions(conn: &Connection, mindmap_id: i64) -> Result<Vec<MindMapVersionInfo>, RweError> {
+    load_mindmap(conn, mindmap_id)?;
+    let mut stmt = conn.prepare(
+        "SELECT mindmap_id, version, title, theme, created_at FROM mindmap_versions WHERE mindmap_id = ?1 ORDER BY version DESC",
+    ).map_err(|e| RweError::database("Prepare error", e))?;
+    let versions = stmt.query_map([mindmap_id], |row| {
+        Ok(MindMapVersionInfo {
+            mindmap_id: row.get(0)?,
+            version: row.get(1)?,
+            title: row.get(2)?,
+            theme: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }).map_err(|e| RweError::database("Query error", e))?;
+    versions.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))
+}
+
+fn load_version(conn: &Connection, mindmap_id: i64, version: i64) -> Result<MindMapVersion, RweError> {
+    let (title, nodes, connections, theme, created_at): (String, String, String, String, String) = conn.query_row(
+        r#"
+        SELECT title, nodes, connections, theme, created_at
+        FROM mindmap_versions WHERE mindmap_id = ?1 AND version = ?2
+        "#,
+        [mindmap_id, version],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    )
+    .optional()
+    .map_err(|e| RweError::database("Query error", e))?
+    .ok_or_else(|| RweError::NotFound(format!("Version {} of mind map {} not found", version, mindmap_id)))?;
+
+    let invalid = |e: serde_json::Error| RweError::Storage(format!("Invalid mind map version: {}", e));
+    Ok(MindMapVersion {
+        mindmap_id,
+        version,
+        title,
+        nodes: serde_json::from_str(&nodes).map_err(invalid)?,
+        connections: serde_json::from_str(&connections).map_err(invalid)?,
+        theme,
+        created_at,
+    })
+}
+
+#[command]
+pub async fn get_mindmap_version(
+    mindmap_id: i64,
+    version: i64,
+    db: State<'_, DbPool>,
+) -> Result<MindMapVersion, RweError> {
+    db.run(move |conn| load_version(conn, mindmap_id, version)).await
+}
+
+/// Puts the map back the way it was at `version`. The restore is saved as a
+/// new version, so the versions after it stay available.
+#[command]
+pub async fn restore_mindmap_version(
+    mindmap_id: i64,
+    version: i64,
+    db: State<'_, DbPool>,
+) -> Result<MindMapData, RweError> {
+    db.run(move |conn| restore(conn, mindmap_id, version)).await
+}
+
+fn restore(conn: &Connection, mindmap_id: i64, version: i64) -> Result<MindMapData, RweError> {
+    with_transaction(conn, |tx| {
+        let current = load_mindmap(tx, mindmap_id)?;
+        let restored = load_version(tx, mindmap_id, version)?;
+        // The map may have been made acyclic since
+        mindmap_schema::validate(&restored.nodes, &restored.connections, current.acyclic)
+            .map_err(RweError::InvalidMindMap)?;
+        tx.execute(
+            "UPDATE mindmaps SET title = ?1, theme = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?3",
+            params![restored.title, restored.theme, mindmap_id],
+        ).map_err(|e| RweError::database("Update mindmap error", e))?;
+        write_graph(tx, mindmap_id, &restored.nodes, &restored.connections)?;
+        let mindmap = load_mindmap(tx, mindmap_id)?;
+        snapshot(tx, &mindmap)?;
+        Ok(mindmap)
+    })
+}
+
+/// A mind map written out for another tool, and a file name to save it as.
+#[derive(Debug, Serialize)]
+pub struct MindMapExport {
+    pub file_name: String,
+    pub content: String,
+}
+
+/// Exports the conversation's most recently saved map, or `mindmap_id` when
+/// given, in `format`.
+#[command]
+pub async fn export_mindmap(
+    conversation_id: i64,
+    format: MindMapExportFormat,
+    mindmap_id: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<MindMapExport, RweError> {
+    let mindmap = db.run(move |conn| match mindmap_id {
+        Some(mindmap_id) => load_mindmap(conn, mindmap_id).map(Some),
+        None => latest_mindmap(conn, conversation_id),
+    }).await?;
+    let mindmap = mindmap
+        .filter(|mindmap| mindmap.conversation_id == conversation_id)
+        .ok_or_else(|| RweError::NotFound(format!("No mind map to export for conversation {}", conversation_id)))?;
+    Ok(MindMapExport {
+        file_name: mindmap_export::file_name(&mindmap.title, format),
+        content: mindmap_export::export(format, &mindmap.title, &mindmap.nodes, &mindmap.connections),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+    use serde_json::{json, Value};
+
+    fn migrated() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO conversations (id, name) VALUES (1, 'Plans')", []).unwrap();
+        conn
+    }
+
+    fn node(id: &str) -> MindMapNode {
+        MindMapNode { id: id.to_string(), x: 10.0, y: 20.0, text: id.to_uppercase(), shape: NodeShape::Circle, color: "#0af".to_string() }
+    }
+
+    fn edge(from: &str, to: &str) -> MindMapEdge {
+        MindMapEdge { from: from.to_string(), to: to.to_string() }
+    }
+
+    fn versions(conn: &Connection, mindmap_id: i64) -> Vec<i64> {
+        let mut versions: Vec<i64> = list_versions(conn, mindmap_id).unwrap().iter().map(|info| info.version).collect();
+        versions.reverse();
+        versions
+    }
+
+    fn backdate(conn: &Connection, mindmap_id: i64, version: i64, modifier: &str) {
+        conn.execute(
+            "UPDATE mindmap_versions SET created_at = datetime('now', 'start of day', ?1, '+12 hours') WHERE mindmap_id = ?2 AND version = ?3",
+            params![modifier, mindmap_id, version],
+        ).unwrap();
+    }
+
+    #[test]
+    fn retention_keeps_recent_versions_and_one_a_day_for_a_month() {
+        let conn = migrated();
+        let save_title = |mindmap_id: Option<i64>, title: &str| {
+            save(&conn, 1, mindmap_id, title, &[node("a")], &[], "default", None).unwrap()
+        };
+        let id = save_title(None, "v1");
+        backdate(&conn, id, 1, "-40 days");
+        for (version, modifier) in [(2, "-10 days"), (3, "-10 days"), (4, "-5 days")] {
+            save_title(Some(id), &format!("v{}", version));
+            backdate(&conn, id, version, modifier);
+        }
+        // A save that changes nothing isn't a version
+        save_title(Some(id), "v4");
+        assert_eq!(versions(&conn, id), [1, 2, 3, 4]);
+
+        for version in 5..=26 {
+            save_title(Some(id), &format!("v{}", version));
+        }
+        // The newest 20, plus the last of each earlier day within 30 days
+        let mut expected = vec![3, 4];
+        expected.extend(7..=26);
+        assert_eq!(versions(&conn, id), expected);
+    }
+
+    #[test]
+    fn restoring_a_version_saves_it_as_the_newest() {
+        let conn = migrated();
+        let id = save(&conn, 1, None, "Draft", &[node("a"), node("b")], &[edge("a", "b"), edge("b", "a")], "default", None).unwrap();
+        save(&conn, 1, Some(id), "Final", &[node("a"), node("b"), node("c")], &[edge("a", "c")], "dark", None).unwrap();
+
+        let mindmap = restore(&conn, id, 1).unwrap();
+        assert_eq!((mindmap.title.as_str(), mindmap.theme.as_str()), ("Draft", "default"));
+        assert_eq!(mindmap.nodes, [node("a"), node("b")]);
+        assert_eq!(mindmap.connections, [edge("a", "b"), edge("b", "a")]);
+        assert_eq!(versions(&conn, id), [1, 2, 3]);
+        assert_eq!(load_version(&conn, id, 2).unwrap().title, "Final");
+
+        // Once the map is acyclic, the version with a loop can't come back
+        save(&conn, 1, Some(id), "Tree", &[node("a"), node("b")], &[edge("a", "b")], "default", Some(true)).unwrap();
+        assert!(matches!(restore(&conn, id, 1), Err(RweError::InvalidMindMap(_))));
+        let mindmap = load_mindmap(&conn, id).unwrap();
+        assert_eq!(mindmap.title, "Tree");
+        assert_eq!(mindmap.connections, [edge("a", "b")]);
+        assert!(matches!(restore(&conn, id, 99), Err(RweError::NotFound(_))));
+    }
+
+    #[test]
+    fn graph_migration_salvages_legacy_maps_and_round_trips() {
+        let conn = migrated();
+        let runner = MigrationRunner::new();
+        runner.migrate_to(&conn, 16).unwrap();
+
+        let legacy_nodes = json!([
+            { "id": "a", "x": 1, "y": 2, "text": "A", "shape": "square", "color": "#fff", "connections": ["b"] },
+            { "id": "b", "x": 3, "y": 4, "text": "B", "shape": "diamond", "color": "#000", "connections": [] },
+            { "id": "a", "x": 5, "y": 6, "text": "Again", "shape": "square", "color": "#fff" },
+            { "id": "c", "text": "No position" },
+        ]).to_string();
+        let legacy_edges = json!([
+            { "from": "a", "to": "b" },
+            { "from": "a", "to": "b" },
+            { "from": "b", "to": "b" },
+            { "from": "a", "to": "gone" },
+        ]).to_string();
+        let valid_nodes = serde_json::to_string(&[node("x"), node("y")]).unwrap();
+        let valid_edges = serde_json::to_string(&[edge("x", "y")]).unwrap();
+        conn.execute(
+            "INSERT INTO mindmaps (id, conversation_id, title, nodes, connections) VALUES (1, 1, 'Legacy', ?1, ?2), (2, 1, 'Valid', ?3, ?4)",
+            params![legacy_nodes, legacy_edges, valid_nodes, valid_edges],
+        ).unwrap();
+        conn.execute(
+            "INSERT INTO mindmap_versions (mindmap_id, version, title, nodes, connections, theme) VALUES (1, 1, 'Legacy', ?1, ?2, 'default')",
+            params![legacy_nodes, legacy_edges],
+        ).unwrap();
+
+        runner.run_migrations(&conn).unwrap();
+        let salvaged = load_graph(&conn, 1).unwrap();
+        let a = MindMapNode { id: "a".to_string(), x: 1.0, y: 2.0, text: "A".to_string(), shape: NodeShape::Square, color: "#fff".to_string() };
+        let b = MindMapNode { id: "b".to_string(), x: 3.0, y: 4.0, text: "B".to_string(), shape: NodeShape::Diamond, color: "#000".to_string() };
+        assert_eq!(salvaged, (vec![a, b], vec![edge("a", "b")]));
+        assert_eq!(load_graph(&conn, 2).unwrap(), (vec![node("x"), node("y")], vec![edge("x", "y")]));
+        let version = load_version(&conn, 1, 1).unwrap();
+        assert_eq!((version.nodes, version.connections), salvaged.clone());
+
+        // Down writes the legacy JSON back, with each node listing its targets
+        runner.migrate_to(&conn, 16).unwrap();
+        let (nodes, connections): (String, String) = conn.query_row(
+            "SELECT nodes, connections FROM mindmaps WHERE id = 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        let nodes: Value = serde_json::from_str(&nodes).unwrap();
+        assert_eq!(nodes[0]["connections"], json!(["b"]));
+        assert_eq!(nodes[1]["connections"], json!([]));
+        assert_eq!(serde_json::from_str::<Value>(&connections).unwrap(), json!([{ "from": "a", "to": "b" }]));
+        let version_nodes: String = conn.query_row("SELECT nodes FROM mindmap_versions WHERE mindmap_id = 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&version_nodes).unwrap(), nodes);
+
+        runner.run_migrations(&conn).unwrap();
+        assert_eq!(load_graph(&conn, 1).unwrap(), salvaged);
+        assert_eq!(load_graph(&conn, 2).unwrap(), (vec![node("x"), node("y")], vec![edge("x", "y")]));
+        let version = load_version(&conn, 1, 1).unwrap();
+        assert_eq!((version.nodes, version.connections), salvaged);
+    }
+}
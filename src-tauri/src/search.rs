@@ -1,7 +1,15 @@
 use serde::{Deserialize, Serialize};
 use tauri::{command, State};
 use rusqlite::Connection;
-use std::sync::Mutex;
+use rusqlite::types::Value;
+use std::collections::HashMap;
+use crate::branches;
+use crate::database_pool::DbPool;
+use crate::embeddings::{self, EmbeddingIndexer, SharedEmbedder};
+use crate::error::RweError;
+use crate::search_query::{self, ContentType, SearchQuery};
+use crate::search_snippet::{self, HighlightRange};
+use crate::search_fuzzy::{self, TermCorrection};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchResult {
@@ -11,125 +19,436 @@ pub struct SearchResult {
     pub content_type: String,
     pub content: String,
     pub snippet: String,
+    pub highlights: Vec<HighlightRange>,
     pub relevance_score: f64,
+    /// When the message was sent; for other results, when the conversation was created.
     pub created_at: String,
+    /// Total edits applied to the query to find this result; 0 for exact matches.
+    pub fuzziness: u32,
+    pub corrections: Vec<TermCorrection>,
+    /// Cosine similarity to the query for results found by `semantic_search`.
+    pub similarity: Option<f32>,
 }
 
-type DbConnection = Mutex<Connection>;
+const MAX_RESULTS: usize = 50;
+const PER_SOURCE_LIMIT: i64 = 30;
+const DEFAULT_SUGGESTION_LIMIT: i64 = 8;
+const RRF_K: f64 = 60.0;
+const MIN_SIMILARITY: f32 = 0.05;
+
+const MESSAGES_QUERY: &str = "
+    SELECT
+        m.id,
+        m.conversation_id,
+        c.name,
+        m.content,
+        m.created_at,
+        bm25(messages_fts) AS rank
+    FROM messages_fts
+    JOIN messages m ON m.id = messages_fts.rowid
+    JOIN conversations c ON c.id = m.conversation_id
+    WHERE messages_fts MATCH ?1 AND c.deleted_at IS NULL
+";
+
+const NOTES_QUERY: &str = "
+    SELECT
+        c.id,
+        c.id,
+        c.name,
+        c.notes,
+        c.created_at,
+        bm25(conversation_notes_fts) AS rank
+    FROM conversation_notes_fts
+    JOIN conversations c ON c.id = conversation_notes_fts.rowid
+    WHERE conversation_notes_fts MATCH ?1 AND c.deleted_at IS NULL
+";
+
+const SUMMARIES_QUERY: &str = "
+    SELECT
+        c.id,
+        c.id,
+        c.name,
+        c.summary,
+        c.created_at,
+        bm25(conversation_summaries_fts) AS rank
+    FROM conversation_summaries_fts
+    JOIN conversations c ON c.id = conversation_summaries_fts.rowid
+    WHERE conversation_summaries_fts MATCH ?1 AND c.deleted_at IS NULL
+";
+
+const TITLES_QUERY: &str = "
+    SELECT
+        c.id,
+        c.id,
+        c.name,
+        c.name,
+        c.created_at,
+        bm25(conversation_names_fts) AS rank
+    FROM conversation_names_fts
+    JOIN conversations c ON c.id = conversation_names_fts.rowid
+    WHERE conversation_names_fts MATCH ?1 AND c.deleted_at IS NULL
+";
+
+const MESSAGE_DETAILS_QUERY: &str = "
+    SELECT m.id, m.conversation_id, c.name, m.content, m.created_at
+    FROM messages m
+    JOIN conversations c ON c.id = m.conversation_id
+    WHERE m.id = ?1 AND c.deleted_at IS NULL
+";
+
+const NOTES_DETAILS_QUERY: &str = "
+    SELECT c.id, c.id, c.name, c.notes, c.created_at
+    FROM conversations c
+    WHERE c.id = ?1 AND c.deleted_at IS NULL
+";
+
+const SUMMARY_DETAILS_QUERY: &str = "
+    SELECT c.id, c.id, c.name, c.summary, c.created_at
+    FROM conversations c
+    WHERE c.id = ?1 AND c.deleted_at IS NULL
+";
+
+const TITLE_DETAILS_QUERY: &str = "
+    SELECT c.id, c.id, c.name, c.name, c.created_at
+    FROM conversations c
+    WHERE c.id = ?1 AND c.deleted_at IS NULL
+";
+
+const SOURCES: [(ContentType, &str); 4] = [
+    (ContentType::Message, MESSAGES_QUERY),
+    (ContentType::Notes, NOTES_QUERY),
+    (ContentType::Summary, SUMMARIES_QUERY),
+    (ContentType::Title, TITLES_QUERY),
+];
 
 #[command]
 pub async fn search_content(
     query: String,
-    db: State<'_, DbConnection>,
-) -> Result<Vec<SearchResult>, String> {
+    db: State<'_, DbPool>,
+) -> Result<Vec<SearchResult>, RweError> {
     if query.trim().is_empty() {
         return Ok(vec![]);
     }
 
-    let conn = db.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let parsed = search_query::parse_query(&query).map_err(RweError::InvalidQuery)?;
+
+    db.run(move |conn| {
+        let results = run_search(conn, &parsed)?;
+        if !results.is_empty() {
+            return Ok(results);
+        }
+        
+        // Nothing matched as typed, so retry with misspelled words swapped for
+        // their closest indexed terms and report how far the results are off
+        let corrections = search_fuzzy::correct_words(conn, &parsed.words())?;
+        if corrections.is_empty() {
+            return Ok(results);
+        }
+        
+        let replacements: Vec<(String, String)> = corrections
+            .iter()
+            .map(|c| (c.original.clone(), c.corrected.clone()))
+            .collect();
+        let fuzziness = corrections.iter().map(|c| c.distance).sum();
+        
+        let mut results = run_search(conn, &parsed.with_corrections(&replacements))?;
+        for result in results.iter_mut() {
+            result.fuzziness = fuzziness;
+            result.corrections = corrections.clone();
+        }
+        
+        Ok(results)
+    }).await
+}
+
+#[command]
+pub async fn suggest_search_terms(
+    prefix: String,
+    limit: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<Vec<String>, RweError> {
+    db.run(move |conn| {
+        search_fuzzy::complete_prefix(conn, &prefix, limit.unwrap_or(DEFAULT_SUGGESTION_LIMIT))
+    }).await
+}
+
+/// Nearest-neighbour search over message, note and summary embeddings.
+/// With `hybrid`, keyword results for the same query are merged in using
+/// reciprocal rank fusion. Text is embedded by the background indexer, so
+/// rows written a moment ago may not be found yet; the search wakes the
+/// indexer in case anything is still pending.
+#[command]
+pub async fn semantic_search(
+    query: String,
+    limit: Option<usize>,
+    hybrid: Option<bool>,
+    db: State<'_, DbPool>,
+    embedder: State<'_, SharedEmbedder>,
+    indexer: State<'_, EmbeddingIndexer>,
+) -> Result<Vec<SearchResult>, RweError> {
+    indexer.schedule();
+    if query.trim().is_empty() {
+        return Ok(vec![]);
+    }
+
+    let limit = limit.unwrap_or(MAX_RESULTS).min(MAX_RESULTS);
+    let keyword_query = if hybrid.unwrap_or(false) {
+        Some(search_query::parse_query(&query).map_err(RweError::InvalidQuery)?)
+    } else {
+        None
+    };
+
+    let embedder = embedder.inner().clone();
+    db.run(move |conn| {
+        let embedder = embedder.as_ref();
+        let query_vector = embedder.embed(&query);
+        let neighbours: Vec<_> = embeddings::nearest_neighbours(conn, embedder, &query_vector, MAX_RESULTS)?
+            .into_iter()
+            .filter(|n| n.similarity >= MIN_SIMILARITY)
+            .collect();
+        
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        let similarities: HashMap<(ContentType, i64), f32> = neighbours
+            .iter()
+            .map(|n| ((n.content_type, n.source_id), n.similarity))
+            .collect();
+        
+        let Some(keyword_query) = keyword_query else {
+            let mut results = Vec::new();
+            for neighbour in neighbours.iter().take(limit) {
+                if let Some(mut result) = load_result(conn, neighbour.content_type, neighbour.source_id, &terms)? {
+                    result.relevance_score = f64::from(neighbour.similarity) * 100.0;
+                    result.similarity = Some(neighbour.similarity);
+                    results.push(result);
+                }
+            }
+            return Ok(results);
+        };
+        
+        let mut keyword_results: HashMap<(ContentType, i64), SearchResult> = HashMap::new();
+        let mut keyword_ranking = Vec::new();
+        for result in run_search(conn, &keyword_query)? {
+            if let Some(content_type) = ContentType::from_source_type(&result.content_type) {
+                keyword_ranking.push((content_type, result.id));
+                keyword_results.insert((content_type, result.id), result);
+            }
+        }
+        let semantic_ranking: Vec<(ContentType, i64)> = neighbours
+            .iter()
+            .map(|n| (n.content_type, n.source_id))
+            .collect();
+        
+        let fused = embeddings::reciprocal_rank_fusion(&[keyword_ranking, semantic_ranking], RRF_K);
+        let best = fused.first().map(|(_, score)| *score).unwrap_or(1.0);
+        
+        let mut results = Vec::new();
+        for (key, score) in fused.into_iter().take(limit) {
+            let result = match keyword_results.remove(&key) {
+                Some(result) => Some(result),
+                None => load_result(conn, key.0, key.1, &terms)?,
+            };
+            if let Some(mut result) = result {
+                result.relevance_score = score / best * 100.0;
+                result.similarity = similarities.get(&key).copied();
+                results.push(result);
+            }
+        }
+        
+        Ok(results)
+    }).await
+}
+
+fn load_result(
+    conn: &Connection,
+    content_type: ContentType,
+    id: i64,
+    query_terms: &[&str],
+) -> Result<Option<SearchResult>, RweError> {
+    let sql = match content_type {
+        ContentType::Message => format!("{} AND {}", MESSAGE_DETAILS_QUERY, branches::ON_ACTIVE_BRANCH_SQL),
+        ContentType::Notes => NOTES_DETAILS_QUERY.to_string(),
+        ContentType::Summary => SUMMARY_DETAILS_QUERY.to_string(),
+        ContentType::Title => TITLE_DETAILS_QUERY.to_string(),
+    };
+    
+    let row = conn.query_row(&sql, [id], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    });
     
-    let query_terms: Vec<&str> = query.split_whitespace().collect();
-    let search_pattern = format!("%{}%", query.to_lowercase());
+    let (id, conversation_id, conversation_name, content, created_at) = match row {
+        Ok(row) => row,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(e) => return Err(RweError::database(&format!("Query {} details error", content_type.as_str()), e)),
+    };
+    let content = content.unwrap_or_default();
+    let snippet = search_snippet::create_snippet(&content, query_terms, 200);
     
+    Ok(Some(SearchResult {
+        id,
+        conversation_id,
+        conversation_name,
+        content_type: content_type.as_str().to_string(),
+        content,
+        snippet: snippet.text,
+        highlights: snippet.highlights,
+        relevance_score: 0.0,
+        created_at,
+        fuzziness: 0,
+        corrections: Vec::new(),
+        similarity: None,
+    }))
+}
+
+pub(crate) fn run_search(conn: &Connection, query: &SearchQuery) -> Result<Vec<SearchResult>, RweError> {
     let mut results = Vec::new();
+    for (content_type, sql) in SOURCES {
+        if query.filters.includes(content_type) {
+            results.extend(run_fts_query(conn, sql, content_type, query)?);
+        }
+    }
+    
+    normalize_relevance_scores(&mut results);
+    results.sort_by(|a, b| b.relevance_score.partial_cmp(&a.relevance_score).unwrap_or(std::cmp::Ordering::Equal));
+    results.truncate(MAX_RESULTS);
     
+    Ok(results)
+}
 
-    let mut stmt = conn.prepare("
-        SELECT 
-            id,
-            id as conversation_id,
-            name as conversation_name,
-            name as content,
-            created_at
-        FROM conversations
-        WHERE LOWER(name) LIKE ?1
-        ORDER BY created_at DESC
-        LIMIT 30
-    ").map_err(|e| format!("Prepare conversations error: {}", e))?;
+fn run_fts_query(
+    conn: &Connection,
+    base_sql: &str,
+    content_type: ContentType,
+    query: &SearchQuery,
+) -> Result<Vec<SearchResult>, RweError> {
+    let (filter_sql, filter_params) = query.filters.sql_clauses(content_type, 2);
+    // Messages left on an inactive branch aren't part of the conversation as shown
+    let branch_sql = match content_type {
+        ContentType::Message => format!(" AND {}", branches::ON_ACTIVE_BRANCH_SQL),
+        ContentType::Notes | ContentType::Summary | ContentType::Title => String::new(),
+    };
+    let sql = format!(
+        "{}{}{} ORDER BY rank LIMIT ?{}",
+        base_sql,
+        branch_sql,
+        filter_sql,
+        2 + filter_params.len()
+    );
+    
+    let mut params = vec![Value::Text(query.match_expression())];
+    params.extend(filter_params);
+    params.push(Value::Integer(PER_SOURCE_LIMIT));
     
-    let conv_iter = stmt.query_map([&search_pattern], |row| {
+    let terms = query.terms();
+    let query_terms: Vec<&str> = terms.iter().map(String::as_str).collect();
+    
+    let mut stmt = conn.prepare(&sql)
+        .map_err(|e| RweError::database(&format!("Prepare {} search error", content_type.as_str()), e))?;
+    
+    let result_iter = stmt.query_map(rusqlite::params_from_iter(params), |row| {
         let content: String = row.get(3)?;
-        let snippet = create_snippet(&content, &query_terms, 200);
-        let relevance_score = calculate_relevance_score(&content, &query_terms);
+        let snippet = search_snippet::create_snippet(&content, &query_terms, 200);
+        let rank: f64 = row.get(5)?;
         
         Ok(SearchResult {
             id: row.get(0)?,
             conversation_id: row.get(1)?,
             conversation_name: row.get(2)?,
-            content_type: "conversation".to_string(),
+            content_type: content_type.as_str().to_string(),
             content,
-            snippet,
-            relevance_score,
+            snippet: snippet.text,
+            highlights: snippet.highlights,
+            // bm25() is negative, with more relevant rows further from zero
+            relevance_score: -rank,
             created_at: row.get(4)?,
+            fuzziness: 0,
+            corrections: Vec::new(),
+            similarity: None,
         })
-    }).map_err(|e| format!("Query conversations error: {}", e))?;
+    }).map_err(|e| RweError::database(&format!("Query {} search error", content_type.as_str()), e))?;
     
-    for result in conv_iter {
-        results.push(result.map_err(|e| format!("Row error: {}", e))?);
+    let mut results = Vec::new();
+    for result in result_iter {
+        results.push(result.map_err(|e| RweError::database("Row error", e))?);
     }
     
-    results.sort_by(|a, b| b.relevance_score.partial_cmp(&a.relevance_score).unwrap_or(std::cmp::Ordering::Equal));
-    
-    Ok(results.into_iter().take(50).collect())
+    Ok(results)
 }
 
-fn calculate_relevance_score(content: &str, query_terms: &[&str]) -> f64 {
-    let content_lower = content.to_lowercase();
-    let mut score = 0.0;
-    
-    for term in query_terms {
-        let term_lower = term.to_lowercase();
-        let occurrences = content_lower.matches(&term_lower).count();
-        score += occurrences as f64 * 2.0;
-        
-        if content_lower.starts_with(&term_lower) {
-            score += 5.0;
-        }
-        
-        if content_lower.contains(&format!(" {}", term_lower)) {
-            score += 3.0;
-        }
+/// Scales BM25 scores to 0-100 relative to the best hit so the UI can show a percentage.
+fn normalize_relevance_scores(results: &mut [SearchResult]) {
+    let best = results.iter().map(|r| r.relevance_score).fold(0.0_f64, f64::max);
+    if best <= 0.0 {
+        return;
+    }
+    for result in results.iter_mut() {
+        result.relevance_score = (result.relevance_score / best * 100.0).max(0.0);
     }
-    
-    let length_factor = (content.len() as f64).log10().max(1.0);
-    score / length_factor
 }
 
-fn create_snippet(content: &str, query_terms: &[&str], max_length: usize) -> String {
-    let content_lower = content.to_lowercase();
-    
-    for term in query_terms {
-        let term_lower = term.to_lowercase();
-        if let Some(pos) = content_lower.find(&term_lower) {
-            let start = pos.saturating_sub(80);
-            let end = (pos + term.len() + 80).min(content.len());
-            let mut snippet = &content[start..end];
-            
-            while !snippet.is_char_boundary(snippet.len()) && snippet.len() > 0 {
-                snippet = &snippet[..snippet.len() - 1];
-            }
-            
-            let mut final_snippet = String::new();
-            if start > 0 {
-                final_snippet.push_str("...");
-            }
-            final_snippet.push_str(snippet);
-            if end < content.len() {
-                final_snippet.push_str("...");
-            }
-            
-            if final_snippet.len() > max_length {
-                let truncated = &final_snippet[..max_length.saturating_sub(3)];
-                return format!("{}...", truncated);
-            }
-            
-            return final_snippet;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::migrations::MigrationRunner;
+
+    fn seeded() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO conversations (id, name, notes) VALUES (1, 'Tokio scheduling', 'work stealing'), (2, 'Pasta', NULL), (3, 'Old tokio notes', NULL);
+             UPDATE conversations SET deleted_at = CURRENT_TIMESTAMP WHERE id = 3;",
+        ).unwrap();
+        database::insert_message(&conn, 2, "user", "boiling water for the tokio team", false).unwrap();
+        conn
     }
-    
-    if content.len() > max_length {
-        format!("{}...", &content[..max_length.saturating_sub(3)])
-    } else {
-        content.to_string()
+
+    fn search(conn: &Connection, query: &str) -> Vec<(String, i64)> {
+        run_search(conn, &search_query::parse_query(query).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|result| (result.content_type, result.conversation_id))
+            .collect()
+    }
+
+    #[test]
+    fn conversations_are_found_by_name() {
+        let conn = seeded();
+        assert_eq!(search(&conn, "schedul"), [("conversation".to_string(), 1)]);
+
+        let mut hits = search(&conn, "tokio");
+        hits.sort();
+        assert_eq!(hits, [("conversation".to_string(), 1), ("message".to_string(), 2)]);
+        assert_eq!(search(&conn, "type:title tokio"), [("conversation".to_string(), 1)]);
+
+        // Renames are re-indexed by trigger
+        conn.execute("UPDATE conversations SET name = 'Cooking' WHERE id = 2", []).unwrap();
+        assert_eq!(search(&conn, "cooking"), [("conversation".to_string(), 2)]);
+        assert!(search(&conn, "pasta").is_empty());
+    }
+
+    #[test]
+    fn date_filters_use_when_a_message_was_sent() {
+        let conn = seeded();
+        conn.execute_batch(
+            "UPDATE conversations SET created_at = DATETIME('now', '-1 year') WHERE id = 2;
+             UPDATE messages SET created_at = DATETIME('now', '-1 day') WHERE conversation_id = 2;",
+        ).unwrap();
+        let yesterday: String = conn.query_row("SELECT DATE('now', '-1 day')", [], |row| row.get(0)).unwrap();
+        let last_month: String = conn.query_row("SELECT DATE('now', '-1 month')", [], |row| row.get(0)).unwrap();
+
+        let query = search_query::parse_query(&format!("after:{} boiling", yesterday)).unwrap();
+        let results = run_search(&conn, &query).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(&results[0].created_at[..10], yesterday);
+
+        assert!(search(&conn, &format!("before:{} boiling", last_month)).is_empty());
+        // The conversation itself is a year old, so its name is filtered out
+        assert!(search(&conn, &format!("after:{} pasta", last_month)).is_empty());
     }
 }
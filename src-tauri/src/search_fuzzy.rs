@@ -0,0 +1,307 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::search_snippet::fold_char;
+use crate::error::RweError;
+
+/// Terms in one first-letter range whose length could be within reach of
+/// the misspelled word, so only a small slice of the vocabulary is compared.
+const CANDIDATE_QUERY: &str = "
+    SELECT term, SUM(doc) FROM (
+        SELECT term, doc FROM messages_fts_vocab WHERE term >= ?1 AND term < ?2
+        UNION ALL SELECT term, doc FROM conversation_notes_fts_vocab WHERE term >= ?1 AND term < ?2
+        UNION ALL SELECT term, doc FROM conversation_summaries_fts_vocab WHERE term >= ?1 AND term < ?2
+        UNION ALL SELECT term, doc FROM conversation_names_fts_vocab WHERE term >= ?1 AND term < ?2
+    )
+    WHERE length(term) BETWEEN ?3 AND ?4
+    GROUP BY term
+";
+
+const PREFIX_QUERY: &str = "
+    SELECT term, SUM(doc) AS docs FROM (
+        SELECT term, doc FROM messages_fts_vocab WHERE term >= ?1 AND term < ?2
+        UNION ALL SELECT term, doc FROM conversation_notes_fts_vocab WHERE term >= ?1 AND term < ?2
+        UNION ALL SELECT term, doc FROM conversation_summaries_fts_vocab WHERE term >= ?1 AND term < ?2
+        UNION ALL SELECT term, doc FROM conversation_names_fts_vocab WHERE term >= ?1 AND term < ?2
+    )
+    GROUP BY term
+    ORDER BY docs DESC, term ASC
+";
+
+/// The vocabulary tables still count rows of trashed conversations, so a term
+/// is only offered once it matches something search can actually return.
+const LIVE_TERM_QUERY: &str = "
+    SELECT EXISTS(
+        SELECT 1 FROM messages_fts
+        JOIN messages m ON m.id = messages_fts.rowid
+        JOIN conversations c ON c.id = m.conversation_id
+        WHERE messages_fts MATCH ?1 AND c.deleted_at IS NULL
+    ) OR EXISTS(
+        SELECT 1 FROM conversation_notes_fts
+        JOIN conversations c ON c.id = conversation_notes_fts.rowid
+        WHERE conversation_notes_fts MATCH ?1 AND c.deleted_at IS NULL
+    ) OR EXISTS(
+        SELECT 1 FROM conversation_summaries_fts
+        JOIN conversations c ON c.id = conversation_summaries_fts.rowid
+        WHERE conversation_summaries_fts MATCH ?1 AND c.deleted_at IS NULL
+    ) OR EXISTS(
+        SELECT 1 FROM conversation_names_fts
+        JOIN conversations c ON c.id = conversation_names_fts.rowid
+        WHERE conversation_names_fts MATCH ?1 AND c.deleted_at IS NULL
+    )
+";
+
+/// A misspelled query word and the indexed term it was replaced with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TermCorrection {
+    pub original: String,
+    pub corrected: String,
+    pub distance: u32,
+}
+
+/// Normalises a word the way the `unicode61 remove_diacritics` tokenizer does,
+/// so it can be compared with terms from the FTS vocabulary tables.
+pub fn normalize_term(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .map(fold_char)
+        .collect()
+}
+
+/// Whether `term` (or, for prefixes, any term starting with it) appears in a
+/// message, note, summary or name of a conversation that isn't in the trash.
+fn is_live_term(conn: &Connection, term: &str, is_prefix: bool) -> Result<bool, RweError> {
+    let expression = if is_prefix { format!("\"{}\"*", term) } else { format!("\"{}\"", term) };
+    conn.query_row(LIVE_TERM_QUERY, [expression], |row| row.get(0))
+        .map_err(|e| RweError::database("Query live term error", e))
+}
+
+/// Indexed terms that start with the word's first or second letter and whose
+/// length is within `allowed` edits of it. The second letter covers a swapped
+/// or extra first letter; a wrong first letter isn't fixed.
+fn candidate_terms(
+    conn: &Connection,
+    normalized: &str,
+    is_prefix: bool,
+    allowed: u32,
+) -> Result<HashMap<String, i64>, RweError> {
+    let len = normalized.chars().count() as i64;
+    let min_len = (len - i64::from(allowed)).max(1);
+    let max_len = if is_prefix { i64::MAX } else { len + i64::from(allowed) };
+
+    let mut starts: Vec<char> = normalized.chars().take(2).collect();
+    starts.dedup();
+
+    let mut candidates = HashMap::new();
+    let mut stmt = conn.prepare(CANDIDATE_QUERY)
+        .map_err(|e| RweError::database("Prepare vocabulary error", e))?;
+    for start in &starts {
+        let lower = start.to_string();
+        let upper = format!("{}{}", start, char::MAX);
+        let term_iter = stmt
+            .query_map(rusqlite::params![lower, upper, min_len, max_len], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })
+            .map_err(|e| RweError::database("Query vocabulary error", e))?;
+        for term in term_iter {
+            let (term, docs) = term.map_err(|e| RweError::database("Row error", e))?;
+            candidates.insert(term, docs);
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Indexed terms starting with `prefix` that occur in live conversations,
+/// most common first.
+pub fn complete_prefix(conn: &Connection, prefix: &str, limit: i64) -> Result<Vec<String>, RweError> {
+    let prefix = normalize_term(prefix);
+    if prefix.is_empty() {
+        return Ok(vec![]);
+    }
+    let upper = format!("{}{}", prefix, char::MAX);
+
+    let mut stmt = conn.prepare(PREFIX_QUERY)
+        .map_err(|e| RweError::database("Prepare suggestions error", e))?;
+    let term_iter = stmt.query_map(rusqlite::params![prefix, upper], |row| row.get::<_, String>(0))
+        .map_err(|e| RweError::database("Query suggestions error", e))?;
+
+    let mut terms = Vec::new();
+    for term in term_iter {
+        if terms.len() as i64 >= limit {
+            break;
+        }
+        let term = term.map_err(|e| RweError::database("Row error", e))?;
+        if is_live_term(conn, &term, false)? {
+            terms.push(term);
+        }
+    }
+
+    Ok(terms)
+}
+
+/// Optimal string alignment distance: Levenshtein plus adjacent
+/// transpositions, so `tokoi` is one edit away from `tokio`.
+pub fn edit_distance(a: &str, b: &str) -> u32 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let width = b.len() + 1;
+    let mut d = vec![0u32; (a.len() + 1) * width];
+
+    for i in 0..=a.len() {
+        d[i * width] = i as u32;
+    }
+    for (j, cell) in d.iter_mut().take(width).enumerate() {
+        *cell = j as u32;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = u32::from(a[i - 1] != b[j - 1]);
+            let mut best = (d[(i - 1) * width + j] + 1)
+                .min(d[i * width + j - 1] + 1)
+                .min(d[(i - 1) * width + j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(d[(i - 2) * width + j - 2] + 1);
+            }
+            d[i * width + j] = best;
+        }
+    }
+
+    d[a.len() * width + b.len()]
+}
+
+/// How many edits a word of this length may need before a candidate is too
+/// different to be a plausible typo.
+fn max_distance(len: usize) -> u32 {
+    match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Corrections for the misspelled words of a query, in query order. Words
+/// that are already searchable, or have no close enough term, are left out.
+/// Candidates come only from the FTS vocabularies, so every correction is a
+/// term the corrected query can match.
+pub fn correct_words(conn: &Connection, words: &[(String, bool)]) -> Result<Vec<TermCorrection>, RweError> {
+    let mut corrections = Vec::new();
+    for (word, is_prefix) in words {
+        if let Some(correction) = correct_word(conn, word, *is_prefix)? {
+            corrections.push(correction);
+        }
+    }
+    Ok(corrections)
+}
+
+/// The closest live term to `word`, preferring fewer edits and then more
+/// frequent terms. Prefix words count as known if any term starts with them,
+/// and otherwise are compared against equally long term prefixes.
+fn correct_word(
+    conn: &Connection,
+    word: &str,
+    is_prefix: bool,
+) -> Result<Option<TermCorrection>, RweError> {
+    let normalized = normalize_term(word);
+    let len = normalized.chars().count();
+    let allowed = max_distance(len);
+    if allowed == 0 || is_live_term(conn, &normalized, is_prefix)? {
+        return Ok(None);
+    }
+
+    let mut ranked: Vec<(u32, i64, String)> = candidate_terms(conn, &normalized, is_prefix, allowed)?
+        .into_iter()
+        .filter_map(|(term, docs)| {
+            let distance = if is_prefix && term.chars().count() > len {
+                let head: String = term.chars().take(len).collect();
+                edit_distance(&normalized, &head)
+            } else {
+                edit_distance(&normalized, &term)
+            };
+            (distance <= allowed).then_some((distance, -docs, term))
+        })
+        .collect();
+    ranked.sort();
+
+    for (distance, _, term) in ranked {
+        if is_live_term(conn, &term, false)? {
+            return Ok(Some(TermCorrection {
+                original: word.to_string(),
+                corrected: term,
+                distance,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::migrations::MigrationRunner;
+
+    fn words(query: &[(&str, bool)]) -> Vec<(String, bool)> {
+        query.iter().map(|(word, is_prefix)| (word.to_string(), *is_prefix)).collect()
+    }
+
+    fn seeded() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO conversations (id, name, notes) VALUES (1, 'Async runtimes', 'tokio scheduler'), (2, 'Trashed', 'tokens');
+             UPDATE conversations SET deleted_at = CURRENT_TIMESTAMP WHERE id = 2;",
+        ).unwrap();
+        database::insert_message(&conn, 1, "user", "Spawning tasks on the tokio runtime", false).unwrap();
+        database::insert_message(&conn, 2, "user", "Refresh tokens and tokenizers", false).unwrap();
+        conn
+    }
+
+    #[test]
+    fn corrects_typos_from_live_terms_only() {
+        let conn = seeded();
+
+        let corrections = correct_words(&conn, &words(&[("tokoi", false), ("runtime", false)])).unwrap();
+        assert_eq!(corrections, vec![TermCorrection { original: "tokoi".into(), corrected: "tokio".into(), distance: 1 }]);
+
+        // A swapped first letter is still found through the second letter
+        assert_eq!(correct_words(&conn, &words(&[("otkio", false)])).unwrap()[0].corrected, "tokio");
+        assert_eq!(correct_words(&conn, &words(&[("sheduler", false)])).unwrap()[0].corrected, "scheduler");
+
+        // Corrected words find the rows they were corrected to
+        let corrected = &correct_words(&conn, &words(&[("spawnnig", false)])).unwrap()[0].corrected;
+        let hits: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH ?1", [corrected], |row| row.get(0),
+        ).unwrap();
+        assert_eq!((corrected.as_str(), hits), ("spawning", 1));
+
+        // Conversation names are indexed too, and a typo that is itself in a
+        // trashed conversation's name is still fixed
+        let corrected = &correct_words(&conn, &words(&[("rutnimes", false)])).unwrap()[0].corrected;
+        let hits: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM conversation_names_fts WHERE conversation_names_fts MATCH ?1", [corrected], |row| row.get(0),
+        ).unwrap();
+        assert_eq!((corrected.as_str(), hits), ("runtimes", 1));
+        conn.execute("UPDATE conversations SET name = 'Tokoi notes' WHERE id = 2", []).unwrap();
+        assert_eq!(correct_words(&conn, &words(&[("tokoi", false)])).unwrap()[0].corrected, "tokio");
+
+        // "tokens" only appears in the trash, so it isn't offered as a fix
+        assert!(correct_words(&conn, &words(&[("tokenz", false)])).unwrap().is_empty());
+
+        // Prefixes are compared with term heads and known if any term starts with them
+        assert!(correct_words(&conn, &words(&[("sched", true)])).unwrap().is_empty());
+        assert_eq!(correct_words(&conn, &words(&[("shced", true)])).unwrap()[0].corrected, "scheduler");
+    }
+
+    #[test]
+    fn suggestions_skip_trashed_conversations() {
+        let conn = seeded();
+        assert_eq!(complete_prefix(&conn, "Tok", 8).unwrap(), ["tokio"]);
+        assert_eq!(complete_prefix(&conn, "s", 1).unwrap().len(), 1);
+
+        conn.execute("UPDATE conversations SET deleted_at = NULL WHERE id = 2", []).unwrap();
+        assert_eq!(complete_prefix(&conn, "tok", 8).unwrap(), ["tokens", "tokio", "tokenizers"]);
+    }
+}
@@ -0,0 +1,687 @@
+use chrono::NaiveDate;
+use rusqlite::types::Value;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    Message,
+    Notes,
+    Summary,
+    Title,
+}
+
+impl ContentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentType::Message => "message",
+            ContentType::Notes => "notes",
+            ContentType::Summary => "summary",
+            ContentType::Title => "conversation",
+        }
+    }
+
+    /// The inverse of `as_str`, for values read back from the database.
+    pub fn from_source_type(value: &str) -> Option<Self> {
+        match value {
+            "message" => Some(ContentType::Message),
+            "notes" => Some(ContentType::Notes),
+            "summary" => Some(ContentType::Summary),
+            "conversation" => Some(ContentType::Title),
+            _ => None,
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "message" | "messages" => Some(ContentType::Message),
+            "note" | "notes" => Some(ContentType::Notes),
+            "summary" | "summaries" => Some(ContentType::Summary),
+            "title" | "titles" | "conversation" | "conversations" => Some(ContentType::Title),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryErrorKind {
+    UnterminatedQuote,
+    DanglingOperator,
+    EmptyFilter,
+    InvalidFilterValue,
+    DuplicateFilter,
+    NegatedAlternative,
+    NoSearchTerms,
+}
+
+/// A malformed query, with the character offset the problem was found at.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryError {
+    pub kind: QueryErrorKind,
+    pub message: String,
+    pub position: usize,
+}
+
+impl QueryError {
+    fn new(kind: QueryErrorKind, position: usize, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into(), position }
+    }
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (at position {})", self.message, self.position)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Atom {
+    Word(String),
+    Phrase(String),
+}
+
+impl Atom {
+    fn to_fts(&self, prefix: bool) -> String {
+        let text = match self {
+            Atom::Word(text) | Atom::Phrase(text) => text,
+        };
+        let quoted = format!("\"{}\"", text.replace('"', "\"\""));
+        if prefix { format!("{}*", quoted) } else { quoted }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SearchFilters {
+    pub role: Option<String>,
+    pub after: Option<NaiveDate>,
+    pub before: Option<NaiveDate>,
+    pub conversation: Option<String>,
+    pub content_types: Vec<ContentType>,
+}
+
+impl SearchFilters {
+    /// Whether rows of `content_type` can match at all. A role filter only
+    /// makes sense for messages, so it excludes notes and summaries.
+    pub fn includes(&self, content_type: ContentType) -> bool {
+        if self.role.is_some() && content_type != ContentType::Message {
+            return false;
+        }
+        self.content_types.is_empty() || self.content_types.contains(&content_type)
+    }
+
+    /// Extra `AND ...` clauses over the `c` (conversations) and `m` (messages)
+    /// aliases, with their positional parameters starting at `first_param`.
+    pub fn sql_clauses(&self, content_type: ContentType, first_param: usize) -> (String, Vec<Value>) {
+        let mut sql = String::new();
+        let mut params = Vec::new();
+
+        if let Some(role) = &self.role {
+            if content_type == ContentType::Message {
+                sql.push_str(&format!(" AND m.role = ?{}", first_param + params.len()));
+                params.push(Value::Text(role.clone()));
+            }
+        }
+        // Messages are dated by when they were sent, everything else by its conversation
+        let created_at = match content_type {
+            ContentType::Message => "m.created_at",
+            ContentType::Notes | ContentType::Summary | ContentType::Title => "c.created_at",
+        };
+        if let Some(after) = self.after {
+            sql.push_str(&format!(" AND DATE({}) >= ?{}", created_at, first_param + params.len()));
+            params.push(Value::Text(after.format("%Y-%m-%d").to_string()));
+        }
+        if let Some(before) = self.before {
+            sql.push_str(&format!(" AND DATE({}) < ?{}", created_at, first_param + params.len()));
+            params.push(Value::Text(before.format("%Y-%m-%d").to_string()));
+        }
+        if let Some(conversation) = &self.conversation {
+            sql.push_str(&format!(" AND LOWER(c.name) LIKE ?{} ESCAPE '\\'", first_param + params.len()));
+            params.push(Value::Text(format!("%{}%", escape_like(&conversation.to_lowercase()))));
+        }
+
+        (sql, params)
+    }
+}
+
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// A parsed search box query: AND-ed groups of OR-ed terms, excluded terms
+/// and structured filters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    groups: Vec<Vec<Atom>>,
+    excluded: Vec<Atom>,
+    pub filters: SearchFilters,
+}
+
+impl SearchQuery {
+    /// The FTS5 MATCH expression. Every term is quoted so user input is never
+    /// interpreted as FTS syntax, and the last bare word is matched as a prefix.
+    pub fn match_expression(&self) -> String {
+        let group_count = self.groups.len();
+
+        let mut expression = self.groups
+            .iter()
+            .enumerate()
+            .map(|(i, group)| {
+                let last_group = i + 1 == group_count;
+                let parts: Vec<String> = group
+                    .iter()
+                    .enumerate()
+                    .map(|(j, atom)| {
+                        let prefix = last_group && j + 1 == group.len() && matches!(atom, Atom::Word(_));
+                        atom.to_fts(prefix)
+                    })
+                    .collect();
+                if parts.len() > 1 { format!("({})", parts.join(" OR ")) } else { parts.join("") }
+            })
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        if !self.excluded.is_empty() {
+            let parts: Vec<String> = self.excluded.iter().map(|atom| atom.to_fts(false)).collect();
+            expression = format!("({}) NOT ({})", expression, parts.join(" OR "));
+        }
+
+        expression
+    }
+
+    /// The individual words being searched for, used for snippet highlighting.
+    pub fn terms(&self) -> Vec<String> {
+        self.groups
+            .iter()
+            .flatten()
+            .flat_map(|atom| match atom {
+                Atom::Word(text) => vec![text.clone()],
+                Atom::Phrase(text) => text.split_whitespace().map(String::from).collect(),
+            })
+            .collect()
+    }
+
+    /// The bare (non-phrase) words being searched for, paired with whether
+    /// each one is matched as a prefix.
+    pub fn words(&self) -> Vec<(String, bool)> {
+        let group_count = self.groups.len();
+        self.groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, group)| {
+                group.iter().enumerate().filter_map(move |(j, atom)| match atom {
+                    Atom::Word(text) => Some((text.clone(), i + 1 == group_count && j + 1 == group.len())),
+                    Atom::Phrase(_) => None,
+                })
+            })
+            .collect()
+    }
+
+    /// A copy of this query with bare words swapped for their corrections.
+    pub fn with_corrections(&self, corrections: &[(String, String)]) -> SearchQuery {
+        let correct = |atom: &Atom| match atom {
+            Atom::Word(text) => corrections
+                .iter()
+                .find(|(original, _)| original == text)
+                .map(|(_, corrected)| Atom::Word(corrected.clone()))
+                .unwrap_or_else(|| atom.clone()),
+            Atom::Phrase(_) => atom.clone(),
+        };
+
+        SearchQuery {
+            groups: self.groups.iter().map(|group| group.iter().map(correct).collect()).collect(),
+            excluded: self.excluded.clone(),
+            filters: self.filters.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Atom(Atom),
+    Filter { key: String, value: String },
+    Not,
+    Or,
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        if chars[i] == '-' {
+            match chars.get(i + 1) {
+                Some(c) if !c.is_whitespace() => {
+                    tokens.push((start, Token::Not));
+                    i += 1;
+                    continue;
+                }
+                _ => return Err(QueryError::new(
+                    QueryErrorKind::DanglingOperator,
+                    start,
+                    "'-' must be followed by a term to exclude",
+                )),
+            }
+        }
+
+        if chars[i] == '"' {
+            let (phrase, end) = read_quoted(&chars, i)?;
+            i = end;
+            if !phrase.trim().is_empty() {
+                tokens.push((start, Token::Atom(Atom::Phrase(phrase))));
+            }
+            continue;
+        }
+
+        let mut word = String::new();
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '"' {
+            word.push(chars[i]);
+            i += 1;
+        }
+
+        if word == "OR" {
+            tokens.push((start, Token::Or));
+            continue;
+        }
+
+        if let Some((key, rest)) = word.split_once(':') {
+            let key = key.to_lowercase();
+            if is_filter_key(&key) {
+                let value = if rest.is_empty() && i < chars.len() && chars[i] == '"' {
+                    let (value, end) = read_quoted(&chars, i)?;
+                    i = end;
+                    value
+                } else {
+                    rest.to_string()
+                };
+                if value.trim().is_empty() {
+                    return Err(QueryError::new(
+                        QueryErrorKind::EmptyFilter,
+                        start,
+                        format!("'{}:' needs a value", key),
+                    ));
+                }
+                tokens.push((start, Token::Filter { key, value }));
+                continue;
+            }
+        }
+
+        tokens.push((start, Token::Atom(Atom::Word(word))));
+    }
+
+    Ok(tokens)
+}
+
+fn read_quoted(chars: &[char], open: usize) -> Result<(String, usize), QueryError> {
+    let mut value = String::new();
+    let mut i = open + 1;
+    while i < chars.len() {
+        if chars[i] == '"' {
+            return Ok((value, i + 1));
+        }
+        value.push(chars[i]);
+        i += 1;
+    }
+    Err(QueryError::new(
+        QueryErrorKind::UnterminatedQuote,
+        open,
+        "Unterminated quote",
+    ))
+}
+
+fn is_filter_key(key: &str) -> bool {
+    matches!(key, "role" | "after" | "before" | "in" | "type")
+}
+
+fn parse_role(value: &str) -> Option<&'static str> {
+    match value.to_lowercase().as_str() {
+        "user" | "me" => Some("user"),
+        "ai" | "model" | "assistant" => Some("ai"),
+        _ => None,
+    }
+}
+
+fn parse_date(value: &str, position: usize) -> Result<NaiveDate, QueryError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| QueryError::new(
+        QueryErrorKind::InvalidFilterValue,
+        position,
+        format!("'{}' is not a date, expected YYYY-MM-DD", value),
+    ))
+}
+
+fn apply_filter(
+    filters: &mut SearchFilters,
+    key: &str,
+    value: &str,
+    position: usize,
+) -> Result<(), QueryError> {
+    let duplicate = || QueryError::new(
+        QueryErrorKind::DuplicateFilter,
+        position,
+        format!("'{}:' can only be used once", key),
+    );
+
+    match key {
+        "role" => {
+            if filters.role.is_some() {
+                return Err(duplicate());
+            }
+            let role = parse_role(value).ok_or_else(|| QueryError::new(
+                QueryErrorKind::InvalidFilterValue,
+                position,
+                format!("Unknown role '{}', expected 'user' or 'model'", value),
+            ))?;
+            filters.role = Some(role.to_string());
+        }
+        "after" => {
+            if filters.after.is_some() {
+                return Err(duplicate());
+            }
+            filters.after = Some(parse_date(value, position)?);
+        }
+        "before" => {
+            if filters.before.is_some() {
+                return Err(duplicate());
+            }
+            filters.before = Some(parse_date(value, position)?);
+        }
+        "in" => {
+            if filters.conversation.is_some() {
+                return Err(duplicate());
+            }
+            filters.conversation = Some(value.to_string());
+        }
+        "type" => {
+            let content_type = ContentType::parse(value).ok_or_else(|| QueryError::new(
+                QueryErrorKind::InvalidFilterValue,
+                position,
+                format!("Unknown type '{}', expected 'message', 'notes', 'summary' or 'title'", value),
+            ))?;
+            if !filters.content_types.contains(&content_type) {
+                filters.content_types.push(content_type);
+            }
+        }
+        _ => unreachable!("filter keys are checked by the tokenizer"),
+    }
+
+    Ok(())
+}
+
+/// Parses search box syntax such as
+/// `role:model after:2026-01-01 in:"Rust async" "tokio runtime" -deprecated`.
+///
+/// Adjacent terms are AND-ed, `OR` joins its neighbours, `-` excludes a term
+/// and `"..."` matches an exact phrase. The last bare word is matched as a
+/// prefix so results follow the user as they type.
+pub fn parse_query(input: &str) -> Result<SearchQuery, QueryError> {
+    let tokens = tokenize(input)?;
+    let mut filters = SearchFilters::default();
+    let mut groups: Vec<Vec<Atom>> = Vec::new();
+    let mut excluded: Vec<Atom> = Vec::new();
+    let mut pending_or: Option<usize> = None;
+    let mut pending_not: Option<usize> = None;
+    let mut last_excluded = false;
+
+    for (position, token) in tokens {
+        match token {
+            Token::Filter { key, value } => {
+                if let Some(at) = pending_or.or(pending_not) {
+                    return Err(QueryError::new(
+                        QueryErrorKind::DanglingOperator,
+                        at,
+                        "Operators cannot be applied to filters",
+                    ));
+                }
+                apply_filter(&mut filters, &key, &value, position)?;
+            }
+            Token::Not => {
+                if pending_or.is_some() {
+                    return Err(QueryError::new(
+                        QueryErrorKind::NegatedAlternative,
+                        position,
+                        "Excluded terms cannot be combined with OR",
+                    ));
+                }
+                pending_not = Some(position);
+            }
+            Token::Or => {
+                if last_excluded {
+                    return Err(QueryError::new(
+                        QueryErrorKind::NegatedAlternative,
+                        position,
+                        "Excluded terms cannot be combined with OR",
+                    ));
+                }
+                if pending_or.is_some() || pending_not.is_some() || groups.is_empty() {
+                    return Err(QueryError::new(
+                        QueryErrorKind::DanglingOperator,
+                        position,
+                        "OR must be placed between two search terms",
+                    ));
+                }
+                pending_or = Some(position);
+            }
+            Token::Atom(atom) => {
+                last_excluded = pending_not.is_some();
+                if pending_not.take().is_some() {
+                    excluded.push(atom);
+                } else if pending_or.take().is_some() {
+                    groups.last_mut().expect("OR requires a preceding group").push(atom);
+                } else {
+                    groups.push(vec![atom]);
+                }
+            }
+        }
+    }
+
+    if let Some(at) = pending_or.or(pending_not) {
+        return Err(QueryError::new(
+            QueryErrorKind::DanglingOperator,
+            at,
+            "Operator is missing a search term",
+        ));
+    }
+
+    if groups.is_empty() {
+        return Err(QueryError::new(
+            QueryErrorKind::NoSearchTerms,
+            0,
+            "Add at least one word or phrase to search for",
+        ));
+    }
+
+    if let (Some(after), Some(before)) = (filters.after, filters.before) {
+        if after >= before {
+            return Err(QueryError::new(
+                QueryErrorKind::InvalidFilterValue,
+                0,
+                "'after:' must be earlier than 'before:'",
+            ));
+        }
+    }
+
+    Ok(SearchQuery { groups, excluded, filters })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: &str) -> (QueryErrorKind, usize) {
+        let error = parse_query(input).unwrap_err();
+        (error.kind, error.position)
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn bare_words_are_anded_and_the_last_is_a_prefix() {
+        let query = parse_query("tokio  runt").unwrap();
+        assert_eq!(query.match_expression(), r#""tokio" AND "runt"*"#);
+        assert_eq!(query.words(), [("tokio".to_string(), false), ("runt".to_string(), true)]);
+        assert_eq!(query.filters, SearchFilters::default());
+    }
+
+    #[test]
+    fn phrases_match_exactly_and_are_never_prefixes() {
+        let query = parse_query(r#"async "tokio runtime""#).unwrap();
+        assert_eq!(query.match_expression(), r#""async" AND "tokio runtime""#);
+        assert_eq!(query.terms(), ["async", "tokio", "runtime"]);
+        assert_eq!(query.words(), [("async".to_string(), false)]);
+
+        // Empty phrases are dropped rather than searched for
+        assert_eq!(parse_query(r#""" rust"#).unwrap().match_expression(), r#""rust"*"#);
+    }
+
+    #[test]
+    fn fts_syntax_in_user_input_is_quoted() {
+        let query = parse_query("NEAR(a AND b*").unwrap();
+        assert_eq!(query.match_expression(), r#""NEAR(a" AND "AND" AND "b*"*"#);
+    }
+
+    #[test]
+    fn minus_excludes_terms_and_phrases() {
+        let query = parse_query(r#"tokio -deprecated -"old api""#).unwrap();
+        assert_eq!(query.match_expression(), r#"("tokio"*) NOT ("deprecated" OR "old api")"#);
+        assert_eq!(query.terms(), ["tokio"]);
+
+        // A hyphen inside a word is part of the word
+        assert_eq!(parse_query("lock-free").unwrap().match_expression(), r#""lock-free"*"#);
+    }
+
+    #[test]
+    fn or_binds_tighter_than_and() {
+        let query = parse_query("alpha beta OR gamma OR \"delta x\" omega").unwrap();
+        assert_eq!(
+            query.match_expression(),
+            r#""alpha" AND ("beta" OR "gamma" OR "delta x") AND "omega"*"#
+        );
+        // Lowercase "or" is an ordinary word
+        assert_eq!(parse_query("this or that").unwrap().match_expression(), r#""this" AND "or" AND "that"*"#);
+    }
+
+    #[test]
+    fn role_filter_maps_model_to_ai_and_limits_to_messages() {
+        for (value, role) in [("model", "ai"), ("AI", "ai"), ("assistant", "ai"), ("user", "user"), ("me", "user")] {
+            let query = parse_query(&format!("role:{} tokio", value)).unwrap();
+            assert_eq!(query.filters.role.as_deref(), Some(role));
+        }
+
+        let filters = parse_query("role:model tokio").unwrap().filters;
+        assert!(filters.includes(ContentType::Message));
+        assert!(!filters.includes(ContentType::Notes));
+        assert!(!filters.includes(ContentType::Summary));
+        let (sql, params) = filters.sql_clauses(ContentType::Message, 2);
+        assert_eq!(sql, " AND m.role = ?2");
+        assert_eq!(params, [Value::Text("ai".to_string())]);
+        assert_eq!(filters.sql_clauses(ContentType::Notes, 2).0, "");
+
+        assert_eq!(error("role:robot tokio"), (QueryErrorKind::InvalidFilterValue, 0));
+    }
+
+    #[test]
+    fn after_is_inclusive_and_before_is_exclusive() {
+        let filters = parse_query("tokio after:2026-01-01 before:2026-02-01").unwrap().filters;
+        assert_eq!(filters.after, Some(date("2026-01-01")));
+        assert_eq!(filters.before, Some(date("2026-02-01")));
+
+        let (sql, params) = filters.sql_clauses(ContentType::Summary, 1);
+        assert_eq!(sql, " AND DATE(c.created_at) >= ?1 AND DATE(c.created_at) < ?2");
+        assert_eq!(params, [Value::Text("2026-01-01".to_string()), Value::Text("2026-02-01".to_string())]);
+
+        let before_only = parse_query("before:2026-02-01 tokio").unwrap().filters;
+        assert_eq!(before_only.after, None);
+        assert_eq!(before_only.sql_clauses(ContentType::Message, 3).0, " AND DATE(m.created_at) < ?3");
+        assert_eq!(before_only.sql_clauses(ContentType::Notes, 3).0, " AND DATE(c.created_at) < ?3");
+
+        assert_eq!(error("tokio after:2026-02-01 before:2026-02-01"), (QueryErrorKind::InvalidFilterValue, 0));
+    }
+
+    #[test]
+    fn bad_dates_report_the_filter_position() {
+        assert_eq!(error("tokio after:2026-13-01"), (QueryErrorKind::InvalidFilterValue, 6));
+        assert_eq!(error("tokio before:yesterday"), (QueryErrorKind::InvalidFilterValue, 6));
+        // Positions count characters, not bytes
+        assert_eq!(error("café after:01/02/2026"), (QueryErrorKind::InvalidFilterValue, 5));
+
+        let error = parse_query("x after:2026-1-1x").unwrap_err();
+        assert!(error.message.contains("2026-1-1x"));
+        assert_eq!(error.to_string(), format!("{} (at position 2)", error.message));
+    }
+
+    #[test]
+    fn in_filter_takes_quoted_names_and_escapes_like() {
+        let filters = parse_query(r#"in:"Rust Async" tokio"#).unwrap().filters;
+        assert_eq!(filters.conversation.as_deref(), Some("Rust Async"));
+        let (sql, params) = filters.sql_clauses(ContentType::Notes, 2);
+        assert_eq!(sql, " AND LOWER(c.name) LIKE ?2 ESCAPE '\\'");
+        assert_eq!(params, [Value::Text("%rust async%".to_string())]);
+
+        let filters = parse_query("in:100%_done tokio").unwrap().filters;
+        assert_eq!(filters.sql_clauses(ContentType::Message, 1).1, [Value::Text("%100\\%\\_done%".to_string())]);
+
+        assert_eq!(error("tokio in:"), (QueryErrorKind::EmptyFilter, 6));
+        assert_eq!(error(r#"tokio in:"  ""#), (QueryErrorKind::EmptyFilter, 6));
+        assert_eq!(error("in:a in:b tokio"), (QueryErrorKind::DuplicateFilter, 5));
+    }
+
+    #[test]
+    fn type_filter_accepts_plurals_and_repeats() {
+        let filters = parse_query("TYPE:notes type:Summaries type:note tokio").unwrap().filters;
+        assert_eq!(filters.content_types, [ContentType::Notes, ContentType::Summary]);
+        assert!(!filters.includes(ContentType::Message));
+        assert!(filters.includes(ContentType::Notes));
+        assert!(!filters.includes(ContentType::Title));
+        assert_eq!(parse_query("type:titles tokio").unwrap().filters.content_types, [ContentType::Title]);
+
+        assert_eq!(error("type:whiteboard tokio"), (QueryErrorKind::InvalidFilterValue, 0));
+        // Unknown keys are plain words
+        assert_eq!(parse_query("https://example.com").unwrap().match_expression(), r#""https://example.com"*"#);
+    }
+
+    #[test]
+    fn unterminated_quotes_report_the_opening_quote() {
+        assert_eq!(error(r#"tokio "runtime"#), (QueryErrorKind::UnterminatedQuote, 6));
+        assert_eq!(error(r#"in:"Rust tokio"#), (QueryErrorKind::UnterminatedQuote, 3));
+        assert_eq!(error(r#"ä ö "x"#), (QueryErrorKind::UnterminatedQuote, 4));
+    }
+
+    #[test]
+    fn misplaced_operators_are_rejected() {
+        assert_eq!(error("OR tokio"), (QueryErrorKind::DanglingOperator, 0));
+        assert_eq!(error("tokio OR"), (QueryErrorKind::DanglingOperator, 6));
+        assert_eq!(error("tokio OR OR rust"), (QueryErrorKind::DanglingOperator, 9));
+        assert_eq!(error("tokio -"), (QueryErrorKind::DanglingOperator, 6));
+        assert_eq!(error("tokio - rust"), (QueryErrorKind::DanglingOperator, 6));
+        assert_eq!(error("tokio OR role:user"), (QueryErrorKind::DanglingOperator, 6));
+        assert_eq!(error("tokio OR -rust"), (QueryErrorKind::NegatedAlternative, 9));
+        assert_eq!(error("-rust OR tokio"), (QueryErrorKind::NegatedAlternative, 6));
+    }
+
+    #[test]
+    fn queries_need_a_positive_term() {
+        assert_eq!(error(""), (QueryErrorKind::NoSearchTerms, 0));
+        assert_eq!(error("role:user after:2026-01-01"), (QueryErrorKind::NoSearchTerms, 0));
+        assert_eq!(error("-deprecated"), (QueryErrorKind::NoSearchTerms, 0));
+        assert_eq!(error("role:user role:ai tokio"), (QueryErrorKind::DuplicateFilter, 10));
+    }
+
+    #[test]
+    fn corrections_replace_bare_words_only() {
+        let query = parse_query(r#""tokoi" tokoi runtme"#).unwrap();
+        let corrected = query.with_corrections(&[
+            ("tokoi".to_string(), "tokio".to_string()),
+            ("runtme".to_string(), "runtime".to_string()),
+        ]);
+        assert_eq!(corrected.match_expression(), r#""tokoi" AND "tokio" AND "runtime"*"#);
+    }
+}
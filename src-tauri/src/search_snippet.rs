@@ -0,0 +1,290 @@
+use serde::{Deserialize, Serialize};
+
+const MAX_WINDOWS: usize = 3;
+const ELLIPSIS: &str = "...";
+const BOUNDARY_SLACK: usize = 12;
+
+/// A highlighted span in a snippet, as `[start, end)` offsets in Unicode
+/// scalar values (what `Array.from(snippet)` indexes on the frontend).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighlightRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub text: String,
+    pub highlights: Vec<HighlightRange>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    start: usize,
+    end: usize,
+    term: usize,
+}
+
+/// Lowercases a character and strips common Latin diacritics so highlighting
+/// agrees with the `remove_diacritics` tokenizer used by the FTS index.
+/// Always maps one char to one char, keeping offsets aligned with the input.
+pub fn fold_char(c: char) -> char {
+    let lower = c.to_lowercase().next().unwrap_or(c);
+    match lower {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => 'c',
+        'ď' | 'đ' => 'd',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => 'e',
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => 'g',
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => 'i',
+        'ñ' | 'ń' | 'ņ' | 'ň' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => 'o',
+        'ś' | 'ŝ' | 'ş' | 'š' => 's',
+        'ţ' | 'ť' => 't',
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => 'u',
+        'ý' | 'ÿ' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        other => other,
+    }
+}
+
+fn fold(text: &str) -> Vec<char> {
+    text.chars().map(fold_char).collect()
+}
+
+/// Every non-overlapping occurrence of any term that starts on a word boundary,
+/// so prefix queries such as `tok*` light up `tokio` but not `stock`.
+fn find_matches(content: &[char], query_terms: &[&str]) -> Vec<Match> {
+    let mut matches = Vec::new();
+
+    for (term_index, term) in query_terms.iter().enumerate() {
+        let needle: Vec<char> = fold(term.trim_matches(|c: char| !c.is_alphanumeric()));
+        if needle.is_empty() || needle.len() > content.len() {
+            continue;
+        }
+        for start in 0..=content.len() - needle.len() {
+            let at_boundary = start == 0 || !content[start - 1].is_alphanumeric();
+            if at_boundary && content[start..start + needle.len()] == needle[..] {
+                matches.push(Match { start, end: start + needle.len(), term: term_index });
+            }
+        }
+    }
+
+    matches.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    let mut merged: Vec<Match> = Vec::with_capacity(matches.len());
+    for m in matches {
+        match merged.last() {
+            Some(last) if m.start < last.end => {}
+            _ => merged.push(m),
+        }
+    }
+    merged
+}
+
+/// Picks the first occurrence of each distinct term as a window anchor,
+/// skipping anchors already visible in an earlier window.
+fn choose_windows(matches: &[Match], content_len: usize, max_length: usize) -> Vec<(usize, usize)> {
+    let mut anchors: Vec<Match> = Vec::new();
+    let mut seen_terms = Vec::new();
+    for m in matches {
+        if !seen_terms.contains(&m.term) {
+            seen_terms.push(m.term);
+            anchors.push(*m);
+        }
+    }
+    anchors.truncate(MAX_WINDOWS);
+
+    let budget = max_length / anchors.len().max(1);
+    let mut windows: Vec<(usize, usize)> = Vec::new();
+
+    for anchor in anchors {
+        if windows.iter().any(|&(s, e)| anchor.start >= s && anchor.end <= e) {
+            continue;
+        }
+        let match_len = anchor.end - anchor.start;
+        let context = budget.saturating_sub(match_len);
+        let before = context / 3;
+        let start = anchor.start.saturating_sub(before);
+        let end = (start + budget.max(match_len)).min(content_len);
+        windows.push((start, end));
+    }
+
+    windows.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(windows.len());
+    for (start, end) in windows {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Nudges window edges onto nearby whitespace so snippets don't open or
+/// close mid-word, without ever cutting into a highlighted match.
+fn snap_to_words(content: &[char], windows: &mut [(usize, usize)], matches: &[Match]) {
+    for (start, end) in windows.iter_mut() {
+        let first_match = matches.iter().find(|m| m.start >= *start).map(|m| m.start).unwrap_or(*end);
+        if *start > 0 {
+            if let Some(offset) = content[*start..first_match.min(*start + BOUNDARY_SLACK)]
+                .iter()
+                .position(|c| c.is_whitespace())
+            {
+                *start += offset + 1;
+            }
+        }
+
+        let last_match = matches.iter().rev().find(|m| m.end <= *end).map(|m| m.end).unwrap_or(*start);
+        if *end < content.len() {
+            let floor = last_match.max(end.saturating_sub(BOUNDARY_SLACK)).max(*start);
+            if let Some(offset) = content[floor..*end].iter().rposition(|c| c.is_whitespace()) {
+                *end = floor + offset;
+            }
+        }
+    }
+}
+
+/// Builds a snippet of at most roughly `max_length` characters showing up to
+/// three windows around distinct query terms, with the positions of every
+/// hit inside it. All slicing happens on chars, so multi-byte text is safe.
+pub fn create_snippet(content: &str, query_terms: &[&str], max_length: usize) -> Snippet {
+    let chars: Vec<char> = content.chars().collect();
+    let folded = fold(content);
+    let matches = find_matches(&folded, query_terms);
+
+    if matches.is_empty() {
+        let text = if chars.len() > max_length {
+            let kept: String = chars[..max_length.saturating_sub(ELLIPSIS.len())].iter().collect();
+            format!("{}{}", kept.trim_end(), ELLIPSIS)
+        } else {
+            content.to_string()
+        };
+        return Snippet { text, highlights: Vec::new() };
+    }
+
+    let mut windows = choose_windows(&matches, chars.len(), max_length);
+    snap_to_words(&chars, &mut windows, &matches);
+
+    let mut text = String::new();
+    let mut text_len = 0;
+    let mut highlights = Vec::new();
+
+    for (i, &(start, end)) in windows.iter().enumerate() {
+        if start > 0 || i > 0 {
+            text.push_str(ELLIPSIS);
+            text_len += ELLIPSIS.len();
+        }
+        for m in matches.iter().filter(|m| m.start >= start && m.end <= end) {
+            highlights.push(HighlightRange {
+                start: text_len + m.start - start,
+                end: text_len + m.end - start,
+            });
+        }
+        text.extend(&chars[start..end]);
+        text_len += end - start;
+    }
+
+    if windows.last().is_some_and(|&(_, end)| end < chars.len()) {
+        text.push_str(ELLIPSIS);
+    }
+
+    Snippet { text, highlights }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The highlighted text, sliced the way the frontend does with `Array.from(snippet)`.
+    fn highlighted(snippet: &Snippet) -> Vec<String> {
+        let chars: Vec<char> = snippet.text.chars().collect();
+        snippet.highlights.iter().map(|h| chars[h.start..h.end].iter().collect()).collect()
+    }
+
+    fn filler(word: &str, count: usize) -> String {
+        vec![word; count].join(" ")
+    }
+
+    #[test]
+    fn fold_char_strips_diacritics_and_case() {
+        assert_eq!("CaFÉ ñoño Ångström".chars().map(fold_char).collect::<String>(), "cafe nono angstrom");
+        assert_eq!(fold_char('ß'), 'ß');
+        assert_eq!(fold_char('🦀'), '🦀');
+
+        let snippet = create_snippet("Un café crème, s'il vous plaît", &["cafe"], 200);
+        assert_eq!(highlighted(&snippet), ["café"]);
+        let snippet = create_snippet("a plain cafe", &["CAFÉ"], 200);
+        assert_eq!(highlighted(&snippet), ["cafe"]);
+    }
+
+    #[test]
+    fn multibyte_text_at_window_edges_is_cut_on_chars() {
+        let content = format!("{} tokio {}", filler("🦀🦀", 150), filler("é", 300));
+        let snippet = create_snippet(&content, &["tokio"], 60);
+        assert_eq!(highlighted(&snippet), ["tokio"]);
+        assert!(snippet.text.starts_with(ELLIPSIS) && snippet.text.ends_with(ELLIPSIS));
+        assert!(snippet.text.chars().count() <= 60 + 2 * ELLIPSIS.len());
+
+        // No match: the fallback truncation is char-based too
+        let snippet = create_snippet(&"日本語".repeat(100), &["tokio"], 20);
+        assert_eq!(snippet.text, format!("{}...", "日本語".repeat(6).chars().take(17).collect::<String>()));
+        assert!(snippet.highlights.is_empty());
+    }
+
+    #[test]
+    fn highlight_offsets_count_chars_not_bytes_or_utf16_units() {
+        let snippet = create_snippet("👩‍💻 wrote 🚀 tokio and Tokio again", &["tokio"], 200);
+        assert_eq!(highlighted(&snippet), ["tokio", "Tokio"]);
+        // 👩‍💻 is three scalar values, 🚀 one
+        assert_eq!(snippet.highlights[0], HighlightRange { start: 12, end: 17 });
+    }
+
+    #[test]
+    fn distinct_terms_far_apart_get_their_own_windows() {
+        let content = format!("tokio {} scheduler {} runtime", filler("filler", 60), filler("padding", 60));
+        let snippet = create_snippet(&content, &["tokio", "scheduler", "runtime"], 90);
+        assert_eq!(highlighted(&snippet), ["tokio", "scheduler", "runtime"]);
+        assert_eq!(snippet.text.matches(ELLIPSIS).count(), 2);
+        assert!(snippet.text.starts_with("tokio"));
+        assert!(snippet.text.ends_with("runtime"));
+
+        // Only word starts match, so a prefix doesn't light up the middle of a word
+        let snippet = create_snippet("stock tokio", &["tok"], 200);
+        assert_eq!(highlighted(&snippet), ["tok"]);
+        assert_eq!(snippet.highlights[0].start, 6);
+    }
+
+    #[test]
+    fn overlapping_windows_merge() {
+        let content = format!("{} tokio scheduler {}", filler("filler", 40), filler("padding", 40));
+        let snippet = create_snippet(&content, &["tokio", "scheduler"], 80);
+        assert_eq!(highlighted(&snippet), ["tokio", "scheduler"]);
+        // One window, so one ellipsis on each side and none between the terms
+        assert_eq!(snippet.text.matches(ELLIPSIS).count(), 2);
+
+        // Overlapping matches of different terms keep the longer one
+        let snippet = create_snippet("tokio-runtime", &["tok", "tokio"], 200);
+        assert_eq!(highlighted(&snippet), ["tokio"]);
+    }
+
+    #[test]
+    fn windows_snap_to_words_but_not_past_text_boundaries() {
+        let content = format!("tokio {}", filler("lengthy", 50));
+        let snippet = create_snippet(&content, &["tokio"], 40);
+        assert!(snippet.text.starts_with("tokio "));
+        // The cut falls between words, not inside "lengthy"
+        let body = snippet.text.strip_suffix(ELLIPSIS).unwrap();
+        assert!(body.ends_with("lengthy"), "{:?}", snippet.text);
+
+        let content = format!("{} tokio", filler("lengthy", 50));
+        let snippet = create_snippet(&content, &["tokio"], 40);
+        assert!(snippet.text.ends_with("tokio"));
+        let body = snippet.text.strip_prefix(ELLIPSIS).unwrap();
+        assert!(body.starts_with("lengthy"), "{:?}", snippet.text);
+
+        // Short text is returned whole
+        let snippet = create_snippet("just tokio", &["tokio"], 200);
+        assert_eq!(snippet.text, "just tokio");
+        assert_eq!(snippet.highlights, [HighlightRange { start: 5, end: 10 }]);
+    }
+}
@@ -0,0 +1,511 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+use rusqlite::{Connection, OptionalExtension};
+use std::io::Write;
+use std::path::Path;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use crate::database_pool::DbPool;
+use crate::database;
+use crate::error::RweError;
+
+const KEY_LEN: usize = 32;
+const DEFAULT_SECRET_NAME: &str = "default";
+
+/// Preference rows that held API keys in plaintext before secrets existed,
+/// with the provider they belong to.
+const PLAINTEXT_KEY_PREFERENCES: &[(&str, &str)] = &[
+    ("gemini_api_key", "gemini"),
+    ("openai_api_key", "openai"),
+];
+
+/// Encrypts secrets with XChaCha20-Poly1305 under a random key kept in a
+/// file next to (not inside) main.db, so copies of the database alone
+/// — exports, backups, sync folders — never expose plaintext keys.
+#[derive(Clone)]
+pub struct SecretStore {
+    cipher: XChaCha20Poly1305,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretInfo {
+    pub provider: String,
+    pub name: String,
+    pub is_active: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl SecretStore {
+    pub fn from_key(key: &[u8; KEY_LEN]) -> Self {
+        Self { cipher: XChaCha20Poly1305::new(key.into()) }
+    }
+
+    /// Reads the key file, creating it with a fresh random key (readable only
+    /// by the current user on Unix) the first time.
+    pub fn load_or_create(path: &Path) -> Result<Self, RweError> {
+        if path.exists() {
+            let bytes = std::fs::read(path)
+                .map_err(|e| RweError::io("Failed to read secret key", e))?;
+            let key: [u8; KEY_LEN] = bytes.as_slice().try_into()
+                .map_err(|_| RweError::Storage(format!("Secret key file {} is corrupt", path.display())))?;
+            return Ok(Self::from_key(&key));
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| RweError::io("Failed to create secret key directory", e))?;
+        }
+
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)
+            .map_err(|e| RweError::io("Failed to create secret key", e))?;
+        file.write_all(&key)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| RweError::io("Failed to write secret key", e))?;
+
+        Ok(Self { cipher: XChaCha20Poly1305::new(&key) })
+    }
+
+    // Provider and name are bound in as associated data, so a ciphertext
+    // copied onto another row fails to decrypt instead of leaking.
+    fn associated_data(provider: &str, name: &str) -> Vec<u8> {
+        format!("rwe-secret:{}:{}", provider, name).into_bytes()
+    }
+
+    fn encrypt(&self, provider: &str, name: &str, value: &str) -> Result<(Vec<u8>, Vec<u8>), RweError> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = Self::associated_data(provider, name);
+        let ciphertext = self.cipher
+            .encrypt(&nonce, Payload { msg: value.as_bytes(), aad: &aad })
+            .map_err(|_| RweError::Internal("Failed to encrypt secret".to_string()))?;
+        Ok((nonce.to_vec(), ciphertext))
+    }
+
+    fn decrypt(&self, provider: &str, name: &str, nonce: &[u8], ciphertext: &[u8]) -> Result<String, RweError> {
+        if nonce.len() != 24 {
+            return Err(RweError::Storage(format!("Secret {}/{} is corrupt", provider, name)));
+        }
+        let aad = Self::associated_data(provider, name);
+        let plaintext = self.cipher
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| RweError::Storage(format!(
+                "Secret {}/{} could not be decrypted, it may come from another device. Please enter it again.",
+                provider, name
+            )))?;
+        String::from_utf8(plaintext).map_err(|_| RweError::Storage(format!("Secret {}/{} is corrupt", provider, name)))
+    }
+}
+
+fn validate_identifier(kind: &str, value: &str) -> Result<(), RweError> {
+    if value.trim().is_empty() {
+        return Err(RweError::Validation(format!("Secret {} must not be empty", kind)));
+    }
+    if value.len() > 64 {
+        return Err(RweError::Validation(format!("Secret {} is too long", kind)));
+    }
+    Ok(())
+}
+
+/// The decrypted value of the provider's active secret, if it has one.
+pub fn get_active(conn: &Connection, store: &SecretStore, provider: &str) -> Result<Option<String>, RweError> {
+    let row = conn.query_row(
+        "SELECT name, nonce, ciphertext FROM secrets WHERE provider = ?1 AND is_active = 1",
+        [provider],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?, row.get::<_, Vec<u8>>(2)?)),
+    ).optional().map_err(|e| RweError::database("Query secret error", e))?;
+
+    match row {
+        Some((name, nonce, ciphertext)) => store.decrypt(provider, &name, &nonce, &ciphertext).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub fn has_active(conn: &Connection, provider: &str) -> Result<bool, RweError> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM secrets WHERE provider = ?1 AND is_active = 1)",
+        [provider],
+        |row| row.get(0),
+    ).map_err(|e| RweError::database("Query secret error", e))
+}
+
+/// Stores `value` under `provider`/`name`, replacing any previous value.
+/// The first secret of a provider always becomes its active one.
+pub fn put(
+    conn: &Connection,
+    store: &SecretStore,
+    provider: &str,
+    name: &str,
+    value: &str,
+    activate: bool,
+) -> Result<(), RweError> {
+    validate_identifier("provider", provider)?;
+    validate_identifier("name", name)?;
+    if value.is_empty() {
+        return Err(RweError::Validation("Secret value must not be empty".to_string()));
+    }
+
+    let (nonce, ciphertext) = store.encrypt(provider, name, value)?;
+    let activate = activate || !has_active(conn, provider)?;
+
+    database::with_transaction(conn, |tx| {
+        if activate {
+            tx.execute("UPDATE secrets SET is_active = 0 WHERE provider = ?1", [provider])
+                .map_err(|e| RweError::database("Update secret error", e))?;
+        }
+        tx.execute(
+            r#"
+            INSERT INTO secrets (provider, name, nonce, ciphertext, is_active, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP)
+            ON CONFLICT(provider, name) DO UPDATE SET
+                nonce = excluded.nonce,
+                ciphertext = excluded.ciphertext,
+                is_active = MAX(is_active, excluded.is_active),
+                updated_at = excluded.updated_at
+            "#,
+            rusqlite::params![provider, name, nonce, ciphertext, activate],
+        ).map_err(|e| RweError::database("Insert secret error", e))?;
+        Ok(())
+    })
+}
+
+/// Replaces the value of the provider's active secret, creating a
+/// `default` one if it has none. Used where the UI edits "the" API key.
+pub fn put_active(conn: &Connection, store: &SecretStore, provider: &str, value: &str) -> Result<(), RweError> {
+    let name = conn.query_row(
+        "SELECT name FROM secrets WHERE provider = ?1 AND is_active = 1",
+        [provider],
+        |row| row.get::<_, String>(0),
+    ).optional().map_err(|e| RweError::database("Query secret error", e))?;
+
+    put(conn, store, provider, name.as_deref().unwrap_or(DEFAULT_SECRET_NAME), value, true)
+}
+
+/// Runs `f` with `secure_delete` on, so deleted key material is zeroed
+/// rather than left in free pages. The connection goes back to the pool
+/// afterwards, so its previous setting is restored even if `f` fails.
+fn with_secure_delete<T>(conn: &Connection, f: impl FnOnce(&Connection) -> Result<T, RweError>) -> Result<T, RweError> {
+    // Read back as 0, 1 or 2, but only set by name: 2 doesn't parse as FAST
+    let previous = match conn.pragma_query_value(None, "secure_delete", |row| row.get::<_, i64>(0))
+        .map_err(|e| RweError::database("Pragma error", e))?
+    {
+        0 => "off",
+        2 => "fast",
+        _ => "on",
+    };
+    conn.pragma_update(None, "secure_delete", true)
+        .map_err(|e| RweError::database("Pragma error", e))?;
+    let result = f(conn);
+    let restored = conn.pragma_update(None, "secure_delete", previous)
+        .map_err(|e| RweError::database("Pragma error", e));
+    let value = result?;
+    restored?;
+    Ok(value)
+}
+
+/// Deletes a secret. Returns false if it didn't exist.
+pub fn delete(conn: &Connection, provider: &str, name: &str) -> Result<bool, RweError> {
+    with_secure_delete(conn, |conn| {
+        let affected = conn.execute("DELETE FROM secrets WHERE provider = ?1 AND name = ?2", [provider, name])
+            .map_err(|e| RweError::database("Delete secret error", e))?;
+        Ok(affected > 0)
+    })
+}
+
+/// Deletes whichever secret is active for `provider`.
+pub fn delete_active(conn: &Connection, provider: &str) -> Result<(), RweError> {
+    with_secure_delete(conn, |conn| {
+        conn.execute("DELETE FROM secrets WHERE provider = ?1 AND is_active = 1", [provider])
+            .map_err(|e| RweError::database("Delete secret error", e))?;
+        Ok(())
+    })
+}
+
+/// Makes `name` the secret used for `provider`. Returns false if it doesn't exist.
+pub fn activate(conn: &Connection, provider: &str, name: &str) -> Result<bool, RweError> {
+    database::with_transaction(conn, |tx| {
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM secrets WHERE provider = ?1 AND name = ?2)",
+            [provider, name],
+            |row| row.get(0),
+        ).map_err(|e| RweError::database("Query secret error", e))?;
+        if !exists {
+            return Ok(false);
+        }
+        tx.execute("UPDATE secrets SET is_active = 0 WHERE provider = ?1", [provider])
+            .map_err(|e| RweError::database("Update secret error", e))?;
+        tx.execute("UPDATE secrets SET is_active = 1 WHERE provider = ?1 AND name = ?2", [provider, name])
+            .map_err(|e| RweError::database("Update secret error", e))?;
+        Ok(true)
+    })
+}
+
+pub fn list(conn: &Connection) -> Result<Vec<SecretInfo>, RweError> {
+    let mut stmt = conn.prepare(
+        "SELECT provider, name, is_active, created_at, updated_at FROM secrets ORDER BY provider, name",
+    ).map_err(|e| RweError::database("Prepare error", e))?;
+
+    let secret_iter = stmt.query_map([], |row| {
+        Ok(SecretInfo {
+            provider: row.get(0)?,
+            name: row.get(1)?,
+            is_active: row.get(2)?,
+            created_at: row.get(3)?,
+            updated_at: row.get(4)?,
+        })
+    }).map_err(|e| RweError::database("Query error", e))?;
+
+    let mut secrets = Vec::new();
+    for secret in secret_iter {
+        secrets.push(secret.map_err(|e| RweError::database("Row error", e))?);
+    }
+
+    Ok(secrets)
+}
+
+/// Encrypts API keys left in `user_preferences` by older versions and
+/// removes the plaintext rows. Returns the number of keys moved.
+pub fn migrate_plaintext_keys(conn: &Connection, store: &SecretStore) -> Result<usize, RweError> {
+    let mut moved = 0;
+    for (preference, provider) in PLAINTEXT_KEY_PREFERENCES {
+        let value = conn.query_row(
+            "SELECT value FROM user_preferences WHERE key = ?1",
+            [preference],
+            |row| row.get::<_, Option<String>>(0),
+        ).optional().map_err(|e| RweError::database("Query preference error", e))?.flatten();
+
+        let Some(value) = value else {
+            continue;
+        };
+        with_secure_delete(conn, |conn| {
+            database::with_transaction(conn, |tx| {
+                if !value.trim().is_empty() && !has_active(tx, provider)? {
+                    put(tx, store, provider, DEFAULT_SECRET_NAME, value.trim(), true)?;
+                    moved += 1;
+                }
+                tx.execute("DELETE FROM user_preferences WHERE key = ?1", [preference])
+                    .map_err(|e| RweError::database("Delete preference error", e))
+            })
+        })?;
+    }
+    Ok(moved)
+}
+
+/// Removes every secret from a database copy, used for exports. VACUUM
+/// rewrites the file so deleted ciphertext doesn't linger in free pages.
+pub fn strip_secrets(conn: &Connection) -> Result<(), RweError> {
+    database::with_transaction(conn, |tx| {
+        tx.execute("DELETE FROM secrets", [])
+            .map_err(|e| RweError::database("Delete secrets error", e))?;
+        tx.execute("DELETE FROM user_preferences WHERE key LIKE '%api_key'", [])
+            .map_err(|e| RweError::database("Delete preference error", e))?;
+        Ok(())
+    })?;
+    conn.execute_batch("VACUUM")
+        .map_err(|e| RweError::database("Vacuum error", e))?;
+    Ok(())
+}
+
+#[command]
+pub async fn list_secrets(db: State<'_, DbPool>) -> Result<Vec<SecretInfo>, RweError> {
+    db.run(move |conn| {
+        list(conn)
+    }).await
+}
+
+#[command]
+pub async fn set_secret(
+    provider: String,
+    name: String,
+    value: String,
+    activate: Option<bool>,
+    db: State<'_, DbPool>,
+    secrets: State<'_, SecretStore>,
+) -> Result<(), RweError> {
+    let secrets = secrets.inner().clone();
+    db.run(move |conn| {
+        put(conn, &secrets, provider.trim(), name.trim(), value.trim(), activate.unwrap_or(false))
+    }).await
+}
+
+#[command]
+pub async fn delete_secret(
+    provider: String,
+    name: String,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        delete(conn, &provider, &name)
+    }).await
+}
+
+#[command]
+pub async fn activate_secret(
+    provider: String,
+    name: String,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        activate(conn, &provider, &name)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+
+    fn secure_delete(conn: &Connection) -> i64 {
+        conn.pragma_query_value(None, "secure_delete", |row| row.get(0)).unwrap()
+    }
+
+    fn migrated() -> (Connection, SecretStore) {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        (conn, SecretStore::from_key(&[7; KEY_LEN]))
+    }
+
+    fn preference(conn: &Connection, key: &str) -> Option<String> {
+        conn.query_row("SELECT value FROM user_preferences WHERE key = ?1", [key], |row| row.get(0))
+            .optional()
+            .unwrap()
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn values_round_trip_and_are_stored_encrypted() {
+        let (conn, store) = migrated();
+        put(&conn, &store, "openai", "work", "sk-work-123", false).unwrap();
+        put(&conn, &store, "openai", "home", "sk-home-456", false).unwrap();
+
+        // The first secret of a provider becomes the active one
+        assert_eq!(get_active(&conn, &store, "openai").unwrap().as_deref(), Some("sk-work-123"));
+        assert!(activate(&conn, "openai", "home").unwrap());
+        assert_eq!(get_active(&conn, &store, "openai").unwrap().as_deref(), Some("sk-home-456"));
+        put_active(&conn, &store, "openai", "sk-home-789").unwrap();
+        assert_eq!(get_active(&conn, &store, "openai").unwrap().as_deref(), Some("sk-home-789"));
+        assert_eq!(get_active(&conn, &store, "gemini").unwrap(), None);
+
+        let ciphertext: Vec<u8> = conn.query_row(
+            "SELECT ciphertext FROM secrets WHERE name = 'home'", [], |row| row.get(0),
+        ).unwrap();
+        assert!(!contains(&ciphertext, b"sk-home-789"));
+
+        // Another device's key can't read it
+        let other = SecretStore::from_key(&[8; KEY_LEN]);
+        assert!(matches!(get_active(&conn, &other, "openai"), Err(RweError::Storage(_))));
+    }
+
+    #[test]
+    fn ciphertext_copied_to_another_secret_does_not_decrypt() {
+        let (conn, store) = migrated();
+        put(&conn, &store, "openai", "work", "sk-work-123", true).unwrap();
+        put(&conn, &store, "gemini", "default", "gemini-key", true).unwrap();
+        put(&conn, &store, "openai", "home", "sk-home-456", false).unwrap();
+
+        conn.execute_batch(
+            "UPDATE secrets SET
+                 nonce = (SELECT nonce FROM secrets WHERE provider = 'openai' AND name = 'work'),
+                 ciphertext = (SELECT ciphertext FROM secrets WHERE provider = 'openai' AND name = 'work')
+             WHERE provider = 'gemini' OR name = 'home';",
+        ).unwrap();
+        assert!(matches!(get_active(&conn, &store, "gemini"), Err(RweError::Storage(_))));
+        activate(&conn, "openai", "home").unwrap();
+        assert!(matches!(get_active(&conn, &store, "openai"), Err(RweError::Storage(_))));
+        activate(&conn, "openai", "work").unwrap();
+        assert_eq!(get_active(&conn, &store, "openai").unwrap().as_deref(), Some("sk-work-123"));
+    }
+
+    #[test]
+    fn plaintext_keys_are_moved_into_secrets_and_removed() {
+        let (conn, store) = migrated();
+        conn.execute_batch(
+            "INSERT INTO user_preferences (key, value) VALUES ('gemini_api_key', '  gemini-plain  '), ('openai_api_key', 'sk-plain');",
+        ).unwrap();
+        put(&conn, &store, "openai", "default", "sk-already-set", true).unwrap();
+
+        // An existing secret wins, but the plaintext copy goes either way
+        assert_eq!(migrate_plaintext_keys(&conn, &store).unwrap(), 1);
+        assert_eq!(preference(&conn, "gemini_api_key"), None);
+        assert_eq!(preference(&conn, "openai_api_key"), None);
+        assert_eq!(get_active(&conn, &store, "gemini").unwrap().as_deref(), Some("gemini-plain"));
+        assert_eq!(get_active(&conn, &store, "openai").unwrap().as_deref(), Some("sk-already-set"));
+        assert_eq!(migrate_plaintext_keys(&conn, &store).unwrap(), 0);
+
+        // Blank leftovers are removed without creating a secret
+        conn.execute("INSERT INTO user_preferences (key, value) VALUES ('gemini_api_key', '  ')", []).unwrap();
+        delete_active(&conn, "gemini").unwrap();
+        assert_eq!(migrate_plaintext_keys(&conn, &store).unwrap(), 0);
+        assert_eq!(preference(&conn, "gemini_api_key"), None);
+        assert!(!has_active(&conn, "gemini").unwrap());
+    }
+
+    #[test]
+    fn stripped_export_copies_hold_no_secrets() {
+        let (conn, store) = migrated();
+        put(&conn, &store, "openai", "work", "sk-work-123", true).unwrap();
+        conn.execute("INSERT INTO user_preferences (key, value) VALUES ('custom_api_key', 'plain-marker-key')", []).unwrap();
+        conn.execute("INSERT INTO conversations (name) VALUES ('Kept')", []).unwrap();
+        let ciphertext: Vec<u8> = conn.query_row("SELECT ciphertext FROM secrets", [], |row| row.get(0)).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("rwe_secrets_export_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("export.db");
+        let mut export = Connection::open(&path).unwrap();
+        database::copy_database(&conn, &mut export).unwrap();
+        strip_secrets(&export).unwrap();
+
+        let secrets: i64 = export.query_row("SELECT COUNT(*) FROM secrets", [], |row| row.get(0)).unwrap();
+        assert_eq!(secrets, 0);
+        assert_eq!(preference(&export, "custom_api_key"), None);
+        let kept: i64 = export.query_row("SELECT COUNT(*) FROM conversations", [], |row| row.get(0)).unwrap();
+        assert_eq!(kept, 1);
+        drop(export);
+
+        // Nothing lingers in free pages of the file either
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!contains(&bytes, &ciphertext));
+        assert!(!contains(&bytes, b"plain-marker-key"));
+        // The source keeps its secrets
+        assert_eq!(get_active(&conn, &store, "openai").unwrap().as_deref(), Some("sk-work-123"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deletes_leave_the_connection_setting_as_it_was() {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        let store = SecretStore::from_key(&[7; KEY_LEN]);
+        put(&conn, &store, "openai", "work", "sk-work", true).unwrap();
+        put(&conn, &store, "openai", "home", "sk-home", false).unwrap();
+        conn.execute("INSERT INTO user_preferences (key, value) VALUES ('gemini_api_key', 'plain')", []).unwrap();
+        assert_eq!(secure_delete(&conn), 0);
+
+        assert!(delete(&conn, "openai", "home").unwrap());
+        assert_eq!(secure_delete(&conn), 0);
+        delete_active(&conn, "openai").unwrap();
+        assert_eq!(secure_delete(&conn), 0);
+        assert_eq!(migrate_plaintext_keys(&conn, &store).unwrap(), 1);
+        assert_eq!(secure_delete(&conn), 0);
+
+        // Restored after a failure too, and a setting other than the default survives
+        conn.pragma_update(None, "secure_delete", "fast").unwrap();
+        let error = with_secure_delete(&conn, |conn| {
+            assert_eq!(secure_delete(conn), 1);
+            Err::<(), _>(RweError::Internal("boom".to_string()))
+        });
+        assert!(error.is_err());
+        assert_eq!(secure_delete(&conn), 2);
+    }
+}
@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+use rusqlite::{Connection, OptionalExtension};
+use crate::database;
+use crate::database_pool::DbPool;
+use crate::error::RweError;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub color: Option<String>,
+    pub created_at: String,
+    pub conversation_count: i64,
+}
+
+const TAG_COLUMNS: &str = r#"
+    t.id, t.name, t.color, t.created_at,
+    (SELECT COUNT(*) FROM conversation_tags ct
+     JOIN conversations c ON c.id = ct.conversation_id
+     WHERE ct.tag_id = t.id AND c.deleted_at IS NULL)
+"#;
+
+fn row_to_tag(row: &rusqlite::Row) -> rusqlite::Result<Tag> {
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        color: row.get(2)?,
+        created_at: row.get(3)?,
+        conversation_count: row.get(4)?,
+    })
+}
+
+fn normalize_name(name: &str) -> Result<String, RweError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(RweError::Validation("Tag name must not be empty".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Accepts `#rgb` or `#rrggbb` (stored lowercase); blank means no colour.
+fn normalize_color(color: Option<String>) -> Result<Option<String>, RweError> {
+    let Some(color) = color.map(|color| color.trim().to_ascii_lowercase()).filter(|color| !color.is_empty()) else {
+        return Ok(None);
+    };
+    let valid = color
+        .strip_prefix('#')
+        .is_some_and(|digits| matches!(digits.len(), 3 | 6) && digits.chars().all(|c| c.is_ascii_hexdigit()));
+    if !valid {
+        return Err(RweError::Validation(format!("Tag colour '{}' is not a #rgb or #rrggbb hex colour", color)));
+    }
+    Ok(Some(color))
+}
+
+/// Looks up a tag by name, ignoring case like the `tags.name` column does.
+fn find_by_name(conn: &Connection, name: &str) -> Result<Option<i64>, RweError> {
+    conn.query_row("SELECT id FROM tags WHERE name = ?1", [name], |row| row.get(0))
+        .optional()
+        .map_err(|e| RweError::database("Query error", e))
+}
+
+#[command]
+pub async fn get_tags(db: State<'_, DbPool>) -> Result<Vec<Tag>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn
+            .prepare(&format!("SELECT {} FROM tags t ORDER BY t.name COLLATE NOCASE", TAG_COLUMNS))
+            .map_err(|e| RweError::database("Prepare error", e))?;
+
+        let tags = stmt
+            .query_map([], row_to_tag)
+            .map_err(|e| RweError::database("Query error", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RweError::database("Row error", e))?;
+
+        Ok(tags)
+    }).await
+}
+
+fn conversation_tags(conn: &Connection, conversation_id: i64) -> Result<Vec<Tag>, RweError> {
+    let mut stmt = conn
+        .prepare(&format!(
+            r#"
+            SELECT {} FROM tags t
+            JOIN conversation_tags link ON link.tag_id = t.id
+            WHERE link.conversation_id = ?1
+            ORDER BY t.name COLLATE NOCASE
+            "#,
+            TAG_COLUMNS
+        ))
+        .map_err(|e| RweError::database("Prepare error", e))?;
+
+    let tags = stmt
+        .query_map([conversation_id], row_to_tag)
+        .map_err(|e| RweError::database("Query error", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RweError::database("Row error", e))?;
+
+    Ok(tags)
+}
+
+#[command]
+pub async fn get_conversation_tags(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<Vec<Tag>, RweError> {
+    db.run(move |conn| conversation_tags(conn, conversation_id)).await
+}
+
+fn create(conn: &Connection, name: &str, color: Option<&str>) -> Result<i64, RweError> {
+    database::with_transaction(conn, |tx| {
+        if find_by_name(tx, name)?.is_some() {
+            return Err(RweError::Conflict(format!("Tag '{}' already exists", name)));
+        }
+
+        tx.execute(
+            "INSERT INTO tags (name, color) VALUES (?1, ?2)",
+            rusqlite::params![name, color],
+        ).map_err(|e| RweError::database("Insert tag error", e))?;
+
+        Ok(tx.last_insert_rowid())
+    })
+}
+
+#[command]
+pub async fn create_tag(
+    name: String,
+    color: Option<String>,
+    db: State<'_, DbPool>,
+) -> Result<i64, RweError> {
+    let name = normalize_name(&name)?;
+    let color = normalize_color(color)?;
+    db.run(move |conn| create(conn, &name, color.as_deref())).await
+}
+
+fn rename(conn: &Connection, tag_id: i64, name: &str) -> Result<bool, RweError> {
+    database::with_transaction(conn, |tx| {
+        if find_by_name(tx, name)?.is_some_and(|id| id != tag_id) {
+            return Err(RweError::Conflict(format!("Tag '{}' already exists", name)));
+        }
+
+        let affected = tx
+            .execute("UPDATE tags SET name = ?1 WHERE id = ?2", rusqlite::params![name, tag_id])
+            .map_err(|e| RweError::database("Update tag error", e))?;
+
+        Ok(affected > 0)
+    })
+}
+
+/// Renames a tag. Renaming onto another tag's name is refused; use
+/// `merge_tags` to fold one tag into another.
+#[command]
+pub async fn rename_tag(
+    tag_id: i64,
+    name: String,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    let name = normalize_name(&name)?;
+    db.run(move |conn| rename(conn, tag_id, &name)).await
+}
+
+#[command]
+pub async fn set_tag_color(
+    tag_id: i64,
+    color: Option<String>,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    let color = normalize_color(color)?;
+    db.run(move |conn| {
+        let affected = conn
+            .execute("UPDATE tags SET color = ?1 WHERE id = ?2", rusqlite::params![color, tag_id])
+            .map_err(|e| RweError::database("Update tag error", e))?;
+
+        Ok(affected > 0)
+    }).await
+}
+
+fn merge(conn: &Connection, source_tag_id: i64, target_tag_id: i64) -> Result<bool, RweError> {
+    if source_tag_id == target_tag_id {
+        return Err(RweError::Validation("Cannot merge a tag into itself".to_string()));
+    }
+    database::with_transaction(conn, |tx| {
+        let target_exists: bool = tx
+            .query_row("SELECT EXISTS(SELECT 1 FROM tags WHERE id = ?1)", [target_tag_id], |row| row.get(0))
+            .map_err(|e| RweError::database("Query error", e))?;
+        if !target_exists {
+            return Err(RweError::NotFound(format!("Tag {} not found", target_tag_id)));
+        }
+
+        tx.execute(
+            r#"
+            INSERT OR IGNORE INTO conversation_tags (conversation_id, tag_id)
+            SELECT conversation_id, ?2 FROM conversation_tags WHERE tag_id = ?1
+            "#,
+            [source_tag_id, target_tag_id],
+        ).map_err(|e| RweError::database("Merge tags error", e))?;
+        let affected = tx
+            .execute("DELETE FROM tags WHERE id = ?1", [source_tag_id])
+            .map_err(|e| RweError::database("Delete tag error", e))?;
+
+        Ok(affected > 0)
+    })
+}
+
+/// Moves every conversation tagged `source_tag_id` onto `target_tag_id`
+/// and deletes the source tag.
+#[command]
+pub async fn merge_tags(
+    source_tag_id: i64,
+    target_tag_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| merge(conn, source_tag_id, target_tag_id)).await
+}
+
+#[command]
+pub async fn delete_tag(
+    tag_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        let affected = conn
+            .execute("DELETE FROM tags WHERE id = ?1", [tag_id])
+            .map_err(|e| RweError::database("Delete tag error", e))?;
+
+        Ok(affected > 0)
+    }).await
+}
+
+fn tag(conn: &Connection, conversation_id: i64, tag_id: i64) -> Result<bool, RweError> {
+    let affected = conn
+        .execute(
+            r#"
+            INSERT OR IGNORE INTO conversation_tags (conversation_id, tag_id)
+            SELECT c.id, t.id FROM conversations c, tags t WHERE c.id = ?1 AND t.id = ?2
+            "#,
+            [conversation_id, tag_id],
+        )
+        .map_err(|e| RweError::database("Tag conversation error", e))?;
+
+    Ok(affected > 0)
+}
+
+#[command]
+pub async fn tag_conversation(
+    conversation_id: i64,
+    tag_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| tag(conn, conversation_id, tag_id)).await
+}
+
+#[command]
+pub async fn untag_conversation(
+    conversation_id: i64,
+    tag_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<bool, RweError> {
+    db.run(move |conn| {
+        let affected = conn
+            .execute(
+                "DELETE FROM conversation_tags WHERE conversation_id = ?1 AND tag_id = ?2",
+                [conversation_id, tag_id],
+            )
+            .map_err(|e| RweError::database("Untag conversation error", e))?;
+
+        Ok(affected > 0)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+
+    fn library() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        database::configure_connection(&conn).unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        for name in ["Retries", "Backoff", "Timeouts"] {
+            conn.execute("INSERT INTO conversations (name) VALUES (?1)", [name]).unwrap();
+        }
+        conn
+    }
+
+    fn names(tags: &[Tag]) -> Vec<(&str, i64)> {
+        tags.iter().map(|tag| (tag.name.as_str(), tag.conversation_count)).collect()
+    }
+
+    fn tag_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn merging_collapses_shared_conversations_and_removes_the_source() {
+        let conn = library();
+        let rust = create(&conn, "rust", Some("#f60")).unwrap();
+        let lang = create(&conn, "Rust-lang", None).unwrap();
+        for conversation in [1, 2] {
+            tag(&conn, conversation, rust).unwrap();
+        }
+        for conversation in [2, 3] {
+            tag(&conn, conversation, lang).unwrap();
+        }
+
+        assert!(merge(&conn, lang, rust).unwrap());
+        assert_eq!(tag_count(&conn), 1);
+        // Conversation 2 had both tags and keeps a single link
+        let links: Vec<(i64, i64)> = conn
+            .prepare("SELECT conversation_id, tag_id FROM conversation_tags ORDER BY conversation_id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(links, [(1, rust), (2, rust), (3, rust)]);
+        for conversation in [1, 2, 3] {
+            assert_eq!(names(&conversation_tags(&conn, conversation).unwrap()), [("rust", 3)]);
+        }
+
+        // The source is gone, so merging it again finds nothing to remove
+        assert!(!merge(&conn, lang, rust).unwrap());
+        assert!(matches!(merge(&conn, rust, rust), Err(RweError::Validation(_))));
+        assert!(matches!(merge(&conn, rust, 999), Err(RweError::NotFound(_))));
+        assert_eq!(tag_count(&conn), 1);
+    }
+
+    #[test]
+    fn rename_and_create_conflicts_ignore_case() {
+        let conn = library();
+        let rust = create(&conn, "Rust", None).unwrap();
+        let go = create(&conn, "go", None).unwrap();
+
+        assert_eq!(rename(&conn, go, "RUST"), Err(RweError::Conflict("Tag 'RUST' already exists".to_string())));
+        assert!(matches!(create(&conn, "rust", None), Err(RweError::Conflict(_))));
+
+        // Changing only the case of a tag's own name is not a conflict
+        assert!(rename(&conn, rust, "rust").unwrap());
+        assert!(!rename(&conn, 999, "zig").unwrap());
+        tag(&conn, 1, rust).unwrap();
+        assert_eq!(names(&conversation_tags(&conn, 1).unwrap()), [("rust", 1)]);
+    }
+}
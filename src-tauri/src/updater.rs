@@ -1,5 +1,11 @@
-use tauri::command;
-use std::path::PathBuf;
+use tauri::{command, AppHandle, State};
+use std::path::{Path, PathBuf};
+use crate::database;
+use crate::database_pool::{self, DbPool, SqlitePool};
+use crate::encryption::{self, DatabaseEncryption};
+use crate::error::RweError;
+use crate::migrations;
+use crate::secrets::{self, SecretStore};
 
 fn get_app_data_dir() -> String {
     std::env::var("APPDATA")
@@ -7,23 +13,23 @@ fn get_app_data_dir() -> String {
         .unwrap_or_else(|_| ".".to_string())
 }
 
-fn get_rwe_data_dir() -> PathBuf {
+pub fn get_rwe_data_dir() -> PathBuf {
     std::path::Path::new(&get_app_data_dir()).join("rwe_data")
 }
 
 #[command]
-pub async fn get_app_version() -> Result<String, String> {
+pub async fn get_app_version() -> Result<String, RweError> {
     Ok(env!("CARGO_PKG_VERSION").to_string())
 }
 
 #[command] 
-pub async fn open_url(url: String) -> Result<(), String> {
+pub async fn open_url(url: String) -> Result<(), RweError> {
     #[cfg(target_os = "windows")]
     {
         std::process::Command::new("cmd")
             .args(&["/C", "start", &url])
             .spawn()
-            .map_err(|e| format!("Failed to open URL: {}", e))?;
+            .map_err(|e| RweError::Internal(format!("Failed to open URL: {}", e)))?;
     }
     
     #[cfg(target_os = "macos")]
@@ -31,7 +37,7 @@ pub async fn open_url(url: String) -> Result<(), String> {
         std::process::Command::new("open")
             .arg(&url)
             .spawn()
-            .map_err(|e| format!("Failed to open URL: {}", e))?;
+            .map_err(|e| RweError::Internal(format!("Failed to open URL: {}", e)))?;
     }
     
     #[cfg(target_os = "linux")]
@@ -39,80 +45,175 @@ pub async fn open_url(url: String) -> Result<(), String> {
         std::process::Command::new("xdg-open")
             .arg(&url)
             .spawn()
-            .map_err(|e| format!("Failed to open URL: {}", e))?;
+            .map_err(|e| RweError::Internal(format!("Failed to open URL: {}", e)))?;
     }
     
     Ok(())
 }
 
 #[command]
-pub async fn get_data_directory() -> Result<String, String> {
+pub async fn get_data_directory() -> Result<String, RweError> {
     let data_path = get_rwe_data_dir();
     Ok(data_path.to_string_lossy().to_string())
 }
 
+/// Copies main.db to the desktop. API keys are removed from the copy unless
+/// `include_secrets` is set; even then they stay encrypted under this
+/// device's key file, which is never exported.
 #[command]
-pub async fn export_user_data() -> Result<String, String> {
+pub async fn export_user_data(
+    include_secrets: Option<bool>,
+    db: State<'_, DbPool>,
+    encryption: State<'_, DatabaseEncryption>,
+) -> Result<String, RweError> {
     let source_dir = get_rwe_data_dir();
     let desktop_dir = std::path::Path::new(&get_app_data_dir()).join("Desktop");
     let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
     let export_path = desktop_dir.join(format!("RWE_Export_{}.db", timestamp));
     
     if !source_dir.exists() {
-        return Err("No data directory found".to_string());
+        return Err(RweError::NotFound("No data directory found".to_string()));
     }
     
     let db_path = source_dir.join("main.db");
     if !db_path.exists() {
-        return Err("No database found".to_string());
+        return Err(RweError::NotFound("No database found".to_string()));
     }
     
-    std::fs::copy(&db_path, &export_path)
-        .map_err(|e| format!("Failed to export data: {}", e))?;
+    let mut export_conn = encryption.open(&export_path)
+        .map_err(|e| RweError::Storage(format!("Failed to export data: {}", e)))?;
+    let include_secrets = include_secrets.unwrap_or(false);
+    
+    let exported = db.run(move |conn| {
+        database::copy_database(conn, &mut export_conn)?;
+        if !include_secrets {
+            secrets::strip_secrets(&export_conn)
+                .map_err(|e| RweError::Storage(format!("Failed to remove secrets from export: {}", e)))?;
+        }
+        Ok(())
+    }).await;
+    if let Err(e) = exported {
+        std::fs::remove_file(&export_path).ok();
+        return Err(e);
+    }
     
     Ok(export_path.to_string_lossy().to_string())
 }
 
+/// Replaces main.db with `import_path`, keeping a backup of the current file,
+/// and reopens the database on it, migrating it if it came from an older
+/// version. If the imported file won't open, the previous one is put back.
 #[command]
-pub async fn import_user_data(import_path: String) -> Result<bool, String> {
+pub async fn import_user_data(
+    import_path: String,
+    app: AppHandle,
+    db: State<'_, DbPool>,
+    encryption: State<'_, DatabaseEncryption>,
+    secrets: State<'_, SecretStore>,
+) -> Result<bool, RweError> {
     let data_dir = get_rwe_data_dir();
     let target_path = data_dir.join("main.db");
+    let staged_path = data_dir.join("main.db.importing");
+    
+    if !Path::new(&import_path).is_file() {
+        return Err(RweError::NotFound(format!("{} not found", import_path)));
+    }
+    // Nothing is replaced unless the file opens the way main.db does here
+    let imported = encryption.open(Path::new(&import_path))?;
+    if !encryption::is_readable(&imported) {
+        return Err(RweError::Validation("The file is not a database this device can open".to_string()));
+    }
+    drop(imported);
     
     std::fs::create_dir_all(&data_dir)
-        .map_err(|e| format!("Failed to create data directory: {}", e))?;
+        .map_err(|e| RweError::io("Failed to create data directory", e))?;
     
     let backup_path = data_dir.join("backups").join(format!("pre_import_backup_{}.db", 
         chrono::Utc::now().format("%Y%m%d_%H%M%S")));
+    let had_database = target_path.exists();
     
-    if target_path.exists() {
+    if had_database {
         std::fs::create_dir_all(backup_path.parent().unwrap())
-            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
-        std::fs::copy(&target_path, &backup_path)
-            .map_err(|e| format!("Failed to backup existing data: {}", e))?;
+            .map_err(|e| RweError::io("Failed to create backup directory", e))?;
+        let mut backup_conn = encryption.open(&backup_path)
+            .map_err(|e| RweError::Storage(format!("Failed to backup existing data: {}", e)))?;
+        db.run(move |conn| database::copy_database(conn, &mut backup_conn)).await?;
     }
-    
-    std::fs::copy(&import_path, &target_path)
-        .map_err(|e| format!("Failed to import data: {}", e))?;
-    
-    Ok(true)
+    std::fs::copy(&import_path, &staged_path)
+        .map_err(|e| RweError::io("Failed to import data", e))?;
+    
+    // Every connection to the current file has to be closed, and its WAL
+    // gone, before the imported file takes its place
+    let mut drained = db.drain()?;
+    drained.close();
+    let swapped = database_pool::remove_wal_files(&target_path)
+        .and_then(|_| std::fs::rename(&staged_path, &target_path).map_err(|e| RweError::io("Failed to import data", e)));
+    let opened = match swapped {
+        Ok(()) => open_imported(&target_path, app, &encryption, &secrets).await,
+        Err(e) => Err(e),
+    };
+    match opened {
+        Ok(pool) => {
+            drained.finish(pool);
+            Ok(true)
+        }
+        Err(e) => {
+            std::fs::remove_file(&staged_path).ok();
+            if had_database {
+                database_pool::remove_wal_files(&target_path)?;
+                std::fs::copy(&backup_path, &target_path)
+                    .map_err(|e| RweError::io("Failed to restore data after a failed import", e))?;
+                drained.finish(encryption.open_pool(&target_path)?);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Opens the pool on a freshly imported main.db and brings it up to date,
+/// as startup does.
+async fn open_imported(
+    path: &Path,
+    app: AppHandle,
+    encryption: &DatabaseEncryption,
+    secrets: &SecretStore,
+) -> Result<SqlitePool, RweError> {
+    let pool = encryption.open_pool(path)?;
+    let passphrase = encryption.passphrase()?;
+    let path = path.to_path_buf();
+    // Data migrations can take a while; keep them off the async runtime
+    let pool = tauri::async_runtime::spawn_blocking(move || {
+        migrations::migrate_pool(pool, &path, passphrase.as_ref().map(|passphrase| passphrase.as_str()), &app)
+    })
+    .await
+    .map_err(|e| RweError::Internal(format!("Task error: {}", e)))?
+    .map_err(|failure| RweError::Storage(failure.message))?;
+    let conn = pool.get().map_err(|e| RweError::Storage(format!("Connection error: {}", e)))?;
+    secrets::migrate_plaintext_keys(&conn, secrets)?;
+    drop(conn);
+    Ok(pool)
 }
 
 #[command]
-pub async fn prepare_for_update() -> Result<String, String> {
+pub async fn prepare_for_update(
+    db: State<'_, DbPool>,
+    encryption: State<'_, DatabaseEncryption>,
+) -> Result<String, RweError> {
     let data_dir = get_rwe_data_dir();
     let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
     let backup_path = data_dir.join("backups").join(format!("pre_update_backup_{}.db", timestamp));
     let source_path = data_dir.join("main.db");
     
     if !source_path.exists() {
-        return Err("No database found to backup".to_string());
+        return Err(RweError::NotFound("No database found to backup".to_string()));
     }
     
     std::fs::create_dir_all(backup_path.parent().unwrap())
-        .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+        .map_err(|e| RweError::io("Failed to create backup directory", e))?;
     
-    std::fs::copy(&source_path, &backup_path)
-        .map_err(|e| format!("Failed to create pre-update backup: {}", e))?;
+    let mut backup_conn = encryption.open(&backup_path)
+        .map_err(|e| RweError::Storage(format!("Failed to create pre-update backup: {}", e)))?;
+    db.run(move |conn| database::copy_database(conn, &mut backup_conn)).await?;
     
     Ok(backup_path.to_string_lossy().to_string())
 }
@@ -0,0 +1,534 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{command, State};
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::database::with_transaction;
+use crate::database_pool::DbPool;
+use crate::error::RweError;
+
+/// One change to a board. Boards are stored as a snapshot plus an
+/// append-only log of these, numbered by revision.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum WhiteboardOp {
+    /// Draws a stroke or shape on top of everything else.
+    Add { element: Value },
+    /// Removes everything drawn so far.
+    Clear,
+}
+
+impl WhiteboardOp {
+    fn apply(self, elements: &mut Vec<Value>) {
+        match self {
+            WhiteboardOp::Add { element } => elements.push(element),
+            WhiteboardOp::Clear => elements.clear(),
+        }
+    }
+}
+
+/// A board's drawing at one revision, and where that sits in its history.
+#[derive(Debug, Serialize)]
+pub struct WhiteboardState {
+    pub conversation_id: i64,
+    /// The revision `elements` were rebuilt at.
+    pub revision: i64,
+    /// The board's current revision; undo and redo move it.
+    pub head_revision: i64,
+    /// The oldest revision still available. Compaction moves it forward.
+    pub snapshot_revision: i64,
+    /// The newest revision in the log; anything after the head can be redone.
+    pub latest_revision: i64,
+    pub elements: Vec<Value>,
+}
+
+/// A conversation's whiteboard; `data` is the drawing JSON the whiteboard
+/// component saves, stored as is.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WhiteboardData {
+    pub id: i64,
+    pub conversation_id: i64,
+    pub data: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A whiteboard the UI kept in localStorage before whiteboards moved into
+/// the database.
+#[derive(Debug, Deserialize)]
+pub struct LocalWhiteboard {
+    pub conversation_id: i64,
+    pub data: String,
+}
+
+struct Board {
+    id: i64,
+    data: String,
+    revision: i64,
+    snapshot_revision: i64,
+}
+
+const BOARD_COLUMNS: &str = "id, data, revision, snapshot_revision";
+
+fn row_to_board(row: &rusqlite::Row) -> rusqlite::Result<Board> {
+    Ok(Board {
+        id: row.get(0)?,
+        data: row.get(1)?,
+        revision: row.get(2)?,
+        snapshot_revision: row.get(3)?,
+    })
+}
+
+fn find_board(conn: &Connection, conversation_id: i64) -> Result<Option<Board>, RweError> {
+    conn.query_row(
+        &format!("SELECT {} FROM whiteboards WHERE conversation_id = ?1", BOARD_COLUMNS),
+        [conversation_id],
+        row_to_board,
+    )
+    .optional()
+    .map_err(|e| RweError::database("Query whiteboard error", e))
+}
+
+fn require_board(conn: &Connection, conversation_id: i64) -> Result<Board, RweError> {
+    find_board(conn, conversation_id)?
+        .ok_or_else(|| RweError::NotFound(format!("No whiteboard for conversation {}", conversation_id)))
+}
+
+/// Strokes in a snapshot. Boards saved whole keep theirs under `drawings`.
+fn snapshot_elements(data: &str) -> Result<Vec<Value>, RweError> {
+    let data: Value = serde_json::from_str(data)
+        .map_err(|e| RweError::Storage(format!("Invalid whiteboard snapshot: {}", e)))?;
+    Ok(data.get("drawings").and_then(Value::as_array).cloned().unwrap_or_default())
+}
+
+fn latest_revision(conn: &Connection, board: &Board) -> Result<i64, RweError> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(revision), ?2) FROM whiteboard_operations WHERE whiteboard_id = ?1",
+        [board.id, board.revision],
+        |row| row.get(0),
+    ).map_err(|e| RweError::database("Query error", e))
+}
+
+/// Replays the log on top of the snapshot up to `revision`.
+fn elements_at(conn: &Connection, board: &Board, revision: i64) -> Result<Vec<Value>, RweError> {
+    let mut elements = snapshot_elements(&board.data)?;
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT op FROM whiteboard_operations
+        WHERE whiteboard_id = ?1 AND revision > ?2 AND revision <= ?3
+        ORDER BY revision
+        "#,
+    ).map_err(|e| RweError::database("Prepare error", e))?;
+    let ops = stmt.query_map(params![board.id, board.snapshot_revision, revision], |row| row.get::<_, String>(0))
+        .map_err(|e| RweError::database("Query error", e))?;
+    for op in ops {
+        let op = op.map_err(|e| RweError::database("Row error", e))?;
+        let op: WhiteboardOp = serde_json::from_str(&op)
+            .map_err(|e| RweError::Storage(format!("Invalid whiteboard operation: {}", e)))?;
+        op.apply(&mut elements);
+    }
+    Ok(elements)
+}
+
+fn state_at(conn: &Connection, conversation_id: i64, revision: Option<i64>) -> Result<WhiteboardState, RweError> {
+    let Some(board) = find_board(conn, conversation_id)? else {
+        if revision.is_some_and(|revision| revision != 0) {
+            return Err(RweError::NotFound(format!("No whiteboard for conversation {}", conversation_id)));
+        }
+        return Ok(WhiteboardState {
+            conversation_id,
+            revision: 0,
+            head_revision: 0,
+            snapshot_revision: 0,
+            latest_revision: 0,
+            elements: Vec::new(),
+        });
+    };
+
+    let latest_revision = latest_revision(conn, &board)?;
+    let revision = revision.unwrap_or(board.revision);
+    if revision < board.snapshot_revision || revision > latest_revision {
+        return Err(RweError::NotFound(format!(
+            "Revision {} is not available; the board has revisions {} to {}",
+            revision, board.snapshot_revision, latest_revision
+        )));
+    }
+
+    Ok(WhiteboardState {
+        conversation_id,
+        revision,
+        head_revision: board.revision,
+        snapshot_revision: board.snapshot_revision,
+        latest_revision,
+        elements: elements_at(conn, &board, revision)?,
+    })
+}
+
+fn set_head(conn: &Connection, board: &Board, revision: i64) -> Result<(), RweError> {
+    conn.execute(
+        "UPDATE whiteboards SET revision = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+        [revision, board.id],
+    ).map_err(|e| RweError::database("Update whiteboard error", e))?;
+    Ok(())
+}
+
+/// Folds the log up to `revision` into the board's snapshot. Later
+/// operations stay in the log, so they can still be redone.
+pub fn compact(conn: &Connection, whiteboard_id: i64, revision: i64) -> Result<(), RweError> {
+    let board = conn.query_row(
+        &format!("SELECT {} FROM whiteboards WHERE id = ?1", BOARD_COLUMNS),
+        [whiteboard_id],
+        row_to_board,
+    ).map_err(|e| RweError::database("Query whiteboard error", e))?;
+    if revision <= board.snapshot_revision {
+        return Ok(());
+    }
+
+    let data = serde_json::json!({ "drawings": elements_at(conn, &board, revision)? }).to_string();
+    conn.execute(
+        "UPDATE whiteboards SET data = ?1, snapshot_revision = ?2 WHERE id = ?3",
+        params![data, revision, board.id],
+    ).map_err(|e| RweError::database("Update whiteboard error", e))?;
+    conn.execute(
+        "DELETE FROM whiteboard_operations WHERE whiteboard_id = ?1 AND revision <= ?2",
+        [board.id, revision],
+    ).map_err(|e| RweError::database("Compact whiteboard error", e))?;
+    Ok(())
+}
+
+/// The board at `revision`, or at its current revision when not given.
+#[command]
+pub async fn get_whiteboard_state(
+    conversation_id: i64,
+    revision: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<WhiteboardState, RweError> {
+    db.run(move |conn| state_at(conn, conversation_id, revision)).await
+}
+
+/// Appends operations after the board's current revision, creating the
+/// board on first use. Anything that had been undone can't be redone after.
+#[command]
+pub async fn append_whiteboard_ops(
+    conversation_id: i64,
+    ops: Vec<WhiteboardOp>,
+    db: State<'_, DbPool>,
+) -> Result<WhiteboardState, RweError> {
+    if ops.is_empty() {
+        return Err(RweError::Validation("No operations to append".to_string()));
+    }
+    for op in &ops {
+        if let WhiteboardOp::Add { element } = op {
+            if !element.is_object() {
+                return Err(RweError::Validation("Whiteboard elements must be objects".to_string()));
+            }
+        }
+    }
+
+    db.run(move |conn| append(conn, conversation_id, &ops)).await
+}
+
+fn append(conn: &Connection, conversation_id: i64, ops: &[WhiteboardOp]) -> Result<WhiteboardState, RweError> {
+    with_transaction(conn, |tx| {
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM conversations WHERE id = ?1)",
+            [conversation_id],
+            |row| row.get(0),
+        ).map_err(|e| RweError::database("Query error", e))?;
+        if !exists {
+            return Err(RweError::NotFound(format!("Conversation {} not found", conversation_id)));
+        }
+        tx.execute(
+            "INSERT INTO whiteboards (conversation_id, data) VALUES (?1, '{}') ON CONFLICT(conversation_id) DO NOTHING",
+            [conversation_id],
+        ).map_err(|e| RweError::database("Insert whiteboard error", e))?;
+        let board = require_board(tx, conversation_id)?;
+
+        tx.execute(
+            "DELETE FROM whiteboard_operations WHERE whiteboard_id = ?1 AND revision > ?2",
+            [board.id, board.revision],
+        ).map_err(|e| RweError::database("Discard redo history error", e))?;
+        let mut revision = board.revision;
+        for op in ops {
+            revision += 1;
+            let op = serde_json::to_string(op)
+                .map_err(|e| RweError::Internal(format!("Failed to encode operation: {}", e)))?;
+            tx.execute(
+                "INSERT INTO whiteboard_operations (whiteboard_id, revision, op) VALUES (?1, ?2, ?3)",
+                params![board.id, revision, op],
+            ).map_err(|e| RweError::database("Insert operation error", e))?;
+        }
+        set_head(tx, &board, revision)?;
+        state_at(tx, conversation_id, None)
+    })
+}
+
+/// Steps the board back one revision. The snapshot is as far back as it goes.
+#[command]
+pub async fn undo_whiteboard(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<WhiteboardState, RweError> {
+    db.run(move |conn| undo(conn, conversation_id)).await
+}
+
+fn undo(conn: &Connection, conversation_id: i64) -> Result<WhiteboardState, RweError> {
+    with_transaction(conn, |tx| {
+        let board = require_board(tx, conversation_id)?;
+        if board.revision <= board.snapshot_revision {
+            return Err(RweError::Conflict("Nothing to undo".to_string()));
+        }
+        set_head(tx, &board, board.revision - 1)?;
+        state_at(tx, conversation_id, None)
+    })
+}
+
+#[command]
+pub async fn redo_whiteboard(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<WhiteboardState, RweError> {
+    db.run(move |conn| redo(conn, conversation_id)).await
+}
+
+fn redo(conn: &Connection, conversation_id: i64) -> Result<WhiteboardState, RweError> {
+    with_transaction(conn, |tx| {
+        let board = require_board(tx, conversation_id)?;
+        if board.revision >= latest_revision(tx, &board)? {
+            return Err(RweError::Conflict("Nothing to redo".to_string()));
+        }
+        set_head(tx, &board, board.revision + 1)?;
+        state_at(tx, conversation_id, None)
+    })
+}
+
+/// Folds the log into the board's snapshot, keeping the last `keep_revisions`
+/// (none by default) undoable.
+#[command]
+pub async fn compact_whiteboard(
+    conversation_id: i64,
+    keep_revisions: Option<i64>,
+    db: State<'_, DbPool>,
+) -> Result<WhiteboardState, RweError> {
+    let keep_revisions = keep_revisions.unwrap_or(0);
+    if keep_revisions < 0 {
+        return Err(RweError::Validation("keep_revisions can't be negative".to_string()));
+    }
+    db.run(move |conn| compact_board(conn, conversation_id, keep_revisions)).await
+}
+
+fn compact_board(conn: &Connection, conversation_id: i64, keep_revisions: i64) -> Result<WhiteboardState, RweError> {
+    with_transaction(conn, |tx| {
+        let board = require_board(tx, conversation_id)?;
+        compact(tx, board.id, board.revision - keep_revisions)?;
+        state_at(tx, conversation_id, None)
+    })
+}
+
+/// The board's snapshot. Boards edited through operations are read with
+/// `get_whiteboard_state`.
+#[command]
+pub async fn get_whiteboard_data(
+    conversation_id: i64,
+    db: State<'_, DbPool>,
+) -> Result<Option<WhiteboardData>, RweError> {
+    db.run(move |conn| {
+        let mut stmt = conn.prepare("SELECT id, conversation_id, data, created_at, updated_at FROM whiteboards WHERE conversation_id = ?1")
+            .map_err(|e| RweError::database("Prepare error", e))?;
+        
+        match stmt.query_row([conversation_id], |row| {
+            Ok(WhiteboardData {
+                id: row.get(0)?,
+                conversation_id: row.get(1)?,
+                data: row.get(2)?,
+                created_at: row.get(3)?,
+                updated_at: row.get(4)?,
+            })
+        }) {
+            Ok(whiteboard) => Ok(Some(whiteboard)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(RweError::database("Query error", e)),
+        }
+    }).await
+}
+
+fn validate_whiteboard(data: &str) -> Result<(), RweError> {
+    serde_json::from_str::<serde_json::Value>(data)
+        .map(|_| ())
+        .map_err(|e| RweError::Validation(format!("Invalid whiteboard data: {}", e)))
+}
+
+/// Replaces the whole board with `data`, which becomes its snapshot at the
+/// current revision; any operation history is dropped.
+#[command]
+pub async fn save_whiteboard_data(
+    conversation_id: i64,
+    data: String,
+    db: State<'_, DbPool>,
+) -> Result<i64, RweError> {
+    validate_whiteboard(&data)?;
+    db.run(move |conn| {
+        with_transaction(conn, |tx| {
+            let whiteboard_id: i64 = tx.query_row(
+                r#"
+                INSERT INTO whiteboards (conversation_id, data) VALUES (?1, ?2)
+                ON CONFLICT(conversation_id) DO UPDATE SET
+                    data = excluded.data,
+                    snapshot_revision = revision,
+                    updated_at = CURRENT_TIMESTAMP
+                RETURNING id
+                "#,
+                params![conversation_id, data],
+                |row| row.get(0),
+            ).map_err(|e| RweError::database("Save whiteboard error", e))?;
+            tx.execute("DELETE FROM whiteboard_operations WHERE whiteboard_id = ?1", [whiteboard_id])
+                .map_err(|e| RweError::database("Save whiteboard error", e))?;
+            Ok(whiteboard_id)
+        })
+    }).await
+}
+
+/// One-time import of whiteboards from the webview's localStorage. Boards
+/// already in the database win, and boards whose conversation no longer
+/// exists are dropped. Returns how many were imported.
+#[command]
+pub async fn import_local_whiteboards(
+    whiteboards: Vec<LocalWhiteboard>,
+    db: State<'_, DbPool>,
+) -> Result<usize, RweError> {
+    for whiteboard in &whiteboards {
+        validate_whiteboard(&whiteboard.data)?;
+    }
+    db.run(move |conn| {
+        with_transaction(conn, |tx| {
+            let mut imported = 0;
+            for whiteboard in &whiteboards {
+                imported += tx.execute(
+                    r#"
+                    INSERT INTO whiteboards (conversation_id, data)
+                    SELECT ?1, ?2 WHERE EXISTS(SELECT 1 FROM conversations WHERE id = ?1)
+                    ON CONFLICT(conversation_id) DO NOTHING
+                    "#,
+                    params![whiteboard.conversation_id, whiteboard.data],
+                ).map_err(|e| RweError::database("Import whiteboard error", e))?;
+            }
+            Ok(imported)
+        })
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations::MigrationRunner;
+    use serde_json::json;
+
+    fn migrated() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        MigrationRunner::new().run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO conversations (id, name) VALUES (1, 'Sketches')", []).unwrap();
+        conn
+    }
+
+    fn add(id: &str) -> WhiteboardOp {
+        WhiteboardOp::Add { element: json!({ "id": id }) }
+    }
+
+    fn ids(state: &WhiteboardState) -> Vec<&str> {
+        state.elements.iter().map(|element| element["id"].as_str().unwrap()).collect()
+    }
+
+    fn logged_revisions(conn: &Connection) -> Vec<i64> {
+        let mut stmt = conn.prepare("SELECT revision FROM whiteboard_operations ORDER BY revision").unwrap();
+        stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn undo_and_redo_step_through_revisions_in_order() {
+        let conn = migrated();
+        append(&conn, 1, &[add("a"), add("b")]).unwrap();
+        append(&conn, 1, &[WhiteboardOp::Clear]).unwrap();
+        let state = append(&conn, 1, &[add("c")]).unwrap();
+        assert_eq!((state.head_revision, state.latest_revision), (4, 4));
+        assert_eq!(ids(&state), ["c"]);
+
+        assert!(ids(&undo(&conn, 1).unwrap()).is_empty());
+        assert_eq!(ids(&undo(&conn, 1).unwrap()), ["a", "b"]);
+        assert!(ids(&redo(&conn, 1).unwrap()).is_empty());
+        assert_eq!(ids(&undo(&conn, 1).unwrap()), ["a", "b"]);
+        let state = undo(&conn, 1).unwrap();
+        assert_eq!((state.head_revision, state.latest_revision), (1, 4));
+        assert_eq!(ids(&state), ["a"]);
+
+        // Appending after an undo drops what could have been redone
+        let state = append(&conn, 1, &[add("d")]).unwrap();
+        assert_eq!((state.head_revision, state.latest_revision), (2, 2));
+        assert_eq!(ids(&state), ["a", "d"]);
+        assert_eq!(redo(&conn, 1).unwrap_err(), RweError::Conflict("Nothing to redo".to_string()));
+
+        undo(&conn, 1).unwrap();
+        assert!(ids(&undo(&conn, 1).unwrap()).is_empty());
+        assert_eq!(undo(&conn, 1).unwrap_err(), RweError::Conflict("Nothing to undo".to_string()));
+        assert_eq!(ids(&state_at(&conn, 1, Some(2)).unwrap()), ["a", "d"]);
+    }
+
+    #[test]
+    fn compaction_folds_the_log_into_the_snapshot_and_keeps_redo() {
+        let conn = migrated();
+        append(&conn, 1, &[add("a"), add("b"), WhiteboardOp::Clear, add("c"), add("d")]).unwrap();
+        undo(&conn, 1).unwrap();
+
+        // Head is at 4; keeping one revision undoable folds up to 3
+        let state = compact_board(&conn, 1, 1).unwrap();
+        assert_eq!((state.snapshot_revision, state.head_revision, state.latest_revision), (3, 4, 5));
+        assert_eq!(ids(&state), ["c"]);
+        assert_eq!(logged_revisions(&conn), [4, 5]);
+        assert!(matches!(state_at(&conn, 1, Some(2)), Err(RweError::NotFound(_))));
+
+        assert!(ids(&undo(&conn, 1).unwrap()).is_empty());
+        assert_eq!(undo(&conn, 1).unwrap_err(), RweError::Conflict("Nothing to undo".to_string()));
+        redo(&conn, 1).unwrap();
+        assert_eq!(ids(&redo(&conn, 1).unwrap()), ["c", "d"]);
+
+        let state = compact_board(&conn, 1, 0).unwrap();
+        assert_eq!((state.snapshot_revision, state.head_revision, state.latest_revision), (5, 5, 5));
+        assert_eq!(ids(&state), ["c", "d"]);
+        assert!(logged_revisions(&conn).is_empty());
+        let data: String = conn.query_row("SELECT data FROM whiteboards WHERE conversation_id = 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&data).unwrap(), json!({ "drawings": [{ "id": "c" }, { "id": "d" }] }));
+    }
+
+    #[test]
+    fn rolling_back_the_op_log_migration_folds_boards_at_their_head() {
+        let conn = migrated();
+        conn.execute("INSERT INTO conversations (id, name) VALUES (2, 'Untouched')", []).unwrap();
+        conn.execute("INSERT INTO whiteboards (conversation_id, data) VALUES (2, '{\"drawings\":[{\"id\":\"x\"}]}')", []).unwrap();
+        append(&conn, 1, &[add("a"), add("b"), add("c")]).unwrap();
+        undo(&conn, 1).unwrap();
+
+        let runner = MigrationRunner::new();
+        runner.migrate_to(&conn, 14).unwrap();
+        let has_log: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'whiteboard_operations')",
+            [],
+            |row| row.get(0),
+        ).unwrap();
+        assert!(!has_log);
+        let boards: Vec<(i64, Value)> = {
+            let mut stmt = conn.prepare("SELECT conversation_id, data FROM whiteboards ORDER BY conversation_id").unwrap();
+            stmt.query_map([], |row| Ok((row.get(0)?, serde_json::from_str(&row.get::<_, String>(1)?).unwrap())))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        // The undone stroke is gone; the board that was never edited is as it was
+        assert_eq!(boards, vec![
+            (1, json!({ "drawings": [{ "id": "a" }, { "id": "b" }] })),
+            (2, json!({ "drawings": [{ "id": "x" }] })),
+        ]);
+
+        runner.run_migrations(&conn).unwrap();
+        let state = state_at(&conn, 1, None).unwrap();
+        assert_eq!((state.snapshot_revision, state.head_revision), (0, 0));
+        assert_eq!(ids(&state), ["a", "b"]);
+    }
+}
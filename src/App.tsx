@@ -8,26 +8,33 @@ import { AppSidebar } from "@/components/app-sidebar";
 import { Tabs, TabsContent, TabsList, TabsTrigger } from "@/components/ui/tabs";
 import 'highlight.js/styles/github-dark.css';
 import { Button } from "./components/ui/button";
-import { MessageSquarePlus, Key } from "lucide-react";
+import { MessageSquarePlus, Key, GitBranch } from "lucide-react";
 import { TopicDialog } from "./components/topic-dialog";
 import { SearchDialog } from "./components/search-dialog";
 import { ApiKeyDialog } from "./components/api-key-dialog";
+import { UnlockDialog } from "./components/unlock-dialog";
 import { OptimizedMarkdown } from "./components/optimized-markdown";
 import { MindMap } from "./components/mind-map";
 import { Whiteboard } from "./components/whiteboard";
 import { CommandPalette } from "./components/command-palette";
+import { BranchSwitcher } from "./components/branch-switcher";
 import { ToggleGroup, ToggleGroupItem } from "./components/ui/toggle-group";
 import { ModeToggle } from "./components/mode-toggle";
 import { 
   initDatabase, 
+  getEncryptionStatus,
   createConversation, 
-  saveMessage, 
   updateConversationNotes,
   updateConversationSummary,
-  getApiKey,
-  type Conversation
+  getMigrationStatus,
+  onMigrationProgress,
+  importLocalWhiteboards,
+  type Conversation,
+  type MigrationProgress
 } from "./services/database";
-import { sendToGemini, convertToGeminiFormat } from "./services/gemini";
+import { streamChatMessage, cancelChatStream, generateCompletion, toChatMessages, getLlmConfig } from "./services/llm";
+import { errorKind, errorMessage } from "./services/errors";
+import { type Branch, forkConversation, getBranches, switchBranch } from "./services/branches";
 import { 
   getConversation,
   getMessages,
@@ -41,14 +48,19 @@ function App() {
   const editorRef = React.useRef<HTMLTextAreaElement | null>(null);
   const chatScrollRef = React.useRef<HTMLDivElement | null>(null);
   const [message, setMessage] = React.useState("");
-  const [conversation, setConversation] = React.useState<Array<{role: string, content: string}>>([]);
+  // Stored messages carry their id, which forking needs
+  const [conversation, setConversation] = React.useState<Array<{role: string, content: string, id?: number}>>([]);
+  const [branches, setBranches] = React.useState<Branch[]>([]);
   const [isLoading, setIsLoading] = React.useState(false);
+  const [activeRequestId, setActiveRequestId] = React.useState<string | null>(null);
   const [isTopicDialogOpen, setIsTopicDialogOpen] = React.useState(false);
   const [isSearchDialogOpen, setIsSearchDialogOpen] = React.useState(false);
   const [isApiKeyDialogOpen, setIsApiKeyDialogOpen] = React.useState(false);
   const [currentConversationId, setCurrentConversationId] = React.useState<number | null>(null);
   const [notes, setNotes] = React.useState("");
   const [isDbInitialized, setIsDbInitialized] = React.useState(false);
+  const [isDbLocked, setIsDbLocked] = React.useState(false);
+  const [migrationProgress, setMigrationProgress] = React.useState<MigrationProgress | null>(null);
   const [refreshSidebar, setRefreshSidebar] = React.useState(0);
   const [currentConversation, setCurrentConversation] = React.useState<Conversation | null>(null);
   const [generatingSummary, setGeneratingSummary] = React.useState(false);
@@ -115,24 +127,63 @@ function App() {
   React.useEffect(() => {
     const initDb = async () => {
       try {
+        const encryption = await getEncryptionStatus();
+        if (encryption.locked) {
+          setIsDbLocked(true);
+          return;
+        }
         await initDatabase();
         setIsDbInitialized(true);
         checkApiKey();
       } catch (error) {
+        // A failed migration won't succeed on retry; say what happened instead
+        const migration = await getMigrationStatus().catch(() => null);
+        if (migration?.failure) {
+          const restored = migration.failure.restored_backup
+            ? `\n\nThe database was restored from ${migration.failure.restored_backup}.`
+            : "";
+          const edited = migration.failure.checksum_mismatches.length
+            ? `\n\nThese migrations have changed since they were applied: ${migration.failure.checksum_mismatches
+                .map((mismatch) => `${mismatch.version} (${mismatch.name})`)
+                .join(", ")}.`
+            : "";
+          alert(`Database upgrade failed: ${migration.failure.message}${restored}${edited}`);
+          return;
+        }
         setTimeout(() => {
           initDb();
         }, 2000);
       }
     };
-    if (!isDbInitialized) {
+    if (!isDbInitialized && !isDbLocked) {
       initDb();
     }
+  }, [isDbInitialized, isDbLocked]);
+
+  React.useEffect(() => {
+    if (isDbInitialized) {
+      importLocalWhiteboards().catch((error) => {
+        console.error('Failed to import whiteboards:', error);
+      });
+    }
+  }, [isDbInitialized]);
+
+  // Startup and unlock migrations run before the database opens; show how far they are
+  React.useEffect(() => {
+    if (isDbInitialized) {
+      setMigrationProgress(null);
+      return;
+    }
+    const unlisten = onMigrationProgress(setMigrationProgress);
+    return () => {
+      unlisten.then((stop) => stop());
+    };
   }, [isDbInitialized]);
 
   const checkApiKey = async () => {
     try {
-      const apiKey = await getApiKey();
-      setHasApiKey(!!apiKey);
+      const status = await getLlmConfig();
+      setHasApiKey(status.ready);
     } catch (error) {
       setHasApiKey(false);
     }
@@ -168,6 +219,7 @@ function App() {
       
       setCurrentConversationId(conversationId);
       setConversation([]);
+      setBranches([]);
       setNotes("");
       setRefreshSidebar(prev => prev + 1);
       
@@ -198,20 +250,24 @@ function App() {
       
       setCurrentConversationId(conversationId);
       setConversation([]);
+      setBranches([]);
       setSummaryUpdate(0);
       
-      const [messages, conv] = await Promise.all([
+      const [messages, conv, convBranches] = await Promise.all([
         getMessages(conversationId),
-        getConversation(conversationId)
+        getConversation(conversationId),
+        getBranches(conversationId)
       ]);
       
       if (conv) {
         setCurrentConversation(conv);
         setNotes(conv.notes || "");
+        setBranches(convBranches);
         
         const formattedMessages = messages.map(msg => ({
           role: msg.role,
-          content: msg.content
+          content: msg.content,
+          id: msg.id
         }));
         
         setConversation(formattedMessages);
@@ -226,6 +282,7 @@ function App() {
       setCurrentConversationId(null);
       setCurrentConversation(null);
       setConversation([]);
+      setBranches([]);
     }
   }, [isDbInitialized, currentConversationId, notes, saveCurrentNotes]);
 
@@ -275,48 +332,85 @@ function App() {
     setMessage("");
     setIsLoading(true);
 
-    if (currentConversationId) {
-      try {
-        await saveMessage(currentConversationId, 'user', userMessage.content);
-        invalidateMessageCache(currentConversationId);
-      } catch (error) {
-        console.error("Failed to save user message:", error);
-      }
-    }
-
     try {
-      const geminiMessages = convertToGeminiFormat(newConversation);
-      const aiContent = await sendToGemini(geminiMessages);
-      const aiMessage = { role: "assistant", content: aiContent };
-      setConversation([...newConversation, aiMessage]);
-
       if (currentConversationId) {
-        try {
-          await saveMessage(currentConversationId, 'ai', aiContent);
-          invalidateMessageCache(currentConversationId);
-        } catch (error) {
-          console.error("Failed to save AI message:", error);
+        const requestId = crypto.randomUUID();
+        setActiveRequestId(requestId);
+        let streamed = "";
+        const reply = await streamChatMessage(requestId, currentConversationId, userMessage.content, (delta) => {
+          streamed += delta;
+          setConversation([...newConversation, { role: "assistant", content: streamed }]);
+        });
+        invalidateMessageCache(currentConversationId);
+        setConversation(reply ? [...newConversation, { role: "assistant", content: reply.content, id: reply.id }] : newConversation);
+        if (branches.length > 0) {
+          setBranches(await getBranches(currentConversationId));
         }
+      } else {
+        const aiContent = await generateCompletion(toChatMessages(newConversation));
+        setConversation([...newConversation, { role: "assistant", content: aiContent }]);
       }
     } catch (error) {
+      if (currentConversationId) {
+        invalidateMessageCache(currentConversationId);
+      }
       let errorMessage = "Sorry, I encountered an error. Please try again.";
       if (error instanceof Error) {
         if (error.message.includes("API key not configured")) {
-          errorMessage = "Please configure your Gemini API key first.";
+          errorMessage = "Please configure your API key first.";
           setIsApiKeyDialogOpen(true);
         } else if (error.message.includes("401")) {
-          errorMessage = "Invalid API key. Please check your Gemini API key.";
+          errorMessage = "Invalid API key. Please check your provider settings.";
         } else if (error.message.includes("429")) {
           errorMessage = "Rate limit exceeded. Please wait a moment before trying again.";
+        } else if (errorKind(error) === "network") {
+          errorMessage = "Couldn't reach the AI provider. Please check your connection and provider settings.";
         }
       }
       
       const errorResponse = { role: "assistant", content: errorMessage };
       setConversation([...newConversation, errorResponse]);
     } finally {
+      setActiveRequestId(null);
       setIsLoading(false);
     }
-  }, [message, isLoading, isDbInitialized, conversation, currentConversationId, hasApiKey]);
+  }, [message, isLoading, isDbInitialized, conversation, currentConversationId, hasApiKey, branches]);
+
+  const showBranch = React.useCallback(async (messages: Array<{role: string, content: string, id: number}>) => {
+    if (!currentConversationId) return;
+    invalidateMessageCache(currentConversationId);
+    setConversation(messages.map(msg => ({ role: msg.role, content: msg.content, id: msg.id })));
+    setBranches(await getBranches(currentConversationId));
+  }, [currentConversationId]);
+
+  const handleFork = React.useCallback(async (messageId: number) => {
+    if (!currentConversationId || isLoading) return;
+    try {
+      await forkConversation(currentConversationId, messageId);
+      invalidateMessageCache(currentConversationId);
+      await showBranch(await getMessages(currentConversationId));
+    } catch (error) {
+      alert(`Failed to fork conversation: ${errorMessage(error)}`);
+    }
+  }, [currentConversationId, isLoading, showBranch]);
+
+  const handleSwitchBranch = React.useCallback(async (branchId: number) => {
+    if (!currentConversationId || isLoading) return;
+    try {
+      await showBranch(await switchBranch(currentConversationId, branchId));
+    } catch (error) {
+      alert(`Failed to switch branch: ${errorMessage(error)}`);
+    }
+  }, [currentConversationId, isLoading, showBranch]);
+
+  const stopStreaming = React.useCallback(async () => {
+    if (!activeRequestId) return;
+    try {
+      await cancelChatStream(activeRequestId);
+    } catch (error) {
+      console.error("Failed to cancel response:", error);
+    }
+  }, [activeRequestId]);
 
   const generateSummary = React.useCallback(async () => {
     if (!currentConversation || !hasApiKey) return;
@@ -334,8 +428,7 @@ Structure your summary with:
 
 Provide a detailed, informative summary.`;
       
-      const geminiMessages = convertToGeminiFormat([{role: "user", content: prompt}]);
-      const summary = await sendToGemini(geminiMessages);
+      const summary = await generateCompletion([{ role: "user", content: prompt }]);
       
       if (currentConversationId) {
         await updateConversationSummary(currentConversationId, summary);
@@ -402,6 +495,12 @@ Provide a detailed, informative summary.`;
                 <Skeleton className="h-8 w-24 ml-auto" />
               </div>
               <div className="flex-1 p-4 space-y-4">
+                {migrationProgress && (
+                  <p className="text-sm text-muted-foreground">
+                    Upgrading database ({migrationProgress.step} of {migrationProgress.steps}): {migrationProgress.name}
+                    {migrationProgress.total ? ` — ${migrationProgress.done ?? 0} of ${migrationProgress.total}` : ""}
+                  </p>
+                )}
                 <Skeleton className="h-4 w-full" />
                 <Skeleton className="h-4 w-3/4" />
                 <Skeleton className="h-4 w-1/2" />
@@ -532,6 +631,7 @@ Provide a detailed, informative summary.`;
                 <div className="flex h-full overflow-hidden">
                   <div className="flex-1 flex flex-col overflow-hidden">
                     <div className="flex-1 overflow-y-auto" ref={chatScrollRef} style={{scrollBehavior: 'smooth'}}>
+                      <BranchSwitcher branches={branches} onSwitch={handleSwitchBranch} />
                       <div className="p-4 space-y-4">
                         {conversation.map((msg, index) => (
                           <div
@@ -551,10 +651,21 @@ Provide a detailed, informative summary.`;
                               ) : (
                                 <OptimizedMarkdown content={msg.content} />
                               )}
+                              {msg.role !== "user" && msg.id !== undefined && (
+                                <button
+                                  onClick={() => handleFork(msg.id!)}
+                                  disabled={isLoading}
+                                  className="mt-2 flex items-center gap-1 text-xs text-muted-foreground hover:text-foreground disabled:opacity-50"
+                                  title="Continue from this reply on a new branch"
+                                >
+                                  <GitBranch className="w-3 h-3" />
+                                  Fork from here
+                                </button>
+                              )}
                             </div>
                           </div>
                         ))}
-                        {isLoading && (
+                        {isLoading && conversation[conversation.length - 1]?.role === "user" && (
                           <div className="flex justify-start">
                             <div className="bg-neutral-200 dark:bg-neutral-800 rounded-lg p-3">
                               <div className="text-sm font-medium mb-1">AI</div>
@@ -573,10 +684,10 @@ Provide a detailed, informative summary.`;
                           className="flex items-center gap-2"
                         >
                           <Key className="w-4 h-4" />
-                          {hasApiKey ? "Update API Key" : "Set API Key"}
+                          {hasApiKey ? "AI Settings" : "Set Up AI Provider"}
                         </Button>
                         <div className="text-xs text-muted-foreground ml-auto">
-                          {hasApiKey ? "Gemini API configured" : "Configure Gemini API key to start chatting"}
+                          {hasApiKey ? "AI provider configured" : "Configure an AI provider to start chatting"}
                         </div>
                       </div>
                       <div className="p-3">
@@ -591,11 +702,13 @@ Provide a detailed, informative summary.`;
                             disabled={isLoading}
                           />
                           <button
-                            onClick={sendMessage}
-                            disabled={!message.trim() || isLoading}
+                            onClick={activeRequestId ? stopStreaming : sendMessage}
+                            disabled={activeRequestId ? false : !message.trim() || isLoading}
                             className="px-4 py-2 bg-gradient-to-r from-blue-500 to-blue-600 hover:from-blue-600 hover:to-blue-700 text-white rounded-lg font-medium transition-all duration-200 disabled:opacity-50 disabled:cursor-not-allowed disabled:hover:from-blue-500 disabled:hover:to-blue-600 min-w-[60px] flex items-center justify-center text-sm"
                           >
-                            {isLoading ? (
+                            {activeRequestId ? (
+                              "Stop"
+                            ) : isLoading ? (
                               <div className="w-4 h-4 border-2 border-white border-t-transparent rounded-full animate-spin" />
                             ) : (
                               "Send"
@@ -624,10 +737,11 @@ Provide a detailed, informative summary.`;
         <ApiKeyDialog 
           open={isApiKeyDialogOpen} 
           onOpenChange={setIsApiKeyDialogOpen}
-          onApiKeySet={() => {
-            checkApiKey();
-            setHasApiKey(true);
-          }}
+          onApiKeySet={checkApiKey}
+        />
+        <UnlockDialog
+          open={isDbLocked}
+          onUnlocked={() => setIsDbLocked(false)}
         />
         <CommandPalette 
           open={isCommandPaletteOpen} 
@@ -8,7 +8,16 @@ import {
 } from "@/components/ui/dialog";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
-import { getApiKey, setApiKey } from "@/services/database";
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select";
+import {
+  getLlmConfig,
+  setLlmConfig,
+  listModels,
+  type LlmConfig,
+  type ProviderKind
+} from "@/services/llm";
+import { listSecrets, activateSecret, type SecretInfo } from "@/services/secrets";
+import { errorMessage } from "@/services/errors";
 
 interface ApiKeyDialogProps {
   open: boolean;
@@ -16,37 +25,98 @@ interface ApiKeyDialogProps {
   onApiKeySet: () => void;
 }
 
+const PROVIDERS: Record<ProviderKind, { label: string; baseUrl: string; model: string; needsKey: boolean }> = {
+  gemini: { label: "Google Gemini", baseUrl: "https://generativelanguage.googleapis.com/v1beta", model: "gemini-2.0-flash", needsKey: true },
+  openai: { label: "OpenAI-compatible", baseUrl: "https://api.openai.com/v1", model: "gpt-4o-mini", needsKey: false },
+  ollama: { label: "Ollama", baseUrl: "http://localhost:11434", model: "llama3.2", needsKey: false },
+};
+
+const DEFAULT_CONFIG: LlmConfig = {
+  provider: 'gemini',
+  base_url: null,
+  model: null,
+  temperature: 0.7,
+  max_output_tokens: 1000,
+};
+
 export function ApiKeyDialog({ open, onOpenChange, onApiKeySet }: ApiKeyDialogProps) {
+  const [config, setConfig] = React.useState<LlmConfig>(DEFAULT_CONFIG);
   const [apiKey, setApiKeyValue] = React.useState("");
+  const [secrets, setSecrets] = React.useState<SecretInfo[]>([]);
+  const [models, setModels] = React.useState<string[]>([]);
+  const [loadingModels, setLoadingModels] = React.useState(false);
+  const [error, setError] = React.useState<string | null>(null);
   const [saving, setSaving] = React.useState(false);
 
   React.useEffect(() => {
     if (open) {
-      loadApiKey();
+      loadConfig();
     }
   }, [open]);
 
-  const loadApiKey = async () => {
+  const loadConfig = async () => {
+    try {
+      const [status, savedSecrets] = await Promise.all([getLlmConfig(), listSecrets()]);
+      setSecrets(savedSecrets);
+      setConfig(status.config);
+      setApiKeyValue("");
+      setModels([]);
+      setError(null);
+    } catch (error) {
+      console.error("Failed to load provider settings:", error);
+    }
+  };
+
+  const provider = PROVIDERS[config.provider];
+  const providerSecrets = secrets.filter(secret => secret.provider === config.provider);
+  const activeSecret = providerSecrets.find(secret => secret.is_active);
+  const hasStoredKey = !!activeSecret;
+  const canSave = !provider.needsKey || hasStoredKey || !!apiKey.trim();
+
+  const updateConfig = (changes: Partial<LlmConfig>) => {
+    setConfig(prev => ({ ...prev, ...changes }));
+    setError(null);
+  };
+
+  const handleActivateSecret = async (name: string) => {
     try {
-      const key = await getApiKey();
-      if (key) {
-        setApiKeyValue(key);
-      }
+      await activateSecret(config.provider, name);
+      setSecrets(await listSecrets());
+      setApiKeyValue("");
     } catch (error) {
-      console.error("Failed to load API key:", error);
+      setError(errorMessage(error));
+    }
+  };
+
+  const handleLoadModels = async () => {
+    setLoadingModels(true);
+    setError(null);
+    try {
+      setModels(await listModels(config));
+    } catch (error) {
+      setError(errorMessage(error));
+    } finally {
+      setLoadingModels(false);
     }
   };
 
   const handleSave = async () => {
-    if (!apiKey.trim()) return;
-    
+    if (!canSave) return;
+
     setSaving(true);
     try {
-      await setApiKey(apiKey.trim());
+      await setLlmConfig(
+        {
+          ...config,
+          base_url: config.base_url?.trim() || null,
+          model: config.model?.trim() || null,
+        },
+        apiKey.trim() ? apiKey.trim() : undefined
+      );
       onApiKeySet();
       onOpenChange(false);
     } catch (error) {
-      console.error("Failed to save API key:", error);
+      setError(errorMessage(error));
     } finally {
       setSaving(false);
     }
@@ -56,38 +126,134 @@ export function ApiKeyDialog({ open, onOpenChange, onApiKeySet }: ApiKeyDialogPr
     <Dialog open={open} onOpenChange={onOpenChange}>
       <DialogContent className="sm:max-w-md">
         <DialogHeader>
-          <DialogTitle>Configure Gemini API Key</DialogTitle>
+          <DialogTitle>Configure AI Provider</DialogTitle>
           <DialogDescription>
-            Enter your Google Gemini API key to start chatting. You can get a free API key from Google AI Studio.
+            Chat with Google Gemini, any OpenAI-compatible endpoint, or a local Ollama server. API keys are encrypted on this device and left out of exports.
           </DialogDescription>
         </DialogHeader>
         
         <div className="space-y-4">
           <div className="space-y-2">
-            <div className="text-sm font-medium">API Key</div>
+            <div className="text-sm font-medium">Provider</div>
+            <Select
+              value={config.provider}
+              onValueChange={(value: ProviderKind) => {
+                updateConfig({ provider: value, base_url: null, model: null });
+                setModels([]);
+              }}
+            >
+              <SelectTrigger className="w-full">
+                <SelectValue />
+              </SelectTrigger>
+              <SelectContent>
+                {(Object.keys(PROVIDERS) as ProviderKind[]).map(kind => (
+                  <SelectItem key={kind} value={kind}>{PROVIDERS[kind].label}</SelectItem>
+                ))}
+              </SelectContent>
+            </Select>
+          </div>
+
+          {providerSecrets.length > 1 && (
+            <div className="space-y-2">
+              <div className="text-sm font-medium">Saved key</div>
+              <Select value={activeSecret?.name} onValueChange={handleActivateSecret}>
+                <SelectTrigger className="w-full">
+                  <SelectValue placeholder="Choose a saved key" />
+                </SelectTrigger>
+                <SelectContent>
+                  {providerSecrets.map(secret => (
+                    <SelectItem key={secret.name} value={secret.name}>{secret.name}</SelectItem>
+                  ))}
+                </SelectContent>
+              </Select>
+            </div>
+          )}
+
+          {config.provider !== 'ollama' && (
+            <div className="space-y-2">
+              <div className="text-sm font-medium">
+                API Key{!provider.needsKey && <span className="text-muted-foreground font-normal"> (optional for local servers)</span>}
+              </div>
+              <Input
+                type="password"
+                placeholder={hasStoredKey ? "Saved — leave blank to keep" : "Enter your API key"}
+                value={apiKey}
+                onChange={(e) => setApiKeyValue(e.target.value)}
+                onKeyDown={(e) => e.key === 'Enter' && handleSave()}
+              />
+            </div>
+          )}
+
+          <div className="space-y-2">
+            <div className="text-sm font-medium">Base URL</div>
             <Input
-              type="password"
-              placeholder="Enter your Gemini API key"
-              value={apiKey}
-              onChange={(e) => setApiKeyValue(e.target.value)}
-              onKeyDown={(e) => e.key === 'Enter' && handleSave()}
+              placeholder={provider.baseUrl}
+              value={config.base_url ?? ""}
+              onChange={(e) => updateConfig({ base_url: e.target.value })}
             />
           </div>
+
+          <div className="space-y-2">
+            <div className="text-sm font-medium">Model</div>
+            <div className="flex gap-2">
+              <Input
+                placeholder={provider.model}
+                value={config.model ?? ""}
+                onChange={(e) => updateConfig({ model: e.target.value })}
+                list="llm-models"
+              />
+              <Button variant="outline" onClick={handleLoadModels} disabled={loadingModels}>
+                {loadingModels ? "Loading..." : "Load models"}
+              </Button>
+            </div>
+            <datalist id="llm-models">
+              {models.map(model => <option key={model} value={model} />)}
+            </datalist>
+          </div>
+
+          <div className="grid grid-cols-2 gap-2">
+            <div className="space-y-2">
+              <div className="text-sm font-medium">Temperature</div>
+              <Input
+                type="number"
+                min={0}
+                max={2}
+                step={0.1}
+                value={config.temperature}
+                onChange={(e) => updateConfig({ temperature: Number(e.target.value) })}
+              />
+            </div>
+            <div className="space-y-2">
+              <div className="text-sm font-medium">Max tokens</div>
+              <Input
+                type="number"
+                min={1}
+                value={config.max_output_tokens}
+                onChange={(e) => updateConfig({ max_output_tokens: Math.max(1, Math.floor(Number(e.target.value))) })}
+              />
+            </div>
+          </div>
+
+          {error && (
+            <div className="text-sm text-red-600 dark:text-red-400 break-words">{error}</div>
+          )}
           
           <div className="flex justify-between items-center">
-            <Button 
-              variant="outline" 
-              onClick={() => window.open("https://aistudio.google.com/app/apikey", "_blank")}
-            >
-              Get API Key
-            </Button>
+            {config.provider === 'gemini' ? (
+              <Button 
+                variant="outline" 
+                onClick={() => window.open("https://aistudio.google.com/app/apikey", "_blank")}
+              >
+                Get API Key
+              </Button>
+            ) : <div />}
             <div className="flex gap-2">
               <Button variant="outline" onClick={() => onOpenChange(false)}>
                 Cancel
               </Button>
               <Button 
                 onClick={handleSave} 
-                disabled={!apiKey.trim() || saving}
+                disabled={!canSave || saving}
               >
                 {saving ? "Saving..." : "Save"}
               </Button>
@@ -5,6 +5,7 @@ import {
   SidebarGroup,
   SidebarGroupContent,
   SidebarGroupLabel,
+  SidebarGroupAction,
   SidebarMenu,
   SidebarMenuButton,
   SidebarMenuItem,
@@ -16,7 +17,7 @@ import {
   DropdownMenuItem,
   DropdownMenuTrigger,
 } from "@/components/ui/dropdown-menu"
-import { MessageSquare, Home, Search, Settings, MoreHorizontal, HandHelpingIcon, BarChart3 } from "lucide-react"
+import { MessageSquare, Home, Search, Settings, MoreHorizontal, HandHelpingIcon, BarChart3, FolderPlus, Trash2 } from "lucide-react"
 import { Skeleton } from "@/components/ui/skeleton"
 import { deleteConversation, type Conversation, getConversationsPaginated, getConversationsCount } from "../services/database"
 import { getConversations, invalidateConversationCache } from "../services/cache"
@@ -24,6 +25,20 @@ import { useAlert } from "./alert-dialog"
 import { AnalysisDialog } from "./analysis-dialog"
 import { VirtualizedConversationList } from "./virtualized-conversation-list"
 import { SettingsDialog } from "./settings-dialog"
+import { TrashDialog } from "./trash-dialog"
+import { ConversationTagsMenu } from "./conversation-tags-menu"
+import {
+  Select,
+  SelectContent,
+  SelectItem,
+  SelectTrigger,
+  SelectValue,
+} from "@/components/ui/select"
+import { type Tag, getTags } from "../services/tags"
+import { ConversationFolderMenu } from "./conversation-folder-menu"
+import { FolderTree } from "./folder-tree"
+import { type FolderNode, createFolder, getFolderConversations, getFolderTree } from "../services/folders"
+import { errorMessage } from "../services/errors"
 
 
 const items = [
@@ -57,6 +72,12 @@ const items = [
     icon: BarChart3,
     onClick: "analysis",
   },
+  {
+    title: "Trash",
+    url: "#",
+    icon: Trash2,
+    onClick: "trash",
+  },
 ]
 
 interface AppSidebarProps {
@@ -72,18 +93,35 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
   const [deletingIds, setDeletingIds] = React.useState<Set<number>>(new Set());
   const [analysisOpen, setAnalysisOpen] = React.useState(false);
   const [settingsOpen, setSettingsOpen] = React.useState(false);
+  const [trashOpen, setTrashOpen] = React.useState(false);
   const [totalCount, setTotalCount] = React.useState(0);
   const [useVirtualized, setUseVirtualized] = React.useState(false);
+  const [tags, setTags] = React.useState<Tag[]>([]);
+  const [tagFilter, setTagFilter] = React.useState<number | null>(null);
+  const [folders, setFolders] = React.useState<FolderNode[]>([]);
+  const [folderFilter, setFolderFilter] = React.useState<number | null>(null);
   const { showAlert, AlertComponent } = useAlert();
 
   const loadConversations = React.useCallback(async () => {
     if (!isDbReady) return;
     
     try {
-      const count = await getConversationsCount();
+      if (folderFilter !== null) {
+        const convos = await getFolderConversations(folderFilter);
+        setTotalCount(convos.length);
+        setUseVirtualized(convos.length > 100);
+        setConversations(convos);
+        return;
+      }
+
+      const count = await getConversationsCount(tagFilter);
       setTotalCount(count);
       
-      if (count > 100) {
+      if (tagFilter !== null) {
+        setUseVirtualized(count > 100);
+        const convos = await getConversationsPaginated(Math.max(count, 1), 0, tagFilter);
+        setConversations(convos);
+      } else if (count > 100) {
         setUseVirtualized(true);
         const convos = await getConversationsPaginated(100, 0);
         setConversations(convos);
@@ -96,20 +134,88 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
       setConversations([]);
       setTotalCount(0);
     }
+  }, [isDbReady, tagFilter, folderFilter]);
+
+  const loadTags = React.useCallback(async () => {
+    if (!isDbReady) return;
+
+    try {
+      const loaded = await getTags();
+      setTags(loaded);
+      setTagFilter(prev => prev !== null && !loaded.some(tag => tag.id === prev) ? null : prev);
+    } catch (error) {
+      setTags([]);
+    }
+  }, [isDbReady]);
+
+  const loadFolders = React.useCallback(async () => {
+    if (!isDbReady) return;
+
+    try {
+      setFolders(await getFolderTree());
+    } catch (error) {
+      setFolders([]);
+    }
   }, [isDbReady]);
 
+  const handleFoldersChanged = React.useCallback(() => {
+    invalidateConversationCache();
+    loadFolders();
+    loadConversations();
+  }, [loadFolders, loadConversations]);
+
+  const handleTrashChanged = React.useCallback(() => {
+    handleFoldersChanged();
+    loadTags();
+  }, [handleFoldersChanged, loadTags]);
+
+  const handleNewFolder = async () => {
+    const name = prompt('Folder name')?.trim();
+    if (!name) return;
+    try {
+      await createFolder(name);
+      loadFolders();
+    } catch (error) {
+      alert(`Failed to create folder: ${errorMessage(error)}`);
+    }
+  };
+
+  const selectFolder = (folderId: number | null) => {
+    setFolderFilter(folderId);
+    if (folderId !== null) setTagFilter(null);
+  };
+
+  const selectTag = (tagId: number | null) => {
+    setTagFilter(tagId);
+    if (tagId !== null) setFolderFilter(null);
+  };
+
+  const handleTagsChanged = React.useCallback(() => {
+    loadTags();
+    if (tagFilter !== null) {
+      loadConversations();
+    }
+  }, [loadTags, loadConversations, tagFilter]);
+
   React.useEffect(() => {
     if (isDbReady) {
       loadConversations();
     }
   }, [refreshTrigger, isDbReady, loadConversations]);
 
+  React.useEffect(() => {
+    if (isDbReady) {
+      loadTags();
+      loadFolders();
+    }
+  }, [refreshTrigger, isDbReady, loadTags, loadFolders]);
+
   const handleDeleteConversation = React.useCallback(async (conversationId: number) => {
     if (!isDbReady || deletingIds.has(conversationId)) return;
     
     showAlert({
       title: "Delete Conversation",
-      description: "Delete this conversation permanently?",
+      description: "Move this conversation to the trash? You can restore it from Trash.",
       confirmText: "Delete",
       cancelText: "Cancel",
       variant: "destructive",
@@ -119,6 +225,9 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
         try {
           await deleteConversation(conversationId);
           invalidateConversationCache();
+          setTotalCount(prev => Math.max(prev - 1, 0));
+          loadFolders();
+          loadTags();
           
           setConversations(prev => {
             const updated = prev.filter(conv => conv.id !== conversationId);
@@ -148,7 +257,7 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
         }
       }
     });
-  }, [isDbReady, deletingIds, currentConversationId, onConversationSelect, showAlert]);
+  }, [isDbReady, deletingIds, currentConversationId, onConversationSelect, showAlert, loadFolders, loadTags]);
   return (
     <Sidebar>
       <SidebarContent>
@@ -170,6 +279,8 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
                           setAnalysisOpen(true)
                         } else if (item.onClick === "settings") {
                           setSettingsOpen(true)
+                        } else if (item.onClick === "trash") {
+                          setTrashOpen(true)
                         } else if (typeof item.onClick === "function") {
                           item.onClick()
                         }
@@ -186,11 +297,52 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
           </SidebarGroupContent>
         </SidebarGroup>
         
+        <SidebarGroup>
+          <SidebarGroupLabel>Folders</SidebarGroupLabel>
+          <SidebarGroupAction title="New Folder" onClick={handleNewFolder} disabled={!isDbReady}>
+            <FolderPlus />
+          </SidebarGroupAction>
+          <SidebarGroupContent>
+            {folders.length === 0 ? (
+              <div className="px-2 py-1 text-sm text-muted-foreground">
+                No folders yet.
+              </div>
+            ) : (
+              <FolderTree
+                folders={folders}
+                selectedFolderId={folderFilter}
+                onFolderSelect={selectFolder}
+                onFoldersChanged={handleFoldersChanged}
+              />
+            )}
+          </SidebarGroupContent>
+        </SidebarGroup>
+
         <SidebarGroup>
           <SidebarGroupLabel>
             Conversations {totalCount > 0 && `(${totalCount})`}
           </SidebarGroupLabel>
           <SidebarGroupContent>
+            {tags.length > 0 && (
+              <div className="px-2 pb-2">
+                <Select
+                  value={tagFilter === null ? "all" : String(tagFilter)}
+                  onValueChange={(value) => selectTag(value === "all" ? null : Number(value))}
+                >
+                  <SelectTrigger className="h-8 w-full">
+                    <SelectValue placeholder="All tags" />
+                  </SelectTrigger>
+                  <SelectContent>
+                    <SelectItem value="all">All tags</SelectItem>
+                    {tags.map((tag) => (
+                      <SelectItem key={tag.id} value={String(tag.id)}>
+                        {tag.name} ({tag.conversation_count})
+                      </SelectItem>
+                    ))}
+                  </SelectContent>
+                </Select>
+              </div>
+            )}
             <SidebarMenu>
               {!isDbReady ? (
                 <>
@@ -206,7 +358,11 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
               ) : conversations.length === 0 ? (
                 <SidebarMenuItem>
                   <div className="px-2 py-1 text-sm text-muted-foreground">
-                    No conversations yet. Create a new topic to get started.
+                    {folderFilter !== null
+                      ? "No conversations in this folder."
+                      : tagFilter !== null
+                      ? "No conversations with this tag."
+                      : "No conversations yet. Create a new topic to get started."}
                   </div>
                 </SidebarMenuItem>
               ) : useVirtualized ? (
@@ -216,6 +372,10 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
                   onConversationSelect={onConversationSelect}
                   onDeleteConversation={handleDeleteConversation}
                   deletingIds={deletingIds}
+                  tags={tags}
+                  onTagsChanged={handleTagsChanged}
+                  folders={folders}
+                  onFoldersChanged={handleFoldersChanged}
                 />
               ) : (
                 conversations.map((conversation) => (
@@ -242,6 +402,17 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
                         <DropdownMenuItem>
                           <span>Edit Name</span>
                         </DropdownMenuItem>
+                        <ConversationTagsMenu
+                          conversationId={conversation.id}
+                          tags={tags}
+                          onTagsChanged={handleTagsChanged}
+                        />
+                        <ConversationFolderMenu
+                          conversationId={conversation.id}
+                          folderId={conversation.folder_id}
+                          folders={folders}
+                          onMoved={handleFoldersChanged}
+                        />
                         <DropdownMenuItem 
                           onClick={() => handleDeleteConversation(conversation.id)}
                           className="text-destructive"
@@ -268,6 +439,7 @@ export function AppSidebar({ onConversationSelect, currentConversationId, refres
       <AlertComponent />
       <AnalysisDialog open={analysisOpen} onOpenChange={setAnalysisOpen} />
       <SettingsDialog open={settingsOpen} onOpenChange={setSettingsOpen} />
+      <TrashDialog open={trashOpen} onOpenChange={setTrashOpen} onTrashChanged={handleTrashChanged} />
     </Sidebar>
   )
 }
\ No newline at end of file
@@ -0,0 +1,38 @@
+"use client"
+
+import * as React from "react"
+import { GitBranch } from "lucide-react"
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select"
+import type { Branch } from "../services/branches"
+
+interface BranchSwitcherProps {
+  branches: Branch[]
+  onSwitch: (branchId: number) => void
+}
+
+// Picks which branch of a forked conversation the chat shows and continues.
+export function BranchSwitcher({ branches, onSwitch }: BranchSwitcherProps) {
+  const active = branches.find((branch) => branch.active)
+  if (branches.length === 0) return null
+
+  return (
+    <div className="flex items-center gap-2 px-4 pt-3">
+      <GitBranch className="w-4 h-4 text-muted-foreground" />
+      <Select
+        value={active ? String(active.id) : undefined}
+        onValueChange={(value) => onSwitch(Number(value))}
+      >
+        <SelectTrigger className="h-8 w-56">
+          <SelectValue placeholder="Branch" />
+        </SelectTrigger>
+        <SelectContent>
+          {branches.map((branch) => (
+            <SelectItem key={branch.id} value={String(branch.id)}>
+              {branch.name} ({branch.message_count})
+            </SelectItem>
+          ))}
+        </SelectContent>
+      </Select>
+    </div>
+  )
+}
@@ -0,0 +1,55 @@
+"use client"
+
+import * as React from "react"
+import {
+  DropdownMenuRadioGroup,
+  DropdownMenuRadioItem,
+  DropdownMenuSeparator,
+  DropdownMenuSub,
+  DropdownMenuSubContent,
+  DropdownMenuSubTrigger,
+} from "@/components/ui/dropdown-menu"
+import { type FolderNode, moveConversation } from "../services/folders"
+import { errorKind, errorMessage } from "../services/errors"
+import { flattenFolders } from "./folder-tree"
+
+interface ConversationFolderMenuProps {
+  conversationId: number
+  folderId?: number | null
+  folders: FolderNode[]
+  onMoved?: () => void
+}
+
+export function ConversationFolderMenu({ conversationId, folderId, folders, onMoved }: ConversationFolderMenuProps) {
+  const handleMove = async (value: string) => {
+    const target = value === "none" ? null : Number(value)
+    if (target === (folderId ?? null)) return
+    try {
+      await moveConversation(conversationId, target)
+      onMoved?.()
+    } catch (error) {
+      // The folder or conversation is gone; refresh so the menu stops offering it
+      if (errorKind(error) === "not_found") onMoved?.()
+      alert(`Failed to move conversation: ${errorMessage(error)}`)
+    }
+  }
+
+  return (
+    <DropdownMenuSub>
+      <DropdownMenuSubTrigger>
+        <span>Move to Folder</span>
+      </DropdownMenuSubTrigger>
+      <DropdownMenuSubContent>
+        <DropdownMenuRadioGroup value={folderId == null ? "none" : String(folderId)} onValueChange={handleMove}>
+          <DropdownMenuRadioItem value="none">No Folder</DropdownMenuRadioItem>
+          {folders.length > 0 && <DropdownMenuSeparator />}
+          {flattenFolders(folders).map(({ folder, depth }) => (
+            <DropdownMenuRadioItem key={folder.id} value={String(folder.id)}>
+              <span style={{ paddingLeft: depth * 12 }}>{folder.name}</span>
+            </DropdownMenuRadioItem>
+          ))}
+        </DropdownMenuRadioGroup>
+      </DropdownMenuSubContent>
+    </DropdownMenuSub>
+  )
+}
@@ -0,0 +1,91 @@
+"use client"
+
+import * as React from "react"
+import {
+  DropdownMenuCheckboxItem,
+  DropdownMenuItem,
+  DropdownMenuSeparator,
+  DropdownMenuSub,
+  DropdownMenuSubContent,
+  DropdownMenuSubTrigger,
+} from "@/components/ui/dropdown-menu"
+import {
+  type Tag,
+  createTag,
+  getConversationTags,
+  tagConversation,
+  untagConversation,
+} from "../services/tags"
+import { errorMessage } from "../services/errors"
+
+interface ConversationTagsMenuProps {
+  conversationId: number
+  tags: Tag[]
+  onTagsChanged?: () => void
+}
+
+export function ConversationTagsMenu({ conversationId, tags, onTagsChanged }: ConversationTagsMenuProps) {
+  const [assigned, setAssigned] = React.useState<Set<number>>(new Set())
+
+  React.useEffect(() => {
+    getConversationTags(conversationId)
+      .then(current => setAssigned(new Set(current.map(tag => tag.id))))
+      .catch(() => setAssigned(new Set()))
+  }, [conversationId])
+
+  const toggleTag = async (tagId: number, checked: boolean) => {
+    try {
+      if (checked) {
+        await tagConversation(conversationId, tagId)
+      } else {
+        await untagConversation(conversationId, tagId)
+      }
+      setAssigned(prev => {
+        const next = new Set(prev)
+        if (checked) next.add(tagId)
+        else next.delete(tagId)
+        return next
+      })
+      onTagsChanged?.()
+    } catch (error) {
+      alert(`Failed to update tags: ${errorMessage(error)}`)
+    }
+  }
+
+  const handleNewTag = async () => {
+    const name = prompt('Tag name')?.trim()
+    if (!name) return
+    try {
+      const tagId = await createTag(name)
+      await tagConversation(conversationId, tagId)
+      setAssigned(prev => new Set(prev).add(tagId))
+      onTagsChanged?.()
+    } catch (error) {
+      alert(`Failed to create tag: ${errorMessage(error)}`)
+    }
+  }
+
+  return (
+    <DropdownMenuSub>
+      <DropdownMenuSubTrigger>
+        <span>Tags</span>
+      </DropdownMenuSubTrigger>
+      <DropdownMenuSubContent>
+        {tags.map(tag => (
+          <DropdownMenuCheckboxItem
+            key={tag.id}
+            checked={assigned.has(tag.id)}
+            onCheckedChange={(checked) => toggleTag(tag.id, checked === true)}
+            onSelect={(e) => e.preventDefault()}
+          >
+            {tag.name}
+          </DropdownMenuCheckboxItem>
+        ))}
+        {tags.length > 0 && <DropdownMenuSeparator />}
+        <DropdownMenuItem onClick={handleNewTag}>
+          <span>New Tag...</span>
+        </DropdownMenuItem>
+      </DropdownMenuSubContent>
+    </DropdownMenuSub>
+  )
+}
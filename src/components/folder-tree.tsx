@@ -0,0 +1,165 @@
+"use client"
+
+import * as React from "react"
+import {
+  SidebarMenu,
+  SidebarMenuAction,
+  SidebarMenuButton,
+  SidebarMenuItem,
+  SidebarMenuSub,
+} from "@/components/ui/sidebar"
+import {
+  DropdownMenu,
+  DropdownMenuContent,
+  DropdownMenuItem,
+  DropdownMenuTrigger,
+} from "@/components/ui/dropdown-menu"
+import { Collapsible, CollapsibleContent } from "@/components/ui/collapsible"
+import { ChevronRight, Folder, FolderOpen, MoreHorizontal } from "lucide-react"
+import {
+  type FolderNode,
+  createFolder,
+  deleteFolder,
+  renameFolder,
+} from "../services/folders"
+import { errorKind, errorMessage } from "../services/errors"
+
+interface FolderTreeProps {
+  folders: FolderNode[]
+  selectedFolderId: number | null
+  onFolderSelect: (folderId: number | null) => void
+  onFoldersChanged: () => void
+}
+
+interface FolderItemProps extends Omit<FolderTreeProps, 'folders'> {
+  folder: FolderNode
+}
+
+function FolderItem({ folder, selectedFolderId, onFolderSelect, onFoldersChanged }: FolderItemProps) {
+  const [open, setOpen] = React.useState(false)
+  const hasChildren = folder.children.length > 0
+
+  const handleNewSubfolder = async () => {
+    const name = prompt('Folder name')?.trim()
+    if (!name) return
+    try {
+      await createFolder(name, folder.id)
+      setOpen(true)
+      onFoldersChanged()
+    } catch (error) {
+      alert(`Failed to create folder: ${errorMessage(error)}`)
+    }
+  }
+
+  const handleRename = async () => {
+    const name = prompt('Folder name', folder.name)?.trim()
+    if (!name || name === folder.name) return
+    try {
+      await renameFolder(folder.id, name)
+      onFoldersChanged()
+    } catch (error) {
+      alert(`Failed to rename folder: ${errorMessage(error)}`)
+    }
+  }
+
+  const handleDelete = async (withContents: boolean) => {
+    const message = withContents
+      ? `Delete "${folder.name}", its subfolders and all ${folder.total_conversation_count} conversations in them?`
+      : `Delete "${folder.name}"? Its subfolders and conversations move up one level.`
+    if (!confirm(message)) return
+    try {
+      await deleteFolder(folder.id, withContents ? 'delete_contents' : 'move_to_parent')
+      if (selectedFolderId === folder.id) onFolderSelect(null)
+      onFoldersChanged()
+    } catch (error) {
+      // Already deleted, e.g. along with a parent folder in another window
+      if (errorKind(error) === 'not_found') {
+        onFoldersChanged()
+        return
+      }
+      alert(`Failed to delete folder: ${errorMessage(error)}`)
+    }
+  }
+
+  return (
+    <Collapsible open={open} onOpenChange={setOpen} asChild>
+      <SidebarMenuItem>
+        <SidebarMenuButton
+          isActive={selectedFolderId === folder.id}
+          onClick={() => {
+            const selecting = selectedFolderId !== folder.id
+            onFolderSelect(selecting ? folder.id : null)
+            setOpen(selecting)
+          }}
+        >
+          {open ? <FolderOpen /> : <Folder />}
+          <span className="truncate">{folder.name}</span>
+          {hasChildren && (
+            <ChevronRight className={`transition-transform ${open ? 'rotate-90' : ''}`} />
+          )}
+          <span className="ml-auto pr-5 text-xs text-muted-foreground">{folder.total_conversation_count}</span>
+        </SidebarMenuButton>
+        <DropdownMenu>
+          <DropdownMenuTrigger asChild>
+            <SidebarMenuAction>
+              <MoreHorizontal />
+            </SidebarMenuAction>
+          </DropdownMenuTrigger>
+          <DropdownMenuContent side="right" align="start">
+            <DropdownMenuItem onClick={handleNewSubfolder}>
+              <span>New Subfolder</span>
+            </DropdownMenuItem>
+            <DropdownMenuItem onClick={handleRename}>
+              <span>Rename</span>
+            </DropdownMenuItem>
+            <DropdownMenuItem onClick={() => handleDelete(false)} className="text-destructive">
+              <span>Delete</span>
+            </DropdownMenuItem>
+            <DropdownMenuItem onClick={() => handleDelete(true)} className="text-destructive">
+              <span>Delete with Contents</span>
+            </DropdownMenuItem>
+          </DropdownMenuContent>
+        </DropdownMenu>
+        {hasChildren && (
+          <CollapsibleContent>
+            <SidebarMenuSub>
+              {folder.children.map((child) => (
+                <FolderItem
+                  key={child.id}
+                  folder={child}
+                  selectedFolderId={selectedFolderId}
+                  onFolderSelect={onFolderSelect}
+                  onFoldersChanged={onFoldersChanged}
+                />
+              ))}
+            </SidebarMenuSub>
+          </CollapsibleContent>
+        )}
+      </SidebarMenuItem>
+    </Collapsible>
+  )
+}
+
+export function FolderTree({ folders, selectedFolderId, onFolderSelect, onFoldersChanged }: FolderTreeProps) {
+  return (
+    <SidebarMenu>
+      {folders.map((folder) => (
+        <FolderItem
+          key={folder.id}
+          folder={folder}
+          selectedFolderId={selectedFolderId}
+          onFolderSelect={onFolderSelect}
+          onFoldersChanged={onFoldersChanged}
+        />
+      ))}
+    </SidebarMenu>
+  )
+}
+
+// Folders in display order with their depth, for flat pickers.
+export function flattenFolders(folders: FolderNode[], depth = 0): Array<{ folder: FolderNode, depth: number }> {
+  return folders.flatMap((folder) => [
+    { folder, depth },
+    ...flattenFolders(folder.children, depth + 1),
+  ])
+}
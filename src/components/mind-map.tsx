@@ -2,23 +2,23 @@ import React, { useState, useRef, useCallback, useEffect } from 'react';
 import { Button } from './ui/button';
 import { Input } from './ui/input';
 import { Plus, Square, Circle, Diamond, Trash2, Link } from 'lucide-react';
-import { saveMindMapData, getMindMapData } from '../services/database';
+import {
+  type MindMapData,
+  type MindMapEdge,
+  type MindMapExportFormat,
+  type MindMapNode,
+  type MindMapVersionInfo,
+  exportMindMap,
+  listMindMaps,
+  listMindMapVersions,
+  restoreMindMapVersion,
+  saveMindMapData
+} from '../services/mindmaps';
+import { errorMessage } from '../services/errors';
 import { useAlert } from './alert-dialog';
 
-interface Node {
-  id: string;
-  x: number;
-  y: number;
-  text: string;
-  shape: 'square' | 'circle' | 'diamond';
-  color: string;
-  connections: string[];
-}
-
-interface Connection {
-  from: string;
-  to: string;
-}
+type Node = MindMapNode;
+type Connection = MindMapEdge;
 
 const COLORS = [
   '#00ff00', '#00ffff', '#ff00ff', '#ffff00', '#ff8000', 
@@ -151,6 +151,9 @@ export function MindMap({ currentConversationId }: { currentConversationId: numb
   const [currentTheme, setCurrentTheme] = useState<keyof typeof THEMES>('terminal');
   const [mapTitle, setMapTitle] = useState('');
   const [isSaving, setIsSaving] = useState(false);
+  const [mindmapId, setMindmapId] = useState<number | null>(null);
+  const [mindmaps, setMindmaps] = useState<MindMapData[]>([]);
+  const [versions, setVersions] = useState<MindMapVersionInfo[]>([]);
   const canvasRef = useRef<HTMLDivElement>(null);
   const { showAlert, AlertComponent } = useAlert();
 
@@ -179,13 +182,17 @@ export function MindMap({ currentConversationId }: { currentConversationId: numb
     
     setIsSaving(true);
     try {
-      await saveMindMapData(
+      const savedId = await saveMindMapData(
         currentConversationId, 
+        mindmapId,
         mapTitle.trim(), 
-        JSON.stringify(nodes), 
-        JSON.stringify(connections), 
+        nodes,
+        connections,
         currentTheme
       );
+      setMindmapId(savedId);
+      setMindmaps(await listMindMaps(currentConversationId));
+      setVersions(await listMindMapVersions(savedId));
       showAlert({
         title: "Success",
         description: "Mind map saved successfully!",
@@ -194,47 +201,87 @@ export function MindMap({ currentConversationId }: { currentConversationId: numb
     } catch (error) {
       showAlert({
         title: "Error",
-        description: "Failed to save mind map",
+        description: `Failed to save mind map: ${errorMessage(error)}`,
         confirmText: "OK"
       });
     } finally {
       setIsSaving(false);
     }
-  }, [mapTitle, nodes, connections, currentTheme, currentConversationId, showAlert]);
+  }, [mapTitle, nodes, connections, currentTheme, currentConversationId, mindmapId, showAlert]);
+
+  const exportMap = useCallback(async (format: MindMapExportFormat) => {
+    if (!currentConversationId || mindmapId === null) return;
+    try {
+      const exported = await exportMindMap(currentConversationId, format, mindmapId);
+      const url = URL.createObjectURL(new Blob([exported.content], { type: 'text/plain' }));
+      const link = document.createElement('a');
+      link.download = exported.file_name;
+      link.href = url;
+      link.click();
+      setTimeout(() => URL.revokeObjectURL(url), 0);
+    } catch (error) {
+      showAlert({
+        title: "Error",
+        description: `Failed to export mind map: ${errorMessage(error)}`,
+        confirmText: "OK"
+      });
+    }
+  }, [currentConversationId, mindmapId, showAlert]);
+
+  const showMindMap = useCallback(async (mindmap: MindMapData | null) => {
+    setSelectedNode(null);
+    setConnectFrom(null);
+    if (mindmap) {
+      setMindmapId(mindmap.id);
+      setMapTitle(mindmap.title);
+      setNodes(mindmap.nodes);
+      setConnections(mindmap.connections);
+      setCurrentTheme(mindmap.theme as keyof typeof THEMES);
+      setVersions(await listMindMapVersions(mindmap.id));
+    } else {
+      setMindmapId(null);
+      setNodes([]);
+      setConnections([]);
+      setMapTitle('');
+      setCurrentTheme('terminal');
+      setVersions([]);
+    }
+  }, []);
+
+  const restoreVersion = useCallback(async (version: number) => {
+    if (!mindmapId || !currentConversationId) return;
+    try {
+      await showMindMap(await restoreMindMapVersion(mindmapId, version));
+      setMindmaps(await listMindMaps(currentConversationId));
+    } catch (error) {
+      showAlert({
+        title: "Error",
+        description: "Failed to restore mind map version",
+        confirmText: "OK"
+      });
+    }
+  }, [mindmapId, currentConversationId, showMindMap, showAlert]);
 
   useEffect(() => {
     const loadMindMapFromDb = async () => {
       if (!currentConversationId) {
-        setNodes([]);
-        setConnections([]);
-        setMapTitle('');
-        setCurrentTheme('terminal');
+        setMindmaps([]);
+        await showMindMap(null);
         return;
       }
       
       try {
-        const savedMindMap = await getMindMapData(currentConversationId);
-        if (savedMindMap) {
-          setMapTitle(savedMindMap.title);
-          setNodes(JSON.parse(savedMindMap.nodes));
-          setConnections(JSON.parse(savedMindMap.connections));
-          setCurrentTheme(savedMindMap.theme as keyof typeof THEMES);
-        } else {
-          setNodes([]);
-          setConnections([]);
-          setMapTitle('');
-          setCurrentTheme('terminal');
-        }
+        const savedMindMaps = await listMindMaps(currentConversationId);
+        setMindmaps(savedMindMaps);
+        await showMindMap(savedMindMaps[0] ?? null);
       } catch (error) {
-        setNodes([]);
-        setConnections([]);
-        setMapTitle('');
-        setCurrentTheme('terminal');
+        setMindmaps([]);
+        await showMindMap(null);
       }
     };
     
     loadMindMapFromDb();
-  }, [currentConversationId]);
+  }, [currentConversationId, showMindMap]);
 
   
   const createNode = useCallback((x: number, y: number) => {
@@ -254,8 +301,7 @@ export function MindMap({ currentConversationId }: { currentConversationId: numb
       y: constrainedY,
       text: newNodeText.trim(),
       shape: selectedShape,
-      color: getRandomColor(),
-      connections: []
+      color: getRandomColor()
     };
     setNodes(prev => [...prev, newNode]);
     setNewNodeText('');
@@ -278,12 +324,11 @@ export function MindMap({ currentConversationId }: { currentConversationId: numb
         setConnectFrom(nodeId);
       } else if (connectFrom !== nodeId) {
         const newConnection = { from: connectFrom, to: nodeId };
-        setConnections(prev => [...prev, newConnection]);
-        setNodes(prev => prev.map(node => 
-          node.id === connectFrom 
-            ? { ...node, connections: [...node.connections, nodeId] }
-            : node
-        ));
+        setConnections(prev =>
+          prev.some(c => c.from === newConnection.from && c.to === newConnection.to)
+            ? prev
+            : [...prev, newConnection]
+        );
         setConnectFrom(null);
         setIsConnecting(false);
       }
@@ -452,6 +497,25 @@ export function MindMap({ currentConversationId }: { currentConversationId: numb
           <h2 className={`text-lg font-bold ${theme.text} font-mono tracking-wider`}>
             Mind Map
           </h2>
+          <select
+            value={mindmapId ?? 'new'}
+            onChange={(e) => {
+              const selected = mindmaps.find(mindmap => String(mindmap.id) === e.target.value);
+              showMindMap(selected ?? null);
+            }}
+            className="px-3 py-1 rounded font-mono text-sm border max-w-40"
+            style={{
+              backgroundColor: theme.nodeBg,
+              borderColor: theme.connection,
+              color: theme.nodeText
+            }}
+            title="Mind maps in this conversation"
+          >
+            {mindmaps.map(mindmap => (
+              <option key={mindmap.id} value={mindmap.id}>{mindmap.title}</option>
+            ))}
+            <option value="new">[NEW_MAP]</option>
+          </select>
           <Input
             type="text"
             placeholder="Enter map title..."
@@ -477,6 +541,45 @@ export function MindMap({ currentConversationId }: { currentConversationId: numb
           >
             {isSaving ? 'SAVING...' : 'SAVE'}
           </Button>
+          {versions.length > 1 && (
+            <select
+              value=""
+              onChange={(e) => restoreVersion(Number(e.target.value))}
+              className="px-3 py-1 rounded font-mono text-sm border"
+              style={{
+                backgroundColor: theme.nodeBg,
+                borderColor: theme.connection,
+                color: theme.nodeText
+              }}
+              title="Restore an earlier version"
+            >
+              <option value="" disabled>[HISTORY]</option>
+              {versions.slice(1).map(version => (
+                <option key={version.version} value={version.version}>
+                  v{version.version} · {version.created_at}
+                </option>
+              ))}
+            </select>
+          )}
+          {mindmapId !== null && (
+            <select
+              value=""
+              onChange={(e) => exportMap(e.target.value as MindMapExportFormat)}
+              className="px-3 py-1 rounded font-mono text-sm border"
+              style={{
+                backgroundColor: theme.nodeBg,
+                borderColor: theme.connection,
+                color: theme.nodeText
+              }}
+              title="Export the saved map"
+            >
+              <option value="" disabled>[EXPORT]</option>
+              <option value="opml">OPML</option>
+              <option value="freemind">FreeMind (.mm)</option>
+              <option value="mermaid">Mermaid</option>
+              <option value="dot">Graphviz DOT</option>
+            </select>
+          )}
           <div className="flex items-center gap-2">
             <Input
               type="text"
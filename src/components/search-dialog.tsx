@@ -9,7 +9,25 @@ import {
 } from "@/components/ui/dialog"
 import { Input } from "@/components/ui/input"
 import { Badge } from "@/components/ui/badge"
-import { type SearchResult } from "@/services/search-types"
+import { type SearchResult, type HighlightRange, isInvalidQueryError } from "@/services/search-types"
+
+function renderSnippet(snippet: string, highlights: HighlightRange[]) {
+  // Ranges are in code points, so index the snippet the same way
+  const chars = Array.from(snippet)
+  const parts: React.ReactNode[] = []
+  let last = 0
+  highlights.forEach((range, i) => {
+    if (range.start > last) parts.push(chars.slice(last, range.start).join(""))
+    parts.push(
+      <mark key={i} className="bg-yellow-200 dark:bg-yellow-800 text-foreground rounded-sm">
+        {chars.slice(range.start, range.end).join("")}
+      </mark>
+    )
+    last = range.end
+  })
+  if (last < chars.length) parts.push(chars.slice(last).join(""))
+  return parts
+}
 
 interface SearchDialogProps {
   open: boolean
@@ -22,12 +40,14 @@ export function SearchDialog({ open, onOpenChange, onResultSelect }: SearchDialo
   const [results, setResults] = React.useState<SearchResult[]>([])
   const [loading, setLoading] = React.useState(false)
   const [searchTime, setSearchTime] = React.useState<number | null>(null)
+  const [queryError, setQueryError] = React.useState<string | null>(null)
 
   React.useEffect(() => {
     if (!open) {
       setQuery("")
       setResults([])
       setSearchTime(null)
+      setQueryError(null)
     }
   }, [open])
 
@@ -37,6 +57,7 @@ export function SearchDialog({ open, onOpenChange, onResultSelect }: SearchDialo
     } else {
       setResults([])
       setSearchTime(null)
+      setQueryError(null)
     }
   }, [query])
 
@@ -52,8 +73,14 @@ export function SearchDialog({ open, onOpenChange, onResultSelect }: SearchDialo
       const endTime = Date.now()
       setSearchTime(endTime - startTime)
       setResults(searchResults || [])
+      setQueryError(null)
     } catch (error) {
-      console.error("Search error:", error)
+      if (isInvalidQueryError(error)) {
+        setQueryError(error.detail.message)
+      } else {
+        console.error("Search error:", error)
+        setQueryError(null)
+      }
       setResults([])
       setSearchTime(null)
     } finally {
@@ -74,6 +101,8 @@ export function SearchDialog({ open, onOpenChange, onResultSelect }: SearchDialo
         return FileText
       case "notes":
         return FileText
+      case "message":
+        return MessageCircle
       case "message_user":
         return User
       case "message_ai":
@@ -101,7 +130,7 @@ export function SearchDialog({ open, onOpenChange, onResultSelect }: SearchDialo
           <div className="flex items-center gap-2">
             <Search className="h-4 w-4 text-muted-foreground" />
             <Input
-              placeholder="Search conversations and messages..."
+              placeholder='Search... e.g. role:model in:"Rust async" "tokio runtime" -deprecated'
               value={query}
               onChange={(e) => setQuery(e.target.value)}
               className="flex-1"
@@ -116,7 +145,13 @@ export function SearchDialog({ open, onOpenChange, onResultSelect }: SearchDialo
               </div>
             )}
             
-            {!loading && query.trim() && query.length >= 2 && results.length === 0 && (
+            {!loading && queryError && (
+              <div className="py-6 text-center text-sm text-destructive">
+                {queryError}
+              </div>
+            )}
+
+            {!loading && !queryError && query.trim() && query.length >= 2 && results.length === 0 && (
               <div className="py-6 text-center text-sm text-muted-foreground">
                 No results found for "{query}".
               </div>
@@ -128,6 +163,16 @@ export function SearchDialog({ open, onOpenChange, onResultSelect }: SearchDialo
               </div>
             )}
             
+            {!loading && results.length > 0 && results[0].fuzziness > 0 && (
+              <div className="px-4 py-2 text-sm text-muted-foreground">
+                Did you mean{" "}
+                <span className="font-medium text-foreground">
+                  {results[0].corrections.map((c) => c.corrected).join(" ")}
+                </span>
+                ? Showing close matches.
+              </div>
+            )}
+
             {searchTime !== null && results.length > 0 && (
               <div className="px-4 py-3 text-xs text-muted-foreground border-b bg-muted">
                 <div className="flex items-center justify-between">
@@ -172,7 +217,7 @@ export function SearchDialog({ open, onOpenChange, onResultSelect }: SearchDialo
                             </Badge>
                           </div>
                           <p className="text-sm text-muted-foreground line-clamp-2">
-                            {result.snippet}
+                            {renderSnippet(result.snippet, result.highlights ?? [])}
                           </p>
                           <div className="flex items-center text-xs text-muted-foreground">
                             <Calendar className="h-3 w-3 mr-1" />
@@ -9,10 +9,20 @@ import {
   DialogTitle,
 } from "@/components/ui/dialog";
 import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
 import { Separator } from "@/components/ui/separator";
 import { UpdateDialog } from "./update-dialog";
 import { exportUserData, importUserData } from "@/services/updater";
-import { backupDatabase, getDatabaseInfo } from "@/services/database";
+import {
+  backupDatabase,
+  checkIntegrity,
+  getDatabaseInfo,
+  getEncryptionStatus,
+  enableEncryption,
+  changePassphrase,
+  type EncryptionStatus,
+} from "@/services/database";
+import { errorMessage } from "@/services/errors";
 
 interface SettingsDialogProps {
   open: boolean
@@ -25,6 +35,12 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
   const [exporting, setExporting] = React.useState(false)
   const [importing, setImporting] = React.useState(false)
   const [backing, setBacking] = React.useState(false)
+  const [checking, setChecking] = React.useState(false)
+  const [encryption, setEncryption] = React.useState<EncryptionStatus | null>(null)
+  const [currentPassphrase, setCurrentPassphrase] = React.useState('')
+  const [newPassphrase, setNewPassphrase] = React.useState('')
+  const [confirmPassphrase, setConfirmPassphrase] = React.useState('')
+  const [encrypting, setEncrypting] = React.useState(false)
 
   const loadDbInfo = async () => {
     try {
@@ -35,9 +51,18 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
     }
   }
 
+  const loadEncryptionStatus = async () => {
+    try {
+      setEncryption(await getEncryptionStatus())
+    } catch (error) {
+      console.error('Failed to load encryption status:', error)
+    }
+  }
+
   React.useEffect(() => {
     if (open) {
       loadDbInfo()
+      loadEncryptionStatus()
     }
   }, [open])
 
@@ -47,7 +72,7 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
       const exportPath = await exportUserData()
       alert(`Data exported successfully to: ${exportPath}`)
     } catch (error) {
-      alert(`Export failed: ${error}`)
+      alert(`Export failed: ${errorMessage(error)}`)
     } finally {
       setExporting(false)
     }
@@ -67,21 +92,22 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
             reader.onload = async () => {
               try {
                 await importUserData(file.name)
-                alert('Data imported successfully! Please restart the app.')
+                alert('Data imported successfully!')
+                window.location.reload()
               } catch (error) {
-                alert(`Import failed: ${error}`)
+                alert(`Import failed: ${errorMessage(error)}`)
               }
             }
             reader.readAsArrayBuffer(file)
           } catch (error) {
-            alert(`Import failed: ${error}`)
+            alert(`Import failed: ${errorMessage(error)}`)
           }
         }
         setImporting(false)
       }
       input.click()
     } catch (error) {
-      alert(`Import failed: ${error}`)
+      alert(`Import failed: ${errorMessage(error)}`)
       setImporting(false)
     }
   }
@@ -92,12 +118,66 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
       const backupPath = await backupDatabase()
       alert(`Backup created successfully at: ${backupPath}`)
     } catch (error) {
-      alert(`Backup failed: ${error}`)
+      alert(`Backup failed: ${errorMessage(error)}`)
     } finally {
       setBacking(false)
     }
   }
 
+  const handleIntegrityCheck = async () => {
+    setChecking(true)
+    try {
+      const report = await checkIntegrity()
+      if (report.integrity_errors.length > 0) {
+        alert(`The database file is damaged. Restore a backup.\n\n${report.integrity_errors.join('\n')}`)
+        return
+      }
+      if (report.issues.length === 0) {
+        alert('No problems found')
+        return
+      }
+      const found = report.issues.map(issue => `${issue.kind.replace(/_/g, ' ')}: ${issue.count}`).join('\n')
+      if (confirm(`Found problems:\n\n${found}\n\nRepair them now?`)) {
+        await checkIntegrity(true)
+        alert('Database repaired')
+        loadDbInfo()
+      }
+    } catch (error) {
+      alert(`Integrity check failed: ${errorMessage(error)}`)
+    } finally {
+      setChecking(false)
+    }
+  }
+
+  const resetPassphraseFields = () => {
+    setCurrentPassphrase('')
+    setNewPassphrase('')
+    setConfirmPassphrase('')
+  }
+
+  const handleEncryption = async () => {
+    if (newPassphrase !== confirmPassphrase) {
+      alert('Passphrases do not match')
+      return
+    }
+    setEncrypting(true)
+    try {
+      if (encryption?.encrypted) {
+        await changePassphrase(currentPassphrase, newPassphrase)
+        alert('Passphrase changed successfully')
+      } else {
+        await enableEncryption(newPassphrase)
+        alert('Database encrypted successfully. Keep your passphrase safe, it cannot be recovered.')
+      }
+      resetPassphraseFields()
+      loadEncryptionStatus()
+    } catch (error) {
+      alert(`Encryption failed: ${errorMessage(error)}`)
+    } finally {
+      setEncrypting(false)
+    }
+  }
+
   const formatBytes = (bytes: number) => {
     if (bytes === 0) return '0 Bytes'
     const k = 1024
@@ -142,6 +222,7 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
                   <div className="grid grid-cols-2 gap-2 text-sm">
                     <div>Conversations: {dbInfo.conversations_count}</div>
                     <div>Messages: {dbInfo.messages_count}</div>
+                    <div>In Trash: {dbInfo.trashed_conversations_count ?? 0}</div>
                     <div>Schema Version: {dbInfo.schema_version}</div>
                     <div>Database Size: {formatBytes(dbInfo.database_size_bytes)}</div>
                   </div>
@@ -178,6 +259,60 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
                     {backing ? 'Creating...' : 'Backup'}
                   </Button>
                 </div>
+                
+                <div className="flex items-center justify-between">
+                  <div>
+                    <p className="font-medium">Check Integrity</p>
+                    <p className="text-sm text-muted-foreground">Find and repair orphaned or out-of-date data</p>
+                  </div>
+                  <Button onClick={handleIntegrityCheck} disabled={checking} variant="outline">
+                    {checking ? 'Checking...' : 'Check'}
+                  </Button>
+                </div>
+              </div>
+            </div>
+
+            <Separator />
+
+            <div>
+              <h3 className="text-lg font-semibold mb-3">Encryption</h3>
+              <p className="text-sm text-muted-foreground mb-3">
+                {encryption?.encrypted
+                  ? 'Your database is encrypted with a passphrase'
+                  : 'Encrypt your database with a passphrase you enter on every launch'}
+              </p>
+              <div className="space-y-2">
+                {encryption?.encrypted && (
+                  <Input
+                    type="password"
+                    placeholder="Current passphrase"
+                    value={currentPassphrase}
+                    onChange={(e) => setCurrentPassphrase(e.target.value)}
+                  />
+                )}
+                <Input
+                  type="password"
+                  placeholder={encryption?.encrypted ? 'New passphrase' : 'Passphrase'}
+                  value={newPassphrase}
+                  onChange={(e) => setNewPassphrase(e.target.value)}
+                />
+                <Input
+                  type="password"
+                  placeholder="Confirm passphrase"
+                  value={confirmPassphrase}
+                  onChange={(e) => setConfirmPassphrase(e.target.value)}
+                />
+                <div className="flex justify-end">
+                  <Button
+                    onClick={handleEncryption}
+                    disabled={encrypting || !newPassphrase || (encryption?.encrypted && !currentPassphrase)}
+                    variant="outline"
+                  >
+                    {encrypting
+                      ? 'Working...'
+                      : encryption?.encrypted ? 'Change Passphrase' : 'Enable Encryption'}
+                  </Button>
+                </div>
               </div>
             </div>
 
@@ -0,0 +1,124 @@
+"use client"
+
+import * as React from "react"
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogHeader,
+  DialogTitle,
+} from "@/components/ui/dialog"
+import { Button } from "@/components/ui/button"
+import {
+  type Conversation,
+  listTrash,
+  purgeConversation,
+  purgeTrash,
+  restoreConversation,
+} from "@/services/database"
+import { errorMessage } from "@/services/errors"
+
+interface TrashDialogProps {
+  open: boolean
+  onOpenChange: (open: boolean) => void
+  onTrashChanged?: () => void
+}
+
+const PURGE_AFTER_DAYS = 30
+
+export function TrashDialog({ open, onOpenChange, onTrashChanged }: TrashDialogProps) {
+  const [trash, setTrash] = React.useState<Conversation[]>([])
+  const [busy, setBusy] = React.useState(false)
+
+  const loadTrash = async () => {
+    try {
+      setTrash(await listTrash())
+    } catch (error) {
+      console.error('Failed to load trash:', error)
+    }
+  }
+
+  React.useEffect(() => {
+    if (open) {
+      loadTrash()
+    }
+  }, [open])
+
+  const run = async (action: () => Promise<unknown>, failure: string) => {
+    setBusy(true)
+    try {
+      await action()
+      await loadTrash()
+      onTrashChanged?.()
+    } catch (error) {
+      alert(`${failure}: ${errorMessage(error)}`)
+    } finally {
+      setBusy(false)
+    }
+  }
+
+  const handlePurgeOld = () => {
+    const cutoff = new Date(Date.now() - PURGE_AFTER_DAYS * 24 * 60 * 60 * 1000)
+    run(() => purgeTrash(cutoff), 'Failed to empty trash')
+  }
+
+  const handleEmpty = () => {
+    if (!confirm(`Permanently delete ${trash.length} conversations? This cannot be undone.`)) return
+    run(() => purgeTrash(), 'Failed to empty trash')
+  }
+
+  return (
+    <Dialog open={open} onOpenChange={onOpenChange}>
+      <DialogContent className="max-w-2xl max-h-[90vh] overflow-y-auto">
+        <DialogHeader>
+          <DialogTitle>Trash</DialogTitle>
+          <DialogDescription>
+            Deleted conversations stay here until you delete them permanently
+          </DialogDescription>
+        </DialogHeader>
+
+        {trash.length === 0 ? (
+          <p className="text-sm text-muted-foreground">Trash is empty.</p>
+        ) : (
+          <div className="space-y-3">
+            {trash.map((conversation) => (
+              <div key={conversation.id} className="flex items-center justify-between gap-4">
+                <div className="min-w-0">
+                  <p className="font-medium truncate">{conversation.name}</p>
+                  <p className="text-xs text-muted-foreground">Deleted {conversation.deleted_at}</p>
+                </div>
+                <div className="flex gap-2 shrink-0">
+                  <Button
+                    size="sm"
+                    variant="outline"
+                    disabled={busy}
+                    onClick={() => run(() => restoreConversation(conversation.id), 'Restore failed')}
+                  >
+                    Restore
+                  </Button>
+                  <Button
+                    size="sm"
+                    variant="destructive"
+                    disabled={busy}
+                    onClick={() => run(() => purgeConversation(conversation.id), 'Delete failed')}
+                  >
+                    Delete Forever
+                  </Button>
+                </div>
+              </div>
+            ))}
+
+            <div className="flex justify-end gap-2 pt-2">
+              <Button variant="outline" disabled={busy} onClick={handlePurgeOld}>
+                Delete Older Than {PURGE_AFTER_DAYS} Days
+              </Button>
+              <Button variant="destructive" disabled={busy} onClick={handleEmpty}>
+                Empty Trash
+              </Button>
+            </div>
+          </div>
+        )}
+      </DialogContent>
+    </Dialog>
+  )
+}
@@ -0,0 +1,76 @@
+import * as React from "react";
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogHeader,
+  DialogTitle,
+} from "@/components/ui/dialog";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { unlockDatabase } from "@/services/database";
+import { errorMessage } from "@/services/errors";
+
+interface UnlockDialogProps {
+  open: boolean;
+  onUnlocked: () => void;
+}
+
+export function UnlockDialog({ open, onUnlocked }: UnlockDialogProps) {
+  const [passphrase, setPassphrase] = React.useState("");
+  const [error, setError] = React.useState<string | null>(null);
+  const [unlocking, setUnlocking] = React.useState(false);
+
+  const handleUnlock = async () => {
+    if (!passphrase) return;
+
+    setUnlocking(true);
+    setError(null);
+    try {
+      await unlockDatabase(passphrase);
+      setPassphrase("");
+      onUnlocked();
+    } catch (error) {
+      setError(errorMessage(error));
+    } finally {
+      setUnlocking(false);
+    }
+  };
+
+  return (
+    <Dialog open={open}>
+      <DialogContent
+        className="sm:max-w-md"
+        showCloseButton={false}
+        onInteractOutside={(e) => e.preventDefault()}
+        onEscapeKeyDown={(e) => e.preventDefault()}
+      >
+        <DialogHeader>
+          <DialogTitle>Unlock Database</DialogTitle>
+          <DialogDescription>
+            Your data is encrypted. Enter your passphrase to open it.
+          </DialogDescription>
+        </DialogHeader>
+
+        <div className="space-y-4">
+          <Input
+            type="password"
+            placeholder="Passphrase"
+            value={passphrase}
+            autoFocus
+            onChange={(e) => setPassphrase(e.target.value)}
+            onKeyDown={(e) => e.key === 'Enter' && handleUnlock()}
+          />
+          {error && (
+            <div className="text-sm text-red-600 dark:text-red-400">{error}</div>
+          )}
+          <div className="flex justify-end">
+            <Button onClick={handleUnlock} disabled={!passphrase || unlocking}>
+              {unlocking ? "Unlocking..." : "Unlock"}
+            </Button>
+          </div>
+        </div>
+      </DialogContent>
+    </Dialog>
+  );
+}
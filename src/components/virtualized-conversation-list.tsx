@@ -15,6 +15,10 @@ import {
 } from "@/components/ui/dropdown-menu"
 import { MessageSquare, MoreHorizontal } from "lucide-react"
 import { type Conversation } from "../services/database"
+import { type Tag } from "../services/tags"
+import { ConversationTagsMenu } from "./conversation-tags-menu"
+import { type FolderNode } from "../services/folders"
+import { ConversationFolderMenu } from "./conversation-folder-menu"
 
 interface VirtualizedConversationListProps {
   conversations: Conversation[]
@@ -22,6 +26,10 @@ interface VirtualizedConversationListProps {
   onConversationSelect?: (conversationId: number) => void
   onDeleteConversation: (conversationId: number) => void
   deletingIds: Set<number>
+  tags?: Tag[]
+  onTagsChanged?: () => void
+  folders?: FolderNode[]
+  onFoldersChanged?: () => void
 }
 
 interface ItemData {
@@ -30,6 +38,10 @@ interface ItemData {
   onConversationSelect?: (conversationId: number) => void
   onDeleteConversation: (conversationId: number) => void
   deletingIds: Set<number>
+  tags?: Tag[]
+  onTagsChanged?: () => void
+  folders?: FolderNode[]
+  onFoldersChanged?: () => void
 }
 
 const ConversationItem = React.memo(({ index, style, data }: {
@@ -37,7 +49,7 @@ const ConversationItem = React.memo(({ index, style, data }: {
   style: React.CSSProperties
   data: ItemData
 }) => {
-  const { conversations, currentConversationId, onConversationSelect, onDeleteConversation, deletingIds } = data
+  const { conversations, currentConversationId, onConversationSelect, onDeleteConversation, deletingIds, tags, onTagsChanged, folders, onFoldersChanged } = data
   const conversation = conversations[index]
 
   if (!conversation) return null
@@ -67,6 +79,17 @@ const ConversationItem = React.memo(({ index, style, data }: {
             <DropdownMenuItem>
               <span>Edit Name</span>
             </DropdownMenuItem>
+            <ConversationTagsMenu
+              conversationId={conversation.id}
+              tags={tags ?? []}
+              onTagsChanged={onTagsChanged}
+            />
+            <ConversationFolderMenu
+              conversationId={conversation.id}
+              folderId={conversation.folder_id}
+              folders={folders ?? []}
+              onMoved={onFoldersChanged}
+            />
             <DropdownMenuItem 
               onClick={() => onDeleteConversation(conversation.id)}
               className="text-destructive"
@@ -95,14 +118,22 @@ export function VirtualizedConversationList({
   currentConversationId,
   onConversationSelect,
   onDeleteConversation,
-  deletingIds
+  deletingIds,
+  tags,
+  onTagsChanged,
+  folders,
+  onFoldersChanged
 }: VirtualizedConversationListProps) {
   const itemData: ItemData = {
     conversations,
     currentConversationId,
     onConversationSelect,
     onDeleteConversation,
-    deletingIds
+    deletingIds,
+    tags,
+    onTagsChanged,
+    folders,
+    onFoldersChanged
   }
 
   const listHeight = Math.min(conversations.length * 44, 400)
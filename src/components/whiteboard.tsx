@@ -2,6 +2,17 @@ import React from 'react';
 import { Button } from './ui/button';
 import { Separator } from './ui/separator';
 import { Pen, Eraser, Square, Circle, Trash2, Undo, Redo, Download } from 'lucide-react';
+import {
+  type WhiteboardOp,
+  type WhiteboardState,
+  appendWhiteboardOps,
+  canRedo,
+  canUndo,
+  compactWhiteboard,
+  getWhiteboardState,
+  redoWhiteboard,
+  undoWhiteboard
+} from '../services/whiteboards';
 
 interface WhiteboardProps {
   conversationId: number | null;
@@ -15,6 +26,11 @@ type DrawingData = {
   size: number;
 };
 
+// Past this many logged operations a board is compacted when opened,
+// keeping the most recent ones undoable
+const MAX_LOGGED_OPS = 500;
+const KEPT_OPS = 100;
+
 export function Whiteboard({ conversationId }: WhiteboardProps) {
   const canvasRef = React.useRef<HTMLCanvasElement>(null);
   const [isDrawing, setIsDrawing] = React.useState(false);
@@ -22,8 +38,9 @@ export function Whiteboard({ conversationId }: WhiteboardProps) {
   const [color, setColor] = React.useState('#000000');
   const [size, setSize] = React.useState(2);
   const [drawings, setDrawings] = React.useState<DrawingData[]>([]);
-  const [history, setHistory] = React.useState<DrawingData[][]>([]);
-  const [historyIndex, setHistoryIndex] = React.useState(-1);
+  const [board, setBoard] = React.useState<WhiteboardState | null>(null);
+  // Keeps writes to the board in the order they were made
+  const pendingWrite = React.useRef<Promise<unknown>>(Promise.resolve());
   const [startPoint, setStartPoint] = React.useState<{ x: number; y: number } | null>(null);
 
   React.useEffect(() => {
@@ -36,8 +53,7 @@ export function Whiteboard({ conversationId }: WhiteboardProps) {
       loadDrawings();
     } else {
       setDrawings([]);
-      setHistory([]);
-      setHistoryIndex(-1);
+      setBoard(null);
     }
   }, [conversationId]);
 
@@ -48,50 +64,47 @@ export function Whiteboard({ conversationId }: WhiteboardProps) {
     }
   }, [drawings, conversationId]);
 
-  const loadDrawings = () => {
+  const loadDrawings = async () => {
     if (!conversationId) return;
     
     try {
-      const saved = localStorage.getItem(`whiteboard_${conversationId}`);
-      if (saved) {
-        const data = JSON.parse(saved);
-        setDrawings(data.drawings || []);
-        setHistory(data.history || []);
-        setHistoryIndex(data.historyIndex || -1);
-      } else {
-        setDrawings([]);
-        setHistory([]);
-        setHistoryIndex(-1);
+      let state = await getWhiteboardState(conversationId);
+      if (state.head_revision - state.snapshot_revision > MAX_LOGGED_OPS) {
+        state = await compactWhiteboard(conversationId, KEPT_OPS);
       }
+      showState(state);
     } catch (error) {
       console.error('Failed to load drawings:', error);
       setDrawings([]);
-      setHistory([]);
-      setHistoryIndex(-1);
+      setBoard(null);
     }
   };
 
-  const saveDrawings = () => {
+  const showState = (state: WhiteboardState) => {
+    setBoard(state);
+    setDrawings(state.elements as unknown as DrawingData[]);
+  };
+
+  const writeBoard = (write: (conversationId: number) => Promise<WhiteboardState>, redraw: boolean) => {
     if (!conversationId) return;
-    try {
-      localStorage.setItem(`whiteboard_${conversationId}`, JSON.stringify({
-        drawings,
-        history,
-        historyIndex
-      }));
-    } catch (error) {
-      console.error('Failed to save drawings:', error);
-    }
+    pendingWrite.current = pendingWrite.current.then(async () => {
+      try {
+        const state = await write(conversationId);
+        if (redraw) {
+          showState(state);
+        } else {
+          setBoard(state);
+        }
+      } catch (error) {
+        console.error('Failed to save drawings:', error);
+      }
+    });
   };
 
-  React.useEffect(() => {
-    if (conversationId && drawings.length > 0) {
-      const timeoutId = setTimeout(() => {
-        saveDrawings();
-      }, 500);
-      return () => clearTimeout(timeoutId);
-    }
-  }, [drawings, conversationId]);
+  // Strokes are already on screen, so appending only updates the history
+  const appendOps = (ops: WhiteboardOp[]) => {
+    writeBoard((id) => appendWhiteboardOps(id, ops), false);
+  };
 
   const redrawCanvas = () => {
     const canvas = canvasRef.current;
@@ -206,46 +219,30 @@ export function Whiteboard({ conversationId }: WhiteboardProps) {
         color,
         size
       };
-      setDrawings(prev => {
-        const newDrawings = [...prev, newDrawing];
-        addToHistory(newDrawings);
-        return newDrawings;
-      });
+      setDrawings(prev => [...prev, newDrawing]);
+      appendOps([{ type: 'add', element: newDrawing }]);
     } else {
-      addToHistory(drawings);
+      const stroke = drawings[drawings.length - 1];
+      if (stroke) {
+        appendOps([{ type: 'add', element: stroke }]);
+      }
     }
 
     setIsDrawing(false);
     setStartPoint(null);
   };
 
-  const addToHistory = (currentDrawings: DrawingData[]) => {
-    setHistory(prev => {
-      const newHistory = prev.slice(0, historyIndex + 1);
-      newHistory.push([...currentDrawings]);
-      return newHistory;
-    });
-    setHistoryIndex(prev => prev + 1);
-  };
-
   const clearCanvas = () => {
     setDrawings([]);
-    setHistory([]);
-    setHistoryIndex(-1);
+    appendOps([{ type: 'clear' }]);
   };
 
   const undo = () => {
-    if (historyIndex > 0) {
-      setHistoryIndex(prev => prev - 1);
-      setDrawings(history[historyIndex - 1] || []);
-    }
+    writeBoard(undoWhiteboard, true);
   };
 
   const redo = () => {
-    if (historyIndex < history.length - 1) {
-      setHistoryIndex(prev => prev + 1);
-      setDrawings(history[historyIndex + 1] || []);
-    }
+    writeBoard(redoWhiteboard, true);
   };
 
   const downloadCanvas = () => {
@@ -327,7 +324,7 @@ export function Whiteboard({ conversationId }: WhiteboardProps) {
             variant="outline"
             size="sm"
             onClick={undo}
-            disabled={historyIndex <= 0}
+            disabled={!board || !canUndo(board)}
           >
             <Undo className="w-4 h-4" />
           </Button>
@@ -335,7 +332,7 @@ export function Whiteboard({ conversationId }: WhiteboardProps) {
             variant="outline"
             size="sm"
             onClick={redo}
-            disabled={historyIndex >= history.length - 1}
+            disabled={!board || !canRedo(board)}
           >
             <Redo className="w-4 h-4" />
           </Button>
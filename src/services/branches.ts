@@ -0,0 +1,29 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { Message } from "./database";
+
+export interface Branch {
+  id: number;
+  conversation_id: number;
+  name: string;
+  fork_message_id: number | null;
+  head_message_id: number;
+  active: boolean;
+  created_at: string;
+  message_count: number;
+}
+
+// Empty until the conversation is first forked.
+export async function getBranches(conversationId: number): Promise<Branch[]> {
+  return await invoke("get_branches", { conversationId });
+}
+
+// Starts a branch sharing the history up to and including messageId and
+// makes it active; the next message sent continues from there.
+export async function forkConversation(conversationId: number, messageId: number, name?: string): Promise<Branch> {
+  return await invoke("fork_conversation", { conversationId, messageId, name: name ?? null });
+}
+
+// Activates a branch and returns its messages.
+export async function switchBranch(conversationId: number, branchId: number): Promise<Message[]> {
+  return await invoke("switch_branch", { conversationId, branchId });
+}
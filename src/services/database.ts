@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 export interface Conversation {
     id: number;
@@ -6,6 +7,8 @@ export interface Conversation {
     created_at: string;
     summary: string;
     notes?: string;
+    folder_id?: number;
+    deleted_at?: string;
 }
 
 export interface Message {
@@ -14,15 +17,28 @@ export interface Message {
     role: 'user' | 'ai';
     content: string;
     seq: number;
+    partial: boolean;
+    created_at: string | null;
+    // Set on replies; token counts only when the provider reports them
+    model: string | null;
+    prompt_tokens: number | null;
+    completion_tokens: number | null;
+    latency_ms: number | null;
+    parent_id: number | null;
+    edited_at: string | null;
 }
 
-export interface MindMapData {
+export interface MessageVersion {
+    message_id: number;
+    version: number;
+    content: string;
+    replaced_at: string | null;
+}
+
+export interface WhiteboardData {
     id: number;
     conversation_id: number;
-    title: string;
-    nodes: string;
-    connections: string;
-    theme: string;
+    data: string;
     created_at: string;
     updated_at: string;
 }
@@ -39,22 +55,42 @@ export async function getConversations(): Promise<Conversation[]> {
     return await invoke("get_conversations");
 }
 
-export async function getConversationsPaginated(limit: number, offset: number): Promise<Conversation[]> {
-    return await invoke("get_conversations_paginated", { limit, offset });
+export async function getConversationsPaginated(limit: number, offset: number, tagId?: number | null): Promise<Conversation[]> {
+    return await invoke("get_conversations_paginated", { limit, offset, tagId: tagId ?? null });
 }
 
-export async function getConversationsCount(): Promise<number> {
-    return await invoke("get_conversations_count");
+export async function getConversationsCount(tagId?: number | null): Promise<number> {
+    return await invoke("get_conversations_count", { tagId: tagId ?? null });
 }
 
 export async function getConversation(conversationId: number): Promise<Conversation | null> {
     return await invoke("get_conversation", { conversationId });
 }
 
+// Moves the conversation to the trash; see restoreConversation / purgeTrash.
 export async function deleteConversation(conversationId: number): Promise<boolean> {
     return await invoke("delete_conversation", { conversationId });
 }
 
+export async function listTrash(): Promise<Conversation[]> {
+    return await invoke("list_trash");
+}
+
+export async function restoreConversation(conversationId: number): Promise<boolean> {
+    return await invoke("restore_conversation", { conversationId });
+}
+
+export async function purgeConversation(conversationId: number): Promise<boolean> {
+    return await invoke("purge_conversation", { conversationId });
+}
+
+// Permanently deletes trashed conversations, only those trashed before
+// olderThan when given.
+export async function purgeTrash(olderThan?: Date): Promise<number> {
+    const cutoff = olderThan ? olderThan.toISOString().slice(0, 19).replace('T', ' ') : null;
+    return await invoke("purge_trash", { olderThan: cutoff });
+}
+
 export async function saveMessage(conversationId: number, role: string, content: string): Promise<number> {
     return await invoke("save_message", { conversationId, role, content });
 }
@@ -63,6 +99,15 @@ export async function getMessages(conversationId: number): Promise<Message[]> {
     return await invoke("get_messages", { conversationId });
 }
 
+// Replaces a message's content; what it said before is kept as a version.
+export async function editMessage(messageId: number, content: string): Promise<Message> {
+    return await invoke("edit_message", { messageId, content });
+}
+
+export async function getMessageVersions(messageId: number): Promise<MessageVersion[]> {
+    return await invoke("get_message_versions", { messageId });
+}
+
 export async function updateConversationNotes(conversationId: number, notes: string): Promise<boolean> {
     return await invoke("update_conversation_notes", { conversationId, notes });
 }
@@ -71,18 +116,39 @@ export async function updateConversationSummary(conversationId: number, summary:
     return await invoke("update_conversation_summary", { conversationId, summary });
 }
 
-export async function getMindMapData(conversationId: number): Promise<MindMapData | null> {
-    return await invoke("get_mindmap_data", { conversationId });
+export async function getWhiteboardData(conversationId: number): Promise<WhiteboardData | null> {
+    return await invoke("get_whiteboard_data", { conversationId });
 }
 
-export async function saveMindMapData(
-    conversationId: number, 
-    title: string, 
-    nodes: string, 
-    connections: string, 
-    theme: string
-): Promise<number> {
-    return await invoke("save_mindmap_data", { conversationId, title, nodes, connections, theme });
+export async function saveWhiteboardData(conversationId: number, data: string): Promise<number> {
+    return await invoke("save_whiteboard_data", { conversationId, data });
+}
+
+const LOCAL_WHITEBOARD_KEY = /^whiteboard_(\d+)$/;
+
+// Whiteboards used to live in localStorage; moves any that are left into the
+// database and clears them from localStorage once they're stored.
+export async function importLocalWhiteboards(): Promise<number> {
+    const keys: string[] = [];
+    const whiteboards: Array<{ conversation_id: number; data: string }> = [];
+    for (let i = 0; i < localStorage.length; i++) {
+        const key = localStorage.key(i);
+        const match = key ? LOCAL_WHITEBOARD_KEY.exec(key) : null;
+        const data = key ? localStorage.getItem(key) : null;
+        if (!key || !match || data === null) continue;
+        keys.push(key);
+        try {
+            JSON.parse(data);
+            whiteboards.push({ conversation_id: Number(match[1]), data });
+        } catch {
+            // Unreadable boards can't be drawn either; drop them with the rest
+        }
+    }
+    if (keys.length === 0) return 0;
+
+    const imported: number = await invoke("import_local_whiteboards", { whiteboards });
+    keys.forEach((key) => localStorage.removeItem(key));
+    return imported;
 }
 
 export async function backupDatabase(): Promise<string> {
@@ -93,10 +159,103 @@ export async function getDatabaseInfo(): Promise<any> {
     return await invoke("get_database_info");
 }
 
-export async function getApiKey(): Promise<string | null> {
-    return await invoke("get_api_key");
+export interface IntegrityIssue {
+    kind: string;
+    count: number;
+}
+
+export interface IntegrityReport {
+    integrity_errors: string[];
+    foreign_key_violations: { table: string; rowid: number | null; parent: string }[];
+    issues: IntegrityIssue[];
+    repaired: boolean;
+}
+
+export async function checkIntegrity(repair = false): Promise<IntegrityReport> {
+    return await invoke("check_integrity", { repair });
+}
+
+export interface MigrationStep {
+    version: number;
+    name: string;
+    direction: "up" | "down";
+}
+
+export interface ChecksumMismatch {
+    version: number;
+    name: string;
+    applied: string;
+    current: string;
+}
+
+export interface MigrationPlan {
+    current_version: number;
+    target_version: number;
+    steps: MigrationStep[];
+    checksum_mismatches: ChecksumMismatch[];
+}
+
+export interface MigrationFailure {
+    message: string;
+    restored_backup: string | null;
+    checksum_mismatches: ChecksumMismatch[];
+}
+
+export interface MigrationStatus {
+    current_version: number | null;
+    latest_version: number;
+    checksum_mismatches: ChecksumMismatch[];
+    failure: MigrationFailure | null;
+}
+
+// Sent as each migration starts, and periodically by long data migrations
+// with done/total filled in.
+export interface MigrationProgress {
+    version: number;
+    name: string;
+    direction: "up" | "down";
+    step: number;
+    steps: number;
+    done: number | null;
+    total: number | null;
+}
+
+export async function onMigrationProgress(handler: (progress: MigrationProgress) => void): Promise<UnlistenFn> {
+    return await listen<MigrationProgress>("migration://progress", (event) => handler(event.payload));
+}
+
+export async function getMigrationStatus(): Promise<MigrationStatus> {
+    return await invoke("get_migration_status");
+}
+
+// Rolls the schema back (or forward) to targetVersion, e.g. before installing
+// an older release. With dryRun it only returns the steps it would take.
+// Version 0 deletes every table and is refused unless confirmDataLoss is set.
+export async function migrateDatabase(targetVersion: number, dryRun = false, confirmDataLoss = false): Promise<MigrationPlan> {
+    return await invoke("migrate_database", { targetVersion, dryRun, confirmDataLoss });
 }
 
 export async function setApiKey(apiKey: string): Promise<void> {
     return await invoke("set_api_key", { apiKey });
 }
+
+export interface EncryptionStatus {
+    encrypted: boolean;
+    locked: boolean;
+}
+
+export async function getEncryptionStatus(): Promise<EncryptionStatus> {
+    return await invoke("get_encryption_status");
+}
+
+export async function unlockDatabase(passphrase: string): Promise<void> {
+    return await invoke("unlock_database", { passphrase });
+}
+
+export async function enableEncryption(passphrase: string): Promise<void> {
+    return await invoke("enable_encryption", { passphrase });
+}
+
+export async function changePassphrase(currentPassphrase: string, newPassphrase: string): Promise<void> {
+    return await invoke("change_passphrase", { currentPassphrase, newPassphrase });
+}
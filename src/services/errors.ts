@@ -0,0 +1,68 @@
+import type { MindMapEdge } from "./mindmaps";
+import type { QueryError } from "./search-types";
+
+export type RweErrorKind =
+    | "not_found"
+    | "conflict"
+    | "validation"
+    | "invalid_query"
+    | "invalid_mind_map"
+    | "storage"
+    | "network"
+    | "locked"
+    | "internal";
+
+// Why a mind map save was rejected, and the node or connection at fault.
+export interface MindMapError {
+    kind:
+        | "malformed"
+        | "empty_node_id"
+        | "duplicate_node_id"
+        | "invalid_position"
+        | "dangling_edge"
+        | "self_loop"
+        | "duplicate_edge"
+        | "cycle";
+    message: string;
+    node_id: string | null;
+    edge: MindMapEdge | null;
+}
+
+// What Tauri commands reject with: the kind to branch on, and a message to
+// show (or, for malformed searches and mind maps, what went wrong where).
+export type RweError =
+    | { kind: "invalid_query"; detail: QueryError }
+    | { kind: "invalid_mind_map"; detail: MindMapError }
+    | { kind: Exclude<RweErrorKind, "invalid_query" | "invalid_mind_map">; detail: string };
+
+export function isRweError(error: unknown): error is RweError {
+    return typeof error === "object" && error !== null && "kind" in error && "detail" in error;
+}
+
+// An RweError rethrown as an Error, for callers that match on error.message.
+export class CommandError extends Error {
+    readonly kind: RweErrorKind;
+
+    constructor(error: RweError) {
+        super(errorMessage(error));
+        this.name = "CommandError";
+        this.kind = error.kind;
+    }
+}
+
+export function errorKind(error: unknown): RweErrorKind | undefined {
+    if (error instanceof CommandError || isRweError(error)) {
+        return error.kind;
+    }
+    return undefined;
+}
+
+export function errorMessage(error: unknown): string {
+    if (isRweError(error)) {
+        return typeof error.detail === "string" ? error.detail : error.detail.message;
+    }
+    if (error instanceof Error) {
+        return error.message;
+    }
+    return String(error);
+}
@@ -0,0 +1,53 @@
+import { invoke } from "@tauri-apps/api/core";
+import { type Conversation } from "./database";
+
+export interface FolderNode {
+  id: number;
+  parent_id?: number;
+  name: string;
+  position: number;
+  created_at: string;
+  conversation_count: number;
+  total_conversation_count: number;
+  children: FolderNode[];
+}
+
+// move_to_parent lifts subfolders and conversations into the parent folder;
+// delete_contents removes the whole subtree including its conversations.
+export type FolderDeleteMode = 'move_to_parent' | 'delete_contents';
+
+export async function getFolderTree(rootId: number | null = null): Promise<FolderNode[]> {
+  return await invoke("get_folder_tree", { rootId });
+}
+
+export async function getFolderConversations(folderId: number | null): Promise<Conversation[]> {
+  return await invoke("get_folder_conversations", { folderId });
+}
+
+export async function createFolder(name: string, parentId: number | null = null): Promise<number> {
+  return await invoke("create_folder", { name, parentId });
+}
+
+export async function renameFolder(folderId: number, name: string): Promise<boolean> {
+  return await invoke("rename_folder", { folderId, name });
+}
+
+export async function moveFolder(folderId: number, parentId: number | null, position?: number): Promise<boolean> {
+  return await invoke("move_folder", { folderId, parentId, position: position ?? null });
+}
+
+export async function reorderFolders(parentId: number | null, folderIds: number[]): Promise<void> {
+  return await invoke("reorder_folders", { parentId, folderIds });
+}
+
+export async function moveConversation(conversationId: number, folderId: number | null, position?: number): Promise<boolean> {
+  return await invoke("move_conversation", { conversationId, folderId, position: position ?? null });
+}
+
+export async function reorderConversations(folderId: number | null, conversationIds: number[]): Promise<void> {
+  return await invoke("reorder_conversations", { folderId, conversationIds });
+}
+
+export async function deleteFolder(folderId: number, mode: FolderDeleteMode = 'move_to_parent'): Promise<boolean> {
+  return await invoke("delete_folder", { folderId, mode });
+}
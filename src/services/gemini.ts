@@ -1,56 +0,0 @@
-import { getApiKey } from "./database";
-
-export interface GeminiMessage {
-  role: 'user' | 'model';
-  parts: { text: string }[];
-}
-
-export interface GeminiResponse {
-  candidates: {
-    content: {
-      parts: { text: string }[];
-    };
-  }[];
-}
-
-export async function sendToGemini(messages: GeminiMessage[]): Promise<string> {
-  const apiKey = await getApiKey();
-  
-  if (!apiKey) {
-    throw new Error("API key not configured");
-  }
-
-  const response = await fetch(`https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key=${apiKey}`, {
-    method: 'POST',
-    headers: {
-      'Content-Type': 'application/json',
-    },
-    body: JSON.stringify({
-      contents: messages,
-      generationConfig: {
-        temperature: 0.7,
-        maxOutputTokens: 1000,
-      },
-    }),
-  });
-
-  if (!response.ok) {
-    const errorText = await response.text();
-    throw new Error(`Gemini API error: ${response.status} - ${errorText}`);
-  }
-
-  const data: GeminiResponse = await response.json();
-  
-  if (!data.candidates?.[0]?.content?.parts?.[0]?.text) {
-    throw new Error("Invalid response from Gemini API");
-  }
-
-  return data.candidates[0].content.parts[0].text;
-}
-
-export function convertToGeminiFormat(conversation: Array<{role: string, content: string}>): GeminiMessage[] {
-  return conversation.map(msg => ({
-    role: msg.role === 'user' ? 'user' as const : 'model' as const,
-    parts: [{ text: msg.content }]
-  }));
-}
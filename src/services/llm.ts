@@ -0,0 +1,99 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import type { Message } from "./database";
+import { CommandError, isRweError } from "./errors";
+
+export interface ChatMessage {
+  role: 'user' | 'assistant';
+  content: string;
+}
+
+// Commands reject with an RweError; rethrow it as an Error so callers can
+// match on error.message ("API key not configured", "401", "429") as well
+// as on its kind.
+async function invokeLlm<T>(command: string, args: Record<string, unknown>): Promise<T> {
+  try {
+    return await invoke<T>(command, args);
+  } catch (error) {
+    if (isRweError(error)) {
+      throw new CommandError(error);
+    }
+    throw error instanceof Error ? error : new Error(String(error));
+  }
+}
+
+export async function sendChatMessage(conversationId: number, content: string): Promise<Message> {
+  return await invokeLlm("send_chat_message", { conversationId, content });
+}
+
+export interface ChatDelta {
+  requestId: string;
+  conversationId: number;
+  delta: string;
+}
+
+// Resolves with the stored reply once the stream ends, or null if it was
+// cancelled before any text arrived. Cancelled replies come back with
+// `partial` set.
+export async function streamChatMessage(
+  requestId: string,
+  conversationId: number,
+  content: string,
+  onDelta: (delta: string) => void
+): Promise<Message | null> {
+  const unlisten = await listen<ChatDelta>("chat://delta", (event) => {
+    if (event.payload.requestId === requestId) {
+      onDelta(event.payload.delta);
+    }
+  });
+  try {
+    return await invokeLlm("stream_chat_message", { requestId, conversationId, content });
+  } finally {
+    unlisten();
+  }
+}
+
+export async function cancelChatStream(requestId: string): Promise<boolean> {
+  return await invoke("cancel_chat_stream", { requestId });
+}
+
+export async function generateCompletion(messages: ChatMessage[]): Promise<string> {
+  return await invokeLlm("generate_completion", { messages });
+}
+
+export function toChatMessages(conversation: Array<{role: string, content: string}>): ChatMessage[] {
+  return conversation.map(msg => ({
+    role: msg.role === 'user' ? 'user' as const : 'assistant' as const,
+    content: msg.content
+  }));
+}
+
+export type ProviderKind = 'gemini' | 'openai' | 'ollama';
+
+export interface LlmConfig {
+  provider: ProviderKind;
+  base_url: string | null;
+  model: string | null;
+  temperature: number;
+  max_output_tokens: number;
+}
+
+export interface LlmStatus {
+  config: LlmConfig;
+  api_key_configured: boolean;
+  ready: boolean;
+}
+
+export async function getLlmConfig(): Promise<LlmStatus> {
+  return await invoke("get_llm_config");
+}
+
+// `apiKey` replaces the selected provider's key; undefined leaves it as is
+// and an empty string removes it.
+export async function setLlmConfig(config: LlmConfig, apiKey?: string): Promise<void> {
+  return await invoke("set_llm_config", { config, apiKey: apiKey ?? null });
+}
+
+export async function listModels(config?: LlmConfig): Promise<string[]> {
+  return await invokeLlm("list_models", { config: config ?? null });
+}
@@ -0,0 +1,99 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export interface MindMapNode {
+  id: string;
+  x: number;
+  y: number;
+  text: string;
+  shape: "square" | "circle" | "diamond";
+  color: string;
+}
+
+export interface MindMapEdge {
+  from: string;
+  to: string;
+}
+
+export interface MindMapData {
+  id: number;
+  conversation_id: number;
+  title: string;
+  nodes: MindMapNode[];
+  connections: MindMapEdge[];
+  theme: string;
+  // Whether the map refuses connections that make a loop.
+  acyclic: boolean;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface MindMapVersionInfo {
+  mindmap_id: number;
+  version: number;
+  title: string;
+  theme: string;
+  created_at: string;
+}
+
+export interface MindMapVersion extends MindMapVersionInfo {
+  nodes: MindMapNode[];
+  connections: MindMapEdge[];
+}
+
+// The conversation's most recently saved map.
+export async function getMindMapData(conversationId: number): Promise<MindMapData | null> {
+  return await invoke("get_mindmap_data", { conversationId });
+}
+
+export async function listMindMaps(conversationId: number): Promise<MindMapData[]> {
+  return await invoke("list_mindmaps", { conversationId });
+}
+
+// A null `mindmapId` starts a new map. Resolves with the map's id, or rejects
+// with an "invalid_mind_map" error naming the node or connection at fault.
+// `acyclic` is kept from the last save when left out.
+export async function saveMindMapData(
+  conversationId: number,
+  mindmapId: number | null,
+  title: string,
+  nodes: MindMapNode[],
+  connections: MindMapEdge[],
+  theme: string,
+  acyclic?: boolean
+): Promise<number> {
+  return await invoke("save_mindmap_data", { conversationId, mindmapId, title, nodes, connections, theme, acyclic });
+}
+
+export async function deleteMindMap(mindmapId: number): Promise<boolean> {
+  return await invoke("delete_mindmap", { mindmapId });
+}
+
+export async function listMindMapVersions(mindmapId: number): Promise<MindMapVersionInfo[]> {
+  return await invoke("list_mindmap_versions", { mindmapId });
+}
+
+export async function getMindMapVersion(mindmapId: number, version: number): Promise<MindMapVersion> {
+  return await invoke("get_mindmap_version", { mindmapId, version });
+}
+
+// Saved as a new version, so nothing after `version` is lost.
+export async function restoreMindMapVersion(mindmapId: number, version: number): Promise<MindMapData> {
+  return await invoke("restore_mindmap_version", { mindmapId, version });
+}
+
+export type MindMapExportFormat = "opml" | "freemind" | "mermaid" | "dot";
+
+export interface MindMapExport {
+  file_name: string;
+  content: string;
+}
+
+// The conversation's most recently saved map, or `mindmapId`, written out
+// for another tool.
+export async function exportMindMap(
+  conversationId: number,
+  format: MindMapExportFormat,
+  mindmapId?: number | null
+): Promise<MindMapExport> {
+  return await invoke("export_mindmap", { conversationId, format, mindmapId });
+}
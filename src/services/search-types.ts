@@ -1,3 +1,16 @@
+import { isRweError } from "./errors";
+
+export interface HighlightRange {
+    start: number;
+    end: number;
+}
+
+export interface TermCorrection {
+    original: string;
+    corrected: string;
+    distance: number;
+}
+
 export interface SearchResult {
     id: number;
     conversation_id: number;
@@ -5,11 +18,35 @@ export interface SearchResult {
     content_type: string;
     content: string;
     snippet: string;
+    highlights: HighlightRange[];
     relevance_score: number;
     created_at: string;
+    fuzziness: number;
+    corrections: TermCorrection[];
+    similarity: number | null;
+}
+
+export interface QueryError {
+    kind: string;
+    message: string;
+    position: number;
+}
+
+export function isInvalidQueryError(error: unknown): error is { kind: "invalid_query"; detail: QueryError } {
+    return isRweError(error) && error.kind === "invalid_query";
 }
 
 export async function searchContent(query: string): Promise<SearchResult[]> {
     const { invoke } = await import("@tauri-apps/api/core");
     return await invoke("search_content", { query });
 }
+
+export async function suggestSearchTerms(prefix: string, limit?: number): Promise<string[]> {
+    const { invoke } = await import("@tauri-apps/api/core");
+    return await invoke("suggest_search_terms", { prefix, limit });
+}
+
+export async function semanticSearch(query: string, limit?: number, hybrid?: boolean): Promise<SearchResult[]> {
+    const { invoke } = await import("@tauri-apps/api/core");
+    return await invoke("semantic_search", { query, limit, hybrid });
+}
@@ -0,0 +1,27 @@
+import { invoke } from "@tauri-apps/api/core";
+
+// Secret values are write-only from the frontend: they are encrypted in the
+// backend and only ever used there.
+export interface SecretInfo {
+  provider: string;
+  name: string;
+  is_active: boolean;
+  created_at: string;
+  updated_at: string;
+}
+
+export async function listSecrets(): Promise<SecretInfo[]> {
+  return await invoke("list_secrets");
+}
+
+export async function setSecret(provider: string, name: string, value: string, activate = false): Promise<void> {
+  return await invoke("set_secret", { provider, name, value, activate });
+}
+
+export async function deleteSecret(provider: string, name: string): Promise<boolean> {
+  return await invoke("delete_secret", { provider, name });
+}
+
+export async function activateSecret(provider: string, name: string): Promise<boolean> {
+  return await invoke("activate_secret", { provider, name });
+}
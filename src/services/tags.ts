@@ -0,0 +1,45 @@
+import { invoke } from "@tauri-apps/api/core";
+
+export interface Tag {
+  id: number;
+  name: string;
+  color?: string;
+  created_at: string;
+  conversation_count: number;
+}
+
+export async function getTags(): Promise<Tag[]> {
+  return await invoke("get_tags");
+}
+
+export async function getConversationTags(conversationId: number): Promise<Tag[]> {
+  return await invoke("get_conversation_tags", { conversationId });
+}
+
+export async function createTag(name: string, color?: string): Promise<number> {
+  return await invoke("create_tag", { name, color: color ?? null });
+}
+
+export async function renameTag(tagId: number, name: string): Promise<boolean> {
+  return await invoke("rename_tag", { tagId, name });
+}
+
+export async function setTagColor(tagId: number, color: string | null): Promise<boolean> {
+  return await invoke("set_tag_color", { tagId, color });
+}
+
+export async function mergeTags(sourceTagId: number, targetTagId: number): Promise<boolean> {
+  return await invoke("merge_tags", { sourceTagId, targetTagId });
+}
+
+export async function deleteTag(tagId: number): Promise<boolean> {
+  return await invoke("delete_tag", { tagId });
+}
+
+export async function tagConversation(conversationId: number, tagId: number): Promise<boolean> {
+  return await invoke("tag_conversation", { conversationId, tagId });
+}
+
+export async function untagConversation(conversationId: number, tagId: number): Promise<boolean> {
+  return await invoke("untag_conversation", { conversationId, tagId });
+}
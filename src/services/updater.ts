@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core"
+import { errorMessage } from "./errors"
 
 export async function checkForUpdates() {
   try {
@@ -38,7 +39,7 @@ export async function checkForUpdates() {
     return { available: false }
   } catch (error) {
     console.error('Update check failed:', error)
-    return { available: false, error: String(error) }
+    return { available: false, error: errorMessage(error) }
   }
 }
 
@@ -50,8 +51,9 @@ export async function prepareForUpdate(): Promise<string> {
   return await invoke('prepare_for_update')
 }
 
-export async function exportUserData(): Promise<string> {
-  return await invoke('export_user_data')
+// API keys are stripped from the export unless `includeSecrets` is set
+export async function exportUserData(includeSecrets = false): Promise<string> {
+  return await invoke('export_user_data', { includeSecrets })
 }
 
 export async function importUserData(importPath: string): Promise<boolean> {
@@ -0,0 +1,45 @@
+import { invoke } from "@tauri-apps/api/core";
+
+// A board is a snapshot plus a log of operations, one revision each.
+export type WhiteboardOp =
+  | { type: "add"; element: Record<string, unknown> }
+  | { type: "clear" };
+
+export interface WhiteboardState {
+  conversation_id: number;
+  revision: number;
+  head_revision: number;
+  snapshot_revision: number;
+  latest_revision: number;
+  elements: Record<string, unknown>[];
+}
+
+export function canUndo(state: WhiteboardState): boolean {
+  return state.head_revision > state.snapshot_revision;
+}
+
+export function canRedo(state: WhiteboardState): boolean {
+  return state.latest_revision > state.head_revision;
+}
+
+// Leave `revision` out for the board as it is now.
+export async function getWhiteboardState(conversationId: number, revision?: number): Promise<WhiteboardState> {
+  return await invoke("get_whiteboard_state", { conversationId, revision: revision ?? null });
+}
+
+export async function appendWhiteboardOps(conversationId: number, ops: WhiteboardOp[]): Promise<WhiteboardState> {
+  return await invoke("append_whiteboard_ops", { conversationId, ops });
+}
+
+export async function undoWhiteboard(conversationId: number): Promise<WhiteboardState> {
+  return await invoke("undo_whiteboard", { conversationId });
+}
+
+export async function redoWhiteboard(conversationId: number): Promise<WhiteboardState> {
+  return await invoke("redo_whiteboard", { conversationId });
+}
+
+// Folds the log into the snapshot, keeping the last `keepRevisions` undoable.
+export async function compactWhiteboard(conversationId: number, keepRevisions?: number): Promise<WhiteboardState> {
+  return await invoke("compact_whiteboard", { conversationId, keepRevisions: keepRevisions ?? null });
+}